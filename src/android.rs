@@ -0,0 +1,108 @@
+//! Cross compiling for Android with the clang and llvm-ar of an NDK
+
+use anyhow::{bail, Result};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The target built with `--android-ndk` unless the cargo args pick one
+pub const DEFAULT_TARGET: &str = "aarch64-linux-android";
+
+/// The API level linked against unless `--android-api` says otherwise, the lowest one UE supports
+pub const DEFAULT_API_LEVEL: &str = "26";
+
+/// The NDK's prebuilt LLVM toolchain for the host
+pub struct AndroidNdk {
+    bin_dir: PathBuf,
+    api_level: String,
+}
+
+impl AndroidNdk {
+    pub fn new(root: &Path, api_level: &str) -> Result<AndroidNdk> {
+        let host = if cfg!(windows) {
+            "windows-x86_64"
+        } else if cfg!(target_os = "macos") {
+            // the macOS NDK ships universal binaries under this name
+            "darwin-x86_64"
+        } else {
+            "linux-x86_64"
+        };
+        let bin_dir = root.join("toolchains/llvm/prebuilt").join(host).join("bin");
+        if !bin_dir.is_dir() {
+            bail!(
+                "{} doesn't look like an Android NDK (r19 or newer), {} doesn't exist",
+                root.display(),
+                bin_dir.display()
+            );
+        }
+        Ok(AndroidNdk {
+            bin_dir,
+            api_level: api_level.to_owned(),
+        })
+    }
+
+    /// The clang wrapper linking for `target` at the API level, e.g.
+    /// `aarch64-linux-android26-clang`
+    pub fn linker(&self, target: &str) -> PathBuf {
+        self.clang(target, "clang")
+    }
+
+    fn clang(&self, target: &str, driver: &str) -> PathBuf {
+        // the 32 bit ARM targets' clang is named after the architecture version
+        let clang_target = match target {
+            "armv7-linux-androideabi" | "thumbv7neon-linux-androideabi" => {
+                "armv7a-linux-androideabi"
+            }
+            _ => target,
+        };
+        let extension = if cfg!(windows) { ".cmd" } else { "" };
+        self.bin_dir.join(format!(
+            "{}{}-{}{}",
+            clang_target, self.api_level, driver, extension
+        ))
+    }
+
+    pub fn ar(&self) -> PathBuf {
+        let extension = if cfg!(windows) { ".exe" } else { "" };
+        self.bin_dir.join(format!("llvm-ar{}", extension))
+    }
+
+    /// The environment variables making cargo link with the NDK's clang, and build scripts using
+    /// the cc crate compile with it
+    pub fn cargo_env(&self, target: &str) -> Vec<(String, OsString)> {
+        let linker = self.linker(target);
+        let env_target = target.replace('-', "_");
+        vec![
+            (
+                format!("CARGO_TARGET_{}_LINKER", env_target.to_uppercase()),
+                linker.clone().into(),
+            ),
+            (format!("CC_{}", env_target), linker.into()),
+            (
+                format!("CXX_{}", env_target),
+                self.clang(target, "clang++").into(),
+            ),
+            (format!("AR_{}", env_target), self.ar().into()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armv7_clang_name() {
+        let ndk = AndroidNdk {
+            bin_dir: PathBuf::from("bin"),
+            api_level: "29".to_owned(),
+        };
+        let extension = if cfg!(windows) { ".cmd" } else { "" };
+        assert_eq!(
+            ndk.linker("armv7-linux-androideabi"),
+            Path::new("bin").join(format!("armv7a-linux-androideabi29-clang{}", extension))
+        );
+        let env = ndk.cargo_env("aarch64-linux-android");
+        assert_eq!(env[0].0, "CARGO_TARGET_AARCH64_LINUX_ANDROID_LINKER");
+        assert_eq!(env[2].0, "CXX_aarch64_linux_android");
+    }
+}
@@ -0,0 +1,123 @@
+//! Reading GNU thin archives, which only reference their member objects instead of containing
+//! them. LIB.EXE can't read those, so their members have to be passed to it directly.
+
+use anyhow::{bail, Context, Result};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const THIN_MAGIC: &[u8] = b"!<thin>\n";
+
+/// Size of a member header: name, date, uid, gid, mode, size and the terminating "`\n"
+const HEADER_LEN: usize = 60;
+
+/// Whether the file starts with the thin archive magic. Files that can't be read aren't.
+pub fn is_thin_archive(path: &Path) -> bool {
+    let mut magic = [0; THIN_MAGIC.len()];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && magic == THIN_MAGIC
+}
+
+/// The paths of the objects a thin archive references, in archive order. Relative paths are
+/// relative to the archive's directory.
+pub fn thin_archive_members(path: &Path) -> Result<Vec<PathBuf>> {
+    let data = std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let Some(mut rest) = data.strip_prefix(THIN_MAGIC) else {
+        bail!("{} isn't a thin archive", path.display());
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut long_names: &[u8] = &[];
+    let mut members = Vec::new();
+    while !rest.is_empty() {
+        if rest.len() < HEADER_LEN || &rest[58..60] != b"`\n" {
+            bail!("Malformed member header in {}", path.display());
+        }
+        let name = std::str::from_utf8(&rest[..16])?.trim_end();
+        let size: usize = std::str::from_utf8(&rest[48..58])?
+            .trim_end()
+            .parse()
+            .with_context(|| format!("Malformed member size in {}", path.display()))?;
+        rest = &rest[HEADER_LEN..];
+        // only the symbol and name tables are stored in the archive itself, padded to an even size
+        let mut take_data = || -> Result<&[u8]> {
+            let padded = size + size % 2;
+            if rest.len() < size {
+                bail!("Truncated member in {}", path.display());
+            }
+            let data = &rest[..size];
+            rest = &rest[padded.min(rest.len())..];
+            Ok(data)
+        };
+        let member = match name {
+            "/" | "/SYM64/" => {
+                take_data()?;
+                continue;
+            }
+            "//" => {
+                long_names = take_data()?;
+                continue;
+            }
+            _ => match name.strip_prefix('/') {
+                Some(offset) => {
+                    let offset: usize = offset.parse().with_context(|| {
+                        format!("Malformed member name {} in {}", name, path.display())
+                    })?;
+                    let long_name = long_names.get(offset..).unwrap_or_default();
+                    let end = long_name
+                        .windows(2)
+                        .position(|w| w == b"/\n")
+                        .unwrap_or(long_name.len());
+                    std::str::from_utf8(&long_name[..end])?
+                }
+                None => name.strip_suffix('/').unwrap_or(name),
+            },
+        };
+        members.push(base_dir.join(member));
+    }
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(name: &str, size: usize) -> String {
+        format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            name, 0, 0, 0, 644, size
+        )
+    }
+
+    #[test]
+    fn thin_archive_member_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let long_names = "deps/a_long_object_name.rcgu.o/\n";
+        let archive = [
+            "!<thin>\n".to_owned(),
+            header("/", 4),
+            "\0\0\0\0".to_owned(),
+            header("//", long_names.len()),
+            long_names.to_owned(),
+            header("/0", 1200),
+            header("short.o/", 300),
+        ]
+        .concat();
+        let thin_path = dir.join("libthin.rlib");
+        std::fs::write(&thin_path, archive).unwrap();
+        let regular_path = dir.join("libregular.rlib");
+        std::fs::write(&regular_path, "!<arch>\n").unwrap();
+
+        assert!(is_thin_archive(&thin_path));
+        assert!(!is_thin_archive(&regular_path));
+        assert!(!is_thin_archive(&dir.join("missing.rlib")));
+        assert_eq!(
+            thin_archive_members(&thin_path).unwrap(),
+            vec![
+                dir.join("deps/a_long_object_name.rcgu.o"),
+                dir.join("short.o")
+            ]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Resolving the relative paths of the command line against `--base-dir`, for build systems that
+//! don't run the tool in the directory their paths are relative to
+
+use std::path::{Path, PathBuf};
+
+/// `path` resolved against `base_dir`, if there is one and the path is relative
+pub fn resolve(base_dir: Option<&str>, path: &str) -> PathBuf {
+    match base_dir {
+        Some(base_dir) if Path::new(path).is_relative() => Path::new(base_dir).join(path),
+        _ => path.into(),
+    }
+}
+
+/// The cargo args with a relative `--manifest-path` resolved against `base_dir`. The args after
+/// `--` belong to rustc and stay as they are.
+pub fn resolve_manifest_path_arg(base_dir: Option<&str>, cargo_args: &[&str]) -> Vec<String> {
+    let mut resolved = Vec::new();
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        if *arg == "--" {
+            resolved.push(arg.to_string());
+            resolved.extend(args.map(|arg| arg.to_string()));
+            break;
+        } else if *arg == "--manifest-path" {
+            resolved.push(arg.to_string());
+            if let Some(path) = args.next() {
+                resolved.push(resolve(base_dir, path).to_string_lossy().into_owned());
+            }
+        } else if let Some(path) = arg.strip_prefix("--manifest-path=") {
+            let path = resolve(base_dir, path);
+            resolved.push(format!("--manifest-path={}", path.to_string_lossy()));
+        } else {
+            resolved.push(arg.to_string());
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_path_arg() {
+        let base_dir = Some("/game");
+        let resolved = |args: &[&str]| resolve_manifest_path_arg(base_dir, args);
+        let expected_path = Path::new("/game").join("Rust/Cargo.toml");
+        let expected_path = expected_path.to_string_lossy();
+        assert_eq!(
+            resolved(&["build", "--manifest-path", "Rust/Cargo.toml"]),
+            ["build", "--manifest-path", &expected_path]
+        );
+        assert_eq!(
+            resolved(&["build", "--manifest-path=Rust/Cargo.toml", "--release"]),
+            [
+                "build".to_owned(),
+                format!("--manifest-path={}", expected_path),
+                "--release".to_owned()
+            ]
+        );
+        // absolute paths, and args after `--`, which cargo passes on, stay as they are
+        assert_eq!(
+            resolved(&["build", "--manifest-path=/other/Cargo.toml"]),
+            ["build", "--manifest-path=/other/Cargo.toml"]
+        );
+        assert_eq!(
+            resolved(&["rustc", "--", "--manifest-path", "Rust/Cargo.toml"]),
+            ["rustc", "--", "--manifest-path", "Rust/Cargo.toml"]
+        );
+        // a trailing --manifest-path without a value is left for cargo to reject
+        assert_eq!(
+            resolved(&["build", "--manifest-path"]),
+            ["build", "--manifest-path"]
+        );
+        assert_eq!(
+            resolve_manifest_path_arg(None, &["build", "--manifest-path", "Rust/Cargo.toml"]),
+            ["build", "--manifest-path", "Rust/Cargo.toml"]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! The module list of the batch subcommand, which runs gen-bindings (and optionally
+//! source-files) for many crates in a single invocation
+
+use crate::bindings::{generate_header, BindingsOptions};
+use crate::header;
+use crate::source_files::{lib_source_files, local_packages, WalkOptions};
+use anyhow::{Context, Result};
+use cargo::core::Workspace;
+use cargo::GlobalContext;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// One entry of the module list
+#[derive(Debug, Deserialize)]
+pub struct Module {
+    pub crate_dir: PathBuf,
+    pub output_header_file: PathBuf,
+    /// Where to write the crate's source files, one per line, like the source-files subcommand.
+    /// Written along with the headers, so not at all when a transactional batch fails
+    #[serde(default)]
+    pub source_files_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModuleList {
+    modules: Vec<Module>,
+}
+
+/// Read a module list from a `.toml` or `.json` file with a `modules` array. Relative paths are
+/// resolved against the directory of the file.
+pub fn read_modules(path: &Path) -> Result<Vec<Module>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read module list {}", path.display()))?;
+    let list: ModuleList = if path.extension() == Some("toml".as_ref()) {
+        toml::from_str(&contents)?
+    } else {
+        serde_json::from_str(&contents)?
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    Ok(list
+        .modules
+        .into_iter()
+        .map(|module| Module {
+            crate_dir: base_dir.join(module.crate_dir),
+            output_header_file: base_dir.join(module.output_header_file),
+            source_files_file: module.source_files_file.map(|path| base_dir.join(path)),
+        })
+        .collect())
+}
+
+/// What processing a module produced. Writing it is up to the caller.
+pub struct ModuleOutput {
+    /// The new header, if it differs from the existing one
+    pub header: Option<Vec<u8>>,
+    /// The contents of the module's `source_files_file`, if it has one
+    pub source_files: Option<String>,
+}
+
+/// Generate the module's header and list its source files
+pub fn process_module(
+    module: &Module,
+    options: &BindingsOptions,
+    ctx: &GlobalContext,
+) -> Result<ModuleOutput> {
+    let new_data = generate_header(&module.crate_dir, options, None)?;
+    let mut source_files = None;
+    if module.source_files_file.is_some() {
+        let cargo_toml_path = module.crate_dir.join("Cargo.toml").canonicalize()?;
+        let ws = Workspace::new(&cargo_toml_path, ctx)?;
+        let mut contents = String::new();
+        for package in local_packages(&ws)? {
+            for file in lib_source_files(&package, &WalkOptions::default())? {
+                contents.push_str(&file.to_string_lossy());
+                contents.push('\n');
+            }
+        }
+        source_files = Some(contents);
+    }
+    let existing_data = std::fs::read(&module.output_header_file).unwrap_or_default();
+    let header =
+        header::changed(&new_data, Some(&existing_data), options.stamp_line()?).then_some(new_data);
+    Ok(ModuleOutput {
+        header,
+        source_files,
+    })
+}
+
+/// Write the module's source file list
+pub fn write_source_files(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, contents)?;
+    Ok(())
+}
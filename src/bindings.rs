@@ -0,0 +1,926 @@
+//! Running cbindgen for gen-bindings
+
+use crate::cfg::Cfg;
+use crate::color::ColorChoice;
+use crate::ffi_lint::LintLevel;
+use crate::{calling_convention, crate_items, ffi_lint, header};
+use anyhow::{bail, Context, Result};
+use cargo::core::Workspace;
+use cargo::GlobalContext;
+use clap::ArgMatches;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// How gen-bindings generates each header
+#[derive(Debug, Default)]
+pub struct BindingsOptions {
+    /// Provenance comment to add to the header
+    pub stamp: Option<String>,
+    /// Crates to macro expand before parsing. An empty list means the crate itself.
+    pub expand: Option<Vec<String>>,
+    /// Features to enable for the expansion
+    pub expand_features: Vec<String>,
+    /// Whether to enable all features for the expansion
+    pub expand_all_features: bool,
+    /// Whether to disable the default features for the expansion
+    pub expand_no_default_features: bool,
+    /// Whether to expand with the release profile
+    pub expand_release: bool,
+    /// Toolchain to expand with, instead of the crate's or rustup's default
+    pub expand_toolchain: Option<String>,
+    /// Modules whose items are exported. If empty, all modules are.
+    pub include_modules: Vec<String>,
+    /// Modules whose items aren't exported
+    pub exclude_modules: Vec<String>,
+    /// Whether to run clang-format over the header
+    pub format: bool,
+    /// .clang-format file to format the header with, instead of the one clang-format finds
+    pub format_style: Option<PathBuf>,
+    /// Whether to check that the header compiles before it's written
+    pub validate_header: bool,
+    /// Compiler command to check the header with, instead of the default for its language
+    pub validate_command: Option<Vec<String>>,
+    /// cfgs the crate is compiled with
+    pub cfgs: Vec<Cfg>,
+    /// cbindgen config to use instead of the crate's cbindgen.toml
+    pub cbindgen_config: Option<PathBuf>,
+    /// Keys of the cbindgen config to override, as `key=value`
+    pub config_overrides: Vec<String>,
+    /// Language of the header, overriding the config's
+    pub language: Option<cbindgen::Language>,
+    /// Namespace of a C++ header, e.g. `Game::Rust`
+    pub namespace: Option<String>,
+    /// Macro to declare every function with, e.g. `GAME_API`
+    pub api_macro: Option<String>,
+    /// Whether to define `api_macro` as empty if it isn't defined yet
+    pub define_api_macro: bool,
+    /// Include guard symbol, overriding the config's
+    pub include_guard: Option<String>,
+    /// File whose contents (e.g. a copyright notice) start the header
+    pub license_header: Option<PathBuf>,
+    /// Comment following the license header
+    pub banner: Option<String>,
+    /// What to do about FFI-safety problems of the exported items, if they're checked
+    pub ffi_lint: Option<LintLevel>,
+    /// Coloring of the lint's warnings
+    pub color: ColorChoice,
+    /// Calling convention macro to check the exported functions against and annotate the
+    /// header's declarations with, e.g. `CDECL`
+    pub calling_convention: Option<String>,
+    /// Prefix the exported functions and statics have to start with, which the header's other
+    /// names get as well
+    pub symbol_prefix: Option<String>,
+    /// Whether to wrap the header with `header::ue_wrap`
+    pub ue_wrap: bool,
+    /// Whether the UE wrapper disables deprecation warnings as well
+    pub disable_deprecation_warnings: bool,
+}
+
+impl BindingsOptions {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        BindingsOptions {
+            stamp: matches.value_of("STAMP").map(str::to_owned),
+            expand: if matches.is_present("EXPAND") {
+                Some(
+                    matches
+                        .values_of("EXPAND")
+                        .map(|crates| crates.map(str::to_owned).collect())
+                        .unwrap_or_default(),
+                )
+            } else {
+                None
+            },
+            expand_features: matches
+                .values_of("EXPAND_FEATURES")
+                .map(|features| features.map(str::to_owned).collect())
+                .unwrap_or_default(),
+            expand_all_features: matches.is_present("EXPAND_ALL_FEATURES"),
+            expand_no_default_features: matches.is_present("EXPAND_NO_DEFAULT_FEATURES"),
+            expand_release: matches.is_present("EXPAND_RELEASE"),
+            expand_toolchain: matches.value_of("EXPAND_TOOLCHAIN").map(str::to_owned),
+            include_modules: matches
+                .values_of("INCLUDE_MODULE")
+                .map(|modules| modules.map(str::to_owned).collect())
+                .unwrap_or_default(),
+            exclude_modules: matches
+                .values_of("EXCLUDE_MODULE")
+                .map(|modules| modules.map(str::to_owned).collect())
+                .unwrap_or_default(),
+            format: matches.is_present("FORMAT"),
+            format_style: matches
+                .value_of("FORMAT")
+                .map(|path| crate::resolve_path(matches, path)),
+            validate_header: matches.is_present("VALIDATE_HEADER"),
+            validate_command: matches
+                .value_of("VALIDATE_COMMAND")
+                .map(crate::link_args::parse_posix_quotes),
+            cfgs: cfgs_from_matches(matches),
+            cbindgen_config: matches
+                .value_of("CBINDGEN_CONFIG")
+                .map(|path| crate::resolve_path(matches, path)),
+            config_overrides: matches
+                .values_of("CBINDGEN_SET")
+                .map(|overrides| overrides.map(str::to_owned).collect())
+                .unwrap_or_default(),
+            language: match matches.value_of("LANGUAGE") {
+                Some("c++") => Some(cbindgen::Language::Cxx),
+                Some(_) => Some(cbindgen::Language::C),
+                None => None,
+            },
+            namespace: matches.value_of("NAMESPACE").map(str::to_owned),
+            api_macro: matches.value_of("API_MACRO").map(str::to_owned),
+            define_api_macro: matches.is_present("DEFINE_API_MACRO"),
+            include_guard: matches.value_of("INCLUDE_GUARD").map(str::to_owned),
+            license_header: matches
+                .value_of("LICENSE_HEADER")
+                .map(|path| crate::resolve_path(matches, path)),
+            banner: matches.value_of("BANNER").map(str::to_owned),
+            ffi_lint: matches
+                .is_present("FFI_LINT")
+                .then(|| LintLevel::from_arg(matches.value_of("FFI_LINT"))),
+            color: ColorChoice::from_arg(matches.value_of("COLOR")),
+            calling_convention: matches.value_of("CALLING_CONVENTION").map(str::to_owned),
+            symbol_prefix: matches.value_of("SYMBOL_PREFIX").map(str::to_owned),
+            ue_wrap: matches.is_present("UE_WRAP"),
+            disable_deprecation_warnings: matches.is_present("DISABLE_DEPRECATION_WARNINGS"),
+        }
+    }
+
+    /// The contents of `license_header`, if there is one
+    fn license(&self) -> Result<Option<String>> {
+        self.license_header
+            .as_ref()
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("Couldn't read {}", path.display()))
+            })
+            .transpose()
+    }
+
+    /// The line of the headers that holds their stamp, if they're stamped
+    pub fn stamp_line(&self) -> Result<Option<usize>> {
+        if self.stamp.is_none() {
+            return Ok(None);
+        }
+        let license = self.license()?;
+        Ok(Some(header::stamp_line(
+            license.as_deref(),
+            self.banner.as_deref(),
+        )))
+    }
+}
+
+/// The `--cfg` values of the command line. They're validated by clap.
+pub fn cfgs_from_matches(matches: &ArgMatches) -> Vec<Cfg> {
+    matches
+        .values_of("CFG")
+        .into_iter()
+        .flatten()
+        .map(|arg| Cfg::parse(arg).expect("invalid cfg"))
+        .collect()
+}
+
+/// Map each cfg to a define in cbindgen's `[defines]` table (unless cbindgen.toml already maps
+/// it) and define that at the top of the header, so the cfg-gated items are enabled like they
+/// are in the compiled lib
+fn apply_cfgs(cfgs: &[Cfg], config: &mut cbindgen::Config) {
+    let mut lines: Vec<String> = cfgs
+        .iter()
+        .map(|cfg| {
+            let define = config
+                .defines
+                .entry(cfg.define_key())
+                .or_insert_with(|| cfg.define_name());
+            format!("#ifndef {0}\n#define {0}\n#endif", define)
+        })
+        .collect();
+    lines.extend(config.after_includes.take());
+    config.after_includes = Some(lines.join("\n"));
+}
+
+/// `RUSTFLAGS` with the cfgs added, for the rustc cbindgen runs to expand macros
+fn expand_rustflags(cfgs: &[Cfg]) -> String {
+    let mut rustflags = std::env::var("RUSTFLAGS").unwrap_or_default();
+    for cfg in cfgs {
+        if !rustflags.is_empty() {
+            rustflags.push(' ');
+        }
+        rustflags.push_str(&format!("--cfg {}", cfg.rustc_arg()));
+    }
+    rustflags
+}
+
+/// The environment cbindgen's expansion runs cargo with: the cfgs in `RUSTFLAGS`, and
+/// `--expand-toolchain` in `RUSTUP_TOOLCHAIN`. cbindgen prefers `CARGO` over rustup's proxy, and
+/// under cargo that's the toolchain's own cargo, so it's removed to make the toolchain apply.
+fn expand_env(options: &BindingsOptions) -> Vec<(&'static str, Option<OsString>)> {
+    let mut env = Vec::new();
+    if !options.cfgs.is_empty() {
+        env.push(("RUSTFLAGS", Some(expand_rustflags(&options.cfgs).into())));
+    }
+    if let Some(toolchain) = &options.expand_toolchain {
+        env.push(("RUSTUP_TOOLCHAIN", Some(toolchain.into())));
+        env.push(("CARGO", None));
+    }
+    env
+}
+
+/// Run `f` with the environment variables set (or removed for `None`), and restore them
+/// afterwards. cbindgen runs cargo for the expansion with our environment.
+fn with_env<T>(env: &[(&str, Option<OsString>)], f: impl FnOnce() -> T) -> T {
+    let saved: Vec<_> = env
+        .iter()
+        .map(|(name, _)| (*name, std::env::var_os(name)))
+        .collect();
+    for (name, value) in env {
+        match value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+    let result = f();
+    for (name, value) in saved {
+        match value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+    result
+}
+
+/// cbindgen expands macros with `rustc -Zunpretty=expanded`, which needs a nightly compiler
+fn check_expand_supported(crate_dir: &Path, toolchain: Option<&str>) -> Result<()> {
+    if std::env::var_os("RUSTC_BOOTSTRAP").is_some() {
+        return Ok(());
+    }
+    let mut command = Command::new("rustc");
+    if let Some(toolchain) = toolchain {
+        command.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    let output = command
+        .arg("-vV")
+        .current_dir(crate_dir)
+        .output()
+        .context("Couldn't run rustc to check for macro expansion support")?;
+    let version = String::from_utf8_lossy(&output.stdout);
+    if !version.contains("-nightly") && !version.contains("-dev") {
+        bail!(
+            "--expand requires a nightly toolchain for {}, since cbindgen expands macros with `rustc -Zunpretty=expanded`. Select one with --expand-toolchain, rust-toolchain.toml or RUSTUP_TOOLCHAIN",
+            crate_dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// The root module of the crate's lib target, e.g. `src/lib.rs`
+fn lib_src_path(crate_dir: &Path) -> Result<PathBuf> {
+    let ctx = GlobalContext::default()?;
+    let ws = Workspace::new(&crate_dir.join("Cargo.toml").canonicalize()?, &ctx)?;
+    let src_path = ws
+        .current()?
+        .targets()
+        .iter()
+        .find(|target| target.is_lib())
+        .and_then(|target| target.src_path().path())
+        .with_context(|| format!("{} has no lib target", crate_dir.display()))?
+        .to_owned();
+    Ok(src_path)
+}
+
+/// Report the FFI-safety problems of the crate's exported items, failing on them with
+/// `LintLevel::Deny`
+fn lint_crate(crate_dir: &Path, level: LintLevel, color: ColorChoice) -> Result<()> {
+    let problems = ffi_lint::lint_crate(&lib_src_path(crate_dir)?)?;
+    if problems.is_empty() {
+        return Ok(());
+    }
+    if level == LintLevel::Deny {
+        bail!(
+            "The exported items of {} aren't FFI-safe:\n{}",
+            crate_dir.display(),
+            problems.join("\n")
+        );
+    }
+    for problem in problems {
+        eprintln!("{}", crate::color::diagnostic("warning", problem, color));
+    }
+    Ok(())
+}
+
+/// Fail on exported functions and function pointers without C's calling convention
+fn check_calling_conventions(crate_dir: &Path) -> Result<()> {
+    let problems = calling_convention::check_crate(&lib_src_path(crate_dir)?)?;
+    if !problems.is_empty() {
+        bail!(
+            "The exported functions of {} don't all have C's calling convention:\n{}",
+            crate_dir.display(),
+            problems.join("\n")
+        );
+    }
+    Ok(())
+}
+
+/// Add the items of the modules selected by `--include-module` and `--exclude-module` to
+/// cbindgen's export include and exclude lists. cbindgen matches these by name only, so an item
+/// with the same name as an excluded one in another module is excluded as well.
+fn apply_module_filter(
+    crate_dir: &Path,
+    options: &BindingsOptions,
+    config: &mut cbindgen::Config,
+) -> Result<()> {
+    let src_path = lib_src_path(crate_dir)?;
+    let matches_any = |module: &str, paths: &[String]| {
+        paths
+            .iter()
+            .any(|path| crate_items::module_matches(module, path))
+    };
+    for item in crate_items::crate_items(&src_path)? {
+        let excluded = matches_any(&item.module, &options.exclude_modules)
+            || (!options.include_modules.is_empty()
+                && !matches_any(&item.module, &options.include_modules));
+        if excluded {
+            config.export.exclude.push(item.name);
+        } else if !options.include_modules.is_empty() {
+            config.export.include.push(item.name);
+        }
+    }
+    Ok(())
+}
+
+/// Split a `--cbindgen-set` override into its dotted key and its value. Values that don't parse
+/// as TOML, e.g. `C++`, are strings.
+pub fn parse_config_override(arg: &str) -> Result<(Vec<String>, toml::Value), String> {
+    let Some((key, value)) = arg.split_once('=') else {
+        return Err(format!("{} isn't key=value", arg));
+    };
+    let key: Vec<String> = key.trim().split('.').map(str::to_owned).collect();
+    if key.iter().any(String::is_empty) {
+        return Err(format!("{} has an empty key", arg));
+    }
+    let value = value.trim();
+    let value = format!("value = {}", value)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_owned()));
+    Ok((key, value))
+}
+
+/// Check that a macro name given on the command line, e.g. of `--api-macro`, is one
+pub fn parse_macro_name(arg: &str) -> Result<(), String> {
+    let valid = arg.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("\"{}\" isn't a valid macro name", arg));
+    }
+    Ok(())
+}
+
+/// The namespaces C++ code sees the header's items in: those of a C++ header, or of a C header
+/// with `cpp_compat`, which cbindgen opens under `__cplusplus`
+pub fn cpp_namespaces(config: &cbindgen::Config) -> Vec<String> {
+    if config.language != cbindgen::Language::Cxx && !config.cpp_compat {
+        return Vec::new();
+    }
+    config
+        .namespace
+        .iter()
+        .chain(config.namespaces.iter().flatten())
+        .cloned()
+        .collect()
+}
+
+/// cbindgen's config: `--cbindgen-config`, or the crate's cbindgen.toml if it has one, with the
+/// `--cbindgen-set` overrides, `--language`, `--namespace`, `--api-macro` and `--include-guard`
+/// applied
+pub fn load_config(crate_dir: &Path, options: &BindingsOptions) -> Result<cbindgen::Config> {
+    let path = match &options.cbindgen_config {
+        Some(path) => Some(path.clone()),
+        None => Some(crate_dir.join("cbindgen.toml")).filter(|path| path.exists()),
+    };
+    let mut table = match &path {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?
+            .parse::<toml::Table>()
+            .with_context(|| format!("Invalid cbindgen config {}", path.display()))?,
+        None => toml::Table::new(),
+    };
+    for arg in &options.config_overrides {
+        let (key, value) = parse_config_override(arg).map_err(anyhow::Error::msg)?;
+        let (last, parents) = key.split_last().expect("keys aren't empty");
+        let mut parent = &mut table;
+        for name in parents {
+            let entry = parent
+                .entry(name.as_str())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            parent = entry
+                .as_table_mut()
+                .with_context(|| format!("Can't override {}, {} isn't a table", arg, name))?;
+        }
+        parent.insert(last.clone(), value);
+    }
+    let mut config: cbindgen::Config =
+        toml::Value::Table(table)
+            .try_into()
+            .with_context(|| match &path {
+                Some(path) => format!("Invalid cbindgen config {}", path.display()),
+                None => "Invalid cbindgen config".to_owned(),
+            })?;
+    config.config_path = path;
+    if let Some(language) = options.language {
+        config.language = language;
+    }
+    if let Some(namespace) = &options.namespace {
+        // cbindgen nests `namespaces` in order
+        config.namespace = None;
+        config.namespaces = Some(namespace.split("::").map(str::to_owned).collect());
+    }
+    if let Some(include_guard) = &options.include_guard {
+        config.include_guard = Some(include_guard.clone());
+    }
+    if let Some(prefix) = &options.symbol_prefix {
+        // types and constants aren't symbols, but still collide in unity builds
+        config.export.prefix.get_or_insert_with(|| prefix.clone());
+    }
+    if let Some(api_macro) = &options.api_macro {
+        config.function.prefix = Some(api_macro.clone());
+        if options.define_api_macro {
+            // UBT defines the module's API macro, so this only applies outside of UE
+            let define = format!("#ifndef {0}\n#define {0}\n#endif\n", api_macro);
+            config.after_includes = Some(match config.after_includes.take() {
+                Some(after_includes) => format!("{}\n{}", define, after_includes),
+                None => define,
+            });
+        }
+    }
+    if config.language == cbindgen::Language::Cxx {
+        // scoped enums, whose variants don't leak into the enclosing namespace
+        config.enumeration.enum_class = true;
+    }
+    Ok(config)
+}
+
+/// Run cbindgen on the crate
+fn build_bindings(crate_dir: &Path, options: &BindingsOptions) -> Result<cbindgen::Bindings> {
+    if let Some(level) = options.ffi_lint {
+        lint_crate(crate_dir, level, options.color)?;
+    }
+    if options.calling_convention.is_some() {
+        check_calling_conventions(crate_dir)?;
+    }
+    let mut config = load_config(crate_dir, options)?;
+    if let Some(expand) = &options.expand {
+        check_expand_supported(crate_dir, options.expand_toolchain.as_deref())?;
+        config.parse.expand.crates = if expand.is_empty() {
+            let ctx = GlobalContext::default()?;
+            let ws = Workspace::new(&crate_dir.join("Cargo.toml").canonicalize()?, &ctx)?;
+            vec![ws.current()?.name().to_string()]
+        } else {
+            expand.clone()
+        };
+        let expand_config = &mut config.parse.expand;
+        if !options.expand_features.is_empty() {
+            expand_config.features = Some(options.expand_features.clone());
+        }
+        expand_config.all_features = options.expand_all_features;
+        expand_config.default_features = !options.expand_no_default_features;
+        if options.expand_release {
+            expand_config.profile = cbindgen::Profile::Release;
+        }
+    }
+    if !options.include_modules.is_empty() || !options.exclude_modules.is_empty() {
+        apply_module_filter(crate_dir, options, &mut config)?;
+    }
+    if !options.cfgs.is_empty() {
+        apply_cfgs(&options.cfgs, &mut config);
+    }
+    let generated = if options.expand.is_none() {
+        generate_bindings(crate_dir, config)?
+    } else {
+        with_env(&expand_env(options), || {
+            generate_bindings(crate_dir, config)
+        })?
+    };
+    if let Some(prefix) = &options.symbol_prefix {
+        check_symbol_prefix(crate_dir, &generated, prefix)?;
+    }
+    Ok(generated)
+}
+
+/// Fail on exported functions and statics whose symbols don't start with `prefix`
+fn check_symbol_prefix(
+    crate_dir: &Path,
+    generated: &cbindgen::Bindings,
+    prefix: &str,
+) -> Result<()> {
+    let mut unprefixed: Vec<&str> = generated
+        .functions
+        .iter()
+        .map(|function| function.path.name())
+        .chain(
+            generated
+                .globals
+                .iter()
+                .map(|global| global.export_name.as_str()),
+        )
+        .filter(|symbol| !symbol.starts_with(prefix))
+        .collect();
+    // functions with cfg'd variants are listed once per variant
+    let mut seen = HashSet::new();
+    unprefixed.retain(|symbol| seen.insert(*symbol));
+    if !unprefixed.is_empty() {
+        bail!(
+            "Symbols {} exports don't start with {}, and may collide with other plugins' in the same process:\n{}",
+            crate_dir.display(),
+            prefix,
+            unprefixed.join("\n")
+        );
+    }
+    Ok(())
+}
+
+fn generate_bindings(crate_dir: &Path, config: cbindgen::Config) -> Result<cbindgen::Bindings> {
+    cbindgen::Builder::new()
+        .with_crate(crate_dir)
+        .with_config(config)
+        .generate()
+        .with_context(|| format!("Couldn't generate headers for {}", crate_dir.display()))
+}
+
+/// The files cbindgen parsed to generate `generated`, including its config file
+fn parsed_files(generated: &cbindgen::Bindings) -> Result<Vec<PathBuf>> {
+    // cbindgen only exposes them through a make style depfile
+    let temp_prefix = std::env::temp_dir().join(format!(
+        "unreal_rust_compile_{}_{}",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    let header_path = temp_prefix.with_extension("h");
+    let depfile_path = temp_prefix.with_extension("d");
+    std::fs::write(&header_path, "")?;
+    generated.generate_depfile(&header_path, &depfile_path);
+    let depfile = std::fs::read_to_string(&depfile_path);
+    let _ = std::fs::remove_file(&header_path);
+    let _ = std::fs::remove_file(&depfile_path);
+    Ok(depfile?
+        .trim_end()
+        .split(" \\\n    ")
+        .skip(1)
+        .map(|file| PathBuf::from(file.replace("\\ ", " ")))
+        .collect())
+}
+
+/// Add the files cbindgen parsed to `sources`, if they're collected
+fn collect_sources(
+    generated: &cbindgen::Bindings,
+    sources: Option<&mut Vec<PathBuf>>,
+) -> Result<()> {
+    if let Some(sources) = sources {
+        for file in parsed_files(generated)? {
+            if !sources.contains(&file) {
+                sources.push(file);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run cbindgen on the crate and apply our post-processing to the header. The files cbindgen
+/// parsed are added to `sources` if it's given.
+pub fn generate_header(
+    crate_dir: &Path,
+    options: &BindingsOptions,
+    sources: Option<&mut Vec<PathBuf>>,
+) -> Result<Vec<u8>> {
+    let generated = build_bindings(crate_dir, options)?;
+    collect_sources(&generated, sources)?;
+    let mut new_data: Vec<u8> = Vec::new();
+    generated.write(&mut new_data);
+    post_process(
+        new_data,
+        generated.config.language,
+        crate_dir,
+        options,
+        None,
+    )
+}
+
+/// The include guard of the functions header of a split header whose types header has `guard`
+fn functions_include_guard(guard: &str) -> String {
+    match guard.strip_suffix("_H") {
+        Some(stem) => format!("{}_FUNCTIONS_H", stem),
+        None => format!("{}_FUNCTIONS", guard),
+    }
+}
+
+/// Run cbindgen on the crate and split the header in two: one with the types and constants, and
+/// one with the functions and statics, which includes the former as `types_include`. UE headers
+/// can then include the types without the whole FFI surface. The files cbindgen parsed are added
+/// to `sources` if it's given.
+pub fn generate_split_headers(
+    crate_dir: &Path,
+    options: &BindingsOptions,
+    types_include: &str,
+    sources: Option<&mut Vec<PathBuf>>,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut generated = build_bindings(crate_dir, options)?;
+    collect_sources(&generated, sources)?;
+    let language = generated.config.language;
+    let functions = std::mem::take(&mut generated.functions);
+    let globals = std::mem::take(&mut generated.globals);
+    let mut types_data = Vec::new();
+    generated.write(&mut types_data);
+    generated.functions = functions;
+    generated.globals = globals;
+    generated.items.clear();
+    generated.constants.clear();
+    let config = &mut generated.config;
+    config.include_guard = config.include_guard.as_deref().map(functions_include_guard);
+    config.includes.push(types_include.to_owned());
+    // the defines at the top are the types header's
+    config.after_includes = None;
+    let mut functions_data = Vec::new();
+    generated.write(&mut functions_data);
+    let types_data = post_process(types_data, language, crate_dir, options, None)?;
+    let functions_data = post_process(
+        functions_data,
+        language,
+        crate_dir,
+        options,
+        Some((types_include, &types_data)),
+    )?;
+    Ok((types_data, functions_data))
+}
+
+/// Line of the merged header's shell where the crate sections go
+const SECTIONS_MARKER: &str = "// unreal_rust_compile: crate sections";
+
+/// Whether a declaration of a section (the text between blank lines) is one of a type,
+/// function, static or constant, rather than a line opening or closing a namespace or an
+/// `extern "C"` block, which sections write once each
+fn is_declaration(chunk: &str) -> bool {
+    chunk.trim_end().ends_with(';')
+        && !chunk.contains("__cplusplus")
+        && !chunk.contains("extern \"C\"")
+        && !chunk.contains("namespace ")
+}
+
+/// Drop the declarations of a section that an earlier section already has, e.g. the types of a
+/// dependency shared by the crates
+fn dedup_declarations(section: &str, seen: &mut HashSet<String>) -> String {
+    section
+        .split("\n\n")
+        .filter(|chunk| !is_declaration(chunk) || seen.insert(chunk.trim().to_owned()))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Run cbindgen on each crate and merge the headers into one, with a section per crate in the
+/// given order. The preamble and trailer (includes, include guard and so on) come from the first
+/// crate's config, and declarations identical to an earlier crate's are only written once. The
+/// files cbindgen parsed for any of the crates are added to `sources` if it's given.
+pub fn generate_merged_header(
+    crate_dirs: &[PathBuf],
+    options: &BindingsOptions,
+    mut sources: Option<&mut Vec<PathBuf>>,
+) -> Result<Vec<u8>> {
+    let mut first = None;
+    let mut after_includes: Vec<String> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut sections = String::new();
+    for crate_dir in crate_dirs {
+        let mut generated = build_bindings(crate_dir, options)?;
+        collect_sources(&generated, sources.as_deref_mut())?;
+        let shell_config = generated.config.clone();
+        if let Some(lines) = &shell_config.after_includes {
+            if !after_includes.contains(lines) {
+                after_includes.push(lines.clone());
+            }
+        }
+        let config = &mut generated.config;
+        config.header = None;
+        config.trailer = None;
+        config.include_guard = None;
+        config.pragma_once = false;
+        config.package_version = false;
+        config.autogen_warning = None;
+        config.include_version = false;
+        config.no_includes = true;
+        config.includes.clear();
+        config.sys_includes.clear();
+        config.after_includes = None;
+        let mut section = Vec::new();
+        generated.write(&mut section);
+        let name = owning_crate(&crate_dir.join("Cargo.toml"))
+            .unwrap_or_else(|| crate_dir.display().to_string());
+        let section = dedup_declarations(&String::from_utf8_lossy(&section), &mut seen);
+        sections.push_str(&format!("\n// {}\n\n{}", name, section.trim_start()));
+        if first.is_none() {
+            first = Some((generated, shell_config));
+        }
+    }
+    let (mut shell, mut config) = first.context("No crates to merge")?;
+    // the sections have their own namespaces
+    config.namespace = None;
+    config.namespaces = None;
+    after_includes.push(SECTIONS_MARKER.to_owned());
+    config.after_includes = Some(after_includes.join("\n"));
+    shell.config = config;
+    shell.items.clear();
+    shell.constants.clear();
+    shell.globals.clear();
+    shell.functions.clear();
+    let mut shell_data = Vec::new();
+    shell.write(&mut shell_data);
+    let shell_data = String::from_utf8_lossy(&shell_data);
+    let (preamble, trailer) = shell_data
+        .split_once(&format!("{}\n", SECTIONS_MARKER))
+        .context("The merged header has no place for the crate sections")?;
+    let merged = format!("{}{}{}", preamble, sections, trailer);
+    let language = shell.config.language;
+    post_process(merged.into_bytes(), language, &crate_dirs[0], options, None)
+}
+
+/// The post-processing `--ue-wrap`, `--format`, `--validate-header`, `--stamp`, `--license-header`
+/// and `--banner` apply to cbindgen's header for `crate_dir`. `included` is a header it includes
+/// by name and its contents, which are inlined for validation.
+fn post_process(
+    mut new_data: Vec<u8>,
+    language: cbindgen::Language,
+    crate_dir: &Path,
+    options: &BindingsOptions,
+    included: Option<(&str, &[u8])>,
+) -> Result<Vec<u8>> {
+    if let Some(annotation) = &options.calling_convention {
+        let (annotated, unannotated) = calling_convention::annotate(&new_data, annotation);
+        if !unannotated.is_empty() {
+            bail!(
+                "Couldn't annotate the declarations of {} in the header for {} with {}",
+                unannotated.join(", "),
+                crate_dir.display(),
+                annotation
+            );
+        }
+        new_data = annotated;
+    }
+    if options.ue_wrap {
+        new_data = header::ue_wrap(&new_data, options.disable_deprecation_warnings);
+    }
+    if options.format {
+        new_data = header::clang_format(&new_data, options.format_style.as_deref())
+            .with_context(|| format!("Couldn't format the header for {}", crate_dir.display()))?;
+    }
+    if options.validate_header {
+        let command = match &options.validate_command {
+            Some(command) => command.clone(),
+            None => {
+                crate::link_args::parse_posix_quotes(header::default_validate_command(language)?)
+            }
+        };
+        let validated = match included {
+            Some((name, contents)) => header::inline_include(&new_data, name, contents),
+            None => new_data.clone(),
+        };
+        header::validate(&validated, &command)
+            .with_context(|| format!("Invalid header for {}", crate_dir.display()))?;
+    }
+    if let Some(stamp) = &options.stamp {
+        new_data = header::add_stamp(&new_data, stamp);
+    }
+    let license = options.license()?;
+    if license.is_some() || options.banner.is_some() {
+        new_data = header::add_preamble(&new_data, license.as_deref(), options.banner.as_deref());
+    }
+    Ok(new_data)
+}
+
+/// The crates and files cbindgen parsed to generate a header
+#[derive(Debug, Serialize)]
+pub struct BindingsSources {
+    pub crates: Vec<String>,
+    pub files: Vec<PathBuf>,
+}
+
+/// The crates of the workspace at `root` with a lib target, in the workspace's order
+pub fn workspace_lib_crates(root: &Path) -> Result<Vec<PathBuf>> {
+    let ctx = GlobalContext::default()?;
+    let ws = Workspace::new(&root.join("Cargo.toml").canonicalize()?, &ctx)?;
+    Ok(ws
+        .members()
+        .filter(|package| package.targets().iter().any(|target| target.is_lib()))
+        .map(|package| package.root().to_owned())
+        .collect())
+}
+
+/// The name of the package whose manifest is closest to `file`
+fn owning_crate(file: &Path) -> Option<String> {
+    let manifest = file
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join("Cargo.toml"))
+        .find(|manifest| manifest.is_file())?;
+    let manifest: toml::Table = std::fs::read_to_string(manifest).ok()?.parse().ok()?;
+    Some(manifest.get("package")?.get("name")?.as_str()?.to_owned())
+}
+
+/// Run cbindgen on the crate and report which files it parsed (including its config file) and
+/// which crates they belong to
+pub fn bindings_sources(crate_dir: &Path, options: &BindingsOptions) -> Result<BindingsSources> {
+    let generated = build_bindings(crate_dir, options)?;
+    let files = parsed_files(&generated)?;
+    let mut crates: Vec<String> = files.iter().filter_map(|file| owning_crate(file)).collect();
+    crates.sort();
+    crates.dedup();
+    Ok(BindingsSources { crates, files })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_overrides() {
+        let options = BindingsOptions {
+            config_overrides: vec![
+                "language=C++".to_owned(),
+                "include_guard = \"GAME_FFI_H\"".to_owned(),
+                "cpp_compat=true".to_owned(),
+                "export.prefix=Rust".to_owned(),
+            ],
+            ..BindingsOptions::default()
+        };
+        let config = load_config(Path::new("/nonexistent"), &options).unwrap();
+        assert_eq!(config.language, cbindgen::Language::Cxx);
+        assert_eq!(config.include_guard.as_deref(), Some("GAME_FFI_H"));
+        assert!(config.cpp_compat);
+        assert_eq!(config.export.prefix.as_deref(), Some("Rust"));
+        assert!(parse_config_override("language").is_err());
+
+        let options = BindingsOptions {
+            config_overrides: vec!["language=C".to_owned()],
+            language: Some(cbindgen::Language::Cxx),
+            namespace: Some("Game::Rust".to_owned()),
+            ..BindingsOptions::default()
+        };
+        let config = load_config(Path::new("/nonexistent"), &options).unwrap();
+        assert_eq!(config.language, cbindgen::Language::Cxx);
+        assert_eq!(
+            config.namespaces,
+            Some(vec!["Game".to_owned(), "Rust".to_owned()])
+        );
+        assert!(config.enumeration.enum_class);
+        assert!(parse_config_override("export..prefix=Rust").is_err());
+
+        let options = BindingsOptions {
+            config_overrides: vec!["after_includes=\"#include <Game.h>\"".to_owned()],
+            api_macro: Some("GAME_API".to_owned()),
+            define_api_macro: true,
+            ..BindingsOptions::default()
+        };
+        let config = load_config(Path::new("/nonexistent"), &options).unwrap();
+        assert_eq!(config.function.prefix.as_deref(), Some("GAME_API"));
+        assert_eq!(
+            config.after_includes.as_deref(),
+            Some("#ifndef GAME_API\n#define GAME_API\n#endif\n\n#include <Game.h>")
+        );
+        assert!(parse_macro_name("GAME_API").is_ok());
+        assert!(parse_macro_name("GAME API").is_err());
+    }
+
+    #[test]
+    fn split_include_guards() {
+        assert_eq!(functions_include_guard("GAME_H"), "GAME_FUNCTIONS_H");
+        assert_eq!(functions_include_guard("GAME_FFI"), "GAME_FFI_FUNCTIONS");
+    }
+
+    #[test]
+    fn merged_sections() {
+        let mut seen = HashSet::new();
+        let a = "namespace Game {\n\nstruct Vec2 {\n  float x;\n};\n\nextern \"C\" {\n\nfloat a_length(Vec2 v);\n\n}  // extern \"C\"\n\n}  // namespace Game\n";
+        assert_eq!(dedup_declarations(a, &mut seen), a);
+        let b = "namespace Game {\n\nstruct Vec2 {\n  float x;\n};\n\nextern \"C\" {\n\nfloat b_length(Vec2 v);\n\n}  // extern \"C\"\n\n}  // namespace Game\n";
+        assert_eq!(
+            dedup_declarations(b, &mut seen),
+            "namespace Game {\n\nextern \"C\" {\n\nfloat b_length(Vec2 v);\n\n}  // extern \"C\"\n\n}  // namespace Game\n"
+        );
+    }
+
+    #[test]
+    fn expansion_env() {
+        assert!(expand_env(&BindingsOptions::default()).is_empty());
+        let options = BindingsOptions {
+            expand_toolchain: Some("nightly-2024-05-01".to_owned()),
+            ..BindingsOptions::default()
+        };
+        assert_eq!(
+            expand_env(&options),
+            vec![
+                ("RUSTUP_TOOLCHAIN", Some("nightly-2024-05-01".into())),
+                ("CARGO", None)
+            ]
+        );
+    }
+}
@@ -0,0 +1,784 @@
+//! UE reflection for a header: `USTRUCT` and `UENUM` mirrors of its structs and enums, and a
+//! Blueprint function library
+//! with a `UFUNCTION` per function whose parameters and return value Blueprints can represent,
+//! converting strings to `FString` and structs to their mirrors
+
+use crate::wrappers::{camel_case, function_declarations, parse_param, Declaration, Param};
+use std::path::{Path, PathBuf};
+
+/// The C types Blueprints have an equivalent of
+const SCALARS: &[(&str, &str)] = &[
+    ("bool", "bool"),
+    ("uint8_t", "uint8"),
+    ("int32_t", "int32"),
+    ("int64_t", "int64"),
+    ("float", "float"),
+    ("double", "double"),
+];
+
+/// A struct the header defines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructDef {
+    pub name: String,
+    /// The fields, or `None` if some field isn't a plain `type name;` declaration, e.g. an array
+    pub fields: Option<Vec<Param>>,
+}
+
+/// How a C type of a signature or field maps to a Blueprint type
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BlueprintType {
+    /// A number or bool, with its UE type
+    Scalar(&'static str),
+    /// A `const char *` borrowed from Rust, as an `FString`
+    String,
+    /// A `char *` the caller owns and frees with `--string-free-fn`, as an `FString`
+    OwnedString,
+    /// A struct mirrored as a USTRUCT, by its C name
+    Struct(String),
+}
+
+/// The mirrors header of a header without an explicit path: `<header>_structs.h` next to it
+pub fn default_structs_path(header_path: &Path) -> PathBuf {
+    let stem = header_path
+        .file_stem()
+        .map_or_else(|| "header".into(), |stem| stem.to_string_lossy());
+    header_path.with_file_name(format!("{}_structs.h", stem))
+}
+
+/// The enums header of a header without an explicit path: `<header>_enums.h` next to it
+pub fn default_enums_path(header_path: &Path) -> PathBuf {
+    let stem = header_path
+        .file_stem()
+        .map_or_else(|| "header".into(), |stem| stem.to_string_lossy());
+    header_path.with_file_name(format!("{}_enums.h", stem))
+}
+
+/// The structs the header defines, in the order of the header, which defines structs before
+/// the structs using them
+pub fn struct_definitions(header: &str) -> Vec<StructDef> {
+    let mut structs = Vec::new();
+    let mut current: Option<(Option<String>, Option<Vec<Param>>)> = None;
+    for line in header.lines() {
+        let declaration = line.strip_prefix("typedef ").unwrap_or(line);
+        if current.is_none() {
+            if declaration == "struct {" {
+                current = Some((None, Some(Vec::new())));
+            } else if let Some(name) = declaration
+                .strip_prefix("struct ")
+                .and_then(|rest| rest.strip_suffix(" {"))
+            {
+                current = Some((Some(name.to_owned()), Some(Vec::new())));
+            }
+            continue;
+        }
+        let Some((name, fields)) = &mut current else {
+            continue;
+        };
+        let trimmed = line.trim();
+        if let Some(rest) = line.strip_prefix('}') {
+            // a typedef'd anonymous struct is named after the braces
+            let name = name
+                .take()
+                .or_else(|| rest.trim().strip_suffix(';').map(str::to_owned));
+            if let Some(name) = name.filter(|name| !name.is_empty()) {
+                structs.push(StructDef {
+                    name,
+                    fields: fields.take(),
+                });
+            }
+            current = None;
+        } else if trimmed.is_empty()
+            || ["//", "/*", "*", "#"]
+                .iter()
+                .any(|start| trimmed.starts_with(start))
+        {
+            continue;
+        } else {
+            let field = trimmed
+                .strip_suffix(';')
+                .filter(|field| !field.contains(['(', '[', '{']))
+                .and_then(parse_param);
+            match (field, fields.as_mut()) {
+                (Some(field), Some(fields)) => fields.push(field),
+                _ => *fields = None,
+            }
+        }
+    }
+    structs
+}
+
+/// An enum the header defines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumDef {
+    pub name: String,
+    /// Whether it's an `enum class`, whose variants are scoped by its name
+    pub scoped: bool,
+    /// The variants and their values, or `None` if some value isn't an integer literal
+    pub variants: Option<Vec<(String, i64)>>,
+}
+
+/// The enums the header defines at the top level, i.e. not the tags of tagged unions in C++
+pub fn enum_definitions(header: &str) -> Vec<EnumDef> {
+    let mut enums = Vec::new();
+    let mut lines = header.lines();
+    while let Some(line) = lines.next() {
+        let declaration = line.strip_prefix("typedef ").unwrap_or(line);
+        let Some(rest) = declaration.strip_prefix("enum ") else {
+            continue;
+        };
+        let (scoped, rest) = match rest.strip_prefix("class ") {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let name: String = rest
+            .chars()
+            .take_while(|&c| c.is_ascii_alphanumeric() || c == '_')
+            .collect();
+        if name.is_empty() || rest.trim_end().ends_with(';') {
+            continue;
+        }
+        // the underlying type of a C enum is on lines of its own, under `#ifdef __cplusplus`
+        let mut open = line.trim_end().ends_with('{');
+        while !open {
+            match lines.next() {
+                Some(line) => open = line.trim_end().ends_with('{'),
+                None => return enums,
+            }
+        }
+        let mut variants = Some(Vec::new());
+        let mut next = 0;
+        for line in lines.by_ref() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('}') {
+                break;
+            }
+            if trimmed.is_empty()
+                || ["//", "/*", "*", "#"]
+                    .iter()
+                    .any(|start| trimmed.starts_with(start))
+            {
+                continue;
+            }
+            let variant = trimmed.trim_end_matches(',');
+            let (variant, value) = match variant.split_once('=') {
+                Some((variant, value)) => (variant.trim(), value.trim().parse().ok()),
+                None => (variant, Some(next)),
+            };
+            match (value, variants.as_mut()) {
+                (Some(value), Some(variants)) => {
+                    variants.push((variant.to_owned(), value));
+                    next = value + 1;
+                }
+                _ => variants = None,
+            }
+        }
+        enums.push(EnumDef {
+            name,
+            scoped,
+            variants,
+        });
+    }
+    enums
+}
+
+/// The name of the struct a type names by value, e.g. `Vec2` for `struct Vec2`
+fn struct_name(ty: &str) -> &str {
+    ty.strip_prefix("struct ").unwrap_or(ty)
+}
+
+/// The Blueprint type of the C type `ty`, given the structs with a USTRUCT mirror. `returned`
+/// types may be owned strings.
+fn blueprint_type(ty: &str, mirrored: &[&StructDef], returned: bool) -> Option<BlueprintType> {
+    if let Some((_, ue)) = SCALARS.iter().find(|(c, _)| *c == ty) {
+        return Some(BlueprintType::Scalar(ue));
+    }
+    match ty {
+        "const char *" => return Some(BlueprintType::String),
+        "char *" if returned => return Some(BlueprintType::OwnedString),
+        _ => {}
+    }
+    let name = struct_name(ty);
+    mirrored
+        .iter()
+        .any(|def| def.name == name)
+        .then(|| BlueprintType::Struct(name.to_owned()))
+}
+
+/// Why `def` can't be mirrored as a USTRUCT given the structs `mirrored` already, if it can't:
+/// its fields have to be scalars or mirrored structs
+fn unmirrorable_reason(def: &StructDef, mirrored: &[&StructDef]) -> Option<String> {
+    let Some(fields) = &def.fields else {
+        return Some("it has a field that isn't a plain declaration, e.g. an array".to_owned());
+    };
+    if fields.is_empty() {
+        return Some("it has no fields".to_owned());
+    }
+    fields
+        .iter()
+        .find_map(|field| match blueprint_type(&field.ty, mirrored, false) {
+            Some(BlueprintType::Scalar(_) | BlueprintType::Struct(_)) => None,
+            _ => Some(format!(
+                "field `{}` has type `{}`, which Blueprints can't represent",
+                field.name, field.ty
+            )),
+        })
+}
+
+/// The structs of `structs` that can be mirrored as USTRUCTs: those whose fields are all scalars
+/// or mirrored structs
+pub fn mirrorable_structs(structs: &[StructDef]) -> Vec<&StructDef> {
+    let mut mirrored: Vec<&StructDef> = Vec::new();
+    // the header defines structs after the structs they contain, so a single pass finds them
+    for def in structs {
+        if unmirrorable_reason(def, &mirrored).is_none() {
+            mirrored.push(def);
+        }
+    }
+    mirrored
+}
+
+/// Names of the header's C items, qualified for code outside of the header's namespace
+pub struct CNames {
+    namespace: String,
+    tag: &'static str,
+}
+
+impl CNames {
+    pub fn new(config: &cbindgen::Config) -> CNames {
+        let cxx = config.language == cbindgen::Language::Cxx;
+        let namespace: String = crate::bindings::cpp_namespaces(config)
+            .iter()
+            .map(|namespace| format!("{}::", namespace))
+            .collect();
+        let tag = if !cxx && config.style == cbindgen::Style::Tag {
+            "struct "
+        } else {
+            ""
+        };
+        CNames { namespace, tag }
+    }
+
+    pub fn struct_type(&self, name: &str) -> String {
+        format!("{}{}{}", self.tag, self.namespace, name)
+    }
+
+    fn function(&self, name: &str) -> String {
+        format!("{}{}", self.namespace, name)
+    }
+}
+
+/// The USTRUCT declaration mirroring `def`, which has to be mirrorable
+pub fn ustruct(def: &StructDef, api: &str, category: &str) -> String {
+    let mut ustruct = format!(
+        "USTRUCT(BlueprintType)\nstruct {}F{}\n{{\n\tGENERATED_BODY()\n",
+        api, def.name
+    );
+    for field in def.fields.iter().flatten() {
+        let (ty, default) = match SCALARS.iter().find(|(c, _)| *c == field.ty) {
+            Some(("bool", ue)) => (ue.to_string(), " = false"),
+            Some((_, ue)) => (ue.to_string(), " = 0"),
+            None => (format!("F{}", struct_name(&field.ty)), ""),
+        };
+        ustruct.push_str(&format!(
+            "\n\tUPROPERTY(EditAnywhere, BlueprintReadWrite, Category = \"{}\")\n\t{} {}{};\n",
+            category,
+            ty,
+            camel_case(&field.name),
+            default
+        ));
+    }
+    ustruct.push_str("};\n");
+    ustruct
+}
+
+/// The `ToRust` and `FromRust` overloads converting between the mirror of `def` and the C
+/// struct, declared with `specifier`, e.g. `inline `
+pub fn conversions(def: &StructDef, names: &CNames, specifier: &str) -> String {
+    let c_type = names.struct_type(&def.name);
+    let fields = def.fields.as_deref().unwrap_or_default();
+    let mut to_rust = format!(
+        "{}{} ToRust(const F{}& Value)\n{{\n\t{} Result{{}};\n",
+        specifier, c_type, def.name, c_type
+    );
+    let mut from_rust = format!(
+        "{}F{} FromRust(const {}& Value)\n{{\n\tF{} Result;\n",
+        specifier, def.name, c_type, def.name
+    );
+    for field in fields {
+        let (c, ue) = (&field.name, camel_case(&field.name));
+        if SCALARS.iter().any(|(c, _)| *c == field.ty) {
+            to_rust.push_str(&format!("\tResult.{} = Value.{};\n", c, ue));
+            from_rust.push_str(&format!("\tResult.{} = Value.{};\n", ue, c));
+        } else {
+            to_rust.push_str(&format!("\tResult.{} = ToRust(Value.{});\n", c, ue));
+            from_rust.push_str(&format!("\tResult.{} = FromRust(Value.{});\n", ue, c));
+        }
+    }
+    to_rust.push_str("\treturn Result;\n}\n");
+    from_rust.push_str("\treturn Result;\n}\n");
+    format!("{}\n{}", to_rust, from_rust)
+}
+
+/// A function of the library: its declaration in the class and its definition
+struct LibraryFunction {
+    declaration: String,
+    definition: String,
+}
+
+/// The library function wrapping `declaration`, or why Blueprints can't call it
+fn library_function(
+    declaration: &Declaration,
+    class: &str,
+    mirrored: &[&StructDef],
+    names: &CNames,
+    string_free_fn: Option<&str>,
+) -> Result<LibraryFunction, String> {
+    let mut params = Vec::new();
+    let mut args = Vec::new();
+    for param in &declaration.params {
+        let name = camel_case(&param.name);
+        match blueprint_type(&param.ty, mirrored, false) {
+            Some(BlueprintType::Scalar(ue)) => {
+                params.push(format!("{} {}", ue, name));
+                args.push(name);
+            }
+            Some(BlueprintType::String) => {
+                params.push(format!("const FString& {}", name));
+                args.push(format!("TCHAR_TO_UTF8(*{})", name));
+            }
+            Some(BlueprintType::Struct(c)) => {
+                params.push(format!("const F{}& {}", c, name));
+                args.push(format!("ToRust({})", name));
+            }
+            Some(BlueprintType::OwnedString) | None => {
+                return Err(format!(
+                    "parameter `{}` has type `{}`, which Blueprints can't represent",
+                    param.name, param.ty
+                ))
+            }
+        }
+    }
+    let call = format!("{}({})", names.function(&declaration.name), args.join(", "));
+    let (ret, body) = match blueprint_type(&declaration.ret, mirrored, true) {
+        _ if declaration.ret == "void" => ("void".to_owned(), format!("\t{};\n", call)),
+        Some(BlueprintType::Scalar(ue)) => (ue.to_owned(), format!("\treturn {};\n", call)),
+        Some(BlueprintType::String) => (
+            "FString".to_owned(),
+            format!("\treturn UTF8_TO_TCHAR({});\n", call),
+        ),
+        Some(BlueprintType::OwnedString) => {
+            let free = string_free_fn.ok_or_else(|| {
+                "it returns an owned string, pass --string-free-fn to free it".to_owned()
+            })?;
+            (
+                "FString".to_owned(),
+                format!(
+                    "\tchar* Result = {};\n\tFString Value = UTF8_TO_TCHAR(Result);\n\t{}(Result);\n\treturn Value;\n",
+                    call,
+                    names.function(free)
+                ),
+            )
+        }
+        Some(BlueprintType::Struct(c)) => {
+            (format!("F{}", c), format!("\treturn FromRust({});\n", call))
+        }
+        None => {
+            return Err(format!(
+                "it returns `{}`, which Blueprints can't represent",
+                declaration.ret
+            ))
+        }
+    };
+    let name = camel_case(&declaration.name);
+    let params = params.join(", ");
+    Ok(LibraryFunction {
+        declaration: format!("\tstatic {} {}({});\n", ret, name, params),
+        definition: format!("{} {}::{}({})\n{{\n{}}}\n", ret, class, name, params, body),
+    })
+}
+
+/// The generated library: its header, its source file and the functions it skipped, with why
+pub struct Library {
+    pub header: String,
+    pub source: String,
+    pub skipped: Vec<(String, String)>,
+}
+
+/// The options of the generated library
+pub struct LibraryOptions<'a> {
+    /// File name of the library's header, which names its class and generated header
+    pub header_name: &'a str,
+    /// File name of the C header to wrap, which the source file includes
+    pub bindings_header_name: &'a str,
+    /// Blueprint category of the functions and properties
+    pub category: &'a str,
+    /// Function freeing the strings functions return as `char *`
+    pub string_free_fn: Option<&'a str>,
+    /// File name of the header of `mirrors_header`, which the library's header includes instead
+    /// of defining the mirrors itself
+    pub structs_header_name: Option<&'a str>,
+}
+
+/// The Blueprint function library wrapping the functions of `header`. The class is named after
+/// the library's header, e.g. `UGameLibrary` for `GameLibrary.h`, and exported with the API
+/// macro of the config.
+pub fn library(header: &str, config: &cbindgen::Config, options: &LibraryOptions) -> Library {
+    let stem = options
+        .header_name
+        .rsplit_once('.')
+        .map_or(options.header_name, |(stem, _)| stem);
+    let class = format!("U{}", stem);
+    let api = config
+        .function
+        .prefix
+        .as_deref()
+        .map_or_else(String::new, |api| format!("{} ", api));
+    let names = CNames::new(config);
+    let structs = struct_definitions(header);
+    let mirrored = mirrorable_structs(&structs);
+    let declarations = function_declarations(header, config.function.prefix.as_deref());
+    let mut functions = Vec::new();
+    let mut skipped = Vec::new();
+    for declaration in &declarations {
+        if options.string_free_fn == Some(declaration.name.as_str()) {
+            continue;
+        }
+        match library_function(
+            declaration,
+            &class,
+            &mirrored,
+            &names,
+            options.string_free_fn,
+        ) {
+            Ok(function) => functions.push(function),
+            Err(reason) => skipped.push((declaration.name.clone(), reason)),
+        }
+    }
+    let comment = format!(
+        "// Blueprint function library wrapping the functions of {}, generated by unreal_rust_compile\n\n",
+        options.bindings_header_name
+    );
+    let structs_include = options
+        .structs_header_name
+        .map_or_else(String::new, |name| format!("#include \"{}\"\n", name));
+    let mut library_header = format!(
+        "{}#pragma once\n\n#include \"CoreMinimal.h\"\n#include \"Kismet/BlueprintFunctionLibrary.h\"\n{}\n#include \"{}.generated.h\"\n\n",
+        comment, structs_include, stem
+    );
+    if options.structs_header_name.is_none() {
+        for def in &mirrored {
+            library_header.push_str(&ustruct(def, &api, options.category));
+            library_header.push('\n');
+        }
+    }
+    library_header.push_str(&format!(
+        "UCLASS()\nclass {}{} : public UBlueprintFunctionLibrary\n{{\n\tGENERATED_BODY()\n\npublic:\n",
+        api, class
+    ));
+    for (i, function) in functions.iter().enumerate() {
+        if i > 0 {
+            library_header.push('\n');
+        }
+        library_header.push_str(&format!(
+            "\tUFUNCTION(BlueprintCallable, Category = \"{}\")\n{}",
+            options.category, function.declaration
+        ));
+    }
+    library_header.push_str("};\n");
+
+    let mut source = format!(
+        "{}#include \"{}\"\n\n#include \"{}\"\n",
+        comment, options.header_name, options.bindings_header_name
+    );
+    if !mirrored.is_empty() && options.structs_header_name.is_none() {
+        source.push_str("\nnamespace\n{\n");
+        let conversions: Vec<String> = mirrored
+            .iter()
+            .map(|def| conversions(def, &names, ""))
+            .collect();
+        source.push_str(&conversions.join("\n"));
+        source.push_str("}\n");
+    }
+    for function in &functions {
+        source.push('\n');
+        source.push_str(&function.definition);
+    }
+    Library {
+        header: library_header,
+        source,
+        skipped,
+    }
+}
+
+/// The header of UENUM mirrors of the enums of `header` (named `header_name`), with static_asserts
+/// that their values are the enums', and for the `enum class`es of C++ headers inline `ToRust` and
+/// `FromRust` conversions. `mirrors_name` is its own file name, which names its generated header.
+/// Also returns the enums it couldn't mirror, with why.
+pub fn enums_header(
+    header_name: &str,
+    header: &str,
+    mirrors_name: &str,
+    config: &cbindgen::Config,
+) -> (String, Vec<(String, String)>) {
+    let stem = mirrors_name
+        .rsplit_once('.')
+        .map_or(mirrors_name, |(stem, _)| stem);
+    let names = CNames::new(config);
+    let mut mirrors = format!(
+        "// UENUM mirrors of the enums of {}, generated by unreal_rust_compile\n\n#pragma once\n\n#include \"CoreMinimal.h\"\n\n#include \"{}\"\n\n#include \"{}.generated.h\"\n",
+        header_name, header_name, stem
+    );
+    let mut asserts = String::new();
+    let mut conversions = String::new();
+    let mut skipped = Vec::new();
+    for def in enum_definitions(header) {
+        let Some(variants) = &def.variants else {
+            skipped.push((def.name, "a value isn't an integer literal".to_owned()));
+            continue;
+        };
+        if variants.is_empty() {
+            skipped.push((def.name, "it has no variants".to_owned()));
+            continue;
+        }
+        if variants.iter().any(|(_, value)| !(0..=255).contains(value)) {
+            skipped.push((
+                def.name,
+                "Blueprint enums are uint8, and a value is out of its range".to_owned(),
+            ));
+            continue;
+        }
+        mirrors.push_str(&format!(
+            "\nUENUM(BlueprintType)\nenum class E{} : uint8\n{{\n",
+            def.name
+        ));
+        for (variant, value) in variants {
+            // C enums' variants may be prefixed with the enum's name
+            let ue_variant = variant
+                .strip_prefix(&format!("{}_", def.name))
+                .unwrap_or(variant);
+            mirrors.push_str(&format!("\t{} = {},\n", ue_variant, value));
+            let c_variant = if def.scoped {
+                format!("{}::{}", names.function(&def.name), variant)
+            } else {
+                names.function(variant)
+            };
+            asserts.push_str(&format!(
+                "static_assert(static_cast<int64>(E{0}::{1}) == static_cast<int64>({2}), \"E{0}::{1} has a different value in Rust\");\n",
+                def.name, ue_variant, c_variant
+            ));
+        }
+        mirrors.push_str("};\n");
+        if def.scoped {
+            let c_type = names.function(&def.name);
+            conversions.push_str(&format!(
+                "\ninline {1} ToRust(E{0} Value)\n{{\n\treturn static_cast<{1}>(Value);\n}}\n\ninline E{0} FromRust({1} Value)\n{{\n\treturn static_cast<E{0}>(Value);\n}}\n",
+                def.name, c_type
+            ));
+        }
+    }
+    if !asserts.is_empty() {
+        mirrors.push('\n');
+        mirrors.push_str(&asserts);
+    }
+    mirrors.push_str(&conversions);
+    (mirrors, skipped)
+}
+
+/// The header of USTRUCT mirrors of the structs of `header` (named `header_name`), with inline
+/// `ToRust` and `FromRust` conversions. `mirrors_name` is its own file name, which names its
+/// generated header. Also returns the structs it couldn't mirror, with why.
+pub fn mirrors_header(
+    header_name: &str,
+    header: &str,
+    mirrors_name: &str,
+    config: &cbindgen::Config,
+    category: &str,
+) -> (String, Vec<(String, String)>) {
+    let stem = mirrors_name
+        .rsplit_once('.')
+        .map_or(mirrors_name, |(stem, _)| stem);
+    let api = config
+        .function
+        .prefix
+        .as_deref()
+        .map_or_else(String::new, |api| format!("{} ", api));
+    let names = CNames::new(config);
+    let structs = struct_definitions(header);
+    let mut mirrored: Vec<&StructDef> = Vec::new();
+    let mut skipped = Vec::new();
+    for def in &structs {
+        match unmirrorable_reason(def, &mirrored) {
+            Some(reason) => skipped.push((def.name.clone(), reason)),
+            None => mirrored.push(def),
+        }
+    }
+    let mut mirrors = format!(
+        "// USTRUCT mirrors of the structs of {}, generated by unreal_rust_compile\n\n#pragma once\n\n#include \"CoreMinimal.h\"\n\n#include \"{}\"\n\n#include \"{}.generated.h\"\n",
+        header_name, header_name, stem
+    );
+    for def in &mirrored {
+        mirrors.push('\n');
+        mirrors.push_str(&ustruct(def, &api, category));
+    }
+    for def in &mirrored {
+        mirrors.push('\n');
+        mirrors.push_str(&conversions(def, &names, "inline "));
+    }
+    (mirrors, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blueprint_library() {
+        let header = "namespace Game {\n\nstruct World;\n\nstruct Vec2 {\n  float x;\n  float y;\n};\n\nstruct Segment {\n  Vec2 start;\n  Vec2 end;\n  uint16_t flags;\n};\n\nstruct Tile {\n  Vec2 position;\n  bool solid;\n};\n\nextern \"C\" {\n\nGAME_API float vec2_length(Vec2 v);\n\nGAME_API Tile tile_at(int32_t x, int32_t y);\n\nGAME_API const char *game_name(void);\n\nGAME_API char *game_describe(const char *what);\n\nGAME_API void game_free_string(char *s);\n\nGAME_API World *world_new(void);\n\nGAME_API float segment_length(Segment segment);\n\n}  // extern \"C\"\n\n}  // namespace Game\n";
+        let structs = struct_definitions(header);
+        assert_eq!(structs.len(), 3);
+        let mirrored: Vec<&str> = mirrorable_structs(&structs)
+            .iter()
+            .map(|def| def.name.as_str())
+            .collect();
+        assert_eq!(mirrored, ["Vec2", "Tile"]);
+
+        let mut config = cbindgen::Config {
+            language: cbindgen::Language::Cxx,
+            namespace: Some("Game".to_owned()),
+            ..cbindgen::Config::default()
+        };
+        config.function.prefix = Some("GAME_API".to_owned());
+        let options = LibraryOptions {
+            header_name: "GameLibrary.h",
+            bindings_header_name: "Game.h",
+            category: "Game",
+            string_free_fn: Some("game_free_string"),
+            structs_header_name: None,
+        };
+        let library = library(header, &config, &options);
+        assert!(library
+            .header
+            .contains("#include \"GameLibrary.generated.h\"\n"));
+        assert!(library.header.contains("USTRUCT(BlueprintType)\nstruct GAME_API FVec2\n{\n\tGENERATED_BODY()\n\n\tUPROPERTY(EditAnywhere, BlueprintReadWrite, Category = \"Game\")\n\tfloat X = 0;\n"));
+        assert!(library.header.contains("\tFVec2 Position;\n"));
+        assert!(library
+            .header
+            .contains("class GAME_API UGameLibrary : public UBlueprintFunctionLibrary\n"));
+        assert!(library.header.contains("\tUFUNCTION(BlueprintCallable, Category = \"Game\")\n\tstatic float Vec2Length(const FVec2& V);\n"));
+        assert!(library
+            .header
+            .contains("\tstatic FTile TileAt(int32 X, int32 Y);\n"));
+        assert!(library
+            .header
+            .contains("\tstatic FString GameDescribe(const FString& What);\n"));
+        assert!(!library.header.contains("GameFreeString"));
+
+        assert!(library
+            .source
+            .contains("#include \"GameLibrary.h\"\n\n#include \"Game.h\"\n"));
+        assert!(library.source.contains("Game::Tile ToRust(const FTile& Value)\n{\n\tGame::Tile Result{};\n\tResult.position = ToRust(Value.Position);\n\tResult.solid = Value.Solid;\n"));
+        assert!(library.source.contains("FTile UGameLibrary::TileAt(int32 X, int32 Y)\n{\n\treturn FromRust(Game::tile_at(X, Y));\n}\n"));
+        assert!(library
+            .source
+            .contains("\treturn UTF8_TO_TCHAR(Game::game_name());\n"));
+        assert!(library.source.contains("\tchar* Result = Game::game_describe(TCHAR_TO_UTF8(*What));\n\tFString Value = UTF8_TO_TCHAR(Result);\n\tGame::game_free_string(Result);\n"));
+        let skipped: Vec<&str> = library
+            .skipped
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(skipped, ["world_new", "segment_length"]);
+
+        let (mirrors, skipped) =
+            mirrors_header("Game.h", header, "Game_structs.h", &config, "Game");
+        assert!(mirrors.contains("#include \"Game.h\"\n\n#include \"Game_structs.generated.h\"\n"));
+        assert!(mirrors.contains("struct GAME_API FTile\n"));
+        assert!(mirrors.contains("inline FVec2 FromRust(const Game::Vec2& Value)\n"));
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, "Segment");
+        assert!(skipped[0].1.contains("`flags`"));
+    }
+
+    #[test]
+    fn enum_mirrors() {
+        let header = "namespace Game {\n\nenum class Mode : uint8_t {\n  Off,\n  On = 4,\n  /// Doc\n  Auto,\n};\n\nenum class Big : int32_t {\n  Low = -1,\n  High = 1000,\n};\n\nstruct Shape {\n  enum class Tag : uint8_t {\n    Circle,\n  };\n};\n\n}  // namespace Game\n";
+        let enums = enum_definitions(header);
+        assert_eq!(
+            enums[0],
+            EnumDef {
+                name: "Mode".to_owned(),
+                scoped: true,
+                variants: Some(vec![
+                    ("Off".to_owned(), 0),
+                    ("On".to_owned(), 4),
+                    ("Auto".to_owned(), 5)
+                ]),
+            }
+        );
+        assert_eq!(enums.len(), 2);
+
+        let config = cbindgen::Config {
+            language: cbindgen::Language::Cxx,
+            namespace: Some("Game".to_owned()),
+            ..cbindgen::Config::default()
+        };
+        let (mirrors, skipped) = enums_header("Game.h", header, "Game_enums.h", &config);
+        assert!(mirrors.contains("#include \"Game_enums.generated.h\"\n\nUENUM(BlueprintType)\nenum class EMode : uint8\n{\n\tOff = 0,\n\tOn = 4,\n\tAuto = 5,\n};\n"));
+        assert!(mirrors.contains("static_assert(static_cast<int64>(EMode::On) == static_cast<int64>(Game::Mode::On), \"EMode::On has a different value in Rust\");\n"));
+        assert!(mirrors.contains("inline Game::Mode ToRust(EMode Value)\n"));
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, "Big");
+
+        let header = "enum Mode\n#ifdef __cplusplus\n  : uint8_t\n#endif // __cplusplus\n {\n  Mode_Off,\n  Mode_On,\n};\ntypedef uint8_t Mode;\n";
+        let config = cbindgen::Config {
+            cpp_compat: true,
+            ..cbindgen::Config::default()
+        };
+        let (mirrors, _) = enums_header("Game.h", header, "Game_enums.h", &config);
+        assert!(mirrors.contains("\tOn = 1,\n"));
+        assert!(mirrors.contains("static_cast<int64>(EMode::On) == static_cast<int64>(Mode_On)"));
+        assert!(!mirrors.contains("ToRust"));
+    }
+
+    #[test]
+    fn struct_mirrors() {
+        let header = "struct Vec2 {\n  float x;\n  float y;\n};\n\nstruct Tile {\n  struct Vec2 position;\n  bool solid;\n};\n\nstruct Grid {\n  uint8_t cells[16];\n};\n\nstruct Level {\n  struct Grid grid;\n};\n\nstruct Player {\n  const char *name;\n};\n";
+        let config = cbindgen::Config {
+            language: cbindgen::Language::C,
+            style: cbindgen::Style::Tag,
+            ..cbindgen::Config::default()
+        };
+        let (mirrors, skipped) =
+            mirrors_header("Game.h", header, "Game_structs.h", &config, "Game");
+        assert!(mirrors.starts_with("// USTRUCT mirrors of the structs of Game.h, generated by unreal_rust_compile\n\n#pragma once\n\n#include \"CoreMinimal.h\"\n\n#include \"Game.h\"\n\n#include \"Game_structs.generated.h\"\n"));
+        // UHT requires the generated header to be the last include
+        assert_eq!(
+            mirrors.rfind("#include").unwrap(),
+            mirrors
+                .find("#include \"Game_structs.generated.h\"")
+                .unwrap()
+        );
+        assert!(mirrors.contains("inline struct Tile ToRust(const FTile& Value)\n{\n\tstruct Tile Result{};\n\tResult.position = ToRust(Value.Position);\n\tResult.solid = Value.Solid;\n\treturn Result;\n}\n"));
+        assert!(mirrors.contains("inline FVec2 FromRust(const struct Vec2& Value)\n{\n\tFVec2 Result;\n\tResult.X = Value.x;\n\tResult.Y = Value.y;\n\treturn Result;\n}\n"));
+        assert!(!mirrors.contains("FGrid"));
+        assert_eq!(
+            skipped,
+            [
+                (
+                    "Grid".to_owned(),
+                    "it has a field that isn't a plain declaration, e.g. an array".to_owned()
+                ),
+                (
+                    "Level".to_owned(),
+                    "field `grid` has type `struct Grid`, which Blueprints can't represent"
+                        .to_owned()
+                ),
+                (
+                    "Player".to_owned(),
+                    "field `name` has type `const char *`, which Blueprints can't represent"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! The bootstrap subcommand's test build, which checks a freshly provisioned build machine by
+//! compiling a tiny crate for each target and generating its header
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// The toolchain installed when the crate doesn't pin one
+pub const DEFAULT_CHANNEL: &str = "stable";
+
+/// The function the probe crate exports, which has to show up in its header
+const PROBE_FUNCTION: &str = "unreal_rust_compile_probe";
+
+/// Write the probe crate to `dir`, pinned to `channel` so that cargo builds it with the
+/// toolchain that was just installed rather than rustup's default
+pub fn write_probe_crate(dir: &Path, channel: &str) -> Result<()> {
+    std::fs::create_dir_all(dir.join("src"))
+        .with_context(|| format!("Couldn't create {}", dir.display()))?;
+    // the empty workspace keeps the crate out of any workspace the temp directory is in
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"unreal_rust_compile_probe\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[lib]\ncrate-type = [\"staticlib\"]\n\n[workspace]\n",
+    )?;
+    std::fs::write(
+        dir.join("rust-toolchain.toml"),
+        format!("[toolchain]\nchannel = \"{}\"\n", channel),
+    )?;
+    std::fs::write(
+        dir.join("src/lib.rs"),
+        format!(
+            "#[no_mangle]\npub extern \"C\" fn {}(value: u32) -> u32 {{\n    value + 1\n}}\n",
+            PROBE_FUNCTION
+        ),
+    )?;
+    Ok(())
+}
+
+/// Build the probe crate for `target`, or the host without one. It's built as a staticlib, so
+/// this checks the toolchain and the target's standard library but not its linker, which
+/// `doctor` checks.
+pub fn test_build(dir: &Path, target: Option<&str>) -> Result<()> {
+    let mut command = Command::new("cargo");
+    command
+        .current_dir(dir)
+        .args(["build", "--lib", "--quiet"])
+        .env("CARGO_TARGET_DIR", dir.join("target"));
+    if let Some(target) = target {
+        command.args(["--target", target]);
+    }
+    let status = command.status().context("Couldn't run cargo")?;
+    if !status.success() {
+        bail!("cargo build failed with {}", status);
+    }
+    Ok(())
+}
+
+/// Check that the header generated for the probe crate declares its function
+pub fn check_probe_header(header: &[u8]) -> Result<()> {
+    if !String::from_utf8_lossy(header).contains(PROBE_FUNCTION) {
+        bail!("The generated header doesn't declare {}", PROBE_FUNCTION);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_crate() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        write_probe_crate(dir, "nightly-2024-05-01").unwrap();
+        let pinned = crate::toolchain::pinned_toolchain(&dir.join("src"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(pinned.channel, "nightly-2024-05-01");
+        assert!(check_probe_header(b"uint32_t unreal_rust_compile_probe(uint32_t value);").is_ok());
+        assert!(check_probe_header(b"").is_err());
+    }
+}
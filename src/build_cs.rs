@@ -0,0 +1,176 @@
+//! gen-buildcs: a fragment of a UE module's `.Build.cs` that links the crate's library, adds the
+//! directories of its headers to the include paths and stages its shared library, to paste into
+//! the module rules' constructor
+
+use std::path::{Path, PathBuf};
+
+/// How the module links the crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Linkage {
+    /// The staticlib, linked into the module
+    Static,
+    /// The cdylib, linked through its import library (or itself) and staged next to the binaries
+    Dynamic,
+}
+
+impl Linkage {
+    pub fn from_arg(value: &str) -> Option<Linkage> {
+        match value {
+            "static" => Some(Linkage::Static),
+            "dynamic" => Some(Linkage::Dynamic),
+            _ => None,
+        }
+    }
+}
+
+/// The files of a crate the module links and stages
+#[derive(Debug, PartialEq, Eq)]
+pub struct Artifacts {
+    /// The file the linker gets
+    pub library: PathBuf,
+    /// The shared library to stage, if linked dynamically
+    pub runtime: Option<PathBuf>,
+}
+
+/// The artifacts cargo writes into `dir` for the lib `crate_name` of a `target` build, named the
+/// way rustc names them for the target's OS
+pub fn artifacts(crate_name: &str, linkage: Linkage, target: &str, dir: &Path) -> Artifacts {
+    let name = crate_name.replace('-', "_");
+    let (library, runtime) = if target.contains("-windows-") {
+        let msvc = target.ends_with("-msvc");
+        match linkage {
+            Linkage::Static if msvc => (format!("{}.lib", name), None),
+            Linkage::Dynamic if msvc => {
+                (format!("{}.dll.lib", name), Some(format!("{}.dll", name)))
+            }
+            Linkage::Static => (format!("lib{}.a", name), None),
+            Linkage::Dynamic => (format!("lib{}.dll.a", name), Some(format!("{}.dll", name))),
+        }
+    } else {
+        let extension = if target.contains("-apple-") {
+            "dylib"
+        } else {
+            "so"
+        };
+        match linkage {
+            Linkage::Static => (format!("lib{}.a", name), None),
+            Linkage::Dynamic => {
+                let shared = format!("lib{}.{}", name, extension);
+                (shared.clone(), Some(shared))
+            }
+        }
+    };
+    Artifacts {
+        library: dir.join(library),
+        runtime: runtime.map(|runtime| dir.join(runtime)),
+    }
+}
+
+/// A C# expression for `path`: relative to the module's directory if it's given, so that the
+/// fragment works from any checkout, or else a verbatim string
+fn path_expression(path: &Path, module_dir: Option<&Path>) -> String {
+    let verbatim = |text: &str| format!("@\"{}\"", text.replace('"', "\"\""));
+    let relative = module_dir.and_then(|module_dir| relative_path(path, module_dir));
+    match relative {
+        Some(relative) => {
+            let components: Vec<String> = relative
+                .components()
+                .map(|component| verbatim(&component.as_os_str().to_string_lossy()))
+                .collect();
+            format!("Path.Combine(ModuleDirectory, {})", components.join(", "))
+        }
+        None => verbatim(&path.to_string_lossy()),
+    }
+}
+
+/// `path` relative to `base`, going up with `..` as far as needed. None if they don't share a
+/// root, e.g. are on different drives
+fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    if path_components.peek() != base_components.peek() {
+        return None;
+    }
+    while path_components.peek().is_some() && path_components.peek() == base_components.peek() {
+        path_components.next();
+        base_components.next();
+    }
+    let mut relative: PathBuf = base_components.map(|_| "..").collect();
+    relative.extend(path_components);
+    Some(relative)
+}
+
+/// The fragment linking `artifacts` built from `crate_name`, with the headers in `include_dirs`
+pub fn snippet(
+    crate_name: &str,
+    artifacts: &Artifacts,
+    include_dirs: &[PathBuf],
+    module_dir: Option<&Path>,
+) -> String {
+    let mut snippet = format!(
+        "// Generated by unreal_rust_compile gen-buildcs for the crate {}\n",
+        crate_name
+    );
+    for dir in include_dirs {
+        snippet.push_str(&format!(
+            "PublicIncludePaths.Add({});\n",
+            path_expression(dir, module_dir)
+        ));
+    }
+    snippet.push_str(&format!(
+        "PublicAdditionalLibraries.Add({});\n",
+        path_expression(&artifacts.library, module_dir)
+    ));
+    if let Some(runtime) = &artifacts.runtime {
+        let file_name = runtime.file_name().unwrap_or_default().to_string_lossy();
+        snippet.push_str(&format!(
+            "RuntimeDependencies.Add(\"$(BinaryOutputDir)/{}\", {});\n",
+            file_name,
+            path_expression(runtime, module_dir)
+        ));
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_cs_snippets() {
+        let dir = Path::new("/game/target/x86_64-pc-windows-msvc/release");
+        let dll = artifacts("game-core", Linkage::Dynamic, "x86_64-pc-windows-msvc", dir);
+        assert_eq!(
+            dll,
+            Artifacts {
+                library: dir.join("game_core.dll.lib"),
+                runtime: Some(dir.join("game_core.dll")),
+            }
+        );
+        let include_dirs = [PathBuf::from("/game/Intermediate/Rust")];
+        assert_eq!(
+            snippet(
+                "game-core",
+                &dll,
+                &include_dirs,
+                Some(Path::new("/game/Source/Game"))
+            ),
+            "// Generated by unreal_rust_compile gen-buildcs for the crate game-core\n\
+             PublicIncludePaths.Add(Path.Combine(ModuleDirectory, @\"..\", @\"..\", @\"Intermediate\", @\"Rust\"));\n\
+             PublicAdditionalLibraries.Add(Path.Combine(ModuleDirectory, @\"..\", @\"..\", @\"target\", @\"x86_64-pc-windows-msvc\", @\"release\", @\"game_core.dll.lib\"));\n\
+             RuntimeDependencies.Add(\"$(BinaryOutputDir)/game_core.dll\", Path.Combine(ModuleDirectory, @\"..\", @\"..\", @\"target\", @\"x86_64-pc-windows-msvc\", @\"release\", @\"game_core.dll\"));\n"
+        );
+
+        let dir = Path::new("/game/target/debug");
+        let staticlib = artifacts("game", Linkage::Static, "x86_64-unknown-linux-gnu", dir);
+        assert_eq!(
+            snippet("game", &staticlib, &include_dirs, None),
+            "// Generated by unreal_rust_compile gen-buildcs for the crate game\n\
+             PublicIncludePaths.Add(@\"/game/Intermediate/Rust\");\n\
+             PublicAdditionalLibraries.Add(@\"/game/target/debug/libgame.a\");\n"
+        );
+        let dylib = artifacts("game", Linkage::Dynamic, "aarch64-apple-darwin", dir);
+        assert_eq!(dylib.runtime, Some(dir.join("libgame.dylib")));
+        assert_eq!(dylib.library, dir.join("libgame.dylib"));
+    }
+}
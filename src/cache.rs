@@ -0,0 +1,217 @@
+//! Cache of the parsed link line, so that no-op rebuilds can skip invoking cargo.
+//!
+//! The cache key covers the source files of every local package in the workspace (path, size
+//! and modification time), their manifests, Cargo.lock, the command line (including the cargo
+//! args, which carry the target, profile and features), `RUSTFLAGS`-style environment variables
+//! and the active rustc version.
+//! Anything outside of that is not tracked and can leave the cache stale, most notably:
+//!
+//! - source files outside of a lib target's source directory (e.g. `include!`d files elsewhere)
+//! - files read by build scripts
+//! - environment variables read by build scripts or `env!`
+//! - libraries in the linker file deleted after the cache was written
+//!
+//! Pass `--no-cache` to always run cargo.
+
+use crate::link_args::{
+    output_file_bytes, LinkerOutput, ResponseFileEncoding, SYSROOT_PLACEHOLDER,
+};
+use anyhow::Result;
+use cargo::GlobalContext;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsString;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+/// Environment variables that change the compiler output without touching any file
+const TRACKED_ENV_VARS: &[&str] = &[
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "CARGO_BUILD_TARGET",
+    "CARGO_TARGET_DIR",
+    "RUSTUP_TOOLCHAIN",
+];
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    output: LinkerOutput,
+}
+
+/// Path of the cache file kept next to the linker file
+pub fn cache_path(output_linker_file: &Path) -> PathBuf {
+    let mut path: OsString = output_linker_file.as_os_str().into();
+    path.push(".cache");
+    path.into()
+}
+
+/// Compute the cache key for a compile of the crate at `manifest_path`. `args` are the tool's
+/// command line args, including the cargo args, and `var` looks up environment variables.
+pub fn cache_key(
+    manifest_path: &Path,
+    args: &[String],
+    var: impl Fn(&str) -> Option<OsString>,
+    ctx: &GlobalContext,
+) -> Result<String> {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    args.hash(&mut hasher);
+    for name in TRACKED_ENV_VARS {
+        var(name).hash(&mut hasher);
+    }
+    let rustc_version = Command::new("rustc").arg("-vV").output()?;
+    rustc_version.stdout.hash(&mut hasher);
+
+    for path in crate::source_files::tracked_files(manifest_path, ctx)? {
+        let metadata = std::fs::metadata(&path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        path.hash(&mut hasher);
+        metadata.len().hash(&mut hasher);
+        modified.hash(&mut hasher);
+    }
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Returns the cached output if it matches `key` and the files it produced are still intact
+pub fn lookup(
+    key: &str,
+    output_linker_file: &Path,
+    output_lib_link_file: &Path,
+    def_file_path: &Path,
+    rsp_encoding: Option<ResponseFileEncoding>,
+    json_file: Option<&Path>,
+) -> Option<LinkerOutput> {
+    let data = std::fs::read(cache_path(output_linker_file)).ok()?;
+    let entry: CacheEntry = serde_json::from_slice(&data).ok()?;
+    if entry.key != key {
+        return None;
+    }
+    let linker_contents = std::fs::read(output_linker_file).ok()?;
+    let lib_contents = std::fs::read(output_lib_link_file).ok()?;
+    if linker_contents != output_file_bytes(&entry.output.linker_file_contents(), rsp_encoding)
+        || lib_contents != output_file_bytes(&entry.output.lib_file_contents(), rsp_encoding)
+    {
+        return None;
+    }
+    if entry.output.def_file.is_some() && !def_file_path.exists() {
+        return None;
+    }
+    if json_file.is_some_and(|path| !path.exists()) {
+        return None;
+    }
+    if !entry
+        .output
+        .lib_inputs()
+        .filter(|input| !input.starts_with(SYSROOT_PLACEHOLDER))
+        .all(|input| Path::new(input).exists())
+    {
+        return None;
+    }
+    Some(entry.output)
+}
+
+pub fn store(key: &str, output_linker_file: &Path, output: &LinkerOutput) -> Result<()> {
+    let entry = CacheEntry {
+        key: key.to_owned(),
+        output: output.clone(),
+    };
+    std::fs::write(
+        cache_path(output_linker_file),
+        serde_json::to_vec_pretty(&entry)?,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_invalidation() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let manifest_path = dir.join("Cargo.toml");
+        std::fs::write(
+            &manifest_path,
+            "[package]\nname = \"cached\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n[lib]\ncrate-type = [\"staticlib\"]\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "pub fn f() {}\n").unwrap();
+        let ctx = GlobalContext::default().unwrap();
+        let args = vec!["--release".to_owned()];
+        let no_env = |_: &str| None;
+        let key = || cache_key(&manifest_path, &args, no_env, &ctx).unwrap();
+
+        // resolving the workspace writes Cargo.lock, as the build does before the key is stored
+        key();
+        let original = key();
+        assert_eq!(key(), original);
+
+        std::fs::write(dir.join("src/lib.rs"), "pub fn f() {}\npub fn g() {}\n").unwrap();
+        let source_changed = key();
+        assert_ne!(source_changed, original);
+
+        let mut manifest = std::fs::read_to_string(&manifest_path).unwrap();
+        manifest.push_str("\n[features]\nextra = []\n");
+        std::fs::write(&manifest_path, manifest).unwrap();
+        let manifest_changed = key();
+        assert_ne!(manifest_changed, source_changed);
+
+        let other_args = vec!["--features=extra".to_owned()];
+        assert_ne!(
+            cache_key(&manifest_path, &other_args, no_env, &ctx).unwrap(),
+            manifest_changed
+        );
+
+        let rustflags = |name: &str| match name {
+            "RUSTFLAGS" => Some(OsString::from("-Ctarget-cpu=native")),
+            _ => None,
+        };
+        let rustflags_changed = cache_key(&manifest_path, &args, rustflags, &ctx).unwrap();
+        assert_ne!(rustflags_changed, manifest_changed);
+        assert_eq!(key(), manifest_changed);
+    }
+
+    #[test]
+    fn lookup_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let input = dir.join("libcached.a");
+        std::fs::write(&input, "").unwrap();
+        let linker_file = dir.join("linker.txt");
+        let lib_file = dir.join("lib.txt");
+        let def_file = dir.join("cached.def");
+        let output = LinkerOutput {
+            linker_lines: vec!["-lpthread".to_owned()],
+            lib_lines: vec![
+                format!("\"{}\"", input.display()),
+                format!("\"{}/lib/libstd.rlib\"", SYSROOT_PLACEHOLDER),
+            ],
+            ..LinkerOutput::default()
+        };
+        let lookup = |key| lookup(key, &linker_file, &lib_file, &def_file, None, None);
+
+        // nothing stored yet
+        assert_eq!(lookup("key"), None);
+
+        std::fs::write(&linker_file, output.linker_file_contents()).unwrap();
+        std::fs::write(&lib_file, output.lib_file_contents()).unwrap();
+        store("key", &linker_file, &output).unwrap();
+        assert_eq!(lookup("key"), Some(output.clone()));
+        assert_eq!(lookup("other key"), None);
+
+        // a hand-edited output file
+        std::fs::write(&linker_file, "-lm\n").unwrap();
+        assert_eq!(lookup("key"), None);
+        std::fs::write(&linker_file, output.linker_file_contents()).unwrap();
+        assert_eq!(lookup("key"), Some(output.clone()));
+
+        // a deleted input of the lib file
+        std::fs::remove_file(&input).unwrap();
+        assert_eq!(lookup("key"), None);
+    }
+}
@@ -0,0 +1,289 @@
+//! gen-bindings' `--calling-convention`: a check that the crate's exported functions and the
+//! function pointers they use have C's calling convention, and an annotation of the header's
+//! declarations with it. On win32 targets, where `extern "system"` is stdcall, a caller and callee
+//! disagreeing about the convention corrupt the stack instead of failing to link.
+
+use crate::crate_items::for_each_module;
+use crate::ffi_lint::is_exported;
+use crate::wrappers::function_declarations;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// The ABIs with C's calling convention, which cbindgen declares functions of
+const C_ABIS: &[&str] = &["C", "C-unwind"];
+
+/// The problem with an ABI of an exported function or function pointer, if any
+fn abi_problem(abi: Option<&syn::Abi>, what: &str) -> Option<String> {
+    let Some(abi) = abi else {
+        return Some(format!(
+            "{} has the Rust ABI, whose calling convention is unspecified. Declare it extern \"C\"",
+            what
+        ));
+    };
+    let Some(name) = &abi.name else {
+        return Some(format!(
+            "{} is declared `extern` without an ABI, which is only implicitly \"C\". Declare it extern \"C\"",
+            what
+        ));
+    };
+    let name = name.value();
+    if C_ABIS.contains(&name.as_str()) {
+        return None;
+    }
+    Some(format!(
+        "{} is extern \"{}\", whose calling convention differs from the header's C one on win32 targets. Declare it extern \"C\"",
+        what, name
+    ))
+}
+
+/// A struct or type alias of the crate, whose function pointers are checked if exported items
+/// use it
+enum LocalType {
+    Struct(Vec<(String, syn::Type)>),
+    Alias(syn::Type),
+}
+
+struct Checker {
+    types: HashMap<String, LocalType>,
+    /// Local types whose function pointers were checked already
+    checked: HashSet<String>,
+    problems: Vec<String>,
+}
+
+impl Checker {
+    /// Check the function pointers in `ty`, and in the local types it uses
+    fn check_type(&mut self, ty: &syn::Type, location: &str, what: &str) {
+        match ty {
+            syn::Type::BareFn(function) => {
+                let what = format!("{}: {}, a function pointer,", location, what);
+                self.problems
+                    .extend(abi_problem(function.abi.as_ref(), &what));
+                for (index, input) in function.inputs.iter().enumerate() {
+                    let what = format!("argument {} of the function pointer", index);
+                    self.check_type(&input.ty, location, &what);
+                }
+                if let syn::ReturnType::Type(_, ty) = &function.output {
+                    self.check_type(ty, location, "the function pointer's return type");
+                }
+            }
+            syn::Type::Ptr(pointer) => self.check_type(&pointer.elem, location, what),
+            syn::Type::Reference(reference) => self.check_type(&reference.elem, location, what),
+            syn::Type::Array(array) => self.check_type(&array.elem, location, what),
+            syn::Type::Paren(paren) => self.check_type(&paren.elem, location, what),
+            syn::Type::Group(group) => self.check_type(&group.elem, location, what),
+            syn::Type::Path(path) => {
+                // e.g. Option<extern "C" fn()>
+                for segment in &path.path.segments {
+                    if let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments {
+                        for argument in &arguments.args {
+                            if let syn::GenericArgument::Type(ty) = argument {
+                                self.check_type(ty, location, what);
+                            }
+                        }
+                    }
+                }
+                if let Some(segment) = path.path.segments.last() {
+                    self.check_local(&segment.ident.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_local(&mut self, name: &str) {
+        if !self.checked.insert(name.to_owned()) {
+            return;
+        }
+        match self.types.remove(name) {
+            Some(LocalType::Struct(fields)) => {
+                for (field, ty) in &fields {
+                    self.check_type(ty, name, &format!("field `{}`", field));
+                }
+            }
+            Some(LocalType::Alias(ty)) => self.check_type(&ty, name, "the alias"),
+            None => {}
+        }
+    }
+}
+
+/// Check the items of the crate's modules, given as their paths and items
+fn check_modules(modules: &[(String, Vec<syn::Item>)]) -> Vec<String> {
+    let mut checker = Checker {
+        types: HashMap::new(),
+        checked: HashSet::new(),
+        problems: Vec::new(),
+    };
+    for (_, items) in modules {
+        for item in items {
+            let (ident, local_type) = match item {
+                syn::Item::Struct(item) => {
+                    let fields = item
+                        .fields
+                        .iter()
+                        .enumerate()
+                        .map(|(index, field)| {
+                            let name = field
+                                .ident
+                                .as_ref()
+                                .map_or_else(|| index.to_string(), |ident| ident.to_string());
+                            (name, field.ty.clone())
+                        })
+                        .collect();
+                    (&item.ident, LocalType::Struct(fields))
+                }
+                syn::Item::Type(item) => (&item.ident, LocalType::Alias((*item.ty).clone())),
+                _ => continue,
+            };
+            checker.types.entry(ident.to_string()).or_insert(local_type);
+        }
+    }
+    for (module, items) in modules {
+        let qualified = |name: &syn::Ident| match module.as_str() {
+            "" => name.to_string(),
+            module => format!("{}::{}", module, name),
+        };
+        for item in items {
+            match item {
+                syn::Item::Fn(function) if is_exported(&function.attrs) => {
+                    let sig = &function.sig;
+                    let location = qualified(&sig.ident);
+                    checker
+                        .problems
+                        .extend(abi_problem(sig.abi.as_ref(), &location));
+                    for input in &sig.inputs {
+                        if let syn::FnArg::Typed(argument) = input {
+                            let what = match &*argument.pat {
+                                syn::Pat::Ident(pat) => format!("argument `{}`", pat.ident),
+                                _ => "an argument".to_owned(),
+                            };
+                            checker.check_type(&argument.ty, &location, &what);
+                        }
+                    }
+                    if let syn::ReturnType::Type(_, ty) = &sig.output {
+                        checker.check_type(ty, &location, "the return type");
+                    }
+                }
+                syn::Item::Static(item) if is_exported(&item.attrs) => {
+                    checker.check_type(&item.ty, &qualified(&item.ident), "its type");
+                }
+                _ => {}
+            }
+        }
+    }
+    checker.problems
+}
+
+/// The calling convention problems of the exported functions of the crate whose root module is
+/// `src_path`, and of the function pointers in their signatures and exported statics, and in the
+/// structs and type aliases these use. Items generated by macros aren't checked.
+pub fn check_crate(src_path: &Path) -> Result<Vec<String>> {
+    let mut modules = Vec::new();
+    for_each_module(src_path, &mut |module, items| {
+        modules.push((module.to_owned(), items.to_vec()));
+    })?;
+    Ok(check_modules(&modules))
+}
+
+/// Whether the return type part of a declaration, up to the function's name, has `annotation`
+fn is_annotated(ret: &str, annotation: &str) -> bool {
+    ret.split(|c: char| c.is_whitespace() || c == '*')
+        .any(|word| word == annotation)
+}
+
+/// Annotate each function declaration of the header with `annotation` (e.g. `CDECL`) between its
+/// return type and its name. Returns the header and the functions whose declarations still lack
+/// it, e.g. because they weren't recognized.
+pub fn annotate(header: &[u8], annotation: &str) -> (Vec<u8>, Vec<String>) {
+    let header = String::from_utf8_lossy(header);
+    let declarations = function_declarations(&header, None);
+    let mut lines: Vec<String> = header.lines().map(str::to_owned).collect();
+    for declaration in &declarations {
+        if is_annotated(&declaration.ret, annotation) {
+            continue;
+        }
+        let call = format!("{}(", declaration.name);
+        // the line starting the declaration, in which only the return type precedes the name
+        let line = lines.iter_mut().find(|line| {
+            let trimmed = line.trim_start();
+            if ["typedef ", "//", "/*", "*", "#"]
+                .iter()
+                .any(|start| trimmed.starts_with(start))
+            {
+                return false;
+            }
+            line.match_indices(&call).any(|(start, _)| {
+                let head = &line[..start];
+                head.ends_with([' ', '*']) && !head.contains(['(', ';', '='])
+            })
+        });
+        if let Some(line) = line {
+            let start = line
+                .match_indices(&call)
+                .map(|(start, _)| start)
+                .find(|start| line[..*start].ends_with([' ', '*']))
+                .expect("the line has the call");
+            line.insert_str(start, &format!("{} ", annotation));
+        }
+    }
+    let mut annotated = lines.join("\n");
+    if header.ends_with('\n') {
+        annotated.push('\n');
+    }
+    let unannotated = function_declarations(&annotated, None)
+        .into_iter()
+        .filter(|declaration| !is_annotated(&declaration.ret, annotation))
+        .map(|declaration| declaration.name)
+        .collect();
+    (annotated.into_bytes(), unannotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calling_conventions() {
+        let file = syn::parse_file(
+            r#"
+            pub type Callback = Option<extern "system" fn(u32)>;
+            #[repr(C)]
+            pub struct Handlers { pub on_event: extern "C" fn(*const Handlers), pub on_exit: fn() }
+
+            #[no_mangle]
+            pub extern "C" fn fine(callback: Option<unsafe extern "C-unwind" fn(u32) -> u32>) {}
+            #[no_mangle]
+            pub extern "C" fn register(callback: Callback, handlers: *const Handlers) {}
+            pub struct Internal { pub run: fn() }
+            #[no_mangle]
+            pub extern fn implicit() {}
+            #[no_mangle]
+            pub extern "stdcall" fn stdcall(callback: *const extern "system" fn()) {}
+            pub extern "system" fn not_exported() {}
+            "#,
+        )
+        .unwrap();
+        let problems = check_modules(&[("ffi".to_owned(), file.items)]);
+        assert_eq!(
+            problems,
+            vec![
+                "Callback: the alias, a function pointer, is extern \"system\", whose calling convention differs from the header's C one on win32 targets. Declare it extern \"C\"",
+                "Handlers: field `on_exit`, a function pointer, has the Rust ABI, whose calling convention is unspecified. Declare it extern \"C\"",
+                "ffi::implicit is declared `extern` without an ABI, which is only implicitly \"C\". Declare it extern \"C\"",
+                "ffi::stdcall is extern \"stdcall\", whose calling convention differs from the header's C one on win32 targets. Declare it extern \"C\"",
+                "ffi::stdcall: argument `callback`, a function pointer, is extern \"system\", whose calling convention differs from the header's C one on win32 targets. Declare it extern \"C\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn annotated_declarations() {
+        let header = "typedef void (*Callback)(int32_t);\n\nGAME_API World *world_new(void);\n\nvoid world_each(World *world,\n                Callback callback);\n\nint32_t CDECL world_width(const World *world);\n";
+        let (annotated, unannotated) = annotate(header.as_bytes(), "CDECL");
+        assert_eq!(
+            String::from_utf8(annotated).unwrap(),
+            "typedef void (*Callback)(int32_t);\n\nGAME_API World *CDECL world_new(void);\n\nvoid CDECL world_each(World *world,\n                Callback callback);\n\nint32_t CDECL world_width(const World *world);\n"
+        );
+        assert!(unannotated.is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+//! The environment cargo runs with under `--clean-env`
+
+use std::ffi::OsString;
+
+/// Environment variables cargo keeps with `--clean-env`, which it and rustup need to find the
+/// toolchain, the linker and a temporary directory
+pub const CLEAN_ENV_VARS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USERPROFILE",
+    "CARGO_HOME",
+    "RUSTUP_HOME",
+    "TMPDIR",
+    "TEMP",
+    "TMP",
+    "SYSTEMROOT",
+    "WINDIR",
+];
+
+/// The variables of `CLEAN_ENV_VARS` and `passthrough` that `var` has a value for, each once
+pub fn clean_env<'a>(
+    passthrough: impl IntoIterator<Item = &'a str>,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Vec<(&'a str, OsString)> {
+    let mut env: Vec<(&str, OsString)> = Vec::new();
+    for name in CLEAN_ENV_VARS.iter().copied().chain(passthrough) {
+        if env.iter().any(|(kept, _)| *kept == name) {
+            continue;
+        }
+        if let Some(value) = var(name) {
+            env.push((name, value));
+        }
+    }
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kept_variables() {
+        let var = |name: &str| match name {
+            "PATH" => Some(OsString::from("/usr/bin")),
+            "TMPDIR" => Some(OsString::from("/tmp")),
+            "RUSTFLAGS" => Some(OsString::from("-Ctarget-cpu=native")),
+            "UE_ROOT" => Some(OsString::from("/ue")),
+            _ => None,
+        };
+        assert_eq!(
+            clean_env([], var),
+            [("PATH", "/usr/bin".into()), ("TMPDIR", "/tmp".into())]
+        );
+        // unset variables are skipped, and ones already kept aren't duplicated
+        assert_eq!(
+            clean_env(["UE_ROOT", "SDK_ROOT", "PATH"], var),
+            [
+                ("PATH", "/usr/bin".into()),
+                ("TMPDIR", "/tmp".into()),
+                ("UE_ROOT", "/ue".into())
+            ]
+        );
+    }
+}
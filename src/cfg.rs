@@ -0,0 +1,91 @@
+//! `--cfg` values, which are passed to rustc and mapped to cbindgen defines so that the header
+//! matches the compiled lib
+
+/// A `name` or `name=value` cfg
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl Cfg {
+    /// Parse a `name` or `name=value` argument. The value may be quoted as in rustc's
+    /// `name="value"` syntax.
+    pub fn parse(arg: &str) -> Result<Cfg, String> {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => {
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+                (name.trim(), Some(value.to_owned()))
+            }
+            None => (arg.trim(), None),
+        };
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(format!("\"{}\" isn't a valid cfg name", name));
+        }
+        Ok(Cfg {
+            name: name.to_owned(),
+            value,
+        })
+    }
+
+    /// The argument to pass to rustc's `--cfg`
+    pub fn rustc_arg(&self) -> String {
+        match &self.value {
+            Some(value) => format!("{}=\"{}\"", self.name, value),
+            None => self.name.clone(),
+        }
+    }
+
+    /// The key of cbindgen's `[defines]` table matching this cfg
+    pub fn define_key(&self) -> String {
+        match &self.value {
+            Some(value) => format!("{} = {}", self.name, value),
+            None => self.name.clone(),
+        }
+    }
+
+    /// The preprocessor define cfg-gated items are wrapped in, unless cbindgen.toml maps the cfg
+    /// to one itself: the upper cased name, followed by the value for `name=value` cfgs
+    pub fn define_name(&self) -> String {
+        let define = match &self.value {
+            Some(value) => format!("{}_{}", self.name, value),
+            None => self.name.clone(),
+        };
+        define
+            .chars()
+            .map(|c| match c {
+                c if c.is_ascii_alphanumeric() => c.to_ascii_uppercase(),
+                _ => '_',
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cfgs() {
+        let flag = Cfg::parse("ue_ffi").unwrap();
+        assert_eq!(flag.rustc_arg(), "ue_ffi");
+        assert_eq!(flag.define_key(), "ue_ffi");
+        assert_eq!(flag.define_name(), "UE_FFI");
+
+        for arg in ["ue_platform=win64", "ue_platform=\"win64\""] {
+            let named = Cfg::parse(arg).unwrap();
+            assert_eq!(named.rustc_arg(), "ue_platform=\"win64\"");
+            assert_eq!(named.define_key(), "ue_platform = win64");
+            assert_eq!(named.define_name(), "UE_PLATFORM_WIN64");
+        }
+
+        assert!(Cfg::parse("1st").is_err());
+        assert!(Cfg::parse("ue-ffi").is_err());
+    }
+}
@@ -0,0 +1,56 @@
+//! Coloring of the tool's own diagnostics and of cargo's output
+
+use std::io::IsTerminal;
+
+/// The value of `--color`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn from_arg(value: Option<&str>) -> ColorChoice {
+        match value {
+            Some("always") => ColorChoice::Always,
+            Some("never") => ColorChoice::Never,
+            _ => ColorChoice::Auto,
+        }
+    }
+
+    /// Whether diagnostics written to stderr should be colored. `Auto` colors them only if
+    /// stderr is a terminal.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => std::io::stderr().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+
+    /// The `--color` value to forward to cargo. cargo's stderr is captured, so its own `auto`
+    /// would never color; `Auto` is resolved against our stderr instead.
+    pub fn cargo_value(self) -> &'static str {
+        if self.enabled() {
+            "always"
+        } else {
+            "never"
+        }
+    }
+}
+
+/// Prefix a diagnostic with an `error`, `warning` or `ok` label, colored the way cargo colors them
+pub fn diagnostic(label: &str, message: impl std::fmt::Display, color: ColorChoice) -> String {
+    if !color.enabled() {
+        return format!("{}: {}", label, message);
+    }
+    let code = match label {
+        "error" => "1;31",
+        "warning" => "1;33",
+        "ok" => "1;32",
+        _ => "1",
+    };
+    format!("\x1b[{}m{}\x1b[0m\x1b[1m:\x1b[0m {}", code, label, message)
+}
@@ -0,0 +1,122 @@
+//! The items of a crate by module, for restricting what cbindgen exports to some modules
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// An item cbindgen could export, and the path of the module it's defined in (empty for the
+/// crate root)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleItem {
+    pub module: String,
+    pub name: String,
+}
+
+/// Whether `module` is the module `path` (e.g. `ffi::internal`, optionally starting with
+/// `crate::`) or one of its submodules
+pub fn module_matches(module: &str, path: &str) -> bool {
+    let path = path.strip_prefix("crate").unwrap_or(path);
+    let path = path.strip_prefix("::").unwrap_or(path);
+    path.is_empty()
+        || module == path
+        || module
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.starts_with("::"))
+}
+
+/// Every struct, enum, union, type alias, function, static and constant of the crate whose root
+/// module is `src_path`. Module files are found the way rustc finds them, except that `#[path]`
+/// attributes and items generated by macros aren't supported.
+pub fn crate_items(src_path: &Path) -> Result<Vec<ModuleItem>> {
+    let mut items = Vec::new();
+    for_each_module(src_path, &mut |module, module_items| {
+        for item in module_items {
+            let ident = match item {
+                syn::Item::Struct(item) => &item.ident,
+                syn::Item::Enum(item) => &item.ident,
+                syn::Item::Union(item) => &item.ident,
+                syn::Item::Type(item) => &item.ident,
+                syn::Item::Fn(item) => &item.sig.ident,
+                syn::Item::Static(item) => &item.ident,
+                syn::Item::Const(item) => &item.ident,
+                _ => continue,
+            };
+            items.push(ModuleItem {
+                module: module.to_owned(),
+                name: ident.to_string(),
+            });
+        }
+    })?;
+    Ok(items)
+}
+
+/// Call `f` with the path and items of each module of the crate whose root module is
+/// `src_path`, found as `crate_items` finds them
+pub fn for_each_module(src_path: &Path, f: &mut dyn FnMut(&str, &[syn::Item])) -> Result<()> {
+    visit_file(src_path, "", true, f)
+}
+
+fn visit_file(
+    path: &Path,
+    module: &str,
+    owns_dir: bool,
+    f: &mut dyn FnMut(&str, &[syn::Item]),
+) -> Result<()> {
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read module {}", path.display()))?;
+    let file = syn::parse_file(&source)
+        .with_context(|| format!("Couldn't parse module {}", path.display()))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    // submodules of `foo.rs` live in `foo/`, those of `lib.rs` and `mod.rs` next to them
+    let child_dir = if owns_dir {
+        dir.to_owned()
+    } else {
+        dir.join(path.file_stem().unwrap_or_default())
+    };
+    visit_items(&file.items, module, &child_dir, f)
+}
+
+fn visit_items(
+    items: &[syn::Item],
+    module: &str,
+    child_dir: &Path,
+    f: &mut dyn FnMut(&str, &[syn::Item]),
+) -> Result<()> {
+    f(module, items);
+    for item in items {
+        let syn::Item::Mod(item) = item else {
+            continue;
+        };
+        let name = item.ident.to_string();
+        let submodule = if module.is_empty() {
+            name.clone()
+        } else {
+            format!("{}::{}", module, name)
+        };
+        match &item.content {
+            Some((_, items)) => visit_items(items, &submodule, &child_dir.join(&name), f)?,
+            None => {
+                let file = child_dir.join(format!("{}.rs", name));
+                if file.is_file() {
+                    visit_file(&file, &submodule, false, f)?;
+                } else {
+                    visit_file(&child_dir.join(&name).join("mod.rs"), &submodule, true, f)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_paths() {
+        assert!(module_matches("ffi", "ffi"));
+        assert!(module_matches("ffi::internal", "crate::ffi"));
+        assert!(module_matches("ffi", "crate"));
+        assert!(!module_matches("ffi_internal", "ffi"));
+        assert!(!module_matches("", "ffi"));
+    }
+}
@@ -0,0 +1,126 @@
+//! The C runtime an MSVC link line links, which has to be the one UE links: objects built
+//! against different CRTs fail UE's final link with LNK2038
+
+use crate::link_args::{split_option, LinkerFlavor};
+
+/// An MSVC CRT, named after the compiler flag selecting it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crt {
+    /// The release DLL CRT, which UE uses by default
+    Md,
+    MdDebug,
+    /// The static release CRT, which rustc links with `+crt-static`
+    Mt,
+    MtDebug,
+}
+
+impl Crt {
+    pub fn from_arg(value: &str) -> Option<Crt> {
+        match value.to_ascii_lowercase().as_str() {
+            "md" => Some(Crt::Md),
+            "mdd" => Some(Crt::MdDebug),
+            "mt" => Some(Crt::Mt),
+            "mtd" => Some(Crt::MtDebug),
+            _ => None,
+        }
+    }
+
+    /// The CRT whose import or static library this is
+    fn from_library(name: &str) -> Option<Crt> {
+        let name = name.to_ascii_lowercase();
+        match name.strip_suffix(".lib").unwrap_or(&name) {
+            "msvcrt" => Some(Crt::Md),
+            "msvcrtd" => Some(Crt::MdDebug),
+            "libcmt" => Some(Crt::Mt),
+            "libcmtd" => Some(Crt::MtDebug),
+            _ => None,
+        }
+    }
+
+    pub fn flag(self) -> &'static str {
+        match self {
+            Crt::Md => "/MD",
+            Crt::MdDebug => "/MDd",
+            Crt::Mt => "/MT",
+            Crt::MtDebug => "/MTd",
+        }
+    }
+
+    fn is_static(self) -> bool {
+        matches!(self, Crt::Mt | Crt::MtDebug)
+    }
+
+    fn is_debug(self) -> bool {
+        matches!(self, Crt::MdDebug | Crt::MtDebug)
+    }
+}
+
+/// The CRT an MSVC link line links, from the CRT library rustc passes as an input or with
+/// `/DEFAULTLIB`
+pub fn link_line_crt(link_line: &str) -> Option<Crt> {
+    LinkerFlavor::Msvc
+        .tokenize(link_line)
+        .iter()
+        .find_map(|arg| {
+            let library = if arg.starts_with(['/', '-']) {
+                let (name, value) = split_option(arg);
+                if !name.eq_ignore_ascii_case("DEFAULTLIB") {
+                    return None;
+                }
+                value
+            } else {
+                arg.rsplit(['/', '\\']).next().unwrap_or(arg)
+            };
+            Crt::from_library(library)
+        })
+}
+
+/// Check that the link line links `ue_crt`, or explain how to make it
+pub fn check_crt(link_line: &str, ue_crt: Crt) -> Result<(), String> {
+    let Some(rust_crt) = link_line_crt(link_line) else {
+        return Ok(());
+    };
+    if rust_crt == ue_crt {
+        return Ok(());
+    }
+    let fix = if rust_crt.is_static() != ue_crt.is_static() {
+        if ue_crt.is_static() {
+            "Build the crate with `-C target-feature=+crt-static` (e.g. in .cargo/config.toml)"
+        } else {
+            "Remove `-C target-feature=+crt-static` from RUSTFLAGS and .cargo/config.toml"
+        }
+    } else if ue_crt.is_debug() {
+        "Rust's standard library is built against the release CRT, so UE has to use it too (bDebugBuildsActuallyUseDebugCRT = false)"
+    } else {
+        "Link the crate against the release CRT"
+    };
+    Err(format!(
+        "The crate links the {} CRT, but UE links {}, which fails the final link with LNK2038. {}",
+        rust_crt.flag(),
+        ue_crt.flag(),
+        fix
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crt_mismatches() {
+        let dynamic =
+            r#""link.exe" "C:\\t\\a.o" "kernel32.lib" "msvcrt.lib" "/NODEFAULTLIB:libcmt""#;
+        assert_eq!(link_line_crt(dynamic), Some(Crt::Md));
+        assert!(check_crt(dynamic, Crt::Md).is_ok());
+        assert!(check_crt(dynamic, Crt::Mt)
+            .unwrap_err()
+            .contains("+crt-static"));
+        assert!(check_crt(dynamic, Crt::MdDebug)
+            .unwrap_err()
+            .contains("bDebugBuildsActuallyUseDebugCRT"));
+
+        let static_crt = r#""link.exe" "C:\\t\\a.o" "/DEFAULTLIB:LIBCMT""#;
+        assert_eq!(link_line_crt(static_crt), Some(Crt::Mt));
+        assert_eq!(link_line_crt(r#""link.exe" "C:\\t\\a.o""#), None);
+    }
+}
@@ -0,0 +1,277 @@
+//! The C++ side of cxx and autocxx bridges, generated and compiled by the tool instead of the
+//! crate's build script, so that its objects go into the lib file along with the crate's
+
+use crate::crt::Crt;
+use crate::link_args::LinkerFlavor;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The tool generating the C++ side of a bridge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CxxGenerator {
+    /// `cxxbridge`, for `#[cxx::bridge]` modules
+    Cxx,
+    /// `autocxx-gen`, for `include_cpp!` invocations
+    Autocxx,
+}
+
+impl CxxGenerator {
+    pub fn from_arg(value: &str) -> Option<CxxGenerator> {
+        match value {
+            "cxx" => Some(CxxGenerator::Cxx),
+            "autocxx" => Some(CxxGenerator::Autocxx),
+            _ => None,
+        }
+    }
+}
+
+/// The bridges of a crate and how to compile them
+pub struct CxxOptions {
+    pub generator: CxxGenerator,
+    /// The Rust files with the bridges, absolute
+    pub bridges: Vec<PathBuf>,
+    /// Directory of the generated headers, sources and objects
+    pub out_dir: PathBuf,
+    /// Directories of the C++ headers the bridges include
+    pub include_dirs: Vec<PathBuf>,
+    /// Compiler command the sources' paths are appended to. Defaults to cl or c++, depending on
+    /// the link line's flavor
+    pub compiler: Option<Vec<String>>,
+}
+
+/// Where the files generated for `bridge` go: its path relative to the crate under `out_dir`, so
+/// that cxx's headers are included as e.g. `src/lib.rs.h`
+fn generated_path(out_dir: &Path, crate_dir: &Path, bridge: &Path) -> PathBuf {
+    match bridge.strip_prefix(crate_dir) {
+        Ok(relative) => out_dir.join(relative),
+        Err(_) => out_dir.join(bridge.file_name().unwrap_or_default()),
+    }
+}
+
+/// Run a generator and return its stdout
+fn run_generator(program: &str, args: &[&std::ffi::OsStr]) -> Result<Vec<u8>> {
+    let output = Command::new(program).args(args).output().with_context(|| {
+        format!(
+            "Couldn't run {}. `cargo install {}` installs it",
+            program,
+            match program {
+                "cxxbridge" => "cxxbridge-cmd",
+                _ => "autocxx-gen",
+            }
+        )
+    })?;
+    if !output.status.success() {
+        bail!(
+            "{} failed with {}:\n{}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// Write a generated file unless it's unchanged, so that the UE code including it isn't rebuilt
+fn write_generated(path: &Path, contents: &[u8]) -> Result<()> {
+    if std::fs::read(path).ok().as_deref() != Some(contents) {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+/// Generate the C++ side of the bridges of the crate in `crate_dir` and return the sources to
+/// compile. cxx's headers are written next to the sources, and `rust/cxx.h` into `out_dir`.
+pub fn generate(options: &CxxOptions, crate_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+    match options.generator {
+        CxxGenerator::Cxx => {
+            write_generated(
+                &options.out_dir.join("rust").join("cxx.h"),
+                &run_generator("cxxbridge", &["--header".as_ref()])?,
+            )?;
+            for bridge in &options.bridges {
+                let path = generated_path(&options.out_dir, crate_dir, bridge);
+                let mut header_path = path.clone().into_os_string();
+                header_path.push(".h");
+                let mut source_path = path.into_os_string();
+                source_path.push(".cc");
+                let header = run_generator("cxxbridge", &[bridge.as_ref(), "--header".as_ref()])?;
+                write_generated(Path::new(&header_path), &header)?;
+                let source = run_generator("cxxbridge", &[bridge.as_ref()])?;
+                write_generated(Path::new(&source_path), &source)?;
+                sources.push(source_path.into());
+            }
+        }
+        CxxGenerator::Autocxx => {
+            for bridge in &options.bridges {
+                let mut dir = generated_path(&options.out_dir, crate_dir, bridge).into_os_string();
+                dir.push(".autocxx");
+                let dir = PathBuf::from(dir);
+                std::fs::create_dir_all(&dir)?;
+                let mut args: Vec<&std::ffi::OsStr> = vec![
+                    bridge.as_ref(),
+                    "--outdir".as_ref(),
+                    dir.as_ref(),
+                    "--gen-cpp".as_ref(),
+                    "--generate-cxx-h".as_ref(),
+                ];
+                for include_dir in &options.include_dirs {
+                    args.push("--inc".as_ref());
+                    args.push(include_dir.as_ref());
+                }
+                run_generator("autocxx-gen", &args)?;
+                let mut generated: Vec<PathBuf> = std::fs::read_dir(&dir)?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<std::io::Result<_>>()?;
+                generated.retain(|path| path.extension().is_some_and(|ext| ext == "cc"));
+                generated.sort();
+                sources.extend(generated);
+            }
+        }
+    }
+    Ok(sources)
+}
+
+/// The command compiling `source` into `object` for a link line of `flavor`
+pub fn compile_command(
+    options: &CxxOptions,
+    flavor: LinkerFlavor,
+    crt: Option<Crt>,
+    source: &Path,
+    object: &Path,
+) -> Vec<String> {
+    let include_dirs = std::iter::once(&options.out_dir).chain(&options.include_dirs);
+    match flavor {
+        LinkerFlavor::Msvc => {
+            let mut command = options.compiler.clone().unwrap_or_else(|| {
+                let crt = crt.unwrap_or(Crt::Md).flag();
+                ["cl", "/nologo", "/c", "/EHsc", "/std:c++17", crt]
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect()
+            });
+            command.extend(include_dirs.map(|dir| format!("/I{}", dir.display())));
+            command.push(format!("/Fo{}", object.display()));
+            command.push(source.display().to_string());
+            command
+        }
+        LinkerFlavor::Gnu => {
+            let mut command = options.compiler.clone().unwrap_or_else(|| {
+                ["c++", "-c", "-std=c++17", "-fPIC"]
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect()
+            });
+            command.extend(include_dirs.map(|dir| format!("-I{}", dir.display())));
+            command.push("-o".to_owned());
+            command.push(object.display().to_string());
+            command.push(source.display().to_string());
+            command
+        }
+    }
+}
+
+/// Compile the generated sources next to themselves and return the objects
+pub fn compile(
+    options: &CxxOptions,
+    flavor: LinkerFlavor,
+    crt: Option<Crt>,
+    sources: &[PathBuf],
+) -> Result<Vec<PathBuf>> {
+    let extension = match flavor {
+        LinkerFlavor::Msvc => "obj",
+        LinkerFlavor::Gnu => "o",
+    };
+    let mut objects = Vec::with_capacity(sources.len());
+    for source in sources {
+        let mut object = source.clone().into_os_string();
+        object.push(".");
+        object.push(extension);
+        let object = PathBuf::from(object);
+        let command = compile_command(options, flavor, crt, source, &object);
+        let (program, args) = command
+            .split_first()
+            .context("The C++ compiler command is empty")?;
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .with_context(|| format!("Couldn't run {} to compile the cxx bridge", program))?;
+        if !output.status.success() {
+            bail!(
+                "Compiling {} failed with `{}`:\n{}{}",
+                source.display(),
+                command.join(" "),
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        objects.push(object);
+    }
+    Ok(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bridge_compile_commands() {
+        let options = CxxOptions {
+            generator: CxxGenerator::Cxx,
+            bridges: vec![PathBuf::from("/game/src/ffi.rs")],
+            out_dir: PathBuf::from("/out/cxx"),
+            include_dirs: vec![PathBuf::from("/game/include")],
+            compiler: None,
+        };
+        let source = generated_path(&options.out_dir, Path::new("/game"), &options.bridges[0]);
+        assert_eq!(source, Path::new("/out/cxx/src/ffi.rs"));
+        let command = compile_command(
+            &options,
+            LinkerFlavor::Msvc,
+            Some(Crt::MdDebug),
+            Path::new("/out/cxx/src/ffi.rs.cc"),
+            Path::new("/out/cxx/src/ffi.rs.cc.obj"),
+        );
+        assert_eq!(
+            command,
+            [
+                "cl",
+                "/nologo",
+                "/c",
+                "/EHsc",
+                "/std:c++17",
+                "/MDd",
+                "/I/out/cxx",
+                "/I/game/include",
+                "/Fo/out/cxx/src/ffi.rs.cc.obj",
+                "/out/cxx/src/ffi.rs.cc"
+            ]
+        );
+
+        let options = CxxOptions {
+            compiler: Some(vec!["clang++".to_owned(), "-O2".to_owned()]),
+            ..options
+        };
+        let command = compile_command(
+            &options,
+            LinkerFlavor::Gnu,
+            None,
+            Path::new("/out/cxx/src/ffi.rs.cc"),
+            Path::new("/out/cxx/src/ffi.rs.cc.o"),
+        );
+        assert_eq!(
+            command,
+            [
+                "clang++",
+                "-O2",
+                "-I/out/cxx",
+                "-I/game/include",
+                "-o",
+                "/out/cxx/src/ffi.rs.cc.o",
+                "/out/cxx/src/ffi.rs.cc"
+            ]
+        );
+    }
+}
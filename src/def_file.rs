@@ -0,0 +1,126 @@
+//! Editing of the module-definition (.def) files rustc generates for the linker
+
+/// Keywords that start a new section of a .def file
+const SECTION_KEYWORDS: &[&str] = &[
+    "LIBRARY",
+    "NAME",
+    "EXPORTS",
+    "HEAPSIZE",
+    "SECTIONS",
+    "STACKSIZE",
+    "STUB",
+    "VERSION",
+];
+
+/// Which exported symbols to keep in the def file
+#[derive(Debug, Default)]
+pub struct ExportFilter {
+    /// If not empty, only symbols matching one of these are exported
+    pub include: Vec<glob::Pattern>,
+    /// Symbols matching one of these aren't exported
+    pub exclude: Vec<glob::Pattern>,
+}
+
+impl ExportFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn keeps(&self, symbol: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(symbol)))
+            && !self.exclude.iter().any(|pattern| pattern.matches(symbol))
+    }
+
+    /// Remove the entries of the EXPORTS section that don't pass the filter
+    pub fn apply(&self, def_file: &str) -> String {
+        let mut filtered = String::with_capacity(def_file.len());
+        let mut in_exports = false;
+        for line in def_file.split_inclusive('\n') {
+            let first_token = line.split_whitespace().next();
+            if let Some(keyword) = first_token.filter(|token| SECTION_KEYWORDS.contains(token)) {
+                in_exports = keyword == "EXPORTS";
+            } else if let Some(entry) = first_token.filter(|_| in_exports) {
+                // entries look like `name[=internal_name] [@ordinal] [NONAME] [DATA] [PRIVATE]`
+                let symbol = entry.split('=').next().unwrap_or(entry);
+                if !self.keeps(symbol) {
+                    continue;
+                }
+            }
+            filtered.push_str(line);
+        }
+        filtered
+    }
+}
+
+/// The symbols an export list rustc generates exports: the EXPORTS of a .def file, the `global`
+/// entries of a GNU version script, or the symbols of an ld64 exported symbols list, without
+/// their leading underscore
+pub fn exported_symbols(contents: &str) -> Vec<String> {
+    let mut symbols = Vec::new();
+    if contents.lines().any(|line| line.trim() == "EXPORTS") {
+        let mut in_exports = false;
+        for line in contents.lines() {
+            let first_token = line.split_whitespace().next();
+            if let Some(keyword) = first_token.filter(|token| SECTION_KEYWORDS.contains(token)) {
+                in_exports = keyword == "EXPORTS";
+            } else if let Some(entry) = first_token.filter(|_| in_exports) {
+                symbols.push(entry.split('=').next().unwrap_or(entry).to_owned());
+            }
+        }
+    } else if contents.contains("global:") {
+        let mut in_global = false;
+        for line in contents.lines().map(str::trim) {
+            match line {
+                "global:" => in_global = true,
+                "local:" => in_global = false,
+                _ if in_global && !line.is_empty() => {
+                    symbols.push(line.trim_end_matches(';').to_owned());
+                }
+                _ => {}
+            }
+        }
+    } else {
+        symbols.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| line.strip_prefix('_').unwrap_or(line).to_owned()),
+        );
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_exports() {
+        let def_file = "LIBRARY\nEXPORTS\n    ue_init\n    ue_tick @2\n    internal_helper\n    ue_debug_dump=impl_dump\n";
+        let filter = ExportFilter {
+            include: vec![glob::Pattern::new("ue_*").unwrap()],
+            exclude: vec![glob::Pattern::new("ue_debug_*").unwrap()],
+        };
+        assert_eq!(
+            filter.apply(def_file),
+            "LIBRARY\nEXPORTS\n    ue_init\n    ue_tick @2\n"
+        );
+    }
+
+    #[test]
+    fn export_lists() {
+        let def_file =
+            "LIBRARY\nEXPORTS\n    game_init\n    tick @2\n    game_debug=impl_dump DATA\n";
+        assert_eq!(
+            exported_symbols(def_file),
+            ["game_init", "tick", "game_debug"]
+        );
+        let version_script = "{\n  global:\n    game_init;\n    tick;\n\n  local:\n    *;\n};\n";
+        assert_eq!(exported_symbols(version_script), ["game_init", "tick"]);
+        assert_eq!(
+            exported_symbols("_game_init\n_tick\n"),
+            ["game_init", "tick"]
+        );
+    }
+}
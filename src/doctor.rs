@@ -0,0 +1,290 @@
+//! The checks of the doctor subcommand, which diagnose the toolchain and environment up front
+//! instead of letting the build fail deep inside cargo or the tool
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// The result of one check, with how to fix it unless it passed
+#[derive(Debug)]
+pub struct Check {
+    pub status: Status,
+    pub message: String,
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(message: impl Into<String>) -> Check {
+        Check {
+            status: Status::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn failed(status: Status, message: impl Into<String>, fix: impl Into<String>) -> Check {
+        Check {
+            status,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DoctorOptions {
+    /// Targets that have to be installed and linkable, besides the host
+    pub targets: Vec<String>,
+    /// Linker to look for instead of the targets' default ones
+    pub linker: Option<String>,
+    /// Output files the tool will have to write
+    pub output_paths: Vec<PathBuf>,
+}
+
+/// The stdout of a command, if it ran successfully
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Find a program on PATH, like the OS would when running it
+pub fn find_in_path(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_owned());
+    }
+    let extensions: &[&str] = if cfg!(windows) {
+        &["", ".exe", ".cmd", ".bat"]
+    } else {
+        &[""]
+    };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .flat_map(|dir| {
+            extensions
+                .iter()
+                .map(move |ext| dir.join(format!("{}{}", program, ext)))
+        })
+        .find(|candidate| candidate.is_file())
+}
+
+fn check_cargo() -> Check {
+    match command_output("cargo", &["--version"]) {
+        Some(version) => Check::ok(version.trim()),
+        None => Check::failed(
+            Status::Error,
+            "cargo wasn't found on PATH",
+            "Install Rust from https://rustup.rs and make sure cargo's bin directory (~/.cargo/bin) is on PATH",
+        ),
+    }
+}
+
+/// The rustup checks, which are skipped without rustup
+fn check_rustup(targets: &[String]) -> Vec<Check> {
+    let Some(version) = command_output("rustup", &["--version"]) else {
+        return vec![Check::failed(
+            Status::Warning,
+            "rustup wasn't found on PATH, so the nightly toolchain, components and targets can't be checked",
+            "Install Rust with rustup from https://rustup.rs",
+        )];
+    };
+    let mut checks = vec![Check::ok(version.lines().next().unwrap_or_default().trim())];
+
+    let toolchains = command_output("rustup", &["toolchain", "list"]).unwrap_or_default();
+    checks.push(
+        if toolchains.lines().any(|line| line.starts_with("nightly")) {
+            Check::ok("a nightly toolchain is installed")
+        } else {
+            Check::failed(
+                Status::Warning,
+                "no nightly toolchain is installed, which --expand and custom target specs need",
+                "rustup toolchain install nightly",
+            )
+        },
+    );
+
+    let components =
+        command_output("rustup", &["component", "list", "--installed"]).unwrap_or_default();
+    checks.push(if components.lines().any(|line| line.starts_with("rust-src")) {
+        Check::ok("rust-src is installed")
+    } else {
+        Check::failed(
+            Status::Warning,
+            "rust-src isn't installed, which -Zbuild-std needs to build the standard library for custom target specs",
+            "rustup component add rust-src",
+        )
+    });
+
+    let installed =
+        command_output("rustup", &["target", "list", "--installed"]).unwrap_or_default();
+    for target in targets {
+        checks.push(if installed.lines().any(|line| line.trim() == target) {
+            Check::ok(format!("target {} is installed", target))
+        } else {
+            Check::failed(
+                Status::Error,
+                format!("target {} isn't installed", target),
+                format!("rustup target add {}", target),
+            )
+        });
+    }
+    checks
+}
+
+/// The visual studio installer's tool locating the MSVC build tools, which rustc finds link.exe
+/// with when it isn't on PATH
+fn vswhere() -> Option<PathBuf> {
+    let program_files = std::env::var_os("ProgramFiles(x86)")?;
+    let vswhere = Path::new(&program_files).join("Microsoft Visual Studio/Installer/vswhere.exe");
+    vswhere.is_file().then_some(vswhere)
+}
+
+fn check_linker(target: &str, linker: Option<&str>) -> Check {
+    let msvc = target.ends_with("-msvc");
+    let linker = linker.unwrap_or(if msvc { "link.exe" } else { "cc" });
+    if let Some(path) = find_in_path(linker) {
+        return Check::ok(format!("linker for {}: {}", target, path.display()));
+    }
+    if msvc && vswhere().is_some() {
+        return Check::ok(format!(
+            "linker for {}: link.exe, found through the Visual Studio installer",
+            target
+        ));
+    }
+    let fix = if msvc {
+        "Install the Visual Studio Build Tools with the \"Desktop development with C++\" workload, or run from a developer command prompt"
+    } else {
+        "Install a C toolchain (e.g. build-essential, the Xcode command line tools or MinGW-w64), or pass the linker to use with --linker"
+    };
+    Check::failed(
+        Status::Error,
+        format!("linker {} for {} wasn't found", linker, target),
+        fix,
+    )
+}
+
+/// Whether Windows allows paths longer than MAX_PATH, which UE's deep intermediate
+/// directories and cargo's target directories inside them easily exceed
+fn check_long_paths() -> Option<Check> {
+    if !cfg!(windows) {
+        return None;
+    }
+    let output = command_output(
+        "reg",
+        &[
+            "query",
+            r"HKLM\SYSTEM\CurrentControlSet\Control\FileSystem",
+            "/v",
+            "LongPathsEnabled",
+        ],
+    )
+    .unwrap_or_default();
+    Some(if output.contains("0x1") {
+        Check::ok("long paths are enabled")
+    } else {
+        Check::failed(
+            Status::Warning,
+            "long paths aren't enabled, so paths longer than 260 characters will fail to build",
+            r"Set HKLM\SYSTEM\CurrentControlSet\Control\FileSystem\LongPathsEnabled to 1 (as administrator: reg add HKLM\SYSTEM\CurrentControlSet\Control\FileSystem /v LongPathsEnabled /t REG_DWORD /d 1 /f), or move the project to a shorter path",
+        )
+    })
+}
+
+/// Whether an output file can be written: its directory has to exist, as the tool doesn't
+/// create it, and be writable, and the file mustn't be read only
+pub fn check_output_path(path: &Path) -> Check {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        return Check::failed(
+            Status::Error,
+            format!("the directory of {} doesn't exist", path.display()),
+            format!("Create {} before running the tool", dir.display()),
+        );
+    }
+    let read_only = |path: &Path| {
+        std::fs::metadata(path).is_ok_and(|metadata| metadata.permissions().readonly())
+    };
+    if read_only(path) {
+        return Check::failed(
+            Status::Error,
+            format!("{} is read only", path.display()),
+            "Make it writable, e.g. check it out if it's under source control, or better, don't check generated files in",
+        );
+    }
+    let probe = dir.join(format!(
+        ".unreal_rust_compile_doctor_{}",
+        rand::random::<u32>()
+    ));
+    match std::fs::write(&probe, "") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            Check::ok(format!("{} is writable", path.display()))
+        }
+        Err(err) => Check::failed(
+            Status::Error,
+            format!("{} can't be written to: {}", dir.display(), err),
+            "Check the directory's permissions",
+        ),
+    }
+}
+
+pub fn run_checks(options: &DoctorOptions) -> Vec<Check> {
+    let mut checks = vec![check_cargo()];
+    checks.extend(check_rustup(&options.targets));
+    let host = command_output("rustc", &["-vV"]).and_then(|version| {
+        version
+            .lines()
+            .find_map(|line| line.strip_prefix("host: ").map(str::to_owned))
+    });
+    let link_targets = if options.targets.is_empty() {
+        host.into_iter().collect()
+    } else {
+        options.targets.clone()
+    };
+    for target in &link_targets {
+        checks.push(check_linker(target, options.linker.as_deref()));
+    }
+    checks.extend(check_long_paths());
+    for path in &options.output_paths {
+        checks.push(check_output_path(path));
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_path_checks() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        assert_eq!(
+            check_output_path(&dir.join("linker.rsp")).status,
+            Status::Ok
+        );
+        let missing = check_output_path(&dir.join("missing/linker.rsp"));
+        assert_eq!(missing.status, Status::Error);
+        assert!(missing.fix.is_some());
+
+        let read_only = dir.join("lib.rsp");
+        std::fs::write(&read_only, "").unwrap();
+        let mut permissions = std::fs::metadata(&read_only).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&read_only, permissions).unwrap();
+        assert_eq!(check_output_path(&read_only).status, Status::Error);
+        // the probe file is removed again
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,26 @@
+/// The status codes the tool exits with, so that build systems can react to each kind of
+/// failure differently instead of treating every nonzero exit the same
+///
+/// | Code | Meaning |
+/// |------|---------|
+/// | 0 | Success |
+/// | 1 | Internal error, e.g. an I/O error, a panic or cargo failing to load the workspace |
+/// | 2 | cargo failed to compile the crate, no usable link line was produced, a `batch` module failed, or the header and exports differ |
+/// | 3 | Invalid command line |
+/// | 4 | The header changed and `--exit-code-on-change` was passed |
+/// | 5 | The build environment isn't set up: `doctor` found a problem, or the pinned toolchain is missing or failed to install |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    InternalError = 1,
+    CompileFailure = 2,
+    UsageError = 3,
+    HeaderChanged = 4,
+    EnvironmentError = 5,
+}
+
+impl ExitCode {
+    pub fn exit(self) -> ! {
+        std::process::exit(self as i32)
+    }
+}
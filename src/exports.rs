@@ -0,0 +1,164 @@
+//! Synthesizing the def file of crates rustc doesn't link, e.g. staticlib-only ones, from the
+//! symbols their own objects in the staticlib define
+
+use crate::def_file::ExportFilter;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const DEFAULT_NM: &str = "nm";
+
+/// A symbol the crate exports, i.e. a `#[no_mangle]` function or static
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    /// Whether it's a static rather than a function
+    pub data: bool,
+}
+
+/// The staticlib among the artifacts of cargo's `--message-format json` output, and the name of
+/// the crate it was built from
+pub fn staticlib_artifact(stdout: &str) -> Option<(PathBuf, String)> {
+    stdout.lines().rev().find_map(|line| {
+        let message: serde_json::Value = serde_json::from_str(line).ok()?;
+        if message["reason"] != "compiler-artifact" {
+            return None;
+        }
+        let staticlib = message["target"]["crate_types"]
+            .as_array()?
+            .iter()
+            .any(|crate_type| crate_type == "staticlib");
+        if !staticlib {
+            return None;
+        }
+        let filename = message["filenames"]
+            .as_array()?
+            .iter()
+            .filter_map(|filename| filename.as_str())
+            .find(|filename| filename.ends_with(".a") || filename.ends_with(".lib"))?;
+        let crate_name = message["target"]["name"].as_str()?.replace('-', "_");
+        Some((PathBuf::from(filename), crate_name))
+    })
+}
+
+/// Parse the symbols of `nm -A -P -g --defined-only`, lines like
+/// `libfoo.a[foo-1a2b.foo.3c4d-cgu.0.rcgu.o]: ue_init T 0 1a`, keeping the unmangled ones of
+/// `crate_name`'s own objects. The others are the standard library's and its dependencies'.
+fn parse_nm_exports(output: &str, crate_name: &str) -> Vec<Export> {
+    // older rustcs name the objects `<crate>-<hash>.<crate>.<hash>-cgu.0.rcgu.o`, newer ones
+    // `<crate>.<hash>.<hash>.rcgu.o`
+    let own_members = [format!("[{}-", crate_name), format!("[{}.", crate_name)];
+    let mut exports = Vec::new();
+    for line in output.lines() {
+        let Some((file, symbol)) = line.split_once("]: ") else {
+            continue;
+        };
+        if !own_members.iter().any(|member| file.contains(member)) {
+            continue;
+        }
+        let mut fields = symbol.split_whitespace();
+        let (Some(name), Some(kind)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        // mangled Rust symbols, and the compiler's own like `__rustc_debug_gdb_scripts_section__`
+        if name.starts_with("_ZN") || name.starts_with("_R") || name.starts_with("__") {
+            continue;
+        }
+        let data = match kind {
+            "T" => false,
+            "D" | "B" | "R" => true,
+            _ => continue,
+        };
+        if !exports.iter().any(|export: &Export| export.name == name) {
+            exports.push(Export {
+                name: name.to_owned(),
+                data,
+            });
+        }
+    }
+    exports
+}
+
+/// The symbols `crate_name`'s objects in `staticlib` export, according to `nm`, which has to
+/// read the staticlib's format (llvm-nm reads both COFF and ELF archives)
+pub fn staticlib_exports(nm: &str, staticlib: &Path, crate_name: &str) -> Result<Vec<Export>> {
+    let output = Command::new(nm)
+        .args(["-A", "-P", "-g", "--defined-only"])
+        .arg(staticlib)
+        .output()
+        .with_context(|| format!("Couldn't run {}", nm))?;
+    if !output.status.success() {
+        bail!(
+            "{} failed with {}: {}",
+            nm,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(parse_nm_exports(
+        &String::from_utf8_lossy(&output.stdout),
+        crate_name,
+    ))
+}
+
+/// A .def file exporting the exports passing `filter`
+pub fn def_file(exports: &[Export], filter: &ExportFilter) -> String {
+    let mut def_file = String::from("EXPORTS\n");
+    for export in exports.iter().filter(|export| filter.keeps(&export.name)) {
+        let data = if export.data { " DATA" } else { "" };
+        def_file.push_str(&format!("    {}{}\n", export.name, data));
+    }
+    def_file
+}
+
+/// A GNU version script exporting the exports passing `filter`, and hiding everything else like
+/// the one rustc generates for cdylibs
+pub fn version_script(exports: &[Export], filter: &ExportFilter) -> String {
+    let mut script = String::from("{\n  global:\n");
+    for export in exports.iter().filter(|export| filter.keeps(&export.name)) {
+        script.push_str(&format!("    {};\n", export.name));
+    }
+    script.push_str("  local:\n    *;\n};\n");
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nm_exports() {
+        let output = "\
+libgame.a[game-1a2b.game.3c4d-cgu.0.rcgu.o]: __rustc_debug_gdb_scripts_section__ V 0 22
+libgame.a[game-1a2b.game.3c4d-cgu.0.rcgu.o]: ue_init T 0 1a
+libgame.a[game-1a2b.game.3c4d-cgu.0.rcgu.o]: UE_VERSION R 0 4
+libgame.a[game-1a2b.game.3c4d-cgu.0.rcgu.o]: _ZN4game6helper17h0123456789abcdefE T 20 8
+libgame.a[game_core-5e6f.game_core.7a8b-cgu.0.rcgu.o]: core_init T 0 1a
+libgame.a[std-9c0d.std.1e2f-cgu.0.rcgu.o]: rust_begin_unwind T 0 40
+libgame.a[game.5g6h.7i8j.rcgu.o]: ue_tick T 0 12
+libgame.a[game_core.9k0l.1m2n.rcgu.o]: core_tick T 0 12
+";
+        let exports = parse_nm_exports(output, "game");
+        assert_eq!(
+            exports,
+            vec![
+                Export {
+                    name: "ue_init".to_owned(),
+                    data: false
+                },
+                Export {
+                    name: "UE_VERSION".to_owned(),
+                    data: true
+                },
+                Export {
+                    name: "ue_tick".to_owned(),
+                    data: false
+                },
+            ]
+        );
+        assert_eq!(
+            def_file(&exports, &ExportFilter::default()),
+            "EXPORTS\n    ue_init\n    UE_VERSION DATA\n    ue_tick\n"
+        );
+    }
+}
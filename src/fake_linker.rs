@@ -0,0 +1,254 @@
+//! Capturing the link line without `--print link-args`: rustc is told to link with the tool
+//! itself, which records the args it's called with instead of linking
+
+use crate::link_args::{parse_posix_quotes, LinkerFlavor};
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+/// The file the fake linker appends the args of each invocation to, as a JSON array per line.
+/// The tool runs as the fake linker when this is set.
+pub const RECORD_ENV_VAR: &str = "UNREAL_RUST_COMPILE_FAKE_LINKER";
+
+/// The flavor of the linker rustc thinks it runs, which determines the response file syntax
+pub const FLAVOR_ENV_VAR: &str = "UNREAL_RUST_COMPILE_FAKE_LINKER_FLAVOR";
+
+/// The directory the fake linker copies the temporary inputs of the link line to, so that they
+/// survive rustc cleaning up without `-C save-temps`
+pub const INPUTS_DIR_ENV_VAR: &str = "UNREAL_RUST_COMPILE_FAKE_LINKER_INPUTS";
+
+/// Options whose value is an export list rustc writes to its temporary directory
+const EXPORT_LIST_OPTIONS: &[&str] = &[
+    "/DEF:",
+    "-Wl,--version-script=",
+    "-Wl,-exported_symbols_list,",
+];
+
+/// Copy the object files and export list of the link line to `dir` and point the args at the
+/// copies. rustc deletes its temporary files and the crate's objects once linking is done.
+fn preserve_inputs(args: &mut [String], dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let mut copied_names = HashSet::new();
+    for arg in args {
+        let (option, path) = EXPORT_LIST_OPTIONS
+            .iter()
+            .find_map(|option| Some((*option, arg.strip_prefix(option)?)))
+            .unwrap_or(("", arg.as_str()));
+        let is_input =
+            !option.is_empty() || [".o", ".obj", ".def"].iter().any(|ext| path.ends_with(ext));
+        if !is_input || !Path::new(path).is_file() {
+            continue;
+        }
+        let file_name = Path::new(path)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let mut copy_name = file_name.to_string();
+        let mut suffix = 1;
+        while !copied_names.insert(copy_name.clone()) {
+            copy_name = format!("{}-{}", suffix, file_name);
+            suffix += 1;
+        }
+        let copy_path = dir.join(copy_name);
+        std::fs::copy(path, &copy_path)
+            .with_context(|| format!("Couldn't copy {} to {}", path, copy_path.display()))?;
+        *arg = format!("{}{}", option, copy_path.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// The args of a response file rustc wrote because the command line was too long. Each arg is
+/// on its own line, double quoted for MSVC linkers and backslash escaped for GNU ones.
+fn response_file_args(contents: &str, flavor: LinkerFlavor) -> Vec<String> {
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(contents);
+    contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| match flavor {
+            LinkerFlavor::Msvc => line
+                .strip_prefix('"')
+                .and_then(|line| line.strip_suffix('"'))
+                .unwrap_or(line)
+                .replace("\\\"", "\""),
+            LinkerFlavor::Gnu => parse_posix_quotes(line).concat(),
+        })
+        .collect()
+}
+
+fn read_response_file(path: &str) -> Result<String> {
+    let bytes = std::fs::read(path).with_context(|| format!("Couldn't read {}", path))?;
+    // link.exe response files may be UTF-16
+    if let Some(utf16) = bytes.strip_prefix(&[0xff, 0xfe]) {
+        let units: Vec<u16> = utf16
+            .as_chunks::<2>()
+            .0
+            .iter()
+            .map(|c| u16::from_le_bytes(*c))
+            .collect();
+        return Ok(String::from_utf16_lossy(&units));
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The paths the linker would have written: the artifact and its import library
+fn outputs(args: &[String], flavor: LinkerFlavor) -> Vec<&str> {
+    let mut outputs = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match flavor {
+            LinkerFlavor::Msvc => {
+                let upper = arg.to_ascii_uppercase();
+                for option in ["/OUT:", "-OUT:", "/IMPLIB:", "-IMPLIB:"] {
+                    if upper.starts_with(option) {
+                        outputs.push(&arg[option.len()..]);
+                    }
+                }
+            }
+            LinkerFlavor::Gnu => {
+                if arg == "-o" {
+                    outputs.extend(args.next().map(String::as_str));
+                } else if let Some(implib) = arg.strip_prefix("-Wl,--out-implib=") {
+                    outputs.push(implib);
+                }
+            }
+        }
+    }
+    outputs
+}
+
+/// Record the args of this invocation, with response files expanded and temporary inputs
+/// preserved. The outputs are created empty, because cargo expects to find the artifacts.
+pub fn run(record: &Path) -> Result<()> {
+    let flavor = match std::env::var(FLAVOR_ENV_VAR).as_deref() {
+        Ok("msvc") => LinkerFlavor::Msvc,
+        _ => LinkerFlavor::Gnu,
+    };
+    let mut args = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.strip_prefix('@') {
+            Some(path) => args.extend(response_file_args(&read_response_file(path)?, flavor)),
+            None => args.push(arg),
+        }
+    }
+    if let Some(dir) = std::env::var_os(INPUTS_DIR_ENV_VAR) {
+        preserve_inputs(&mut args, Path::new(&dir))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(record)
+        .with_context(|| format!("Couldn't open {}", record.display()))?;
+    writeln!(file, "{}", serde_json::to_string(&args)?)?;
+    for output in outputs(&args, flavor) {
+        std::fs::write(output, "")?;
+    }
+    Ok(())
+}
+
+/// The link lines of the recorded invocations, formatted like `--print link-args` prints them
+/// for a linker named `linker`
+pub fn recorded_link_lines(
+    record: &str,
+    linker: &str,
+    flavor: LinkerFlavor,
+) -> Result<Vec<String>> {
+    let quote = |arg: &str| match flavor {
+        LinkerFlavor::Msvc => format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\"")),
+        LinkerFlavor::Gnu => format!("'{}'", arg.replace('\'', r"'\''")),
+    };
+    let mut link_lines = Vec::new();
+    for line in record.lines() {
+        let args: Vec<String> = serde_json::from_str(line)?;
+        let quoted: Vec<String> = std::iter::once(linker)
+            .chain(args.iter().map(String::as_str))
+            .map(quote)
+            .collect();
+        link_lines.push(quoted.join(" "));
+    }
+    Ok(link_lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_args::tokenize_link_line;
+    use std::path::PathBuf;
+
+    #[test]
+    fn response_files_and_recorded_lines() {
+        let msvc =
+            "\"/OUT:C:\\t\\game.dll\"\n\"/LIBPATH:C:\\Program Files\\x\"\n\"a \\\"b\\\".o\"\n";
+        let args = response_file_args(msvc, LinkerFlavor::Msvc);
+        assert_eq!(
+            args,
+            vec![
+                r"/OUT:C:\t\game.dll",
+                r"/LIBPATH:C:\Program Files\x",
+                r#"a "b".o"#
+            ]
+        );
+        assert_eq!(outputs(&args, LinkerFlavor::Msvc), vec![r"C:\t\game.dll"]);
+        let record = serde_json::to_string(&args).unwrap();
+        let lines = recorded_link_lines(&record, "link.exe", LinkerFlavor::Msvc).unwrap();
+        assert_eq!(tokenize_link_line(&lines[0])[1..], args[..]);
+
+        let gnu = "-o\n/t/lib\\ game.so\n-Wl,--out-implib=/t/game.dll.a\nC:\\\\t\\\\a.o\n";
+        let args = response_file_args(gnu, LinkerFlavor::Gnu);
+        assert_eq!(
+            args,
+            vec![
+                "-o",
+                "/t/lib game.so",
+                "-Wl,--out-implib=/t/game.dll.a",
+                r"C:\t\a.o"
+            ]
+        );
+        assert_eq!(
+            outputs(&args, LinkerFlavor::Gnu),
+            vec!["/t/lib game.so", "/t/game.dll.a"]
+        );
+        let record = serde_json::to_string(&args).unwrap();
+        let lines = recorded_link_lines(&record, "cc", LinkerFlavor::Gnu).unwrap();
+        assert_eq!(tokenize_link_line(&lines[0])[1..], args[..]);
+    }
+
+    #[test]
+    fn preserved_inputs() {
+        let scratch = tempfile::tempdir().unwrap();
+        let dir = scratch.path();
+        let temp = dir.join("rustcXyZ");
+        std::fs::create_dir_all(&temp).unwrap();
+        for name in ["symbols.o", "list", "lib.def"] {
+            std::fs::write(temp.join(name), name).unwrap();
+        }
+        std::fs::create_dir_all(dir.join("deps")).unwrap();
+        std::fs::write(dir.join("deps/symbols.o"), "crate").unwrap();
+        let path = |p: PathBuf| p.to_string_lossy().into_owned();
+        let mut args = vec![
+            format!("-Wl,--version-script={}", path(temp.join("list"))),
+            path(temp.join("symbols.o")),
+            path(dir.join("deps/symbols.o")),
+            format!("/DEF:{}", path(temp.join("lib.def"))),
+            "-lc".to_owned(),
+            path(dir.join("missing.o")),
+        ];
+        let inputs = dir.join("inputs");
+        preserve_inputs(&mut args, &inputs).unwrap();
+        assert_eq!(
+            args,
+            vec![
+                format!("-Wl,--version-script={}", path(inputs.join("list"))),
+                path(inputs.join("symbols.o")),
+                path(inputs.join("1-symbols.o")),
+                format!("/DEF:{}", path(inputs.join("lib.def"))),
+                "-lc".to_owned(),
+                path(dir.join("missing.o")),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(inputs.join("1-symbols.o")).unwrap(),
+            "crate"
+        );
+    }
+}
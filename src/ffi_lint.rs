@@ -0,0 +1,476 @@
+//! gen-bindings' `--ffi-lint`: a check of the signatures of the crate's exported functions and
+//! statics, and of the types they use by value, for what C can't represent. cbindgen writes
+//! declarations for those anyway, which then crash at runtime instead of failing to compile.
+
+use crate::crate_items::for_each_module;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// What `--ffi-lint` does about problems
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    Warn,
+    Deny,
+}
+
+impl LintLevel {
+    pub fn from_arg(value: Option<&str>) -> LintLevel {
+        match value {
+            Some("warn") => LintLevel::Warn,
+            _ => LintLevel::Deny,
+        }
+    }
+}
+
+/// Integer types whose layout C has no (stable) equivalent for
+const WIDE_INTEGERS: &[&str] = &["i128", "u128"];
+
+/// Standard library types that aren't FFI-safe by value, as the reason why
+const RUST_TYPES: &[(&str, &str)] = &[
+    ("String", "pass a *const c_char (e.g. from CString) instead"),
+    ("str", "pass a pointer and a length instead"),
+    ("Vec", "pass a pointer and a length instead"),
+    ("VecDeque", "pass a pointer and a length instead"),
+    ("HashMap", "wrap it in an opaque type passed by pointer"),
+    ("HashSet", "wrap it in an opaque type passed by pointer"),
+    ("BTreeMap", "wrap it in an opaque type passed by pointer"),
+    ("BTreeSet", "wrap it in an opaque type passed by pointer"),
+    ("Rc", "pass a pointer from Rc::into_raw instead"),
+    ("Arc", "pass a pointer from Arc::into_raw instead"),
+    ("Cow", "pass a pointer and a length instead"),
+    ("Result", "return an error code instead"),
+    (
+        "char",
+        "Rust's char is a 32-bit code point, use u32 or c_char instead",
+    ),
+];
+
+/// Types Option is FFI-safe around, thanks to the null pointer optimization
+const NULLABLE_TYPES: &[&str] = &[
+    "Box",
+    "NonNull",
+    "NonZeroU8",
+    "NonZeroU16",
+    "NonZeroU32",
+    "NonZeroU64",
+    "NonZeroUsize",
+    "NonZeroI8",
+    "NonZeroI16",
+    "NonZeroI32",
+    "NonZeroI64",
+    "NonZeroIsize",
+    "NonZero",
+];
+
+/// Whether the item is exported under its own or a given name
+pub fn is_exported(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        let path = attr.path();
+        if path.is_ident("no_mangle") || path.is_ident("export_name") {
+            return true;
+        }
+        // `#[unsafe(no_mangle)]` since edition 2024
+        match &attr.meta {
+            syn::Meta::List(list) if path.is_ident("unsafe") => {
+                let tokens = list.tokens.to_string();
+                tokens.starts_with("no_mangle") || tokens.starts_with("export_name")
+            }
+            _ => false,
+        }
+    })
+}
+
+/// The `#[repr]` of the item, e.g. `["C"]` or `["u8"]`
+fn reprs(attrs: &[syn::Attribute]) -> Vec<String> {
+    let mut reprs = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            if let Some(ident) = meta.path.get_ident() {
+                reprs.push(ident.to_string());
+            }
+            // skip the arguments of align(N) and packed(N)
+            if meta.input.peek(syn::token::Paren) {
+                let _content;
+                syn::parenthesized!(_content in meta.input);
+            }
+            Ok(())
+        });
+    }
+    reprs
+}
+
+/// A struct, enum, union or type alias of the crate
+enum LocalType {
+    Struct {
+        reprs: Vec<String>,
+        fields: Vec<(String, syn::Type)>,
+    },
+    Enum {
+        reprs: Vec<String>,
+        fields: Vec<(String, syn::Type)>,
+    },
+    Alias(syn::Type),
+}
+
+fn named_fields(fields: &syn::Fields) -> Vec<(String, syn::Type)> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let name = field
+                .ident
+                .as_ref()
+                .map_or_else(|| index.to_string(), |ident| ident.to_string());
+            (name, field.ty.clone())
+        })
+        .collect()
+}
+
+struct Linter {
+    types: HashMap<String, LocalType>,
+    /// Local types whose fields were checked already
+    checked: HashSet<String>,
+    problems: Vec<String>,
+}
+
+impl Linter {
+    fn problem(&mut self, location: &str, message: String) {
+        self.problems.push(format!("{}: {}", location, message));
+    }
+
+    /// Check a type of a signature (`in_signature`) or of a field of a type used by value
+    fn check_type(&mut self, ty: &syn::Type, location: &str, what: &str, in_signature: bool) {
+        match ty {
+            syn::Type::Paren(inner) => self.check_type(&inner.elem, location, what, in_signature),
+            syn::Type::Group(inner) => self.check_type(&inner.elem, location, what, in_signature),
+            syn::Type::Reference(reference) => self.check_pointee(&reference.elem, location, what),
+            syn::Type::Ptr(pointer) => self.check_pointee(&pointer.elem, location, what),
+            syn::Type::Slice(_) => self.problem(
+                location,
+                format!("{} is a slice, pass a pointer and a length instead", what),
+            ),
+            syn::Type::Array(array) => {
+                if in_signature {
+                    self.problem(
+                        location,
+                        format!(
+                            "{} is an array, which C passes by pointer. Wrap it in a #[repr(C)] struct or pass a pointer",
+                            what
+                        ),
+                    );
+                } else {
+                    self.check_type(&array.elem, location, what, false);
+                }
+            }
+            syn::Type::TraitObject(_) | syn::Type::ImplTrait(_) => self.problem(
+                location,
+                format!("{} is a trait object, which has no C equivalent", what),
+            ),
+            syn::Type::Tuple(tuple) if !tuple.elems.is_empty() => self.problem(
+                location,
+                format!("{} is a tuple, use a #[repr(C)] struct instead", what),
+            ),
+            syn::Type::BareFn(function) => {
+                if function.abi.is_none() {
+                    self.problem(
+                        location,
+                        format!(
+                            "{} is a function pointer with the Rust ABI, declare it extern \"C\"",
+                            what
+                        ),
+                    );
+                }
+            }
+            syn::Type::Path(path) => self.check_path(path, location, what, in_signature),
+            _ => {}
+        }
+    }
+
+    /// Check the type a pointer or reference points to: only fat pointers aren't FFI-safe
+    fn check_pointee(&mut self, pointee: &syn::Type, location: &str, what: &str) {
+        let fat = match pointee {
+            syn::Type::Slice(_) => Some("a slice"),
+            syn::Type::TraitObject(_) => Some("a trait object"),
+            syn::Type::Path(path) if path.qself.is_none() && path.path.is_ident("str") => {
+                Some("a str")
+            }
+            syn::Type::Path(path) => match self.types.get(&last_ident(path)) {
+                Some(LocalType::Alias(alias)) => {
+                    let alias = alias.clone();
+                    return self.check_pointee(&alias, location, what);
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(fat) = fat {
+            self.problem(
+                location,
+                format!(
+                    "{} is a pointer to {}, which is twice the size of a C pointer. Pass a pointer and a length instead",
+                    what, fat
+                ),
+            );
+        }
+    }
+
+    fn check_path(&mut self, path: &syn::TypePath, location: &str, what: &str, in_signature: bool) {
+        let name = last_ident(path);
+        let arguments = path.path.segments.last().map(|segment| &segment.arguments);
+        let first_argument = match arguments {
+            Some(syn::PathArguments::AngleBracketed(arguments)) => {
+                arguments.args.iter().find_map(|argument| match argument {
+                    syn::GenericArgument::Type(ty) => Some(ty.clone()),
+                    _ => None,
+                })
+            }
+            _ => None,
+        };
+        if WIDE_INTEGERS.contains(&name.as_str()) {
+            self.problem(
+                location,
+                format!("{} is a {}, which has no stable C ABI", what, name),
+            );
+            return;
+        }
+        if let Some((_, reason)) = RUST_TYPES.iter().find(|(rust_type, _)| *rust_type == name) {
+            self.problem(location, format!("{} is a {}, {}", what, name, reason));
+            return;
+        }
+        match name.as_str() {
+            "Box" => {
+                if let Some(boxed) = first_argument {
+                    self.check_pointee(&boxed, location, what);
+                }
+                return;
+            }
+            "Option" => {
+                let nullable = match &first_argument {
+                    Some(syn::Type::Reference(_)) | Some(syn::Type::BareFn(_)) => true,
+                    Some(syn::Type::Path(inner)) => {
+                        NULLABLE_TYPES.contains(&last_ident(inner).as_str())
+                    }
+                    _ => false,
+                };
+                if !nullable {
+                    self.problem(
+                        location,
+                        format!(
+                            "{} is an Option, which is only FFI-safe around references, Box, NonNull, NonZero integers and extern \"C\" function pointers",
+                            what
+                        ),
+                    );
+                }
+                return;
+            }
+            _ => {}
+        }
+        self.check_local(&name, location, what, in_signature);
+    }
+
+    /// Check that a local type used by value has a C layout, and so do its fields
+    fn check_local(&mut self, name: &str, location: &str, what: &str, in_signature: bool) {
+        let (reprs, fields, is_enum) = match self.types.get(name) {
+            None => return,
+            Some(LocalType::Alias(alias)) => {
+                let alias = alias.clone();
+                return self.check_type(&alias, location, what, in_signature);
+            }
+            Some(LocalType::Struct { reprs, fields }) => (reprs, fields, false),
+            Some(LocalType::Enum { reprs, fields }) => (reprs, fields, true),
+        };
+        let c_layout = reprs.iter().any(|repr| {
+            repr == "C"
+                || repr == "transparent"
+                || (is_enum && (repr.starts_with('u') || repr.starts_with('i')))
+        });
+        let fields = fields.clone();
+        if !c_layout {
+            self.problem(
+                location,
+                format!(
+                    "{} is {}, which has no #[repr(C)], so its layout is unspecified",
+                    what, name
+                ),
+            );
+        }
+        if !self.checked.insert(name.to_owned()) {
+            return;
+        }
+        for (field, ty) in &fields {
+            self.check_type(ty, name, &format!("field `{}`", field), false);
+        }
+    }
+}
+
+fn last_ident(path: &syn::TypePath) -> String {
+    path.path
+        .segments
+        .last()
+        .map(|segment| segment.ident.to_string())
+        .unwrap_or_default()
+}
+
+fn qualified(module: &str, name: &syn::Ident) -> String {
+    if module.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}::{}", module, name)
+    }
+}
+
+/// Check the items of the crate's modules, given as their paths and items
+fn lint_modules(modules: &[(String, Vec<syn::Item>)]) -> Vec<String> {
+    let mut linter = Linter {
+        types: HashMap::new(),
+        checked: HashSet::new(),
+        problems: Vec::new(),
+    };
+    for (_, items) in modules {
+        for item in items {
+            let (ident, local_type) = match item {
+                syn::Item::Struct(item) => (
+                    &item.ident,
+                    LocalType::Struct {
+                        reprs: reprs(&item.attrs),
+                        fields: named_fields(&item.fields),
+                    },
+                ),
+                syn::Item::Union(item) => (
+                    &item.ident,
+                    LocalType::Struct {
+                        reprs: reprs(&item.attrs),
+                        fields: named_fields(&syn::Fields::Named(item.fields.clone())),
+                    },
+                ),
+                syn::Item::Enum(item) => (
+                    &item.ident,
+                    LocalType::Enum {
+                        reprs: reprs(&item.attrs),
+                        fields: item
+                            .variants
+                            .iter()
+                            .flat_map(|variant| {
+                                named_fields(&variant.fields)
+                                    .into_iter()
+                                    .map(move |(field, ty)| {
+                                        (format!("{}.{}", variant.ident, field), ty)
+                                    })
+                            })
+                            .collect(),
+                    },
+                ),
+                syn::Item::Type(item) => (&item.ident, LocalType::Alias((*item.ty).clone())),
+                _ => continue,
+            };
+            linter.types.entry(ident.to_string()).or_insert(local_type);
+        }
+    }
+    for (module, items) in modules {
+        for item in items {
+            match item {
+                syn::Item::Fn(function) if is_exported(&function.attrs) => {
+                    let sig = &function.sig;
+                    let location = qualified(module, &sig.ident);
+                    if sig.abi.is_none() {
+                        linter.problem(
+                            &location,
+                            "exported with the Rust ABI, which cbindgen skips. Declare it extern \"C\"".to_owned(),
+                        );
+                        continue;
+                    }
+                    let generic = sig
+                        .generics
+                        .params
+                        .iter()
+                        .any(|param| !matches!(param, syn::GenericParam::Lifetime(_)));
+                    if generic {
+                        linter.problem(
+                            &location,
+                            "generic functions have no single symbol to call".to_owned(),
+                        );
+                        continue;
+                    }
+                    for input in &sig.inputs {
+                        if let syn::FnArg::Typed(argument) = input {
+                            let what = match &*argument.pat {
+                                syn::Pat::Ident(pat) => format!("argument `{}`", pat.ident),
+                                _ => "an argument".to_owned(),
+                            };
+                            linter.check_type(&argument.ty, &location, &what, true);
+                        }
+                    }
+                    if let syn::ReturnType::Type(_, ty) = &sig.output {
+                        linter.check_type(ty, &location, "the return type", true);
+                    }
+                }
+                syn::Item::Static(item) if is_exported(&item.attrs) => {
+                    let location = qualified(module, &item.ident);
+                    linter.check_type(&item.ty, &location, "its type", false);
+                }
+                _ => {}
+            }
+        }
+    }
+    linter.problems
+}
+
+/// The FFI-safety problems of the exported items of the crate whose root module is `src_path`.
+/// Items generated by macros aren't checked.
+pub fn lint_crate(src_path: &Path) -> Result<Vec<String>> {
+    let mut modules = Vec::new();
+    for_each_module(src_path, &mut |module, items| {
+        modules.push((module.to_owned(), items.to_vec()));
+    })?;
+    Ok(lint_modules(&modules))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffi_problems() {
+        let file = syn::parse_file(
+            r#"
+            #[repr(C)]
+            pub struct Vec2 { pub x: f32, pub y: f32 }
+            pub struct Unspecified { pub a: u8 }
+            #[repr(C)]
+            pub struct Named { pub name: String }
+            #[repr(u8)]
+            pub enum Mode { Off, On }
+            pub struct Opaque(Vec<u8>);
+            pub type Callback = Option<extern "C" fn(u32)>;
+
+            #[no_mangle]
+            pub extern "C" fn fine(v: Vec2, mode: Mode, opaque: *mut Opaque, name: *const c_char, callback: Callback, bytes: Option<&u8>) -> Box<Opaque> { todo!() }
+            #[no_mangle]
+            pub extern "C" fn greet(name: String, names: &[u8]) -> (u32, u32) { todo!() }
+            #[unsafe(no_mangle)]
+            pub extern "C" fn layout(a: Unspecified, b: Named, c: Option<u32>) {}
+            #[no_mangle]
+            pub extern "C" fn generic<T>(value: T) {}
+            #[no_mangle]
+            pub fn rust_abi() {}
+            pub extern "C" fn not_exported(name: String) {}
+            "#,
+        )
+        .unwrap();
+        let problems = lint_modules(&[("ffi".to_owned(), file.items)]);
+        assert_eq!(
+            problems,
+            vec![
+                "ffi::greet: argument `name` is a String, pass a *const c_char (e.g. from CString) instead",
+                "ffi::greet: argument `names` is a pointer to a slice, which is twice the size of a C pointer. Pass a pointer and a length instead",
+                "ffi::greet: the return type is a tuple, use a #[repr(C)] struct instead",
+                "ffi::layout: argument `a` is Unspecified, which has no #[repr(C)], so its layout is unspecified",
+                "Named: field `name` is a String, pass a *const c_char (e.g. from CString) instead",
+                "ffi::layout: argument `c` is an Option, which is only FFI-safe around references, Box, NonNull, NonZero integers and extern \"C\" function pointers",
+                "ffi::generic: generic functions have no single symbol to call",
+                "ffi::rust_abi: exported with the Rust ABI, which cbindgen skips. Declare it extern \"C\"",
+            ]
+        );
+    }
+}
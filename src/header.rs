@@ -0,0 +1,659 @@
+//! Post-processing of the header generated by cbindgen
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const STAMP_PREFIX: &str = "// generated from ";
+
+/// Lines of context around the changes of `unified_diff`
+const DIFF_CONTEXT: usize = 3;
+
+/// Above this many cells, the changed middle of two headers is diffed as a whole instead of
+/// line by line
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Insert a provenance comment as the first line of the header, before `add_preamble`
+pub fn add_stamp(header: &[u8], stamp: &str) -> Vec<u8> {
+    let mut stamped = format!("{}{}\n", STAMP_PREFIX, stamp).into_bytes();
+    stamped.extend_from_slice(header);
+    stamped
+}
+
+/// Lines of `text` as a comment, leaving it as is if it's one already
+fn as_comment(text: &str) -> String {
+    let mut comment = String::new();
+    for line in text.trim_end().lines() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty()
+            || trimmed.starts_with("//")
+            || trimmed.starts_with("/*")
+            || trimmed.starts_with('*')
+        {
+            comment.push_str(line.trim_end());
+        } else {
+            comment.push_str("// ");
+            comment.push_str(line.trim_end());
+        }
+        comment.push('\n');
+    }
+    comment
+}
+
+/// The license header and the banner as comments, each followed by a blank line
+fn preamble(license: Option<&str>, banner: Option<&str>) -> String {
+    let mut preamble = String::new();
+    for text in license.iter().chain(&banner) {
+        preamble.push_str(&as_comment(text));
+        preamble.push('\n');
+    }
+    preamble
+}
+
+/// Start the header with the license header and the banner, as comments, so that header lints
+/// find e.g. the copyright notice on the first line
+pub fn add_preamble(header: &[u8], license: Option<&str>, banner: Option<&str>) -> Vec<u8> {
+    let mut data = preamble(license, banner).into_bytes();
+    data.extend_from_slice(header);
+    data
+}
+
+/// The index of the line `add_stamp` put the stamp on, once `add_preamble` started the header
+/// with `license` and `banner`
+pub fn stamp_line(license: Option<&str>, banner: Option<&str>) -> usize {
+    preamble(license, banner).lines().count()
+}
+
+/// Wrap the header for inclusion from UE code: `#pragma once`, and UE's third party include
+/// macros and MSVC's warning state around the rest, so that UE's stricter warnings (e.g. for
+/// the C++ standard library headers cbindgen includes) don't fail the module's build. The UE
+/// macros are only used if they're defined, so the header still compiles on its own.
+pub fn ue_wrap(header: &[u8], disable_deprecation_warnings: bool) -> Vec<u8> {
+    let has_pragma_once = header
+        .split(|&b| b == b'\n')
+        .any(|line| line.trim_ascii() == b"#pragma once");
+    let mut wrapped = Vec::with_capacity(header.len() + 512);
+    if !has_pragma_once {
+        wrapped.extend_from_slice(b"#pragma once\n\n");
+    }
+    let mut open = vec![
+        "#ifdef _MSC_VER\n#pragma warning(push)\n#endif",
+        "#ifdef THIRD_PARTY_INCLUDES_START\nTHIRD_PARTY_INCLUDES_START\n#endif",
+    ];
+    let mut close = vec![
+        "#ifdef THIRD_PARTY_INCLUDES_END\nTHIRD_PARTY_INCLUDES_END\n#endif",
+        "#ifdef _MSC_VER\n#pragma warning(pop)\n#endif",
+    ];
+    if disable_deprecation_warnings {
+        open.push("#ifdef PRAGMA_DISABLE_DEPRECATION_WARNINGS\nPRAGMA_DISABLE_DEPRECATION_WARNINGS\n#endif");
+        close.insert(
+            0,
+            "#ifdef PRAGMA_ENABLE_DEPRECATION_WARNINGS\nPRAGMA_ENABLE_DEPRECATION_WARNINGS\n#endif",
+        );
+    }
+    wrapped.extend_from_slice(open.join("\n").as_bytes());
+    wrapped.extend_from_slice(b"\n\n");
+    wrapped.extend_from_slice(header);
+    if !header.ends_with(b"\n") {
+        wrapped.push(b'\n');
+    }
+    wrapped.push(b'\n');
+    wrapped.extend_from_slice(close.join("\n").as_bytes());
+    wrapped.push(b'\n');
+    wrapped
+}
+
+/// The header with its `#include "<name>"` line replaced by `included`, so that it can be
+/// checked on its own
+pub fn inline_include(header: &[u8], name: &str, included: &[u8]) -> Vec<u8> {
+    let include = format!("#include \"{}\"", name);
+    let mut inlined = Vec::with_capacity(header.len() + included.len());
+    for line in header.split_inclusive(|&b| b == b'\n') {
+        if line.trim_ascii() == include.as_bytes() {
+            inlined.extend_from_slice(included);
+            if !included.ends_with(b"\n") {
+                inlined.push(b'\n');
+            }
+        } else {
+            inlined.extend_from_slice(line);
+        }
+    }
+    inlined
+}
+
+/// The part of the header that's relevant when deciding whether it changed. The stamp, on line
+/// `stamp_line` of stamped headers, is dropped so that a new stamp alone doesn't cause the
+/// header to be rewritten.
+pub fn comparison_data(header: &[u8], stamp_line: Option<usize>) -> Vec<u8> {
+    let mut data = Vec::with_capacity(header.len());
+    for (index, line) in header.split_inclusive(|&b| b == b'\n').enumerate() {
+        if Some(index) != stamp_line || !line.starts_with(STAMP_PREFIX.as_bytes()) {
+            data.extend_from_slice(line);
+        }
+    }
+    data
+}
+
+/// Whether `new` differs from the `existing` header. A header that has nothing to compare
+/// against, because it goes to stdout and no `--existing-header` was given, never changed.
+pub fn changed(new: &[u8], existing: Option<&[u8]>, stamp_line: Option<usize>) -> bool {
+    existing.is_some_and(|existing| {
+        comparison_data(new, stamp_line) != comparison_data(existing, stamp_line)
+    })
+}
+
+/// Read the header a generated header is compared against, from stdin for `-`. A missing file
+/// is read as an empty header, a header that's new, unless the user named it and it `must_exist`.
+pub fn read_existing(path: &Path, must_exist: bool) -> Result<Vec<u8>> {
+    if path == Path::new("-") {
+        let mut data = Vec::new();
+        std::io::stdin().read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    match std::fs::read(path) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !must_exist => Ok(Vec::new()),
+        data => data.with_context(|| format!("Couldn't read {}", path.display())),
+    }
+}
+
+/// A line of an edit script: the indices of the line in the old and the new version, with `None`
+/// for the side it's missing from
+type DiffStep = (Option<usize>, Option<usize>);
+
+/// The edit script turning `old` into `new`
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<DiffStep> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_middle, new_middle) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let mut script: Vec<_> = (0..prefix).map(|i| (Some(i), Some(i))).collect();
+    let (n, m) = (old_middle.len(), new_middle.len());
+    if (n + 1) * (m + 1) > MAX_DIFF_CELLS {
+        script.extend((0..n).map(|i| (Some(prefix + i), None)));
+        script.extend((0..m).map(|j| (None, Some(prefix + j))));
+    } else {
+        // longest common subsequence lengths of the suffixes
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] = if old_middle[i] == new_middle[j] {
+                    lcs[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                script.push((Some(prefix + i), Some(prefix + j)));
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1]) {
+                script.push((Some(prefix + i), None));
+                i += 1;
+            } else {
+                script.push((None, Some(prefix + j)));
+                j += 1;
+            }
+        }
+    }
+    let offset = (old.len() - suffix, new.len() - suffix);
+    script.extend((0..suffix).map(|k| (Some(offset.0 + k), Some(offset.1 + k))));
+    script
+}
+
+/// The one-based first line and the line count of one side of the hunk `script[start..end]`.
+/// An empty side starts at the line before it, as in diff's output.
+fn hunk_range(
+    script: &[DiffStep],
+    start: usize,
+    end: usize,
+    side: fn(&DiffStep) -> Option<usize>,
+) -> (usize, usize) {
+    let hunk = &script[start..end];
+    let count = hunk.iter().filter(|step| side(step).is_some()).count();
+    let first = match hunk.iter().find_map(side) {
+        Some(line) => line + 1,
+        None => script[..start]
+            .iter()
+            .rev()
+            .find_map(side)
+            .map_or(0, |line| line + 1),
+    };
+    (first, count)
+}
+
+/// A unified diff of two versions of the header at `path`, or an empty string if they only
+/// differ by their stamp on line `stamp_line`
+pub fn unified_diff(path: &str, old: &[u8], new: &[u8], stamp_line: Option<usize>) -> String {
+    if comparison_data(old, stamp_line) == comparison_data(new, stamp_line) {
+        return String::new();
+    }
+    let (old, new) = (String::from_utf8_lossy(old), String::from_utf8_lossy(new));
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let script = diff_lines(&old_lines, &new_lines);
+    let changed: Vec<usize> = (0..script.len())
+        .filter(|&k| script[k].0.is_none() || script[k].1.is_none())
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+    let mut diff = format!("--- {}\n+++ {}\n", path, path);
+    let mut k = 0;
+    while k < changed.len() {
+        // extend the hunk while the next change is within twice the context
+        let start = changed[k].saturating_sub(DIFF_CONTEXT);
+        let mut last = changed[k];
+        while k + 1 < changed.len() && changed[k + 1] - last <= 2 * DIFF_CONTEXT {
+            k += 1;
+            last = changed[k];
+        }
+        let end = (last + DIFF_CONTEXT + 1).min(script.len());
+        let (old_start, old_count) = hunk_range(&script, start, end, |step| step.0);
+        let (new_start, new_count) = hunk_range(&script, start, end, |step| step.1);
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_count, new_start, new_count
+        ));
+        for step in &script[start..end] {
+            match step {
+                (Some(i), Some(_)) => diff.push_str(&format!(" {}\n", old_lines[*i])),
+                (Some(i), None) => diff.push_str(&format!("-{}\n", old_lines[*i])),
+                (None, Some(j)) => diff.push_str(&format!("+{}\n", new_lines[*j])),
+                (None, None) => {}
+            }
+        }
+        k += 1;
+    }
+    diff
+}
+
+/// Run clang-format over the header, with the style of `style_file` or else of the
+/// `.clang-format` found from the current directory up, falling back to clang-format's default
+pub fn clang_format(header: &[u8], style_file: Option<&Path>) -> Result<Vec<u8>> {
+    let style = match style_file {
+        Some(path) => format!("--style=file:{}", path.display()),
+        None => "--style=file".to_owned(),
+    };
+    let mut child = Command::new("clang-format")
+        .arg(style)
+        .arg("--assume-filename=header.h")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Couldn't run clang-format to format the header")?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let header = header.to_owned();
+    // written from another thread so that a full stdout pipe can't block the write
+    let writer = std::thread::spawn(move || stdin.write_all(&header));
+    let output = child.wait_with_output()?;
+    writer
+        .join()
+        .expect("the header writer panicked")
+        .context("Couldn't pass the header to clang-format")?;
+    if !output.status.success() {
+        bail!(
+            "clang-format failed with {}:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// Escape a path for a make style depfile
+fn depfile_escape(path: &str) -> String {
+    path.replace(' ', "\\ ").replace('#', "\\#")
+}
+
+/// Write a make style depfile listing the files `targets` were generated from, so that build
+/// systems only regenerate them when one of those changed. The file is only touched if its
+/// contents changed.
+pub fn write_depfile(path: &Path, targets: &[&str], sources: &[PathBuf]) -> Result<()> {
+    let mut contents = targets
+        .iter()
+        .map(|target| depfile_escape(target))
+        .collect::<Vec<_>>()
+        .join(" ");
+    contents.push(':');
+    for source in sources {
+        contents.push_str(" \\\n    ");
+        contents.push_str(&depfile_escape(&source.to_string_lossy()));
+    }
+    contents.push('\n');
+    if std::fs::read_to_string(path).ok().as_deref() != Some(&contents) {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, contents)
+            .with_context(|| format!("Couldn't write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Create the stamp file `path` or update its modification time, for build steps that depend on
+/// it to rerun when a header changed
+pub fn touch(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|file| file.set_modified(std::time::SystemTime::now()))
+        .with_context(|| format!("Couldn't touch {}", path.display()))
+}
+
+/// Default path of the hash file of a header: `<header>.sha256`
+pub fn hash_path(header_path: &Path) -> PathBuf {
+    let mut hash_path: OsString = header_path.as_os_str().into();
+    hash_path.push(".sha256");
+    hash_path.into()
+}
+
+/// Write the SHA-256 of the header bytes to `hash_path`, in `sha256sum` format so that
+/// `sha256sum -c` can check it. The file is only touched if the hash changed.
+pub fn write_hash(hash_path: &Path, header_name: &str, header: &[u8]) -> Result<()> {
+    let contents = format!("{}  {}\n", hex::encode(Sha256::digest(header)), header_name);
+    if std::fs::read_to_string(hash_path).ok().as_deref() != Some(&contents) {
+        std::fs::write(hash_path, contents)?;
+    }
+    Ok(())
+}
+
+/// The compiler command `--validate-header` runs by default for a header in `language`, with
+/// the header appended
+pub fn default_validate_command(language: cbindgen::Language) -> Result<&'static str> {
+    Ok(match (language, cfg!(windows)) {
+        (cbindgen::Language::Cxx, true) => "cl /nologo /Zs /TP",
+        (cbindgen::Language::Cxx, false) => "c++ -fsyntax-only -x c++",
+        (cbindgen::Language::C, true) => "cl /nologo /Zs /TC",
+        (cbindgen::Language::C, false) => "cc -fsyntax-only -x c",
+        (cbindgen::Language::Cython, _) => {
+            bail!("There's no default --validate-command for Cython headers")
+        }
+    })
+}
+
+/// Check that the header parses by running `command` with the path of a temporary copy of it
+/// appended. Fails with the compiler's output if it doesn't.
+pub fn validate(header: &[u8], command: &[String]) -> Result<()> {
+    let (program, args) = command
+        .split_first()
+        .context("The header validation command is empty")?;
+    let header_path = std::env::temp_dir().join(format!(
+        "unreal_rust_compile_{}_{}.h",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    std::fs::write(&header_path, header)?;
+    let output = Command::new(program).args(args).arg(&header_path).output();
+    let _ = std::fs::remove_file(&header_path);
+    let output =
+        output.with_context(|| format!("Couldn't run {} to validate the header", program))?;
+    if !output.status.success() {
+        bail!(
+            "The generated header doesn't compile with `{}`:\n{}{}",
+            command.join(" "),
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling_path: OsString = path.as_os_str().into();
+    sibling_path.push(suffix);
+    sibling_path.into()
+}
+
+/// Write every header, along with any other file that goes with them such as a source file list,
+/// to a temporary file next to its destination, and only move them into place once all of them
+/// were written. Existing headers are backed up while they're replaced,
+/// so that if any step fails the ones already moved are rolled back and none of the destinations
+/// end up changed.
+pub fn write_all_or_nothing(headers: &[(PathBuf, Vec<u8>)]) -> Result<()> {
+    let mut staged = Vec::new();
+    let stage_result = headers.iter().try_for_each(|(path, data)| -> Result<()> {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let staging_path = sibling_path(path, ".tmp");
+        std::fs::write(&staging_path, data)?;
+        staged.push((staging_path, path));
+        Ok(())
+    });
+    if let Err(err) = stage_result {
+        for (staging_path, _) in &staged {
+            let _ = std::fs::remove_file(staging_path);
+        }
+        return Err(err);
+    }
+    // the destinations moved into place, with their backup if they existed
+    let mut replaced: Vec<(&Path, Option<PathBuf>)> = Vec::new();
+    let replace_result = staged
+        .iter()
+        .try_for_each(|(staging_path, path)| -> Result<()> {
+            let backup_path = match std::fs::metadata(path) {
+                Ok(metadata) if metadata.is_file() => {
+                    let backup_path = sibling_path(path, ".bak");
+                    std::fs::rename(path, &backup_path)?;
+                    Some(backup_path)
+                }
+                _ => None,
+            };
+            if let Err(err) = std::fs::rename(staging_path, path) {
+                if let Some(backup_path) = &backup_path {
+                    let _ = std::fs::rename(backup_path, path);
+                }
+                return Err(err.into());
+            }
+            replaced.push((path, backup_path));
+            Ok(())
+        });
+    if let Err(err) = replace_result {
+        for (path, backup_path) in replaced {
+            match backup_path {
+                Some(backup_path) => {
+                    let _ = std::fs::rename(backup_path, path);
+                }
+                None => {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+        }
+        for (staging_path, _) in &staged {
+            let _ = std::fs::remove_file(staging_path);
+        }
+        return Err(err);
+    }
+    for (_, backup_path) in replaced {
+        if let Some(backup_path) = backup_path {
+            let _ = std::fs::remove_file(backup_path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preamble() {
+        let header = add_stamp(b"#pragma once\n", "abc123");
+        let header = add_preamble(
+            &header,
+            Some("// Copyright Game Studio. All Rights Reserved.\n"),
+            Some("Generated from the Rust crate.\nDon't edit it."),
+        );
+        assert_eq!(
+            String::from_utf8(header).unwrap(),
+            "// Copyright Game Studio. All Rights Reserved.\n\n// Generated from the Rust crate.\n// Don't edit it.\n\n// generated from abc123\n#pragma once\n"
+        );
+        assert_eq!(as_comment("/*\n * MIT\n */\n"), "/*\n * MIT\n */\n");
+    }
+
+    #[test]
+    fn inlined_include() {
+        let header = b"#include <cstdint>\n#include \"GameTypes.h\"\n\nvoid a(Vec2 v);\n";
+        assert_eq!(
+            inline_include(header, "GameTypes.h", b"struct Vec2 {};"),
+            b"#include <cstdint>\nstruct Vec2 {};\n\nvoid a(Vec2 v);\n"
+        );
+    }
+
+    #[test]
+    fn ue_wrapped_header() {
+        let wrapped = String::from_utf8(ue_wrap(b"#include <cstdint>\n", true)).unwrap();
+        assert!(wrapped.starts_with("#pragma once\n\n#ifdef _MSC_VER\n#pragma warning(push)\n"));
+        let start = wrapped.find("THIRD_PARTY_INCLUDES_START\n#endif").unwrap();
+        let include = wrapped.find("#include <cstdint>").unwrap();
+        let end = wrapped.find("THIRD_PARTY_INCLUDES_END\n#endif").unwrap();
+        assert!(start < include && include < end);
+        assert!(wrapped.find("PRAGMA_ENABLE_DEPRECATION_WARNINGS").unwrap() < end);
+        assert!(wrapped.ends_with("#pragma warning(pop)\n#endif\n"));
+
+        let wrapped = String::from_utf8(ue_wrap(b"#pragma once\n\nvoid a();\n", false)).unwrap();
+        assert_eq!(wrapped.matches("#pragma once").count(), 1);
+        assert!(!wrapped.contains("DEPRECATION"));
+    }
+
+    #[test]
+    fn stamp_position() {
+        let license = "// generated from the template of Epic Games\n";
+        let header = |stamp: &str| {
+            let stamped = add_stamp(b"void a();\n", stamp);
+            add_preamble(&stamped, Some(license), Some("Don't edit"))
+        };
+        let stamp_line = Some(stamp_line(Some(license), Some("Don't edit")));
+        assert_eq!(stamp_line, Some(4));
+        assert_eq!(
+            comparison_data(&header("abc"), stamp_line),
+            b"// generated from the template of Epic Games\n\n// Don't edit\n\nvoid a();\n"
+        );
+        assert!(!changed(&header("abc"), Some(&header("def")), stamp_line));
+        // a license line with the stamp's prefix is part of the header
+        let relicensed = add_preamble(
+            &add_stamp(b"void a();\n", "abc"),
+            Some("// generated from another template\n"),
+            Some("Don't edit"),
+        );
+        assert!(changed(&header("abc"), Some(&relicensed), stamp_line));
+    }
+
+    #[test]
+    fn header_diff() {
+        let old = b"// generated from abc\n#pragma once\n\nstruct A;\n\nvoid a();\nvoid b();\n";
+        let new =
+            b"// generated from def\n#pragma once\n\nstruct A;\n\nvoid a();\nvoid c(int x);\n";
+        assert_eq!(
+            unified_diff("Game.h", old, new, Some(0)),
+            "--- Game.h\n+++ Game.h\n@@ -1,7 +1,7 @@\n-// generated from abc\n+// generated from def\n #pragma once\n \n struct A;\n \n void a();\n-void b();\n+void c(int x);\n"
+        );
+        assert_eq!(
+            unified_diff(
+                "Game.h",
+                old,
+                b"// generated from def\n#pragma once\n\nstruct A;\n\nvoid a();\nvoid b();\n",
+                Some(0)
+            ),
+            ""
+        );
+        assert_eq!(
+            unified_diff("Game.h", b"", b"void a();\n", None),
+            "--- Game.h\n+++ Game.h\n@@ -0,0 +1,1 @@\n+void a();\n"
+        );
+    }
+
+    #[test]
+    fn existing_header() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("Game.h");
+        // a header written for the first time, or one named by --existing-header that's missing
+        assert_eq!(read_existing(&path, false).unwrap(), b"");
+        assert!(read_existing(&path, true).is_err());
+        std::fs::write(&path, "void a();\n").unwrap();
+        assert_eq!(read_existing(&path, true).unwrap(), b"void a();\n");
+
+        assert!(!changed(b"void a();\n", Some(b"void a();\n"), None));
+        assert!(changed(b"void a();\n", Some(b""), None));
+        // written to stdout with nothing to compare against
+        assert!(!changed(b"void a();\n", None, None));
+    }
+
+    #[test]
+    fn stamp_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("stamp");
+        touch(&path).unwrap();
+        let created = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(created - std::time::Duration::from_secs(60))
+            .unwrap();
+        touch(&path).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().modified().unwrap() >= created);
+        assert_eq!(std::fs::read(&path).unwrap(), b"");
+    }
+
+    #[test]
+    fn depfile() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("depfile.d");
+        let sources = [
+            PathBuf::from("/src/Game Rust/src/lib.rs"),
+            PathBuf::from("/src/Game Rust/cbindgen.toml"),
+        ];
+        write_depfile(&path, &["Game.h", "GameFunctions.h"], &sources).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "Game.h GameFunctions.h: \\\n    /src/Game\\ Rust/src/lib.rs \\\n    /src/Game\\ Rust/cbindgen.toml\n"
+        );
+    }
+
+    #[test]
+    fn rolled_back_writes() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("Game.h"), "old").unwrap();
+        // a directory in place of the last header makes moving it into place fail
+        std::fs::create_dir_all(dir.join("GameFunctions.h").join("occupied")).unwrap();
+        let headers = [
+            (dir.join("Game.h"), b"new".to_vec()),
+            (dir.join("GameTypes.h"), b"new".to_vec()),
+            (dir.join("GameFunctions.h"), b"new".to_vec()),
+        ];
+        assert!(write_all_or_nothing(&headers).is_err());
+        assert_eq!(std::fs::read(dir.join("Game.h")).unwrap(), b"old");
+        assert!(!dir.join("GameTypes.h").exists());
+        let mut files: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["Game.h", "GameFunctions.h"]);
+
+        std::fs::remove_dir_all(dir.join("GameFunctions.h")).unwrap();
+        write_all_or_nothing(&headers).unwrap();
+        for (path, _) in &headers {
+            assert_eq!(std::fs::read(path).unwrap(), b"new");
+        }
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 3);
+    }
+}
@@ -0,0 +1,131 @@
+//! Building staticlibs for iOS, which UE's iOS toolchain links itself: the crate has no link step,
+//! so the archive and the frameworks and libraries rustc says it needs are written out instead
+//! of a parsed link line
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The target built for devices
+pub const DEVICE_TARGET: &str = "aarch64-apple-ios";
+
+/// The targets built with `--simulator`, for Apple silicon and Intel Macs
+pub const SIMULATOR_TARGETS: &[&str] = &["aarch64-apple-ios-sim", "x86_64-apple-ios"];
+
+const NATIVE_STATIC_LIBS_NOTE: &str = "native-static-libs:";
+
+/// The architecture of a target triple, which lipo can have only one slice of
+pub fn architecture(target: &str) -> &str {
+    target.split('-').next().unwrap_or(target)
+}
+
+/// The args of rustc's `native-static-libs` note, printed with `--print native-static-libs`
+pub fn native_static_libs(stderr: &str) -> Vec<String> {
+    stderr
+        .lines()
+        .filter_map(|line| {
+            let start = line.find(NATIVE_STATIC_LIBS_NOTE)? + NATIVE_STATIC_LIBS_NOTE.len();
+            Some(line[start..].split_whitespace().map(str::to_owned))
+        })
+        .flatten()
+        .collect()
+}
+
+/// The linker file lines for the `native-static-libs` args, deduplicated. Like for macOS link
+/// lines, frameworks are written as `-Wl,-framework,<name>` for a linker driver.
+pub fn linker_lines(native_static_libs: &[String]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut args = native_static_libs.iter();
+    while let Some(arg) = args.next() {
+        let line = match arg.as_str() {
+            "-framework" => match args.next() {
+                Some(framework) => format!("-Wl,-framework,{}", framework),
+                None => continue,
+            },
+            _ => arg.clone(),
+        };
+        if !lines.contains(&line) {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// The staticlibs among the artifacts of cargo's `--message-format json` output
+pub fn staticlib_artifacts(stdout: &str) -> Vec<PathBuf> {
+    let mut archives = Vec::new();
+    for line in stdout.lines() {
+        let Ok(message) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        if message["reason"] != "compiler-artifact" {
+            continue;
+        }
+        let filenames = message["filenames"].as_array().into_iter().flatten();
+        for filename in filenames.filter_map(|f| f.as_str()) {
+            if filename.ends_with(".a") {
+                archives.push(PathBuf::from(filename));
+            }
+        }
+    }
+    archives
+}
+
+/// Merge the archives of different architectures into a universal one
+pub fn run_lipo(archives: &[PathBuf], output: &Path) -> Result<()> {
+    let result = Command::new("lipo")
+        .arg("-create")
+        .args(archives)
+        .arg("-output")
+        .arg(output)
+        .output()
+        .context("Couldn't run lipo")?;
+    if !result.status.success() {
+        bail!(
+            "lipo failed with {}: {}",
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_static_libs_linker_lines() {
+        let stderr = "   Compiling ue_plugin v0.1.0\nnote: Link against the following native artifacts when linking against this static library. The order and any duplication can be significant on some platforms.\n\nnote: native-static-libs: -framework Security -framework CoreFoundation -liconv -lSystem -lc -lm -framework Security\n\n    Finished `dev` profile";
+        let libs = native_static_libs(stderr);
+        assert_eq!(libs.len(), 10);
+        assert_eq!(
+            linker_lines(&libs),
+            vec![
+                "-Wl,-framework,Security",
+                "-Wl,-framework,CoreFoundation",
+                "-liconv",
+                "-lSystem",
+                "-lc",
+                "-lm",
+            ]
+        );
+        assert_eq!(architecture("aarch64-apple-ios-sim"), "aarch64");
+    }
+
+    #[test]
+    fn staticlib_artifact_paths() {
+        let stdout = concat!(
+            r#"{"reason":"compiler-artifact","target":{"crate_types":["lib"]},"filenames":["/t/aarch64-apple-ios/debug/deps/libdep.rlib"]}"#,
+            "\n",
+            r#"{"reason":"compiler-artifact","target":{"crate_types":["staticlib"]},"filenames":["/t/aarch64-apple-ios/debug/libue_plugin.a"]}"#,
+            "\n",
+            r#"{"reason":"build-finished","success":true}"#,
+            "\n",
+        );
+        assert_eq!(
+            staticlib_artifacts(stdout),
+            vec![PathBuf::from("/t/aarch64-apple-ios/debug/libue_plugin.a")]
+        );
+    }
+}
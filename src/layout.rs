@@ -0,0 +1,252 @@
+//! Layout checks for the structs of a header: static_asserts comparing the C compiler's size and
+//! alignment of each struct with the ones rustc computed with `-Zprint-type-sizes`
+
+use crate::cfg::Cfg;
+use anyhow::{bail, Context, Result};
+use cargo::core::Workspace;
+use cargo::GlobalContext;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The size and alignment rustc computed for a type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeLayout {
+    /// The path of the type, relative to its crate for the crate's own types
+    pub path: String,
+    pub size: u64,
+    pub align: u64,
+}
+
+impl TypeLayout {
+    fn name(&self) -> &str {
+        self.path.rsplit("::").next().unwrap_or(&self.path)
+    }
+}
+
+/// The asserts header of a header without an explicit path: `<header>_asserts.h` next to it
+pub fn default_asserts_path(header_path: &Path) -> PathBuf {
+    let stem = header_path
+        .file_stem()
+        .map_or_else(|| "header".into(), |stem| stem.to_string_lossy());
+    header_path.with_file_name(format!("{}_asserts.h", stem))
+}
+
+/// Parse rustc's `-Zprint-type-sizes` output, lines like
+/// ``print-type-size type: `inner::Pair`: 4 bytes, alignment: 2 bytes``. Generic types are
+/// skipped, since cbindgen names their instantiations differently.
+fn parse_type_sizes(output: &str) -> Vec<TypeLayout> {
+    let mut layouts = Vec::new();
+    for line in output.lines() {
+        let Some(rest) = line.strip_prefix("print-type-size type: `") else {
+            continue;
+        };
+        let Some((path, rest)) = rest.split_once("`: ") else {
+            continue;
+        };
+        if path.contains('<') {
+            continue;
+        }
+        let Some((size, rest)) = rest.split_once(" bytes, alignment: ") else {
+            continue;
+        };
+        let Some(align) = rest.strip_suffix(" bytes") else {
+            continue;
+        };
+        if let (Ok(size), Ok(align)) = (size.parse(), align.parse()) {
+            layouts.push(TypeLayout {
+                path: path.to_owned(),
+                size,
+                align,
+            });
+        }
+    }
+    layouts
+}
+
+/// Build the crate with `-Zprint-type-sizes`, which needs a nightly `toolchain` unless the crate
+/// selects one, and return the layouts of its types and its dependencies'. It's built in a target
+/// directory of its own so that the crate's actual build isn't invalidated.
+pub fn type_layouts(
+    crate_dir: &Path,
+    toolchain: Option<&str>,
+    target: Option<&str>,
+    cfgs: &[Cfg],
+) -> Result<Vec<TypeLayout>> {
+    let ctx = GlobalContext::default()?;
+    let ws = Workspace::new(&crate_dir.join("Cargo.toml").canonicalize()?, &ctx)?;
+    let target_dir = ws
+        .target_dir()
+        .into_path_unlocked()
+        .join("unreal_rust_compile_layout");
+    let mut command = Command::new("cargo");
+    command
+        .current_dir(crate_dir)
+        .args(["rustc", "--lib", "--quiet"])
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .stderr(Stdio::inherit());
+    if let Some(toolchain) = toolchain {
+        // the CARGO of an outer cargo would bypass rustup's proxy
+        command
+            .env("RUSTUP_TOOLCHAIN", toolchain)
+            .env_remove("CARGO");
+    }
+    if let Some(target) = target {
+        command.args(["--target", target]);
+    }
+    // rustc only prints the sizes when it compiles the crate, so the cfg that's different on
+    // every run makes cargo rebuild it
+    command
+        .args(["--", "-Zprint-type-sizes", "--cfg"])
+        .arg(format!(
+            "unreal_rust_compile_layout_{:08x}",
+            rand::random::<u32>()
+        ));
+    for cfg in cfgs {
+        command.args(["--cfg", &cfg.rustc_arg()]);
+    }
+    let output = command.output().context("Couldn't run cargo")?;
+    if !output.status.success() {
+        bail!(
+            "Building {} with -Zprint-type-sizes failed with {}. It needs a nightly toolchain, e.g. --layout-toolchain nightly",
+            crate_dir.display(),
+            output.status
+        );
+    }
+    Ok(parse_type_sizes(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// The names of the structs and unions the header defines (rather than only declares), whether
+/// named by their tag or by a typedef
+fn defined_structs(header: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut anonymous = false;
+    for line in header.lines() {
+        let declaration = line.strip_prefix("typedef ").unwrap_or(line);
+        let tagged = declaration
+            .strip_prefix("struct ")
+            .or_else(|| declaration.strip_prefix("union "));
+        if let Some(name) = tagged.and_then(|rest| rest.strip_suffix(" {")) {
+            names.push(name.to_owned());
+        } else if declaration == "struct {" || declaration == "union {" {
+            anonymous = true;
+        } else if anonymous && line.starts_with("} ") {
+            anonymous = false;
+            if let Some(name) = line[2..].strip_suffix(';') {
+                names.push(name.to_owned());
+            }
+        }
+    }
+    names
+}
+
+/// The name cbindgen gives the Rust item `name`, as `ExportConfig::rename` does
+fn export_name(config: &cbindgen::Config, name: &str) -> String {
+    let export = &config.export;
+    let mut renamed = name.to_owned();
+    if let Some(rename) = export.rename.get(name) {
+        renamed.clone_from(rename);
+        if export.renaming_overrides_prefixing {
+            return renamed;
+        }
+    }
+    if let Some(prefix) = &export.prefix {
+        renamed.insert_str(0, prefix);
+    }
+    renamed
+}
+
+/// The companion header of `header_name` (with contents `header`) asserting that each struct it
+/// defines has the layout rustc computed for it. Structs whose Rust name is ambiguous among the
+/// layouts are skipped.
+pub fn asserts_header(
+    header_name: &str,
+    header: &str,
+    layouts: &[TypeLayout],
+    config: &cbindgen::Config,
+) -> String {
+    let cxx = config.language == cbindgen::Language::Cxx;
+    let namespace = match (&config.namespaces, &config.namespace) {
+        (Some(namespaces), _) if cxx => format!("{}::", namespaces.join("::")),
+        (None, Some(namespace)) if cxx => format!("{}::", namespace),
+        _ => String::new(),
+    };
+    let tag = if !cxx && config.style == cbindgen::Style::Tag {
+        "struct "
+    } else {
+        ""
+    };
+    let (assert, alignof) = if cxx {
+        ("static_assert", "alignof")
+    } else {
+        ("_Static_assert", "_Alignof")
+    };
+    let defined = defined_structs(header);
+    let mut asserts = format!(
+        "// Layout checks of the structs of {}, generated from rustc's layouts\n\n#pragma once\n\n#include \"{}\"\n\n",
+        header_name, header_name
+    );
+    for name in &defined {
+        let mut candidates = layouts
+            .iter()
+            .filter(|layout| export_name(config, layout.name()) == *name);
+        let Some(layout) = candidates.next() else {
+            continue;
+        };
+        if candidates.any(|other| (other.size, other.align) != (layout.size, layout.align)) {
+            continue;
+        }
+        let ty = format!("{}{}{}", tag, namespace, name);
+        asserts.push_str(&format!(
+            "{}(sizeof({}) == {} && {}({}) == {}, \"{} has a different layout in Rust\");\n",
+            assert, ty, layout.size, alignof, ty, layout.align, name
+        ));
+    }
+    asserts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_asserts() {
+        let output = "\
+print-type-size type: `Vec2`: 8 bytes, alignment: 4 bytes
+print-type-size     field `.x`: 4 bytes
+print-type-size type: `inner::Pair`: 4 bytes, alignment: 2 bytes
+print-type-size type: `std::option::Option<u32>`: 8 bytes, alignment: 4 bytes
+";
+        let layouts = parse_type_sizes(output);
+        assert_eq!(
+            layouts,
+            vec![
+                TypeLayout {
+                    path: "Vec2".to_owned(),
+                    size: 8,
+                    align: 4
+                },
+                TypeLayout {
+                    path: "inner::Pair".to_owned(),
+                    size: 4,
+                    align: 2
+                },
+            ]
+        );
+
+        let header = "namespace Game {\n\nstruct Opaque;\n\nstruct RustVec2 {\n  float x;\n};\n\nstruct RustPair {\n  uint16_t a;\n};\n\n}  // namespace Game\n";
+        let mut config = cbindgen::Config {
+            language: cbindgen::Language::Cxx,
+            namespace: Some("Game".to_owned()),
+            ..cbindgen::Config::default()
+        };
+        config.export.prefix = Some("Rust".to_owned());
+        let asserts = asserts_header("Game.h", header, &layouts, &config);
+        assert!(asserts.contains("#include \"Game.h\"\n"));
+        assert!(asserts.contains("static_assert(sizeof(Game::RustVec2) == 8 && alignof(Game::RustVec2) == 4, \"RustVec2 has a different layout in Rust\");\n"));
+        assert!(asserts.contains("sizeof(Game::RustPair) == 4"));
+        assert!(!asserts.contains("Opaque"));
+
+        let header = "typedef struct {\n  float x;\n} Vec2;\n";
+        assert_eq!(defined_structs(header), vec!["Vec2".to_owned()]);
+    }
+}
@@ -0,0 +1,1913 @@
+use crate::archive;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+pub fn parse_quotes(s: impl AsRef<str>) -> Vec<String> {
+    let s = s.as_ref();
+    let mut args = vec![];
+    let mut in_string = false;
+    let mut escaping = false;
+    let mut current_str = String::default();
+
+    for x in s.chars() {
+        if in_string {
+            if x == '\\' && !escaping {
+                escaping = true;
+            } else if x == '"' && !escaping {
+                // a quoted section doesn't end the arg, e.g. `/IMPLIB:"C:\a b"` is a single arg
+                in_string = false;
+            } else {
+                current_str.push(x);
+                escaping = false;
+            }
+        } else if x == ' ' {
+            if !current_str.is_empty() {
+                args.push(current_str.clone());
+            }
+
+            current_str = String::default();
+        } else if x == '"' {
+            in_string = true;
+        } else {
+            current_str.push(x);
+        }
+    }
+
+    if !current_str.is_empty() {
+        args.push(current_str);
+    }
+
+    args
+}
+
+/// Split a link line the way a POSIX shell would: single quotes group without any escaping,
+/// double quotes group with backslash escaping `$`, `` ` ``, `"`, `\` and newlines, and an
+/// unquoted backslash escapes any character. GNU linker drivers print their args this way.
+pub fn parse_posix_quotes(s: impl AsRef<str>) -> Vec<String> {
+    let mut args = vec![];
+    let mut current_str = String::default();
+    // whether an arg was started, so that `''` yields an empty arg
+    let mut in_arg = false;
+    let mut chars = s.as_ref().chars().peekable();
+
+    while let Some(x) = chars.next() {
+        match x {
+            '\'' => {
+                in_arg = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    current_str.push(c);
+                }
+            }
+            '"' => {
+                in_arg = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.peek() {
+                            Some('$' | '`' | '"' | '\\' | '\n') => {
+                                current_str.push(chars.next().unwrap())
+                            }
+                            _ => current_str.push(c),
+                        },
+                        _ => current_str.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                if let Some(c) = chars.next() {
+                    current_str.push(c);
+                }
+            }
+            ' ' | '\t' | '\n' => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current_str));
+                    in_arg = false;
+                }
+            }
+            _ => {
+                in_arg = true;
+                current_str.push(x);
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(current_str);
+    }
+
+    args
+}
+
+/// The linker of a link line, skipping leading `NAME=value` environment assignments. The line is
+/// tokenized the MSVC way first, since MSVC linkers are usually installed under
+/// `C:\Program Files`, and the POSIX way if that doesn't find one of them.
+pub fn linker_token(link_line: &str) -> Option<String> {
+    let linker = |args: Vec<String>| args.into_iter().find(|arg| !arg.contains('='));
+    let msvc_linker = linker(parse_quotes(link_line));
+    if msvc_linker
+        .as_deref()
+        .is_some_and(|linker| KNOWN_LINKERS.contains(&file_name(linker)))
+    {
+        return msvc_linker;
+    }
+    linker(parse_posix_quotes(link_line))
+}
+
+/// The args of a tokenized link line after its linker and any environment assignments before it
+fn linker_args(args: &[String]) -> &[String] {
+    let linker_idx = args.iter().position(|arg| !arg.contains('='));
+    &args[linker_idx.map_or(args.len(), |idx| idx + 1)..]
+}
+
+/// How a link line is quoted and how its options are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkerFlavor {
+    /// MSVC style quoting, options start with `/` or `-`
+    Msvc,
+    /// POSIX shell quoting, options start with `-`
+    Gnu,
+}
+
+impl LinkerFlavor {
+    /// The flavor of the link line's linker: MSVC for the MSVC linkers, GNU for everything else
+    /// (e.g. `cc` or `ld`)
+    pub fn detect(link_line: &str) -> LinkerFlavor {
+        match linker_token(link_line) {
+            Some(linker) if KNOWN_LINKERS.contains(&file_name(&linker)) => LinkerFlavor::Msvc,
+            _ => LinkerFlavor::Gnu,
+        }
+    }
+
+    /// The flavor's name as `--linker-format` takes it
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkerFlavor::Msvc => "msvc",
+            LinkerFlavor::Gnu => "gnu",
+        }
+    }
+
+    /// The flavor of a link line whose linker isn't known, from its options: MSVC if it has an
+    /// `/OUT:` option (e.g. `clang-cl`'s link lines), GNU otherwise
+    pub fn guess(link_line: &str) -> LinkerFlavor {
+        let out = parse_quotes(link_line).iter().any(|arg| {
+            arg.get(..5)
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case("/OUT:"))
+        });
+        if out {
+            LinkerFlavor::Msvc
+        } else {
+            LinkerFlavor::Gnu
+        }
+    }
+
+    pub fn tokenize(self, link_line: &str) -> Vec<String> {
+        match self {
+            LinkerFlavor::Msvc => parse_quotes(link_line),
+            LinkerFlavor::Gnu => parse_posix_quotes(link_line),
+        }
+    }
+
+    fn is_option(self, arg: &str) -> bool {
+        match self {
+            LinkerFlavor::Msvc => arg.starts_with('/') || arg.starts_with('-'),
+            LinkerFlavor::Gnu => arg.starts_with('-'),
+        }
+    }
+}
+
+/// The flavor `parse_link_line` parses a link line as: its linker's if that's known, or else
+/// `LinkerFlavor::guess`'s
+fn link_line_flavor(link_line: &str) -> LinkerFlavor {
+    known_linker_flavor(link_line, &[]).unwrap_or_else(|| LinkerFlavor::guess(link_line))
+}
+
+/// Split a link line with the tokenizer matching the linker's flavor
+pub fn tokenize_link_line(link_line: &str) -> Vec<String> {
+    link_line_flavor(link_line).tokenize(link_line)
+}
+
+/// Split a `/NAME:value` or `-NAME:value` option into its name and value. Only the first colon
+/// separates the two, so the value may contain colons itself (e.g. drive letters).
+pub fn split_option(arg: &str) -> (&str, &str) {
+    let arg = &arg[1..];
+    arg.split_once(':').unwrap_or((arg, ""))
+}
+
+/// Recognize rust-lld's flavor option, which linker drivers write as `-flavor value`,
+/// `-flavor=value` or `--flavor value`. Returns whether the value is the next arg, or `None` if
+/// `arg` isn't a flavor option.
+fn flavor_option(arg: &str) -> Option<bool> {
+    let name = arg.strip_prefix("--").or_else(|| arg.strip_prefix('-'))?;
+    if name == "flavor" {
+        Some(true)
+    } else if name.starts_with("flavor=") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Windows import libraries that UE's own link line links already, so that the linker file
+/// leaves them out. Linking them twice is harmless at best, and a conflict with other flags.
+pub const UE_SYSTEM_LIBS: &[&str] = &[
+    "msvcrt", "kernel32", "advapi32", "user32", "ws2_32", "dbghelp", "shell32", "ole32",
+    "oleaut32", "uuid",
+];
+
+/// MSVC options besides the ones `parse_link_line` interprets that are written to the linker
+/// file as they are
+const FORWARDED_MSVC_OPTIONS: &[&str] = &[
+    // the CRT and other default libraries have to match UE's final link
+    "DEFAULTLIB",
+    "NODEFAULTLIB",
+    // std's debugger visualizers, which Visual Studio loads from the PDB
+    "NATVIS",
+    // DLLs loaded on first use, e.g. optional ones like d3d12 or CUDA's
+    "DELAYLOAD",
+];
+
+/// The library implementing `/DELAYLOAD`, which link.exe needs along with the option
+const DELAY_LOAD_HELPER: &str = "delayimp.lib";
+
+/// Stands in for the rustc sysroot in the output files with `--portable-paths`
+pub const SYSROOT_PLACEHOLDER: &str = "$SYSROOT";
+
+/// How the args in the output files are quoted, for the tool or shell that reads them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgQuoting {
+    /// Values in double quotes, as in a link.exe response file
+    Msvc,
+    /// Each arg as a single quoted PowerShell string
+    PowerShell,
+    /// Each arg single quoted for a POSIX shell
+    Posix,
+    /// Each arg double quoted and escaped as a whole, for a response file passed with `@file`
+    Rsp,
+}
+
+impl ArgQuoting {
+    pub fn from_arg(value: Option<&str>) -> ArgQuoting {
+        match value {
+            Some("powershell") => ArgQuoting::PowerShell,
+            Some("posix") => ArgQuoting::Posix,
+            Some("rsp") => ArgQuoting::Rsp,
+            _ => ArgQuoting::Msvc,
+        }
+    }
+
+    /// Quote an MSVC style line, e.g. `/LIBPATH:"C:\a b"`, for this consumer
+    fn requote(self, line: &str) -> String {
+        // Windows paths can't contain double quotes, so all of them are quoting
+        let arg = line.replace('"', "");
+        match self {
+            ArgQuoting::Msvc => line.to_owned(),
+            ArgQuoting::PowerShell => format!("'{}'", arg.replace('\'', "''")),
+            ArgQuoting::Posix => format!("'{}'", arg.replace('\'', r"'\''")),
+            ArgQuoting::Rsp => quote_msvc_arg(&arg),
+        }
+    }
+}
+
+/// Double quote an arg the way the MSVC runtime and link.exe split response files: backslashes
+/// are only escaped before a double quote, including the closing one
+fn quote_msvc_arg(arg: &str) -> String {
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        if c == '"' {
+            backslashes = backslashes * 2 + 1;
+        }
+        quoted.extend(std::iter::repeat_n('\\', backslashes));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// The encoding of the output files with `--arg-quoting rsp`. LIB.EXE and link.exe read
+/// response files as UTF-16 if they start with its byte order mark, and as ANSI otherwise,
+/// which mangles non-ASCII paths without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFileEncoding {
+    Utf8,
+    Utf8Bom,
+    Utf16,
+}
+
+impl ResponseFileEncoding {
+    pub fn from_arg(value: Option<&str>) -> ResponseFileEncoding {
+        match value {
+            Some("utf8-bom") => ResponseFileEncoding::Utf8Bom,
+            Some("utf16") => ResponseFileEncoding::Utf16,
+            _ => ResponseFileEncoding::Utf8,
+        }
+    }
+
+    /// The bytes of a response file with the lines of `contents`, ending with CRLF
+    pub fn encode(self, contents: &str) -> Vec<u8> {
+        let contents = contents.replace('\n', "\r\n");
+        match self {
+            ResponseFileEncoding::Utf8 => contents.into_bytes(),
+            ResponseFileEncoding::Utf8Bom => format!("\u{feff}{}", contents).into_bytes(),
+            ResponseFileEncoding::Utf16 => "\u{feff}"
+                .encode_utf16()
+                .chain(contents.encode_utf16())
+                .flat_map(u16::to_le_bytes)
+                .collect(),
+        }
+    }
+}
+
+/// The bytes of an output file with `contents`: as they are, or as a response file
+pub fn output_file_bytes(contents: &str, encoding: Option<ResponseFileEncoding>) -> Vec<u8> {
+    match encoding {
+        Some(encoding) => encoding.encode(contents),
+        None => contents.as_bytes().to_owned(),
+    }
+}
+
+/// The contents of the linker and lib files derived from a single link line
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkerOutput {
+    /// Lines of OUTPUT_LINKER_FILE
+    pub linker_lines: Vec<String>,
+    /// Lines of OUTPUT_LIB_LINK_FILE
+    pub lib_lines: Vec<String>,
+    /// The `/DEF` file rustc generated, which needs to be copied to the def file path
+    pub def_file: Option<PathBuf>,
+    /// Options of the link line that weren't written to either file
+    #[serde(default)]
+    pub dropped_args: Vec<String>,
+}
+
+impl LinkerOutput {
+    pub fn linker_file_contents(&self) -> String {
+        self.linker_lines
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    pub fn lib_file_contents(&self) -> String {
+        self.lib_lines
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    /// The object and rlib files in the lib file
+    pub fn lib_inputs(&self) -> impl Iterator<Item = &str> {
+        self.lib_lines.iter().filter_map(|line| {
+            let quote = line.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            line.strip_prefix(quote)?.strip_suffix(quote)
+        })
+    }
+
+    /// Make the relative inputs of both files absolute by resolving them against `dir`, cargo's
+    /// working directory. Linker file inputs without a directory (e.g. `kernel32.lib`) are
+    /// searched for by the linker and stay as they are.
+    pub fn resolve_relative_inputs(&mut self, dir: &Path) {
+        let lines = self
+            .lib_lines
+            .iter_mut()
+            .map(|line| (line, true))
+            .chain(self.linker_lines.iter_mut().map(|line| (line, false)));
+        for (line, is_lib_line) in lines {
+            let input = match line.strip_prefix('"').and_then(|l| l.strip_suffix('"')) {
+                Some(input) if !is_absolute_path(input) => input,
+                _ => continue,
+            };
+            if is_lib_line || input.contains(['/', '\\']) {
+                *line = format!("\"{}\"", dir.join(input).to_string_lossy());
+            }
+        }
+    }
+
+    /// The thin archives among the inputs of the lib file, whose members LIB.EXE can't find
+    pub fn thin_archives(&self) -> Vec<&str> {
+        self.lib_inputs()
+            .filter(|input| archive::is_thin_archive(Path::new(input)))
+            .collect()
+    }
+
+    /// Replace the thin archives of the lib file with the objects they reference
+    pub fn expand_thin_archives(&mut self) -> anyhow::Result<()> {
+        let mut lib_lines = Vec::with_capacity(self.lib_lines.len());
+        for line in std::mem::take(&mut self.lib_lines) {
+            match line.strip_prefix('"').and_then(|l| l.strip_suffix('"')) {
+                Some(input) if archive::is_thin_archive(Path::new(input)) => {
+                    for member in archive::thin_archive_members(Path::new(input))? {
+                        lib_lines.push(format!("\"{}\"", member.to_string_lossy()));
+                    }
+                }
+                _ => lib_lines.push(line),
+            }
+        }
+        self.lib_lines = lib_lines;
+        Ok(())
+    }
+
+    /// Rewrite every line of both files with the given quoting
+    pub fn requote(&mut self, quoting: ArgQuoting) {
+        for line in self.linker_lines.iter_mut().chain(&mut self.lib_lines) {
+            *line = quoting.requote(line);
+        }
+    }
+
+    /// Copy the object files of the lib file into `dir` and point the lib file at the copies, so
+    /// they survive cargo cleaning up its temporary files
+    pub fn copy_objects_to(&mut self, dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let mut copied_names = HashSet::new();
+        for line in &mut self.lib_lines {
+            let object = match object_input(line) {
+                Some(object) => PathBuf::from(object),
+                None => continue,
+            };
+            let file_name = object.file_name().unwrap_or_default().to_string_lossy();
+            let mut copy_name = file_name.to_string();
+            let mut suffix = 1;
+            while !copied_names.insert(copy_name.clone()) {
+                copy_name = format!("{}-{}", suffix, file_name);
+                suffix += 1;
+            }
+            let copy_path = dir.join(copy_name);
+            std::fs::copy(&object, &copy_path)?;
+            *line = format!("\"{}\"", copy_path.to_string_lossy());
+        }
+        Ok(())
+    }
+
+    /// Set a `/NAME:value` option in the linker file, replacing any earlier occurrence of it
+    pub fn set_linker_option(&mut self, name: &str, value: &str) {
+        let prefix = format!("/{}:", name);
+        self.linker_lines.retain(|line| !line.starts_with(&prefix));
+        self.linker_lines.push(format!("{}{}", prefix, value));
+    }
+
+    /// Drop the `/name` options (matched case insensitively) from the linker file
+    pub fn remove_linker_option(&mut self, name: &str) {
+        self.linker_lines.retain(|line| {
+            let option = line.strip_prefix('/').unwrap_or_default();
+            let option_name = option.split(':').next().unwrap_or_default();
+            !option_name.eq_ignore_ascii_case(name)
+        });
+    }
+
+    /// Have UE's link write a map file to `path`, replacing any map file option of the link line.
+    /// ld64, recognized by its exported symbols list, takes `-map` instead of `-Map`.
+    pub fn set_map_file(&mut self, flavor: LinkerFlavor, path: &Path) {
+        let path = path.to_string_lossy();
+        if flavor == LinkerFlavor::Msvc {
+            self.remove_linker_option("MAP");
+            self.linker_lines.push(format!("/MAP:\"{}\"", path));
+            return;
+        }
+        self.linker_lines
+            .retain(|line| !line.starts_with("-Wl,-Map=") && !line.starts_with("-Wl,-map,"));
+        let ld64 = self
+            .linker_lines
+            .iter()
+            .any(|line| line.contains(EXPORTED_SYMBOLS_LIST_OPTION));
+        self.linker_lines.push(if ld64 {
+            format!("-Wl,-map,\"{}\"", path)
+        } else {
+            format!("-Wl,-Map=\"{}\"", path)
+        });
+    }
+
+    /// Drop the system libraries named in `libs` (case insensitively, with or without `.lib`)
+    /// from the linker file, whether they're given as an input or with `/DEFAULTLIB`
+    pub fn remove_system_libs(&mut self, libs: &[String]) {
+        let excluded = |library: &str| {
+            let library = library.trim_matches('"');
+            let library = library.rsplit(['/', '\\']).next().unwrap_or(library);
+            let name = match library.len().checked_sub(4) {
+                Some(stem) if library[stem..].eq_ignore_ascii_case(".lib") => &library[..stem],
+                _ => library,
+            };
+            libs.iter().any(|lib| {
+                let lib = lib.strip_suffix(".lib").unwrap_or(lib);
+                lib.eq_ignore_ascii_case(name)
+            })
+        };
+        self.linker_lines.retain(|line| {
+            if line.starts_with('/') {
+                let (name, value) = split_option(line);
+                !(name.eq_ignore_ascii_case("DEFAULTLIB") && excluded(value))
+            } else {
+                !(line.starts_with('"') && excluded(line))
+            }
+        });
+    }
+
+    /// Replace the directory `prefix` with `placeholder` wherever a line of either file contains
+    /// a path in it
+    pub fn replace_prefix(&mut self, prefix: &str, placeholder: &str) {
+        let prefix = prefix.trim_end_matches(['/', '\\']);
+        if prefix.is_empty() {
+            return;
+        }
+        for line in self.linker_lines.iter_mut().chain(&mut self.lib_lines) {
+            *line = replace_dir(line, prefix, placeholder);
+        }
+    }
+
+    /// Append the lines of `other`, for combining the inputs of several link lines. The first
+    /// `/DEF` file is kept. `deduplicate` removes the lines both have afterwards.
+    pub fn merge(&mut self, other: LinkerOutput) {
+        self.linker_lines.extend(other.linker_lines);
+        self.lib_lines.extend(other.lib_lines);
+        if self.def_file.is_none() {
+            self.def_file = other.def_file;
+        }
+        for arg in other.dropped_args {
+            if !self.dropped_args.contains(&arg) {
+                self.dropped_args.push(arg);
+            }
+        }
+    }
+}
+
+impl LinkerOutput {
+    /// Remove redundant lines and order the lib file deterministically, so that the output files
+    /// only change when the link does. The lib file's inputs are archived, where their order
+    /// doesn't matter, so they're sorted after its options.
+    pub fn deduplicate(&mut self, flavor: LinkerFlavor) {
+        let mut seen = HashSet::new();
+        self.lib_lines.retain(|line| seen.insert(line.clone()));
+        // a stable sort, so the options keep their order
+        self.lib_lines.sort_by(|a, b| {
+            let input = |line: &String| line.starts_with('"').then(|| line.clone());
+            input(a).cmp(&input(b))
+        });
+        match flavor {
+            // link.exe searches every library whatever its position
+            LinkerFlavor::Msvc => {
+                let mut seen = HashSet::new();
+                self.linker_lines.retain(|line| seen.insert(line.clone()));
+            }
+            LinkerFlavor::Gnu => dedup_gnu_linker_lines(&mut self.linker_lines),
+        }
+    }
+}
+
+/// Which positional option a `-Wl,` option overrides, e.g. `-Bdynamic` overrides `-Bstatic`
+fn gnu_positional_group(line: &str) -> Option<&'static str> {
+    match line.strip_prefix("-Wl,")? {
+        "-Bstatic" | "-Bdynamic" => Some("static"),
+        "--whole-archive" | "--no-whole-archive" => Some("whole-archive"),
+        "--as-needed" | "--no-as-needed" => Some("as-needed"),
+        _ => None,
+    }
+}
+
+/// Deduplicate the lines of a GNU linker file without changing how anything is linked. GNU
+/// linkers only search an archive for the symbols undefined at its position, so of a library
+/// given twice with the same positional options in effect, the last one is kept. Search paths
+/// and other options are kept the first time. Positional options that are overridden before
+/// anything follows them, or that are in effect already, are removed. Trailing ones affect the
+/// libraries UE links after the linker file, so they stay.
+fn dedup_gnu_linker_lines(lines: &mut Vec<String>) {
+    let is_library = |line: &str| line.starts_with('"') || line.starts_with("-l");
+    let mut state = BTreeMap::new();
+    let mut states = Vec::with_capacity(lines.len());
+    for line in lines.iter() {
+        if let Some(group) = gnu_positional_group(line) {
+            state.insert(group, line.clone());
+        }
+        states.push(state.clone());
+    }
+    let mut keep = vec![true; lines.len()];
+    let mut seen_libraries = HashSet::new();
+    for idx in (0..lines.len()).rev() {
+        if is_library(&lines[idx]) {
+            keep[idx] = seen_libraries.insert((&lines[idx], &states[idx]));
+        }
+    }
+    let mut seen_options = HashSet::new();
+    for (idx, line) in lines.iter().enumerate() {
+        if !is_library(line) && gnu_positional_group(line).is_none() {
+            keep[idx] = seen_options.insert(line);
+        }
+    }
+
+    let mut state = BTreeMap::new();
+    for idx in 0..lines.len() {
+        let Some(group) = gnu_positional_group(&lines[idx]) else {
+            continue;
+        };
+        let next = (idx + 1..lines.len())
+            .filter(|next| keep[*next])
+            .find(|next| gnu_positional_group(&lines[*next]).is_none_or(|g| g == group));
+        let overridden = next.is_some_and(|next| gnu_positional_group(&lines[next]).is_some());
+        if overridden || state.get(group) == Some(&lines[idx]) {
+            keep[idx] = false;
+        } else {
+            state.insert(group, lines[idx].clone());
+        }
+    }
+    let mut keep = keep.into_iter();
+    lines.retain(|_| keep.next().unwrap_or(true));
+}
+
+/// `line` with `dir` replaced by `placeholder` where it's followed by a path separator, a quote
+/// or the end of the line, so that e.g. `C:\rust` isn't replaced in `C:\rust-libs`
+fn replace_dir(line: &str, dir: &str, placeholder: &str) -> String {
+    let mut replaced = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(dir) {
+        let end = start + dir.len();
+        let whole = rest[end..]
+            .chars()
+            .next()
+            .is_none_or(|c| matches!(c, '/' | '\\' | '"' | '\''));
+        replaced.push_str(&rest[..start]);
+        replaced.push_str(if whole { placeholder } else { dir });
+        rest = &rest[end..];
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+/// The object file (`.o` or `.obj`) a lib file line names, quoted or not. Options aren't inputs.
+fn object_input(line: &str) -> Option<&str> {
+    let input = match line.chars().next()? {
+        quote @ ('"' | '\'') => line.strip_prefix(quote)?.strip_suffix(quote)?,
+        '-' => return None,
+        _ => line,
+    };
+    let extension = Path::new(input).extension()?.to_str()?;
+    (extension == "o" || extension.eq_ignore_ascii_case("obj")).then_some(input)
+}
+
+/// Whether a path is absolute on either Unix or Windows, whichever the tool runs on
+fn is_absolute_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with(['/', '\\'])
+        || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+/// Linkers whose command lines `parse_link_line` understands
+const KNOWN_LINKERS: &[&str] = &["link.exe", "lld-link.exe", "rust-lld.exe"];
+
+/// GNU style linkers and linker drivers `parse_link_line` understands, besides cross
+/// compilation prefixed ones like `x86_64-linux-gnu-gcc`
+const KNOWN_GNU_LINKERS: &[&str] = &[
+    "cc", "c++", "gcc", "g++", "clang", "clang++", "ld", "ld.bfd", "ld.gold", "ld.lld", "ld64",
+    "ld64.lld", "rust-lld",
+];
+
+fn is_gnu_linker(name: &str) -> bool {
+    // e.g. the NDK's clang wrappers on Windows
+    let name = name
+        .strip_suffix(".cmd")
+        .or_else(|| name.strip_suffix(".exe"))
+        .unwrap_or(name);
+    KNOWN_GNU_LINKERS.contains(&name)
+        || KNOWN_GNU_LINKERS
+            .iter()
+            .any(|linker| name.strip_suffix(linker).is_some_and(|p| p.ends_with('-')))
+}
+
+/// How many of the following args are the values of a GNU or ld64 option, e.g. 1 for `-L dir`
+/// or `-o out` and 0 if the value is attached
+fn gnu_option_value_count(arg: &str) -> usize {
+    match arg {
+        "-o" | "-L" | "-l" | "-z" | "-T" | "-e" | "-m" | "-Xlinker" => 1,
+        // ld64
+        "-arch" | "-framework" | "-syslibroot" | "-isysroot" | "-install_name" => 1,
+        EXPORTED_SYMBOLS_LIST_OPTION | FORCE_LOAD_OPTION => 1,
+        "-platform_version" => 3,
+        _ => 0,
+    }
+}
+
+/// `-Wl,` options that change how the libraries after them are linked, so they have to stay in
+/// place among the libraries of the linker file
+const GNU_POSITIONAL_OPTIONS: &[&str] = &[
+    "-Bstatic",
+    "-Bdynamic",
+    "--whole-archive",
+    "--no-whole-archive",
+    "--as-needed",
+    "--no-as-needed",
+];
+
+/// The export list option rustc passes GNU linkers for a cdylib, the counterpart of `/DEF`
+const VERSION_SCRIPT_OPTION: &str = "-Wl,--version-script=";
+
+/// ld64's counterpart of `/DEF`, passed as `-Wl,-exported_symbols_list,<path>` through a driver
+const EXPORTED_SYMBOLS_LIST_OPTION: &str = "-exported_symbols_list";
+
+/// ld64's option to link every member of an archive, passed as `-Wl,-force_load,<path>`
+/// through a driver
+const FORCE_LOAD_OPTION: &str = "-force_load";
+
+/// The import library option of MinGW's ld, the counterpart of `/IMPLIB`
+const OUT_IMPLIB_OPTION: &str = "-Wl,--out-implib=";
+
+/// Startup objects rustc passes MinGW linkers itself, which belong in the linker file rather than
+/// being archived with the crate's objects
+const MINGW_STARTUP_OBJECTS: &[&str] = &[
+    "crt2.o",
+    "dllcrt2.o",
+    "crtbegin.o",
+    "crtend.o",
+    "rsbegin.o",
+    "rsend.o",
+];
+
+/// The `.def` export list rustc passes MinGW linkers for a cdylib, as an input or through
+/// `-Wl,`, the counterpart of `/DEF`
+fn mingw_def_file(arg: &str) -> Option<&str> {
+    let path = arg.strip_prefix("-Wl,").unwrap_or(arg);
+    (path.ends_with(".def") && !path.starts_with('-')).then_some(path)
+}
+
+/// The file name of a path that may use either kind of separator
+pub fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Whether a line references a `.def` file, a GNU version script or an ld64 exported symbols
+/// list, as the link lines of cdylibs do whatever their linker
+fn exports_symbols(line: &str) -> bool {
+    line.contains(".def")
+        || line.contains("--version-script=")
+        || line.contains(EXPORTED_SYMBOLS_LIST_OPTION)
+}
+
+/// Find every link line in rustc's `--print link-args` output. A crate with several linked
+/// crate types (e.g. a cdylib and a staticlib) prints one per artifact. Lines run by one of
+/// `linkers` are recognized in addition to those of the known linkers.
+pub fn find_link_lines<'a>(stdout: &'a str, linkers: &[&str]) -> Vec<&'a str> {
+    stdout
+        .lines()
+        .filter(|line| {
+            exports_symbols(line)
+                || linker_token(line).is_some_and(|token| {
+                    KNOWN_LINKERS.contains(&file_name(&token))
+                        || is_gnu_linker(file_name(&token))
+                        || linkers
+                            .iter()
+                            .any(|linker| file_name(&token) == file_name(linker))
+                })
+        })
+        .collect()
+}
+
+/// The value of the `/OUT` (or for GNU linkers `-o`) option of a link line, i.e. the path of the
+/// artifact it produces
+pub fn link_line_output(link_line: &str) -> Option<String> {
+    let args = tokenize_link_line(link_line);
+    match LinkerFlavor::detect(link_line) {
+        LinkerFlavor::Msvc => args
+            .iter()
+            .filter(|arg| arg.starts_with('/') || arg.starts_with('-'))
+            .map(|arg| split_option(arg))
+            .find(|(name, _)| name.eq_ignore_ascii_case("OUT"))
+            .map(|(_, value)| value.to_owned()),
+        LinkerFlavor::Gnu => args
+            .iter()
+            .position(|arg| arg == "-o")
+            .and_then(|idx| args.get(idx + 1))
+            .cloned(),
+    }
+}
+
+/// The file name of the artifact a link line produces
+pub fn link_line_artifact(link_line: &str) -> Option<String> {
+    link_line_output(link_line).map(|out| file_name(&out).to_owned())
+}
+
+/// Pick the link line producing `artifact`, matched against the file name of its `/OUT` option,
+/// or failing that its file name without extension. Without an artifact this is the cdylib's
+/// line, i.e. the last one referencing a `.def` file, a GNU version script or an ld64 exported
+/// symbols list.
+pub fn select_link_line<'a>(link_lines: &[&'a str], artifact: Option<&str>) -> Option<&'a str> {
+    // verbose cargo output may follow the link lines, so search backwards
+    let mut candidates = link_lines.iter().rev().copied();
+    let artifact = match artifact {
+        Some(artifact) => artifact,
+        None => return candidates.find(|line| exports_symbols(line)),
+    };
+    let artifacts: Vec<_> = candidates
+        .filter_map(|line| Some((line, link_line_artifact(line)?)))
+        .collect();
+    artifacts
+        .iter()
+        .find(|(_, out)| out == artifact)
+        .or_else(|| {
+            artifacts
+                .iter()
+                .find(|(_, out)| Path::new(out).file_stem() == Some(artifact.as_ref()))
+        })
+        .map(|(line, _)| *line)
+}
+
+/// Split a MSVC or GNU link line into the args for the linker and lib files. References to the
+/// `/DEF` file or GNU version script are rewritten to `def_file_path`. Link lines of linkers
+/// that aren't known are parsed as `LinkerFlavor::guess` guesses. The `rustc` subcommand picks
+/// the flavor itself (see `--linker-format` and `--allowed-linkers`) and uses
+/// `parse_link_line_as`.
+#[cfg(test)]
+pub fn parse_link_line(link_line: &str, def_file_path: &Path) -> LinkerOutput {
+    parse_link_line_as(link_line, def_file_path, link_line_flavor(link_line))
+}
+
+/// A linker accepted besides the known ones, e.g. `clang-cl` or a team's wrapper script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedLinker {
+    pub name: String,
+    pub flavor: LinkerFlavor,
+}
+
+impl AllowedLinker {
+    /// Parse `name`, or `name=msvc` / `name=gnu` for the flavor of its link lines, which
+    /// defaults to MSVC like `--linker`'s
+    pub fn from_arg(arg: &str) -> Result<AllowedLinker, String> {
+        let (name, flavor) = match arg.split_once('=') {
+            Some((name, "msvc")) => (name, LinkerFlavor::Msvc),
+            Some((name, "gnu")) => (name, LinkerFlavor::Gnu),
+            Some((_, flavor)) => {
+                return Err(format!(
+                    "Unknown linker flavor \"{}\" of allowed linker {}, expected msvc or gnu",
+                    flavor, arg
+                ))
+            }
+            None => (arg, LinkerFlavor::Msvc),
+        };
+        Ok(AllowedLinker {
+            name: name.to_owned(),
+            flavor,
+        })
+    }
+
+    fn matches(&self, linker: &str) -> bool {
+        let name = file_name(linker);
+        let stem = name.strip_suffix(".exe").unwrap_or(name);
+        let allowed = file_name(&self.name);
+        name == allowed || stem == allowed.strip_suffix(".exe").unwrap_or(allowed)
+    }
+}
+
+/// The flavor of the link line's linker if it's one of the known linkers or of `allowed`
+pub fn known_linker_flavor(link_line: &str, allowed: &[AllowedLinker]) -> Option<LinkerFlavor> {
+    let linker = linker_token(link_line)?;
+    if KNOWN_LINKERS.contains(&file_name(&linker)) {
+        Some(LinkerFlavor::Msvc)
+    } else if is_gnu_linker(file_name(&linker)) {
+        Some(LinkerFlavor::Gnu)
+    } else {
+        allowed
+            .iter()
+            .find(|allowed| allowed.matches(&linker))
+            .map(|allowed| allowed.flavor)
+    }
+}
+
+/// Like `parse_link_line`, but for a linker of the given flavor, whatever its name. This
+/// supports linker shims `parse_link_line` doesn't recognize.
+pub fn parse_link_line_as(
+    link_line: &str,
+    def_file_path: &Path,
+    flavor: LinkerFlavor,
+) -> LinkerOutput {
+    parse_link_args(
+        linker_args(&flavor.tokenize(link_line)),
+        def_file_path,
+        flavor,
+    )
+}
+
+fn parse_link_args(args: &[String], def_file_path: &Path, flavor: LinkerFlavor) -> LinkerOutput {
+    if flavor == LinkerFlavor::Gnu {
+        return parse_gnu_link_args(args, def_file_path);
+    }
+    let mut output = LinkerOutput::default();
+    let mut idx = 0;
+    while idx < args.len() {
+        let arg = &args[idx];
+        idx += 1;
+        if let Some(value_is_separate) = flavor_option(arg) {
+            if value_is_separate {
+                // consume argument
+                idx += 1;
+            }
+        } else if flavor.is_option(arg) {
+            let (option_name, option_arg) = split_option(arg);
+            match option_name {
+                "LIBPATH" | "IMPLIB" => {
+                    output
+                        .linker_lines
+                        .push(format!("/{}:\"{}\"", option_name, option_arg));
+                }
+                name if FORWARDED_MSVC_OPTIONS
+                    .iter()
+                    .any(|forwarded| forwarded.eq_ignore_ascii_case(name)) =>
+                {
+                    output
+                        .linker_lines
+                        .push(format_option(option_name, option_arg));
+                }
+                "DEF" => {
+                    output.def_file = Some(option_arg.into());
+                    // include DEF file for both linker and lib
+                    let def_line = format!("/DEF:\"{}\"", def_file_path.to_string_lossy());
+                    output.linker_lines.push(def_line.clone());
+                    output.lib_lines.push(def_line);
+                }
+                _ => output.dropped_args.push(arg.clone()),
+            }
+        } else if !arg.ends_with(".exe") {
+            if arg.ends_with(".o") || arg.ends_with(".rlib") {
+                // include only object/rlib files in lib file
+                output.lib_lines.push(format!("\"{}\"", arg));
+            } else {
+                output.linker_lines.push(format!("\"{}\"", arg));
+            }
+        }
+    }
+    // delay loaded DLLs need the delay load helper
+    let delay_loads = output
+        .linker_lines
+        .iter()
+        .any(|line| line.to_ascii_uppercase().starts_with("/DELAYLOAD:"));
+    let has_helper = output
+        .linker_lines
+        .iter()
+        .any(|line| file_name(line.trim_matches('"')).eq_ignore_ascii_case(DELAY_LOAD_HELPER));
+    if delay_loads && !has_helper {
+        output
+            .linker_lines
+            .push(format!("\"{}\"", DELAY_LOAD_HELPER));
+    }
+    output
+}
+
+/// The ld64 option and value of a `-Wl,<option>,<value>` arg, or of `<option>` followed by its
+/// value when ld64 is run directly
+fn ld64_option<'a>(
+    arg: &'a str,
+    args: &mut std::slice::Iter<'a, String>,
+    option: &str,
+) -> Option<&'a str> {
+    if arg == option {
+        return Some(args.next().map(String::as_str).unwrap_or_default());
+    }
+    arg.strip_prefix("-Wl,")?
+        .strip_prefix(option)?
+        .strip_prefix(',')
+}
+
+/// Split the args of a GNU or ld64 linker (driver) into the args for the linker and archive
+/// files. Library search paths, libraries and frameworks go to the linker file along with the
+/// `-Wl,` options affecting how they're linked, other options are dropped like for MSVC. The
+/// linker file is meant for a linker driver like `cc`, so ld64 options are written as `-Wl,`
+/// options.
+fn parse_gnu_link_args(args: &[String], def_file_path: &Path) -> LinkerOutput {
+    let mut output = LinkerOutput::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if flavor_option(arg).is_some_and(|value_is_separate| value_is_separate) {
+            args.next();
+        } else if let Some(version_script) = arg.strip_prefix(VERSION_SCRIPT_OPTION) {
+            output.def_file = Some(version_script.into());
+            output.linker_lines.push(format!(
+                "{}\"{}\"",
+                VERSION_SCRIPT_OPTION,
+                def_file_path.to_string_lossy()
+            ));
+        } else if let Some(def_file) = mingw_def_file(arg) {
+            output.def_file = Some(def_file.into());
+            output
+                .linker_lines
+                .push(format!("\"{}\"", def_file_path.to_string_lossy()));
+        } else if let Some(implib) = arg.strip_prefix(OUT_IMPLIB_OPTION) {
+            output
+                .linker_lines
+                .push(format!("{}\"{}\"", OUT_IMPLIB_OPTION, implib));
+        } else if let Some(list) = ld64_option(arg, &mut args, EXPORTED_SYMBOLS_LIST_OPTION) {
+            output.def_file = Some(list.into());
+            output.linker_lines.push(format!(
+                "-Wl,{},\"{}\"",
+                EXPORTED_SYMBOLS_LIST_OPTION,
+                def_file_path.to_string_lossy()
+            ));
+        } else if let Some(archive) = ld64_option(arg, &mut args, FORCE_LOAD_OPTION) {
+            output
+                .linker_lines
+                .push(format!("-Wl,{},\"{}\"", FORCE_LOAD_OPTION, archive));
+        } else if gnu_option_value_count(arg) > 0 {
+            let values: Vec<&String> = args.by_ref().take(gnu_option_value_count(arg)).collect();
+            let value = values
+                .first()
+                .map(|value| value.as_str())
+                .unwrap_or_default();
+            match arg.as_str() {
+                "-L" => output.linker_lines.push(format!("-L\"{}\"", value)),
+                "-l" => output.linker_lines.push(format!("-l{}", value)),
+                "-framework" => output
+                    .linker_lines
+                    .push(format!("-Wl,-framework,{}", value)),
+                // e.g. "-z relro"
+                _ => output.dropped_args.push(
+                    std::iter::once(arg)
+                        .chain(values)
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+            }
+        } else if let Some(dir) = arg.strip_prefix("-L") {
+            output.linker_lines.push(format!("-L\"{}\"", dir));
+        } else if arg.starts_with("-l")
+            || arg
+                .strip_prefix("-Wl,")
+                .is_some_and(|option| GNU_POSITIONAL_OPTIONS.contains(&option))
+        {
+            output.linker_lines.push(arg.clone());
+        } else if arg.starts_with('-') {
+            output.dropped_args.push(arg.clone());
+        } else if (arg.ends_with(".o") && !MINGW_STARTUP_OBJECTS.contains(&file_name(arg)))
+            || arg.ends_with(".rlib")
+        {
+            output.lib_lines.push(format!("\"{}\"", arg));
+        } else {
+            output.linker_lines.push(format!("\"{}\"", arg));
+        }
+    }
+    output
+}
+
+/// The `name` options (matched case insensitively) of a link line, formatted for the linker
+/// file, to keep options `parse_link_line` would otherwise drop
+pub fn find_options(link_line: &str, flavor: LinkerFlavor, name: &str) -> Vec<String> {
+    linker_args(&flavor.tokenize(link_line))
+        .iter()
+        .filter(|arg| flavor.is_option(arg) && flavor_option(arg).is_none())
+        .map(|arg| split_option(arg))
+        .filter(|(option_name, _)| option_name.eq_ignore_ascii_case(name))
+        .map(|(option_name, option_arg)| format_option(option_name, option_arg))
+        .collect()
+}
+
+/// Format an MSVC option for the linker file, with its value quoted if it has one
+fn format_option(name: &str, value: &str) -> String {
+    if value.is_empty() {
+        format!("/{}", name)
+    } else {
+        format!("/{}:\"{}\"", name, value)
+    }
+}
+
+/// An input of the link line that didn't end up in exactly one of the output files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoutingAnomaly {
+    Dropped(String),
+    Duplicated(String),
+}
+
+impl std::fmt::Display for RoutingAnomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoutingAnomaly::Dropped(token) => {
+                write!(
+                    f,
+                    "link input {} wasn't written to either output file",
+                    token
+                )
+            }
+            RoutingAnomaly::Duplicated(token) => write!(
+                f,
+                "link input {} was written to both the linker and lib files",
+                token
+            ),
+        }
+    }
+}
+
+/// Check that every input (object, rlib or library) of the link line was routed to exactly one
+/// of the output files. This tokenizes the link line independently of `parse_link_line`'s
+/// routing, so misparses there show up as anomalies.
+#[cfg(test)]
+pub fn check_routing(link_line: &str, output: &LinkerOutput) -> Vec<RoutingAnomaly> {
+    check_routing_as(link_line, output, link_line_flavor(link_line))
+}
+
+/// Like `check_routing`, for a link line parsed with `parse_link_line_as` as `flavor`
+pub fn check_routing_as(
+    link_line: &str,
+    output: &LinkerOutput,
+    flavor: LinkerFlavor,
+) -> Vec<RoutingAnomaly> {
+    let inputs_of = |lines: &[String]| -> Vec<String> {
+        lines
+            .iter()
+            .filter_map(|line| line.strip_prefix('"')?.strip_suffix('"'))
+            .map(str::to_owned)
+            .collect()
+    };
+    let linker_inputs = inputs_of(&output.linker_lines);
+    let lib_inputs = inputs_of(&output.lib_lines);
+
+    let mut anomalies = Vec::new();
+    let tokens = flavor.tokenize(link_line);
+    let mut args = linker_args(&tokens).iter().cloned();
+    while let Some(arg) = args.next() {
+        if let Some(value_is_separate) = flavor_option(&arg) {
+            if value_is_separate {
+                args.next();
+            }
+            continue;
+        }
+        if flavor == LinkerFlavor::Gnu && gnu_option_value_count(&arg) > 0 {
+            args.by_ref()
+                .take(gnu_option_value_count(&arg))
+                .for_each(drop);
+            continue;
+        }
+        if flavor.is_option(&arg) {
+            continue;
+        }
+        if arg.ends_with(".exe") {
+            continue;
+        }
+        // MinGW's export list is rewritten to the def file path like `/DEF`
+        if flavor == LinkerFlavor::Gnu && mingw_def_file(&arg).is_some() {
+            continue;
+        }
+        match (linker_inputs.contains(&arg), lib_inputs.contains(&arg)) {
+            (false, false) => anomalies.push(RoutingAnomaly::Dropped(arg)),
+            (true, true) => anomalies.push(RoutingAnomaly::Duplicated(arg)),
+            _ => {}
+        }
+    }
+    anomalies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_option_keeps_colons_in_value() {
+        assert_eq!(
+            split_option(r"/LIBPATH:C:\Program Files\x"),
+            ("LIBPATH", r"C:\Program Files\x")
+        );
+        assert_eq!(split_option(r"/IMPLIB:C:\a:b"), ("IMPLIB", r"C:\a:b"));
+        assert_eq!(split_option("/DLL"), ("DLL", ""));
+        assert_eq!(split_option("-NOLOGO"), ("NOLOGO", ""));
+    }
+
+    #[test]
+    fn quoted_option_values_stay_attached() {
+        assert_eq!(
+            parse_quotes(r#""/LIBPATH:C:\\Program Files\\x" /IMPLIB:"C:\\a:b" /DLL"#),
+            vec![r"/LIBPATH:C:\Program Files\x", r"/IMPLIB:C:\a:b", "/DLL"]
+        );
+    }
+
+    #[test]
+    fn posix_quotes() {
+        assert_eq!(
+            parse_posix_quotes(r#"'a b' "c \"d\" \e" f\ g '' 'h\'i"#),
+            vec!["a b", r#"c "d" \e"#, "f g", "", r"h\i"]
+        );
+    }
+
+    #[test]
+    fn gnu_link_line_with_single_quoted_paths() {
+        let link_line = concat!(
+            r#"LC_ALL="C" PATH="/usr/bin:/bin" VSLANG="1033" "cc" "-m64" "#,
+            r#"'/home/a b/target/debug/deps/foo.foo.1a2b-cgu.0.rcgu.o' "#,
+            r#"'-Wl,--version-script=/tmp/rustc x/list' "-Wl,--as-needed" "#,
+            r#"'/home/a b/.rustup/toolchains/stable/lib/libstd-123.rlib' "-lgcc_s" "#,
+            r#""-L" '/home/a b/target/debug/deps' "-o" '/home/a b/target/debug/libfoo.so'"#,
+        );
+        let args = tokenize_link_line(link_line);
+        assert_eq!(
+            &args[..4],
+            ["LC_ALL=C", "PATH=/usr/bin:/bin", "VSLANG=1033", "cc"]
+        );
+        assert!(args.contains(&"/home/a b/target/debug/deps/foo.foo.1a2b-cgu.0.rcgu.o".to_owned()));
+        assert!(args.contains(&"-Wl,--version-script=/tmp/rustc x/list".to_owned()));
+        assert!(
+            args.contains(&"/home/a b/.rustup/toolchains/stable/lib/libstd-123.rlib".to_owned())
+        );
+        assert_eq!(args.len(), 14);
+
+        // MSVC link lines keep using the MSVC tokenizer
+        assert_eq!(
+            tokenize_link_line(r#""link.exe" "/LIBPATH:C:\\a b" 'x'"#),
+            vec!["link.exe", r"/LIBPATH:C:\a b", "'x'"]
+        );
+    }
+
+    /// Check `tokenize_link_line` against the captured link lines in tests/fixtures/link_lines, and
+    /// that the lines' flavor is detected and every input routed. Fixtures named `msvc_*` are
+    /// MSVC link lines.
+    #[test]
+    fn link_line_fixtures() {
+        let fixture_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/link_lines");
+        let mut fixtures = 0;
+        for entry in std::fs::read_dir(&fixture_dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() != Some("txt".as_ref()) {
+                continue;
+            }
+            let contents = std::fs::read_to_string(&path).unwrap();
+            let (link_line, expected) = contents
+                .split_once("\n---\n")
+                .unwrap_or_else(|| panic!("{} has no --- separator", path.display()));
+            let expected: Vec<&str> = expected.lines().collect();
+            assert_eq!(
+                tokenize_link_line(link_line),
+                expected,
+                "{}",
+                path.display()
+            );
+            let msvc = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("msvc_"));
+            let flavor = if msvc {
+                LinkerFlavor::Msvc
+            } else {
+                LinkerFlavor::Gnu
+            };
+            assert_eq!(
+                LinkerFlavor::detect(link_line),
+                flavor,
+                "{}",
+                path.display()
+            );
+            assert_eq!(
+                known_linker_flavor(link_line, &[]),
+                Some(flavor),
+                "{}",
+                path.display()
+            );
+            let output = parse_link_line(link_line, Path::new("build_def.def"));
+            assert_eq!(
+                check_routing(link_line, &output),
+                vec![],
+                "{}",
+                path.display()
+            );
+            fixtures += 1;
+        }
+        assert!(fixtures > 0, "no fixtures in {}", fixture_dir.display());
+    }
+
+    #[test]
+    fn linker_under_program_files() {
+        let link_line = r#""C:\\Program Files\\Microsoft Visual Studio\\2022\\Community\\VC\\Tools\\MSVC\\14.38.33130\\bin\\HostX64\\x64\\link.exe" "/NOLOGO" "/LIBPATH:C:\\t" "C:\\t\\a.o" "/OUT:C:\\t\\a.dll" "/DLL""#;
+        let linker = linker_token(link_line).unwrap();
+        assert_eq!(file_name(&linker), "link.exe");
+        assert_eq!(LinkerFlavor::detect(link_line), LinkerFlavor::Msvc);
+        let output = parse_link_line(link_line, Path::new("build_def.def"));
+        assert_eq!(check_routing(link_line, &output), vec![]);
+    }
+
+    #[test]
+    fn parse_link_line_options_with_colons() {
+        let output = parse_link_line(
+            r#""link.exe" "/LIBPATH:C:\\Program Files\\x" /IMPLIB:"C:\\a:b" /DLL "/DEF:C:\\t\\lib.def""#,
+            Path::new("build_def.def"),
+        );
+        assert_eq!(
+            output.linker_lines,
+            vec![
+                r#"/LIBPATH:"C:\Program Files\x""#,
+                r#"/IMPLIB:"C:\a:b""#,
+                r#"/DEF:"build_def.def""#,
+            ]
+        );
+        assert_eq!(output.lib_lines, vec![r#"/DEF:"build_def.def""#]);
+        assert_eq!(output.def_file, Some(PathBuf::from(r"C:\t\lib.def")));
+    }
+
+    #[test]
+    fn set_linker_option_replaces_existing() {
+        let mut output = parse_link_line(
+            r#""link.exe" "a.o" "/SUBSYSTEM:CONSOLE" "/ENTRY:mainCRTStartup" "/DEBUG""#,
+            Path::new("build_def.def"),
+        );
+        // rustc's are for its own binary, not UE's
+        assert!(output.linker_lines.is_empty());
+        assert_eq!(
+            output.dropped_args,
+            ["/SUBSYSTEM:CONSOLE", "/ENTRY:mainCRTStartup", "/DEBUG"]
+        );
+        output.set_linker_option("SUBSYSTEM", "CONSOLE");
+        output.set_linker_option("ENTRY", "mainCRTStartup");
+        output.set_linker_option("SUBSYSTEM", "WINDOWS");
+        assert_eq!(
+            output.linker_lines,
+            vec!["/ENTRY:mainCRTStartup", "/SUBSYSTEM:WINDOWS"]
+        );
+    }
+
+    #[test]
+    fn deduplicate_gnu_link_lines() {
+        let link_line = r#""cc" "-L/x" "/t/b.o" "/t/a.o" "-Wl,-Bstatic" "/x/libnative.a" "-Wl,-Bdynamic" "-lgcc_s" "-lc""#;
+        let mut output = parse_link_line(link_line, Path::new("build_def.def"));
+        output.merge(parse_link_line(link_line, Path::new("build_def.def")));
+        output.deduplicate(LinkerFlavor::Gnu);
+        assert_eq!(output.lib_lines, vec![r#""/t/a.o""#, r#""/t/b.o""#]);
+        assert_eq!(
+            output.linker_lines,
+            vec![
+                r#"-L"/x""#,
+                "-Wl,-Bstatic",
+                r#""/x/libnative.a""#,
+                "-Wl,-Bdynamic",
+                "-lgcc_s",
+                "-lc"
+            ]
+        );
+
+        // the same library linked statically and dynamically stays twice
+        let lines = [
+            "-Wl,-Bstatic",
+            "-lz",
+            "-Wl,-Bdynamic",
+            "-lc",
+            "-Wl,-Bstatic",
+            "-lz",
+            "-Wl,-Bdynamic",
+            "-lz",
+        ];
+        let mut output = LinkerOutput {
+            linker_lines: lines.iter().map(|line| line.to_string()).collect(),
+            ..LinkerOutput::default()
+        };
+        output.deduplicate(LinkerFlavor::Gnu);
+        assert_eq!(
+            output.linker_lines,
+            vec![
+                "-Wl,-Bdynamic",
+                "-lc",
+                "-Wl,-Bstatic",
+                "-lz",
+                "-Wl,-Bdynamic",
+                "-lz"
+            ]
+        );
+    }
+
+    #[test]
+    fn system_libs_removed() {
+        let mut output = parse_link_line(
+            r#""link.exe" "a.o" "kernel32.lib" "C:\\sdk\\WS2_32.LIB" "bcrypt.lib" "/DEFAULTLIB:msvcrt" "/DEFAULTLIB:oldnames""#,
+            Path::new("build_def.def"),
+        );
+        let libs: Vec<String> = UE_SYSTEM_LIBS.iter().map(|lib| lib.to_string()).collect();
+        output.remove_system_libs(&libs);
+        assert_eq!(
+            output.linker_lines,
+            vec![r#""bcrypt.lib""#, r#"/DEFAULTLIB:"oldnames""#]
+        );
+        output.remove_system_libs(&["bcrypt.lib".to_owned()]);
+        assert_eq!(output.linker_lines, vec![r#"/DEFAULTLIB:"oldnames""#]);
+    }
+
+    #[test]
+    fn map_file() {
+        let mut output = parse_link_line(
+            r#""link.exe" "a.o" "/MAP:C:\\t\\rust.map" "/DEF:C:\\tmp\\lib.def""#,
+            Path::new("build_def.def"),
+        );
+        output.set_map_file(LinkerFlavor::Msvc, Path::new("UnrealGame.map"));
+        assert_eq!(
+            output.linker_lines,
+            vec![r#"/DEF:"build_def.def""#, r#"/MAP:"UnrealGame.map""#]
+        );
+
+        let mut output = parse_link_line(r#""cc" "a.o" "-Wl,-Map=/t/rust.map""#, Path::new("d"));
+        output.set_map_file(LinkerFlavor::Gnu, Path::new("/o/game.map"));
+        assert_eq!(output.linker_lines, vec![r#"-Wl,-Map="/o/game.map""#]);
+    }
+
+    #[test]
+    fn allowed_linkers() {
+        let allowed = [
+            AllowedLinker::from_arg("clang-cl").unwrap(),
+            AllowedLinker::from_arg("/opt/bin/ld-wrapper=gnu").unwrap(),
+        ];
+        let clang_cl = r#""C:\\LLVM\\bin\\clang-cl.exe" "/OUT:C:\\t\\a.dll" "a.o""#;
+        assert_eq!(known_linker_flavor(clang_cl, &[]), None);
+        assert_eq!(
+            known_linker_flavor(clang_cl, &allowed),
+            Some(LinkerFlavor::Msvc)
+        );
+        assert_eq!(LinkerFlavor::guess(clang_cl), LinkerFlavor::Msvc);
+        let wrapper = r#""ld-wrapper" "-o" "/t/liba.so" "/t/a.o""#;
+        assert_eq!(
+            known_linker_flavor(wrapper, &allowed),
+            Some(LinkerFlavor::Gnu)
+        );
+        assert_eq!(LinkerFlavor::guess(wrapper), LinkerFlavor::Gnu);
+        assert_eq!(
+            known_linker_flavor(r#""cc" "a.o""#, &[]),
+            Some(LinkerFlavor::Gnu)
+        );
+        assert!(AllowedLinker::from_arg("clang-cl=ld").is_err());
+    }
+
+    #[test]
+    fn known_linkers_under_program_files() {
+        let link_exe = r#""C:\\Program Files\\Microsoft Visual Studio\\2022\\Community\\VC\\Tools\\MSVC\\14.38.33130\\bin\\HostX64\\x64\\link.exe" "/NOLOGO" "a.o""#;
+        assert_eq!(known_linker_flavor(link_exe, &[]), Some(LinkerFlavor::Msvc));
+        let allowed = [AllowedLinker::from_arg("clang-cl").unwrap()];
+        let clang_cl = r#""C:\\Program Files\\LLVM\\bin\\clang-cl.exe" "/OUT:C:\\t\\a.dll" "a.o""#;
+        assert_eq!(
+            known_linker_flavor(clang_cl, &allowed),
+            Some(LinkerFlavor::Msvc)
+        );
+    }
+
+    #[test]
+    fn delay_load_forwarded() {
+        let output = parse_link_line(
+            r#""link.exe" "a.o" "/DELAYLOAD:nvcuda.dll" "/delayload:d3d12.dll""#,
+            Path::new("build_def.def"),
+        );
+        assert_eq!(
+            output.linker_lines,
+            vec![
+                r#"/DELAYLOAD:"nvcuda.dll""#,
+                r#"/delayload:"d3d12.dll""#,
+                r#""delayimp.lib""#
+            ]
+        );
+        let output = parse_link_line(
+            r#""link.exe" "a.o" "/DELAYLOAD:nvcuda.dll" "C:\\sdk\\DelayImp.lib""#,
+            Path::new("build_def.def"),
+        );
+        assert_eq!(
+            output.linker_lines,
+            vec![r#"/DELAYLOAD:"nvcuda.dll""#, r#""C:\sdk\DelayImp.lib""#]
+        );
+    }
+
+    #[test]
+    fn natvis_forwarded() {
+        let mut output = parse_link_line(
+            r#""link.exe" "a.o" "/NATVIS:C:\\rust\\lib\\rustlib\\etc\\intrinsic.natvis" "/natvis:C:\\rust\\lib\\rustlib\\etc\\libcore.natvis" "/DEFAULTLIB:msvcrt""#,
+            Path::new("build_def.def"),
+        );
+        assert_eq!(
+            output.linker_lines,
+            vec![
+                r#"/NATVIS:"C:\rust\lib\rustlib\etc\intrinsic.natvis""#,
+                r#"/natvis:"C:\rust\lib\rustlib\etc\libcore.natvis""#,
+                r#"/DEFAULTLIB:"msvcrt""#
+            ]
+        );
+        output.remove_linker_option("NATVIS");
+        assert_eq!(output.linker_lines, vec![r#"/DEFAULTLIB:"msvcrt""#]);
+    }
+
+    #[test]
+    fn flavor_option_forms() {
+        for link_line in [
+            r#""rust-lld.exe" "-flavor" "link" "a.o" "kernel32.lib""#,
+            r#""rust-lld.exe" "-flavor=link" "a.o" "kernel32.lib""#,
+            r#""rust-lld.exe" "--flavor" "link" "a.o" "kernel32.lib""#,
+        ] {
+            let output = parse_link_line(link_line, Path::new("build_def.def"));
+            assert_eq!(output.lib_lines, vec![r#""a.o""#], "{}", link_line);
+            assert_eq!(
+                output.linker_lines,
+                vec![r#""kernel32.lib""#],
+                "{}",
+                link_line
+            );
+            assert!(
+                check_routing(link_line, &output).is_empty(),
+                "{}",
+                link_line
+            );
+        }
+    }
+
+    #[test]
+    fn select_and_merge_link_lines() {
+        let stdout = concat!(
+            "   Compiling foo v0.1.0\n",
+            r#""link.exe" "a.o" "kernel32.lib" "/DEF:C:\\t\\lib.def" "/OUT:C:\\t\\foo.dll""#,
+            "\n",
+            r#""lld-link.exe" "b.o" "user32.lib" "/OUT:C:\\t\\foo_static.exe""#,
+            "\n",
+        );
+        let lines = find_link_lines(stdout, &[]);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(link_line_artifact(lines[0]).as_deref(), Some("foo.dll"));
+        assert_eq!(select_link_line(&lines, None), Some(lines[0]));
+        assert_eq!(select_link_line(&lines, Some("foo_static")), Some(lines[1]));
+        assert_eq!(select_link_line(&lines, Some("foo.dll")), Some(lines[0]));
+        assert_eq!(select_link_line(&lines, Some("bar")), None);
+
+        let def_file_path = Path::new("build_def.def");
+        let mut output = parse_link_line(lines[0], def_file_path);
+        output.merge(parse_link_line(lines[1], def_file_path));
+        output.deduplicate(LinkerFlavor::Msvc);
+        assert_eq!(
+            output.lib_lines,
+            vec![r#"/DEF:"build_def.def""#, r#""a.o""#, r#""b.o""#]
+        );
+        assert_eq!(
+            output.linker_lines,
+            vec![
+                r#""kernel32.lib""#,
+                r#"/DEF:"build_def.def""#,
+                r#""user32.lib""#
+            ]
+        );
+        assert_eq!(output.def_file, Some(PathBuf::from(r"C:\t\lib.def")));
+        for line in lines {
+            assert!(check_routing(line, &output).is_empty(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn explicit_linker_flavor() {
+        let stdout = concat!(
+            r#""C:\\tools\\link-shim.cmd" "a.o" "/LIBPATH:C:\\x" "kernel32.lib""#,
+            "\n",
+            r#""cc" "-m64" "b.o" "/usr/lib/libc.so""#,
+            "\n",
+        );
+        let lines = find_link_lines(stdout, &["link-shim.cmd"]);
+        // the cc line is recognized by its linker's name
+        assert_eq!(lines.len(), 2);
+        let def_file_path = Path::new("build_def.def");
+        let output = parse_link_line_as(lines[0], def_file_path, LinkerFlavor::Msvc);
+        assert_eq!(output.lib_lines, vec![r#""a.o""#]);
+        assert_eq!(
+            output.linker_lines,
+            vec![r#"/LIBPATH:"C:\x""#, r#""kernel32.lib""#]
+        );
+        assert!(check_routing_as(lines[0], &output, LinkerFlavor::Msvc).is_empty());
+
+        let gnu_line = lines[1];
+        let output = parse_link_line_as(gnu_line, def_file_path, LinkerFlavor::Gnu);
+        assert_eq!(output.lib_lines, vec![r#""b.o""#]);
+        assert_eq!(output.linker_lines, vec![r#""/usr/lib/libc.so""#]);
+        assert!(check_routing_as(gnu_line, &output, LinkerFlavor::Gnu).is_empty());
+    }
+
+    #[test]
+    fn parse_gnu_link_line() {
+        let fixture =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/link_lines/gnu_cc.txt");
+        let contents = std::fs::read_to_string(fixture).unwrap();
+        let link_line = contents.lines().next().unwrap();
+        let stdout = format!("   Compiling game v0.1.0\n{}\n", link_line);
+        assert_eq!(find_link_lines(&stdout, &[]), vec![link_line]);
+        assert_eq!(select_link_line(&[link_line], None), Some(link_line));
+        assert_eq!(link_line_artifact(link_line).as_deref(), Some("libgame.so"));
+
+        let output = parse_link_line(link_line, Path::new("build_def.def"));
+        assert_eq!(
+            output.lib_lines,
+            vec![
+                r#""/tmp/rustcxTz9Qa/symbols.o""#,
+                r#""/home/dev/game/target/debug/deps/game.game.5c2a9f1e-cgu.0.rcgu.o""#,
+                r#""/home/dev/.rustup/toolchains/stable-x86_64-unknown-linux-gnu/lib/rustlib/x86_64-unknown-linux-gnu/lib/libstd-1f2e3d4c5b6a7980.rlib""#,
+            ]
+        );
+        assert_eq!(
+            output.linker_lines,
+            vec![
+                r#"-Wl,--version-script="build_def.def""#,
+                "-Wl,--as-needed",
+                "-Wl,-Bstatic",
+                "-Wl,-Bdynamic",
+                "-lgcc_s",
+                "-lc",
+                r#"-L"/home/dev/game/target/debug/deps""#,
+            ]
+        );
+        assert_eq!(
+            output.def_file,
+            Some(PathBuf::from("/tmp/rustcxTz9Qa/list"))
+        );
+        assert!(check_routing(link_line, &output).is_empty());
+    }
+
+    #[test]
+    fn parse_macos_link_line() {
+        let fixture =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/link_lines/macos_cc.txt");
+        let contents = std::fs::read_to_string(fixture).unwrap();
+        let link_line = contents.lines().next().unwrap();
+        assert_eq!(select_link_line(&[link_line], None), Some(link_line));
+        assert_eq!(
+            link_line_output(link_line).as_deref(),
+            Some("/Users/dev/game/target/debug/deps/libgame.dylib")
+        );
+
+        let output = parse_link_line(link_line, Path::new("build_def.def"));
+        assert_eq!(
+            output.lib_lines,
+            vec![
+                r#""/var/folders/xy/T/rustcAbC123/symbols.o""#,
+                r#""/Users/dev/game/target/debug/deps/game.game.5c2a9f1e-cgu.0.rcgu.o""#,
+                r#""/Users/dev/.rustup/toolchains/stable-aarch64-apple-darwin/lib/rustlib/aarch64-apple-darwin/lib/libstd-1f2e3d4c5b6a7980.rlib""#,
+            ]
+        );
+        assert_eq!(
+            output.linker_lines,
+            vec![
+                r#"-Wl,-exported_symbols_list,"build_def.def""#,
+                r#"-Wl,-force_load,"/Users/dev/game/native/libglue.a""#,
+                "-Wl,-framework,CoreFoundation",
+                "-lSystem",
+                "-lc",
+                "-lm",
+                r#"-L"/Users/dev/game/target/debug/deps""#,
+            ]
+        );
+        assert_eq!(
+            output.def_file,
+            Some(PathBuf::from("/var/folders/xy/T/rustcAbC123/list"))
+        );
+        assert!(check_routing(link_line, &output).is_empty());
+
+        // ld64 run directly takes the values as separate args
+        let direct = parse_link_line(
+            r#""ld64.lld" "-arch" "arm64" "-platform_version" "macos" "11.0.0" "14.0" "-exported_symbols_list" "/t/list" "-force_load" "/t/libglue.a" "a.o" "-o" "/t/libgame.dylib""#,
+            Path::new("build_def.def"),
+        );
+        assert_eq!(direct.lib_lines, vec![r#""a.o""#]);
+        assert_eq!(
+            direct.linker_lines,
+            vec![
+                r#"-Wl,-exported_symbols_list,"build_def.def""#,
+                r#"-Wl,-force_load,"/t/libglue.a""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_windows_gnu_link_line() {
+        let fixture =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/link_lines/windows_gnu.txt");
+        let contents = std::fs::read_to_string(fixture).unwrap();
+        let link_line = contents.lines().next().unwrap();
+        assert_eq!(select_link_line(&[link_line], None), Some(link_line));
+        assert_eq!(link_line_artifact(link_line).as_deref(), Some("game.dll"));
+
+        let output = parse_link_line(link_line, Path::new("build_def.def"));
+        let sysroot_lib = r"C:\Users\dev\.rustup\toolchains\stable-x86_64-pc-windows-gnu\lib\rustlib\x86_64-pc-windows-gnu\lib";
+        let deps = r"C:\Users\dev\game\target\debug\deps";
+        assert_eq!(
+            output.lib_lines,
+            vec![
+                r#""C:\Users\dev\AppData\Local\Temp\rustcQx1b2C\symbols.o""#.to_owned(),
+                format!(r#""{}\game.game.5c2a9f1e-cgu.0.rcgu.o""#, deps),
+                format!(r#""{}\libstd-1f2e3d4c5b6a7980.rlib""#, sysroot_lib),
+            ]
+        );
+        assert_eq!(
+            output.linker_lines[..4],
+            [
+                format!(r#""{}\self-contained\dllcrt2.o""#, sysroot_lib),
+                format!(r#""{}\rsbegin.o""#, sysroot_lib),
+                r#""build_def.def""#.to_owned(),
+                format!(r#"-L"{}""#, deps),
+            ]
+        );
+        assert!(output
+            .linker_lines
+            .contains(&format!(r#"-Wl,--out-implib="{}\libgame.dll.a""#, deps)));
+        assert_eq!(
+            output.linker_lines.last(),
+            Some(&format!(r#""{}\rsend.o""#, sysroot_lib))
+        );
+        assert_eq!(
+            output.def_file,
+            Some(PathBuf::from(
+                r"C:\Users\dev\AppData\Local\Temp\rustcQx1b2C\list.def"
+            ))
+        );
+        assert!(check_routing(link_line, &output).is_empty());
+
+        // newer rustc versions pass the export list through -Wl,
+        let wl = parse_link_line(r#""gcc" "-Wl,C:\\t\\list.def" "a.o""#, Path::new("d.def"));
+        assert_eq!(wl.def_file, Some(PathBuf::from(r"C:\t\list.def")));
+    }
+
+    #[test]
+    fn find_options_by_name() {
+        let link_line =
+            r#""link.exe" "a.o" "/NODEFAULTLIB:libcmt" "/nodefaultlib:msvcrt" "/DEBUG""#;
+        assert_eq!(
+            find_options(link_line, LinkerFlavor::Msvc, "NODEFAULTLIB"),
+            vec![r#"/NODEFAULTLIB:"libcmt""#, r#"/nodefaultlib:"msvcrt""#]
+        );
+        assert_eq!(
+            find_options(link_line, LinkerFlavor::Msvc, "debug"),
+            vec!["/DEBUG"]
+        );
+        assert!(find_options(link_line, LinkerFlavor::Msvc, "LTCG").is_empty());
+
+        let link_line = r#""link.exe" "/WHOLEARCHIVE:C:\\t\\native.lib" "/INCLUDE:register_plugin" "/ALTERNATENAME:__imp_f=f""#;
+        assert_eq!(
+            find_options(link_line, LinkerFlavor::Msvc, "WHOLEARCHIVE"),
+            vec![r#"/WHOLEARCHIVE:"C:\t\native.lib""#]
+        );
+        assert_eq!(
+            find_options(link_line, LinkerFlavor::Msvc, "ALTERNATENAME"),
+            vec![r#"/ALTERNATENAME:"__imp_f=f""#]
+        );
+    }
+
+    #[test]
+    fn arg_quoting() {
+        let mut output = parse_link_line(
+            r#""link.exe" "C:\\it's\\a.o" "/LIBPATH:C:\\a b" "/NODEFAULTLIB:libcmt""#,
+            Path::new("build_def.def"),
+        );
+        let msvc = output.clone();
+        output.requote(ArgQuoting::Msvc);
+        assert_eq!(output, msvc);
+
+        let mut powershell = msvc.clone();
+        powershell.requote(ArgQuoting::PowerShell);
+        assert_eq!(
+            powershell.linker_lines,
+            vec![r"'/LIBPATH:C:\a b'", "'/NODEFAULTLIB:libcmt'"]
+        );
+        assert_eq!(powershell.lib_lines, vec![r"'C:\it''s\a.o'"]);
+
+        let mut posix = msvc.clone();
+        posix.requote(ArgQuoting::Posix);
+        assert_eq!(posix.lib_lines, vec![r"'C:\it'\''s\a.o'"]);
+
+        let mut rsp = msvc;
+        rsp.requote(ArgQuoting::Rsp);
+        assert_eq!(
+            rsp.linker_lines,
+            vec![r#""/LIBPATH:C:\a b""#, r#""/NODEFAULTLIB:libcmt""#]
+        );
+        assert_eq!(quote_msvc_arg(r"C:\a b\"), r#""C:\a b\\""#);
+        assert_eq!(quote_msvc_arg(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(
+            ResponseFileEncoding::Utf16.encode("/DEBUG\n"),
+            b"\xff\xfe/\0D\0E\0B\0U\0G\0\r\0\n\0"
+        );
+        assert_eq!(
+            ResponseFileEncoding::Utf8Bom.encode("a\n"),
+            b"\xef\xbb\xbfa\r\n"
+        );
+    }
+
+    #[test]
+    fn relative_inputs_resolve_against_cargo_dir() {
+        let mut output = parse_link_line(
+            r#""link.exe" "deps\\foo.foo.1a2b-cgu.0.rcgu.o" "bar.o" "C:\\t\\libstd.rlib" "kernel32.lib" "native\\helper.lib" "/LIBPATH:deps""#,
+            Path::new("build_def.def"),
+        );
+        let cargo_dir = Path::new("/work/crate");
+        output.resolve_relative_inputs(cargo_dir);
+        let quoted = |path: PathBuf| format!("\"{}\"", path.to_string_lossy());
+        assert_eq!(
+            output.lib_lines,
+            vec![
+                quoted(cargo_dir.join(r"deps\foo.foo.1a2b-cgu.0.rcgu.o")),
+                quoted(cargo_dir.join("bar.o")),
+                r#""C:\t\libstd.rlib""#.to_owned(),
+            ]
+        );
+        assert_eq!(
+            output.linker_lines,
+            vec![
+                r#""kernel32.lib""#.to_owned(),
+                quoted(cargo_dir.join(r"native\helper.lib")),
+                r#"/LIBPATH:"deps""#.to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn routing_anomalies() {
+        let link_line = r#""link.exe" "-flavor" "msvc" "a.o" "b.rlib" "kernel32.lib""#;
+        let output = parse_link_line(link_line, Path::new("build_def.def"));
+        assert!(check_routing(link_line, &output).is_empty());
+
+        let mut output = output;
+        output.linker_lines.push(r#""a.o""#.to_owned());
+        output
+            .linker_lines
+            .retain(|line| line != r#""kernel32.lib""#);
+        assert_eq!(
+            check_routing(link_line, &output),
+            vec![
+                RoutingAnomaly::Duplicated("a.o".to_owned()),
+                RoutingAnomaly::Dropped("kernel32.lib".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn copied_objects() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let objects = [
+            dir.join("deps/game.game.cgu.0.rcgu.o"),
+            dir.join("cxx/game.game.cgu.0.rcgu.o"),
+            dir.join("cxx/bridge.obj"),
+        ];
+        for object in &objects {
+            std::fs::create_dir_all(object.parent().unwrap()).unwrap();
+            std::fs::write(object, object.to_string_lossy().as_bytes()).unwrap();
+        }
+        let rlib = format!("\"{}\"", dir.join("deps/libserde-0f1e.rlib").display());
+        let mut output = LinkerOutput {
+            lib_lines: vec![
+                format!("\"{}\"", objects[0].display()),
+                rlib.clone(),
+                format!("'{}'", objects[1].display()),
+                objects[2].to_string_lossy().into_owned(),
+                r#"/DEF:"build_def.def""#.to_owned(),
+            ],
+            ..LinkerOutput::default()
+        };
+        let copies = dir.join("objects");
+        output.copy_objects_to(&copies).unwrap();
+        let copied = [
+            copies.join("game.game.cgu.0.rcgu.o"),
+            copies.join("1-game.game.cgu.0.rcgu.o"),
+            copies.join("bridge.obj"),
+        ];
+        let quoted = |path: &PathBuf| format!("\"{}\"", path.display());
+        assert_eq!(
+            output.lib_lines,
+            [
+                quoted(&copied[0]),
+                rlib,
+                quoted(&copied[1]),
+                quoted(&copied[2]),
+                r#"/DEF:"build_def.def""#.to_owned()
+            ]
+        );
+        for (object, copy) in objects.iter().zip(&copied) {
+            assert_eq!(std::fs::read(copy).unwrap(), std::fs::read(object).unwrap());
+        }
+        assert_eq!(std::fs::read_dir(&copies).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn replaced_prefixes() {
+        let sysroot = r"C:\Users\dev\.rustup\toolchains\stable";
+        let mut output = LinkerOutput {
+            linker_lines: vec![
+                format!(
+                    r#"/LIBPATH:"{}\lib\rustlib\x86_64-pc-windows-msvc\lib""#,
+                    sysroot
+                ),
+                format!(r#""{}-old\lib\legacy.lib""#, sysroot),
+                format!(r#""{}""#, sysroot),
+                r#""kernel32.lib""#.to_owned(),
+            ],
+            lib_lines: vec![format!(r#""{}/lib/libstd.rlib""#, sysroot)],
+            ..LinkerOutput::default()
+        };
+        let original = output.clone();
+        output.replace_prefix("", SYSROOT_PLACEHOLDER);
+        output.replace_prefix("/", SYSROOT_PLACEHOLDER);
+        assert_eq!(output, original);
+
+        // with or without a trailing separator
+        for prefix in [sysroot.to_owned(), format!("{}\\", sysroot)] {
+            let mut output = original.clone();
+            output.replace_prefix(&prefix, SYSROOT_PLACEHOLDER);
+            assert_eq!(
+                output.linker_lines,
+                vec![
+                    r#"/LIBPATH:"$SYSROOT\lib\rustlib\x86_64-pc-windows-msvc\lib""#.to_owned(),
+                    format!(r#""{}-old\lib\legacy.lib""#, sysroot),
+                    r#""$SYSROOT""#.to_owned(),
+                    r#""kernel32.lib""#.to_owned(),
+                ]
+            );
+            assert_eq!(output.lib_lines, vec![r#""$SYSROOT/lib/libstd.rlib""#]);
+        }
+    }
+}
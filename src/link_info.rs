@@ -0,0 +1,138 @@
+//! The JSON description of the parsed link line written with `rustc --output-format json`, for
+//! build logic (e.g. a Build.cs) that would rather not parse the linker and lib files
+
+use crate::link_args::{LinkerFlavor, LinkerOutput};
+use serde::Serialize;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Options of the linker and lib files referencing the def file
+const DEF_FILE_OPTIONS: &[&str] = &[
+    "/DEF:",
+    "-Wl,--version-script=",
+    "-Wl,-exported_symbols_list,",
+];
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct LinkInfo {
+    /// "msvc" or "gnu"
+    pub linker_flavor: &'static str,
+    /// Library search paths
+    pub libpaths: Vec<String>,
+    /// The object files of the lib file
+    pub objects: Vec<String>,
+    /// The rlibs of the lib file
+    pub rlibs: Vec<String>,
+    /// The def file, version script or exported symbols list next to the lib file
+    pub def_file: Option<String>,
+    /// Libraries given by path or searched for, e.g. `kernel32.lib` or `-lpthread`
+    pub system_libs: Vec<String>,
+    /// macOS frameworks
+    pub frameworks: Vec<String>,
+    /// The other options of the linker file, e.g. `/SUBSYSTEM:WINDOWS` or `-Wl,-Bstatic`
+    pub linker_options: Vec<String>,
+    /// Options of the link line that weren't written to either file
+    pub unknown_args: Vec<String>,
+}
+
+/// The JSON file written next to the linker file
+pub fn json_path(output_linker_file: &Path) -> PathBuf {
+    let mut path: OsString = output_linker_file.as_os_str().into();
+    path.push(".json");
+    path.into()
+}
+
+impl LinkInfo {
+    /// Describe the lines of `output`, which have to be quoted as rustc prints them (i.e. before
+    /// `LinkerOutput::requote`)
+    pub fn new(output: &LinkerOutput, flavor: LinkerFlavor, def_file_path: &Path) -> LinkInfo {
+        let def_file = def_file_path.to_string_lossy().into_owned();
+        let mut info = LinkInfo {
+            linker_flavor: flavor.name(),
+            def_file: output.def_file.as_ref().map(|_| def_file.clone()),
+            ..LinkInfo::default()
+        };
+        let is_def_file = |arg: &str| {
+            arg == def_file
+                || DEF_FILE_OPTIONS
+                    .iter()
+                    .any(|option| arg.starts_with(option))
+        };
+        for line in &output.linker_lines {
+            // Windows paths can't contain double quotes, so all of them are quoting
+            let arg = line.replace('"', "");
+            if is_def_file(&arg) {
+                continue;
+            }
+            if line.starts_with('"') || arg.starts_with("-l") {
+                info.system_libs.push(arg);
+            } else if let Some(dir) = arg
+                .strip_prefix("/LIBPATH:")
+                .or_else(|| arg.strip_prefix("-L"))
+            {
+                info.libpaths.push(dir.to_owned());
+            } else if let Some(framework) = arg.strip_prefix("-Wl,-framework,") {
+                info.frameworks.push(framework.to_owned());
+            } else {
+                info.linker_options.push(arg);
+            }
+        }
+        for line in &output.lib_lines {
+            let arg = line.replace('"', "");
+            if is_def_file(&arg) {
+                continue;
+            }
+            if arg.ends_with(".rlib") {
+                info.rlibs.push(arg);
+            } else {
+                info.objects.push(arg);
+            }
+        }
+        // options kept with --keep-linker-arg are written to the linker file after all
+        info.unknown_args = output
+            .dropped_args
+            .iter()
+            .filter(|arg| {
+                !info
+                    .linker_options
+                    .iter()
+                    .any(|option| option.get(1..) == arg.get(1..))
+            })
+            .cloned()
+            .collect();
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link_args::{find_options, parse_link_line};
+
+    #[test]
+    fn msvc_link_info() {
+        let link_line = r#""link.exe" "/NOLOGO" "C:\\t\\a.o" "C:\\t\\libstd.rlib" "kernel32.lib" "/LIBPATH:C:\\sdk" "/DEF:C:\\tmp\\lib.def" "/NODEFAULTLIB:libcmt" "/INCLUDE:register_plugin" "/SUBSYSTEM:WINDOWS""#;
+        let mut output = parse_link_line(link_line, Path::new("build_def.def"));
+        output
+            .linker_lines
+            .extend(find_options(link_line, LinkerFlavor::Msvc, "INCLUDE"));
+        let info = LinkInfo::new(&output, LinkerFlavor::Msvc, Path::new("build_def.def"));
+        assert_eq!(
+            info,
+            LinkInfo {
+                linker_flavor: "msvc",
+                libpaths: vec![r"C:\sdk".to_owned()],
+                objects: vec![r"C:\t\a.o".to_owned()],
+                rlibs: vec![r"C:\t\libstd.rlib".to_owned()],
+                def_file: Some("build_def.def".to_owned()),
+                system_libs: vec!["kernel32.lib".to_owned()],
+                frameworks: vec![],
+                linker_options: vec![
+                    "/NODEFAULTLIB:libcmt".to_owned(),
+                    "/INCLUDE:register_plugin".to_owned()
+                ],
+                unknown_args: vec!["/NOLOGO".to_owned(), "/SUBSYSTEM:WINDOWS".to_owned()],
+            }
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Cross compiling Linux (e.g. dedicated server) builds from Windows with the clang toolchain UE
+//! bundles for it, found through `LINUX_MULTIARCH_ROOT`
+
+use anyhow::{bail, Result};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The target built with `--linux-multiarch-root` unless the cargo args pick one
+pub const DEFAULT_TARGET: &str = "x86_64-unknown-linux-gnu";
+
+/// The environment variable UBT finds the toolchain with
+pub const ROOT_ENV_VAR: &str = "LINUX_MULTIARCH_ROOT";
+
+/// The toolchain's directory for one architecture, which is both the clang install and the
+/// sysroot
+pub struct LinuxCrossToolchain {
+    dir: PathBuf,
+    clang_target: &'static str,
+}
+
+impl LinuxCrossToolchain {
+    pub fn new(root: &Path, target: &str) -> Result<LinuxCrossToolchain> {
+        // the toolchain's directories are named after the clang targets UBT compiles for
+        let clang_target = match target {
+            "x86_64-unknown-linux-gnu" => "x86_64-unknown-linux-gnu",
+            "aarch64-unknown-linux-gnu" => "aarch64-unknown-linux-gnueabi",
+            _ => bail!("UE's Linux toolchain doesn't support the target {}", target),
+        };
+        let dir = root.join(clang_target);
+        if !dir.join("bin").is_dir() {
+            bail!(
+                "{} doesn't look like UE's Linux cross compile toolchain, {} doesn't exist",
+                root.display(),
+                dir.join("bin").display()
+            );
+        }
+        Ok(LinuxCrossToolchain { dir, clang_target })
+    }
+
+    fn tool(&self, name: &str) -> PathBuf {
+        let extension = if cfg!(windows) { ".exe" } else { "" };
+        self.dir.join("bin").join(format!("{}{}", name, extension))
+    }
+
+    /// The clang and lld flags compiling and linking for the target against the toolchain's
+    /// sysroot, like UBT does
+    fn clang_flags(&self) -> Vec<String> {
+        vec![
+            format!("--target={}", self.clang_target),
+            format!("--sysroot={}", self.dir.to_string_lossy()),
+        ]
+    }
+
+    /// The `-C link-arg=` values making the toolchain's clang link for the target with lld
+    pub fn rustc_link_args(&self) -> Vec<String> {
+        self.clang_flags()
+            .into_iter()
+            .chain(["-fuse-ld=lld".to_owned()])
+            .map(|flag| format!("link-arg={}", flag))
+            .collect()
+    }
+
+    /// The environment variables making cargo link with the toolchain's clang, and build
+    /// scripts using the cc crate compile with it
+    pub fn cargo_env(&self, target: &str) -> Vec<(String, OsString)> {
+        let env_target = target.replace('-', "_");
+        let flags = self.clang_flags().join(" ");
+        vec![
+            (
+                format!("CARGO_TARGET_{}_LINKER", env_target.to_uppercase()),
+                self.tool("clang").into(),
+            ),
+            (format!("CC_{}", env_target), self.tool("clang").into()),
+            (format!("CXX_{}", env_target), self.tool("clang++").into()),
+            (format!("AR_{}", env_target), self.tool("llvm-ar").into()),
+            (format!("CFLAGS_{}", env_target), flags.clone().into()),
+            (format!("CXXFLAGS_{}", env_target), flags.into()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toolchain_dirs() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("aarch64-unknown-linux-gnueabi/bin")).unwrap();
+
+        let toolchain = LinuxCrossToolchain::new(root, "aarch64-unknown-linux-gnu").unwrap();
+        let sysroot = root.join("aarch64-unknown-linux-gnueabi");
+        assert_eq!(
+            toolchain.rustc_link_args(),
+            vec![
+                "link-arg=--target=aarch64-unknown-linux-gnueabi".to_owned(),
+                format!("link-arg=--sysroot={}", sysroot.to_string_lossy()),
+                "link-arg=-fuse-ld=lld".to_owned(),
+            ]
+        );
+        // only the aarch64 toolchain is installed
+        assert!(LinuxCrossToolchain::new(root, DEFAULT_TARGET).is_err());
+        assert!(LinuxCrossToolchain::new(root, "x86_64-pc-windows-msvc").is_err());
+    }
+}
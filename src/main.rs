@@ -1,294 +1,2818 @@
-use anyhow::Result;
-use cargo::core::manifest::TargetSourcePath;
-use cargo::core::TargetKind;
+mod android;
+mod archive;
+mod base_dir;
+mod batch;
+mod bindings;
+mod blueprint;
+mod bootstrap;
+mod build_cs;
+mod cache;
+mod calling_convention;
+mod cargo_env;
+mod cfg;
+mod color;
+mod crate_items;
+mod crt;
+mod cxx_bridge;
+mod def_file;
+mod doctor;
+mod exit_code;
+mod exports;
+mod fake_linker;
+mod ffi_lint;
+mod header;
+mod ios;
+mod layout;
+mod link_args;
+mod link_info;
+mod linux_cross;
+mod output_paths;
+mod platform;
+mod rust_bindings;
+mod source_files;
+mod toolchain;
+mod verify_abi;
+mod wrappers;
+
+use anyhow::{Context, Result};
+use bindings::{generate_header, generate_merged_header, generate_split_headers, BindingsOptions};
+use cargo::core::Workspace;
 use cargo::GlobalContext;
-use std::fs::{self, DirEntry};
+use color::ColorChoice;
+use def_file::ExportFilter;
+use exit_code::ExitCode;
+use link_args::{
+    find_link_lines, parse_link_line_as, select_link_line, ArgQuoting, LinkerFlavor, LinkerOutput,
+    ResponseFileEncoding,
+};
+use output_paths::{write_output_file, CONFIG_PLACEHOLDER, TARGET_PLACEHOLDER};
+use source_files::{lib_source_files, local_packages, WalkOptions};
+use std::collections::BTreeMap;
 use std::io;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::{io::Write, path::Path};
-
-use cbindgen::{self};
-use clap::{App, Arg, SubCommand};
-
-pub fn parse_quotes(s: impl AsRef<str>) -> Vec<String> {
-    let s = s.as_ref();
-    let mut args = vec![];
-    let mut in_string = false;
-    let mut escaping = false;
-    let mut current_str = String::default();
-
-    for x in s.chars() {
-        if in_string {
-            if x == '\\' && !escaping {
-                escaping = true;
-            } else if x == '"' && !escaping {
-                if !current_str.is_empty() {
-                    args.push(current_str);
-                }
 
-                current_str = String::default();
-                in_string = false;
-            } else {
-                current_str.push(x);
-                escaping = false;
-            }
-        } else if x == ' ' {
-            if !current_str.is_empty() {
-                args.push(current_str.clone());
-            }
+use clap::{App, Arg, ArgMatches, SubCommand};
 
-            current_str = String::default();
-        } else if x == '"' {
-            if !current_str.is_empty() {
-                args.push(current_str.clone());
-            }
+/// Flags controlling cargo's network and lock file access, for subcommands that load a workspace
+fn cargo_network_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("OFFLINE")
+            .long("offline")
+            .takes_value(false)
+            .help("Run cargo without accessing the network"),
+        Arg::with_name("LOCKED")
+            .long("locked")
+            .takes_value(false)
+            .help("Require Cargo.lock to be up to date"),
+        Arg::with_name("FROZEN")
+            .long("frozen")
+            .takes_value(false)
+            .help("Equivalent to --offline and --locked"),
+    ]
+}
 
-            in_string = true;
-            current_str = String::default();
-        } else {
-            current_str.push(x);
+/// Flags picking and overriding the cbindgen config, for the subcommands generating headers
+fn cbindgen_config_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("CBINDGEN_CONFIG")
+            .long("cbindgen-config")
+            .takes_value(true)
+            .help("cbindgen.toml to generate the header with instead of the one in the crate's root"),
+        Arg::with_name("CBINDGEN_SET")
+            .long("cbindgen-set")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .validator(|v| bindings::parse_config_override(&v).map(|_| ()))
+            .help("Override a key of the cbindgen config, as \"key=value\" with a TOML value, e.g. \"language=C++\", \"cpp_compat=true\" or \"export.prefix=Rust\". Values that aren't valid TOML are strings. May be repeated"),
+        Arg::with_name("LANGUAGE")
+            .long("language")
+            .takes_value(true)
+            .possible_values(&["c", "c++"])
+            .help("Language of the header, overriding the cbindgen config's. C++ headers declare enums as enum class"),
+        Arg::with_name("NAMESPACE")
+            .long("namespace")
+            .takes_value(true)
+            .help("Namespace to declare the items of a C++ header in, e.g. \"Game::Rust\""),
+        Arg::with_name("API_MACRO")
+            .long("api-macro")
+            .takes_value(true)
+            .validator(|v| bindings::parse_macro_name(&v))
+            .help("Macro to declare every function with, e.g. \"GAME_API\" for the UE module the Rust code is linked into"),
+        Arg::with_name("DEFINE_API_MACRO")
+            .long("define-api-macro")
+            .takes_value(false)
+            .requires("API_MACRO")
+            .help("Define the --api-macro as empty if it isn't defined, so that the header compiles outside of UE"),
+    ]
+}
+
+/// Flags stamping, prefacing, wrapping and formatting the headers, for the subcommands writing
+/// them
+fn header_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("STAMP")
+            .long("stamp")
+            .takes_value(true)
+            .help("Provenance string (e.g. a git SHA) written as a \"// generated from <stamp>\" comment at the top of each header. Changing only the stamp doesn't rewrite a header"),
+        Arg::with_name("LICENSE_HEADER")
+            .long("license-header")
+            .takes_value(true)
+            .help("File (e.g. a copyright notice) to start each header with. Lines that aren't comments yet are commented out with //"),
+        Arg::with_name("BANNER")
+            .long("banner")
+            .takes_value(true)
+            .help("Comment to write at the top of each header, after the license header"),
+        Arg::with_name("UE_WRAP")
+            .long("ue-wrap")
+            .takes_value(false)
+            .help("Wrap the header for UE code: #pragma once, THIRD_PARTY_INCLUDES_START/END and #pragma warning(push/pop) around cbindgen's output"),
+        Arg::with_name("DISABLE_DEPRECATION_WARNINGS")
+            .long("disable-deprecation-warnings")
+            .takes_value(false)
+            .requires("UE_WRAP")
+            .help("Also wrap the header in PRAGMA_DISABLE/ENABLE_DEPRECATION_WARNINGS"),
+        Arg::with_name("FORMAT")
+            .long("format")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .help("Run clang-format over the header before comparing it, with the style of the given .clang-format file, or else of the one found from the current directory up"),
+    ]
+}
+
+/// --expand and the flags configuring the build cbindgen expands macros with
+fn expand_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("EXPAND")
+            .long("expand")
+            .takes_value(true)
+            .min_values(0)
+            .use_delimiter(true)
+            .help("Macro expand crates (comma separated, defaults to the crate itself) before generating bindings. Requires a nightly toolchain"),
+        Arg::with_name("EXPAND_FEATURES")
+            .long("expand-features")
+            .takes_value(true)
+            .use_delimiter(true)
+            .multiple(true)
+            .number_of_values(1)
+            .requires("EXPAND")
+            .help("Features (comma separated) to enable for the macro expansion, e.g. the ones the crate is built with. May be repeated"),
+        Arg::with_name("EXPAND_ALL_FEATURES")
+            .long("expand-all-features")
+            .takes_value(false)
+            .requires("EXPAND")
+            .help("Enable all features for the macro expansion"),
+        Arg::with_name("EXPAND_NO_DEFAULT_FEATURES")
+            .long("expand-no-default-features")
+            .takes_value(false)
+            .requires("EXPAND")
+            .help("Don't enable the default features for the macro expansion"),
+        Arg::with_name("EXPAND_RELEASE")
+            .long("expand-release")
+            .takes_value(false)
+            .requires("EXPAND")
+            .help("Expand macros with the release profile, for code that depends on debug_assertions"),
+        Arg::with_name("EXPAND_TOOLCHAIN")
+            .long("expand-toolchain")
+            .takes_value(true)
+            .requires("EXPAND")
+            .help("Toolchain to expand macros with, e.g. \"nightly-2024-05-01\", when the crate builds with a stable one"),
+    ]
+}
+
+/// The `--cfg` option shared by the subcommands running cbindgen or rustc, which have to agree on
+/// the cfgs for the header to match the lib
+fn cfg_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("CFG")
+        .long("cfg")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .validator(|v| cfg::Cfg::parse(&v).map(|_| ()))
+        .help("Enable a cfg, as \"name\" or \"name=value\". Passed to rustc as --cfg and mapped to a define in the header, see the README. May be repeated")
+}
+
+/// A cargo context configured with the flags from `cargo_network_args`
+fn cargo_context(matches: &ArgMatches) -> Result<GlobalContext> {
+    let mut ctx = GlobalContext::default()?;
+    ctx.configure(
+        0,
+        false,
+        None,
+        matches.is_present("FROZEN"),
+        matches.is_present("LOCKED"),
+        matches.is_present("OFFLINE"),
+        &None,
+        &[],
+        &[],
+    )?;
+    Ok(ctx)
+}
+
+/// The manifest cargo will build, based on a `--manifest-path` in the cargo args. The path is
+/// relative to cargo's working directory.
+fn manifest_path_from_args(cargo_args: &[&str]) -> PathBuf {
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        if *arg == "--" {
+            break;
+        } else if *arg == "--manifest-path" {
+            if let Some(path) = args.next() {
+                return path.into();
+            }
+        } else if let Some(path) = arg.strip_prefix("--manifest-path=") {
+            return path.into();
         }
     }
+    PathBuf::from("Cargo.toml")
+}
 
-    if !current_str.is_empty() {
-        args.push(current_str);
+/// A list of the crate manifest's `[package.metadata.unreal_rust_compile]` table, e.g. the
+/// linker options to keep with `keep-linker-args = ["WHOLEARCHIVE", "INCLUDE"]`, so that every
+/// build of a crate needing them gets them without passing `--keep-linker-arg`
+fn manifest_list(manifest_path: &Path, key: &str) -> Result<Vec<String>> {
+    // cargo may find the manifest in a parent directory, and report it missing itself
+    if !manifest_path.is_file() {
+        return Ok(Vec::new());
     }
+    let contents = std::fs::read_to_string(manifest_path)
+        .with_context(|| format!("Couldn't read {}", manifest_path.display()))?;
+    let manifest: toml::Value = toml::from_str(&contents)
+        .with_context(|| format!("Invalid manifest {}", manifest_path.display()))?;
+    let Some(names) = manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("unreal_rust_compile"))
+        .and_then(|table| table.get(key))
+    else {
+        return Ok(Vec::new());
+    };
+    names.clone().try_into().with_context(|| {
+        format!(
+            "package.metadata.unreal_rust_compile.{} of {} isn't a list of strings",
+            key,
+            manifest_path.display()
+        )
+    })
+}
 
-    args
+/// The sysroot of the toolchain rustup selects for `dir`
+fn rustc_sysroot(dir: &Path) -> Result<String> {
+    let output = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!("rustc --print sysroot failed with {}", output.status);
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_owned())
 }
 
-fn visit_dirs(dir: &Path, cb: &dyn Fn(&DirEntry)) -> io::Result<()> {
-    if dir.is_dir() {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                visit_dirs(&path, cb)?;
-            } else {
-                cb(&entry);
-            }
+/// The target rustc builds for when the cargo args don't pick one
+fn rustc_host() -> Result<String> {
+    let output = Command::new("rustc").arg("-vV").output()?;
+    if !output.status.success() {
+        anyhow::bail!("rustc -vV failed with {}", output.status);
+    }
+    String::from_utf8(output.stdout)?
+        .lines()
+        .find_map(|line| line.strip_prefix("host: ").map(str::to_owned))
+        .context("rustc -vV printed no host")
+}
+
+/// The directory of `target` cargo puts the artifacts of the cargo args' profile in: `debug`
+/// for the dev profile, `release` for `--release`, and the profile's name for `--profile`
+fn cargo_profile_dir(cargo_args: &[&str]) -> String {
+    let mut args = cargo_args.iter().take_while(|arg| **arg != "--");
+    let mut profile = "dev";
+    while let Some(arg) = args.next() {
+        if *arg == "--release" || *arg == "-r" {
+            profile = "release";
+        } else if *arg == "--profile" {
+            profile = args.next().copied().unwrap_or(profile);
+        } else if let Some(value) = arg.strip_prefix("--profile=") {
+            profile = value;
         }
     }
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        profile => profile,
+    }
+    .to_owned()
+}
+
+/// Whether cargo's own args (before any `--`) contain `--flag` or `--flag=value`
+fn cargo_flag_given(cargo_args: &[&str], flag: &str) -> bool {
+    cargo_args
+        .iter()
+        .take_while(|arg| **arg != "--")
+        .any(|arg| *arg == flag || arg.strip_prefix(flag).is_some_and(|v| v.starts_with('=')))
+}
+
+/// The `--target` of the cargo args, or `CARGO_BUILD_TARGET`
+fn cargo_target(cargo_args: &[&str]) -> Option<String> {
+    cargo_args_target(cargo_args).or_else(|| std::env::var("CARGO_BUILD_TARGET").ok())
+}
+
+/// The last `--target` of the cargo args themselves
+fn cargo_args_target(cargo_args: &[&str]) -> Option<String> {
+    let mut args = cargo_args.iter().take_while(|arg| **arg != "--");
+    let mut target = None;
+    while let Some(arg) = args.next() {
+        if *arg == "--target" {
+            target = args.next().map(|target| target.to_string());
+        } else if let Some(value) = arg.strip_prefix("--target=") {
+            target = Some(value.to_owned());
+        }
+    }
+    target
+}
+
+/// The flavor of the linker rustc runs: the given one, or the one of the target of the cargo
+/// args, or if there's none, of the target the tool itself was built for
+fn link_flavor(linker_flavor: Option<LinkerFlavor>, cargo_args: &[&str]) -> LinkerFlavor {
+    let msvc = match (linker_flavor, cargo_target(cargo_args)) {
+        (Some(flavor), _) => flavor == LinkerFlavor::Msvc,
+        (None, Some(target)) => target.ends_with("-msvc"),
+        (None, None) => cfg!(target_env = "msvc"),
+    };
+    if msvc {
+        LinkerFlavor::Msvc
+    } else {
+        LinkerFlavor::Gnu
+    }
+}
+
+/// A link arg that's different on every run, which makes cargo relink so that rustc prints the
+/// link line. It has to suit the target's linker: the target of the cargo args, or if there's
+/// none, the one the tool itself was built for.
+fn relink_arg(linker_flavor: Option<LinkerFlavor>, cargo_args: &[&str]) -> String {
+    let nonce = rand::random::<u16>();
+    let target = cargo_target(cargo_args);
+    let msvc = link_flavor(linker_flavor, cargo_args) == LinkerFlavor::Msvc;
+    let apple = match &target {
+        Some(target) => target.contains("-apple-"),
+        None => cfg!(target_vendor = "apple"),
+    };
+    let windows = match &target {
+        Some(target) => target.contains("-windows-"),
+        None => cfg!(windows),
+    };
+    if msvc {
+        format!("-Clink-arg=/VERSION:{}", nonce)
+    } else if windows {
+        // MinGW's counterpart of /VERSION
+        format!("-Clink-arg=-Wl,--major-image-version={}", nonce)
+    } else if apple {
+        // ld64 has no build ID option
+        format!("-Clink-arg=-Wl,-headerpad,0x{:x}", nonce)
+    } else {
+        format!("-Clink-arg=-Wl,--build-id=0x{:04x}", nonce)
+    }
+}
+
+/// Generate the dSYM bundle of a binary with dsymutil
+fn run_dsymutil(binary: &Path, dsym: &Path) -> Result<()> {
+    let output = Command::new("dsymutil")
+        .arg(binary)
+        .arg("-o")
+        .arg(dsym)
+        .output()
+        .context("Couldn't run dsymutil")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "dsymutil failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let matches = App::new("unreal_rust_compile")
+/// Messages cargo fails with when `--offline`, `--locked` or `--frozen` forbid what it needs
+const NETWORK_DENIED_MESSAGES: &[&str] = &[
+    "you're using offline mode",
+    "but --offline was specified",
+    "but --frozen was specified",
+    "but --locked was passed",
+];
+
+/// Resolve a path from the command line against `--base-dir`, if it's relative
+fn resolve_path(matches: &ArgMatches, path: &str) -> PathBuf {
+    base_dir::resolve(matches.value_of("BASE_DIR"), path)
+}
+
+/// A `--crate_dir` resolved against `--base-dir` and canonicalized
+fn resolve_crate_dir(matches: &ArgMatches, crate_dir: &str) -> Result<PathBuf> {
+    let resolved = resolve_path(matches, crate_dir);
+    let crate_dir = resolved
+        .canonicalize()
+        .with_context(|| format!("Crate directory {} doesn't exist", resolved.display()))?;
+    eprintln!("info: crate directory {}", crate_dir.display());
+    Ok(crate_dir)
+}
+
+/// The cargo args with a relative `--manifest-path` resolved against `--base-dir`
+fn resolve_manifest_path_arg(matches: &ArgMatches, cargo_args: &[&str]) -> Vec<String> {
+    base_dir::resolve_manifest_path_arg(matches.value_of("BASE_DIR"), cargo_args)
+}
+
+/// The absolute path an output file will be written to, with symlinks resolved as far as the
+/// file or its directory already exist
+fn resolved_output_path(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (parent.canonicalize(), path.file_name()) {
+        (Ok(parent), Some(file_name)) => parent.join(file_name),
+        _ => path.to_owned(),
+    }
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("unreal_rust_compile")
         .version("0.1")
         .author("Elliott Mahler <jointogethe.r@gmail.com>")
         .about("Runs cargo and cbindgen on a crate. Intended for use with Unreal Engine's build system.")
+        .arg(Arg::with_name("COLOR").long("color").global(true).takes_value(true).possible_values(&["auto", "always", "never"]).help("Coloring of diagnostics, also forwarded to cargo. \"auto\" colors them if stderr is a terminal [default: auto]"))
         .subcommand(SubCommand::with_name("gen-bindings")
             .about("Generate bindings using cbindgen")
             .version("0.1")
-            .arg(Arg::with_name("CRATE_DIR").long("crate_dir").required(true).takes_value(true).help("Input crate directory"))
-            .arg(Arg::with_name("OUTPUT_HEADER_FILE").long("output_header_file").required(true).takes_value(true).help("Destination filename for the generated C header")
+            .arg(Arg::with_name("CRATE_DIR").long("crate_dir").required_unless("WORKSPACE").takes_value(true).multiple(true).number_of_values(1).help("Input crate directory. May be repeated along with output_header_file to generate several headers, or with --merge to generate one"))
+            .arg(Arg::with_name("WORKSPACE").long("workspace").takes_value(true).conflicts_with("CRATE_DIR").help("Workspace root whose crates with a lib target are merged into one header, as with --merge"))
+            .arg(Arg::with_name("MERGE").long("merge").takes_value(false).help("Merge the headers of all crate_dirs into the single output_header_file, with a section per crate in the given order. Declarations shared by the crates are written once"))
+            .arg(Arg::with_name("OUTPUT_HEADER_FILE").long("output_header_file").required(true).takes_value(true).multiple(true).number_of_values(1).help("Destination filename for the generated C header, or \"-\" to write it to stdout")
             )
+            .arg(Arg::with_name("OUTPUT_FUNCTIONS_HEADER_FILE").long("output_functions_header_file").takes_value(true).conflicts_with_all(&["MERGE", "WORKSPACE"]).help("Destination filename for a header with the functions and statics, which includes output_header_file. output_header_file then only has the types and constants, so UE headers can include it without the whole FFI surface. Requires a single crate"))
+            .arg(Arg::with_name("LAYOUT_ASSERTS").long("layout-asserts").takes_value(true).min_values(0).conflicts_with_all(&["MERGE", "WORKSPACE"]).help("Write a header of static_asserts checking that the size and alignment of each struct of output_header_file is the one rustc computed, to this file or <output_header_file>_asserts.h. Builds the crate with -Zprint-type-sizes, which needs a nightly toolchain. Requires a single crate"))
+            .arg(Arg::with_name("CPP_WRAPPERS").long("cpp-wrappers").takes_value(true).min_values(0).conflicts_with_all(&["MERGE", "WORKSPACE"]).help("Write a header of C++ classes owning the handles of output_header_file, to this file or <output_header_file>_wrappers.h. A handle is a pointer freed by a <prefix>_free (or _destroy, _delete) function. Its class' constructor calls <prefix>_new, and it has a factory per other <prefix>_ function returning the handle and a method per one taking it. Requires a single crate"))
+            .arg(Arg::with_name("UE_STRUCTS").long("ue-structs").takes_value(true).min_values(0).conflicts_with_all(&["MERGE", "WORKSPACE"]).help("Write a header of USTRUCT(BlueprintType) mirrors of the structs of output_header_file, with inline ToRust/FromRust conversions, to this file or <output_header_file>_structs.h. Structs with fields Blueprints can't represent are skipped. Requires a single crate"))
+            .arg(Arg::with_name("UE_ENUMS").long("ue-enums").takes_value(true).min_values(0).conflicts_with_all(&["MERGE", "WORKSPACE"]).help("Write a header of UENUM(BlueprintType) mirrors of the enums of output_header_file, with static_asserts that their values match, to this file or <output_header_file>_enums.h. Enums with values outside of uint8 are skipped. Requires a single crate"))
+            .arg(Arg::with_name("UE_CATEGORY").long("ue-category").takes_value(true).default_value("Rust").help("Blueprint category of the properties of --ue-structs"))
+            .arg(Arg::with_name("LAYOUT_TOOLCHAIN").long("layout-toolchain").takes_value(true).requires("LAYOUT_ASSERTS").help("Toolchain to compute the layouts with, e.g. \"nightly\", when the crate builds with a stable one"))
+            .arg(Arg::with_name("LAYOUT_TARGET").long("layout-target").takes_value(true).requires("LAYOUT_ASSERTS").help("Target to compute the layouts for, e.g. the one UE builds for, if it's not the host"))
+            .arg(Arg::with_name("TRANSACTIONAL").long("transactional").takes_value(false).help("When generating several headers, only update any of them if all of them were generated successfully"))
+            .arg(Arg::with_name("EXISTING_HEADER").long("existing-header").takes_value(true).help("Header to compare the generated header against, or \"-\" to read it from stdin. Defaults to output_header_file"))
+            .arg(Arg::with_name("INCLUDE_GUARD").long("include-guard").takes_value(true).validator(|v| bindings::parse_macro_name(&v)).help("Include guard symbol of the header, overriding the cbindgen config's. Requires a single crate"))
+            .arg(Arg::with_name("WRITE_HASH").long("write-hash").takes_value(false).help("Write the SHA-256 of each header to <output_header_file>.sha256, in sha256sum format"))
+            .arg(Arg::with_name("HASH_FILE").long("hash-file").takes_value(true).help("Write the SHA-256 of the header to this file instead of <output_header_file>.sha256. Requires a single crate"))
+            .arg(Arg::with_name("INCLUDE_MODULE").long("include-module").takes_value(true).multiple(true).number_of_values(1).help("Only export the items of this module and its submodules, e.g. \"ffi\" or \"crate::api::v2\". May be repeated"))
+            .arg(Arg::with_name("EXCLUDE_MODULE").long("exclude-module").takes_value(true).multiple(true).number_of_values(1).help("Don't export the items of this module and its submodules. May be repeated. Items are excluded by name, so same-named items elsewhere are excluded too"))
+            .arg(Arg::with_name("VALIDATE_HEADER").long("validate-header").takes_value(false).help("Check that the generated header compiles before writing it, and fail if it doesn't"))
+            .arg(Arg::with_name("VALIDATE_COMMAND").long("validate-command").takes_value(true).requires("VALIDATE_HEADER").help("Compiler command for --validate-header, run with the header's path appended. Defaults to \"cl /nologo /Zs\" on Windows and \"cc -fsyntax-only\" or \"c++ -fsyntax-only\" elsewhere, depending on the header's language"))
+            .arg(Arg::with_name("EXIT_CODE_ON_CHANGE").long("exit-code-on-change").takes_value(false).help("Exit with status code 4 when the header changed"))
+            .arg(Arg::with_name("STAMP_FILE").long("stamp-file").takes_value(true).help("File to create or touch when the header changed, for build steps to depend on. Left untouched otherwise"))
+            .arg(Arg::with_name("DEPFILE").long("depfile").takes_value(true).help("Write a make style depfile listing the Rust source files and cbindgen config the header was generated from, so the build only reruns gen-bindings when one of them changed. Requires a single output_header_file"))
+            .arg(Arg::with_name("HEADER_DIFF").long("header-diff").takes_value(true).min_values(0).help("Report how changed headers changed as a unified diff, on stderr or in this file"))
+            .arg(Arg::with_name("SYMBOL_PREFIX").long("symbol-prefix").takes_value(true).validator(|v| bindings::parse_macro_name(&v)).help("Prefix, e.g. \"game_\", every exported function and static has to start with, so they don't collide with other plugins' symbols. The header's types and constants get it too, unless the cbindgen config sets export.prefix"))
+            .arg(Arg::with_name("CALLING_CONVENTION").long("calling-convention").takes_value(true).validator(|v| bindings::parse_macro_name(&v)).help("Calling convention macro, e.g. UE's CDECL, to write between the return type and name of each function declaration of the header. Fails if an exported function isn't explicitly extern \"C\", or a function pointer it uses has another ABI, which would corrupt the stack on win32 targets"))
+            .arg(Arg::with_name("FFI_LINT").long("ffi-lint").takes_value(true).min_values(0).possible_values(&["warn", "deny"]).help("Check the signatures of the exported functions and statics, and the types they use by value, for what C can't represent (String, slices, trait objects, generics, missing #[repr(C)], ...). \"deny\" (the default) fails on problems, \"warn\" only reports them"))
+            .arg(cfg_arg())
+            .args(&cbindgen_config_args())
+            .args(&header_args())
+            .args(&expand_args())
+        )
+        .subcommand(SubCommand::with_name("bindings-sources")
+            .about("Print the crates and files cbindgen parses to generate the header, as JSON")
+            .version("0.1")
+            .arg(Arg::with_name("CRATE_DIR").long("crate_dir").required(true).takes_value(true).help("Input crate directory"))
+            .arg(cfg_arg())
+            .args(&cbindgen_config_args())
+            .args(&expand_args())
+        )
+        .subcommand(SubCommand::with_name("gen-ue-wrappers")
+            .about("Generate a UE Blueprint function library wrapping the crate's C API")
+            .version("0.1")
+            .arg(Arg::with_name("CRATE_DIR").long("crate_dir").required(true).takes_value(true).help("Input crate directory"))
+            .arg(Arg::with_name("BINDINGS_HEADER").long("bindings_header").required(true).takes_value(true).help("Name of the header gen-bindings generates for the crate, which the library's source file includes, e.g. \"Game.h\""))
+            .arg(Arg::with_name("OUTPUT_HEADER_FILE").long("output_header_file").required(true).takes_value(true).help("Destination filename for the library's header. Its name is the class' without the U, e.g. GameLibrary.h for UGameLibrary"))
+            .arg(Arg::with_name("OUTPUT_SOURCE_FILE").long("output_source_file").required(true).takes_value(true).help("Destination filename for the library's source file"))
+            .arg(Arg::with_name("CATEGORY").long("category").takes_value(true).default_value("Rust").help("Blueprint category of the functions and struct properties"))
+            .arg(Arg::with_name("STRUCTS_HEADER").long("structs_header").takes_value(true).help("Name of the header gen-bindings --ue-structs generates, which the library includes instead of defining its own struct mirrors"))
+            .arg(Arg::with_name("STRING_FREE_FN").long("string-free-fn").takes_value(true).help("Exported function freeing the strings functions return as char *, e.g. one calling CString::from_raw. Functions returning them are skipped without it"))
+            .arg(cfg_arg())
+            .args(&cbindgen_config_args())
+            .args(&expand_args())
+        )
+        .subcommand(SubCommand::with_name("gen-rust-bindings")
+            .about("Generate Rust FFI declarations of the C functions of UE headers using bindgen")
+            .version("0.1")
+            .arg(Arg::with_name("HEADER").long("header").required(true).takes_value(true).multiple(true).number_of_values(1).help("Engine or plugin header to declare the functions of. May be repeated"))
+            .arg(Arg::with_name("OUTPUT_FILE").long("output_file").required(true).takes_value(true).help("Destination filename for the Rust module"))
+            .arg(Arg::with_name("INCLUDE_DIR").long("include_dir").takes_value(true).multiple(true).number_of_values(1).help("Directory to search for the headers' includes. May be repeated"))
+            .arg(Arg::with_name("INCLUDE_DIRS_FILE").long("include_dirs_file").takes_value(true).help("File of further include directories, one per line, e.g. written by a Build.cs from its include paths. Relative directories are relative to the file"))
+            .arg(Arg::with_name("DEFINE").long("define").takes_value(true).multiple(true).number_of_values(1).help("Preprocessor definition, NAME or NAME=VALUE, e.g. \"GAME_API=\". May be repeated"))
+            .arg(Arg::with_name("ALLOWLIST").long("allowlist").takes_value(true).multiple(true).number_of_values(1).help("Regex of the functions, variables and types to declare, along with the types they use. May be repeated. Defaults to everything the headers themselves declare"))
+            .arg(Arg::with_name("CLANG_ARG").long("clang-arg").takes_value(true).multiple(true).number_of_values(1).allow_hyphen_values(true).help("Further arg for clang, which parses the headers as C++17. May be repeated"))
+        )
+        .subcommand(SubCommand::with_name("batch")
+            .about("Generate bindings for every module of a module list")
+            .version("0.1")
+            .arg(Arg::with_name("MODULES").long("modules").required(true).takes_value(true).help("JSON or TOML file with a \"modules\" array of { crate_dir, output_header_file, source_files_file } entries. source_files_file is optional and receives the crate's source files, one per line. Relative paths are relative to the file"))
+            .arg(Arg::with_name("TRANSACTIONAL").long("transactional").takes_value(false).help("Only update any of the headers if all modules succeeded"))
+            .arg(Arg::with_name("EXIT_CODE_ON_CHANGE").long("exit-code-on-change").takes_value(false).help("Exit with status code 4 when any header changed"))
+            .arg(Arg::with_name("STAMP_FILE").long("stamp-file").takes_value(true).help("File to create or touch when any header was written. Left untouched otherwise"))
+            .arg(Arg::with_name("SYMBOL_PREFIX").long("symbol-prefix").takes_value(true).validator(|v| bindings::parse_macro_name(&v)).help("Prefix every exported function and static of every crate has to start with, and which the headers' other names get, as with gen-bindings"))
+            .arg(Arg::with_name("CALLING_CONVENTION").long("calling-convention").takes_value(true).validator(|v| bindings::parse_macro_name(&v)).help("Calling convention macro to annotate every header's function declarations with, checking the exported functions' ABIs, as gen-bindings does"))
+            .arg(Arg::with_name("FFI_LINT").long("ffi-lint").takes_value(true).min_values(0).possible_values(&["warn", "deny"]).help("Check the exported items of every crate for what C can't represent, as gen-bindings does. \"deny\" (the default) fails the crate's module on problems, \"warn\" only reports them"))
+            .arg(cfg_arg())
+            .args(&cbindgen_config_args())
+            .args(&header_args())
+            .args(&expand_args())
+            .args(&cargo_network_args())
         )
         .subcommand(SubCommand::with_name("rustc")
             .about("Compile crate")
             .version("0.1")
-            .arg(Arg::with_name("OUTPUT_LINKER_FILE").long("output_linker_file").required(true).takes_value(true).help("Path to output linker args at"))
-            .arg(Arg::with_name("OUTPUT_LIB_LINK_FILE").long("output_lib_link_file").required(true).takes_value(true).help("Path to output library linker (LIB.EXE) args at"))
+            .arg(Arg::with_name("OUTPUT_LINKER_FILE").long("output_linker_file").required(true).takes_value(true).help("Path to output linker args at. {target}, {profile} and {config} in it are replaced by the target, cargo profile directory and --ue-config"))
+            .arg(Arg::with_name("OUTPUT_LIB_LINK_FILE").long("output_lib_link_file").required(true).takes_value(true).help("Path to output library linker (LIB.EXE) args at, with the placeholders of --output_linker_file"))
+            .arg(Arg::with_name("CRATE_DIR").long("crate_dir").visible_alias("cwd").takes_value(true).help("Working directory to run cargo in. Defaults to the current directory. Relative output paths are still resolved against the current directory"))
+            .arg(Arg::with_name("MANIFEST_PATH").long("manifest-path").takes_value(true).help("Cargo.toml of the crate to build, passed to cargo as --manifest-path. Relative paths are resolved against --base-dir, or --crate_dir without one"))
             .arg(Arg::with_name("GEN_RESPONSE_FILE").long("gen_response_file").takes_value(false).help("Whether to generate a response file"))
+            .arg(Arg::with_name("CAPTURE_LINK_ARGS").long("capture-link-args").takes_value(true).possible_values(&["print", "fake-linker"]).default_value("print").help("How the link line is captured with gen_response_file: from rustc's --print link-args, or by having rustc run this tool as its linker, which records the args instead of linking. The fake linker copies the objects and export list to <output_lib_link_file>.inputs, so unlike print it doesn't need -C save-temps, and leaves empty artifacts behind"))
+            .arg(Arg::with_name("FAIL_IF_NO_LINK_ARGS").long("fail-if-no-link-args").takes_value(true).possible_values(&["true", "false"]).default_value("true").help("Whether it's an error for rustc not to print a link line with gen_response_file, e.g. for check-only builds"))
+            .arg(Arg::with_name("WRITE_UNCHANGED").long("write-unchanged").takes_value(false).help("Rewrite the linker, lib and def files even when their contents didn't change. By default they're left untouched, so that their modification times don't trigger a relink"))
+            .arg(Arg::with_name("NO_CACHE").long("no-cache").takes_value(false).help("Always run cargo instead of reusing the link args of a previous identical build. The cache doesn't track files read by build scripts or environment variables other than RUSTFLAGS, so use this if those affect the build"))
+            .arg(Arg::with_name("STRICT_ROUTING").long("strict-routing").takes_value(false).help("Fail without writing the output files, instead of warning, when a link input isn't written to exactly one of them"))
+            .arg(Arg::with_name("SYNTHESIZE_DEF_FILE").long("synthesize-def-file").takes_value(false).help("With gen_response_file, if rustc prints no link line (e.g. for a staticlib-only crate), synthesize the def file (or version script) from the #[no_mangle] symbols of the crate's objects in its staticlib, and write the staticlib to the lib file"))
+            .arg(Arg::with_name("NM").long("nm").takes_value(true).help("nm to list the staticlib's symbols with for --synthesize-def-file, e.g. llvm-nm for MSVC .lib files. Defaults to nm"))
+            .arg(Arg::with_name("ARTIFACT").long("artifact").takes_value(true).conflicts_with("MERGE_LINK_LINES").help("When rustc prints several link lines (e.g. for a crate building both a cdylib and a staticlib), process the one producing this artifact, matched against the file name of its /OUT option with or without extension. Defaults to the cdylib's link line, i.e. the one referencing a .def file"))
+            .arg(Arg::with_name("MERGE_LINK_LINES").long("merge-link-lines").takes_value(false).help("Merge the inputs of every link line rustc prints into the output files instead of processing only one"))
+            .arg(Arg::with_name("LINKER").long("linker").takes_value(true).help("Linker (or linker shim) rustc runs. Its link lines are recognized whatever its name, and are parsed as --linker-format instead of guessing the format from the linker's name"))
+            .arg(Arg::with_name("ALLOWED_LINKERS").long("allowed-linkers").takes_value(true).multiple(true).use_delimiter(true).help("Comma separated linkers to accept besides the known ones (link.exe, lld-link, cc, clang, ld, ...), e.g. \"clang-cl,ld-wrapper=gnu\". Their link lines are parsed as msvc unless the name is followed by =gnu. Added to the crate's package.metadata.unreal_rust_compile.allowed-linkers"))
+            .arg(Arg::with_name("PERMISSIVE").long("permissive").takes_value(false).help("Warn about the link lines of unrecognized linkers instead of failing, and parse them as msvc if they have an /OUT option, gnu otherwise"))
+            .arg(Arg::with_name("LINKER_FORMAT").long("linker-format").takes_value(true).possible_values(&["msvc", "gnu"]).help("Quoting and option syntax of the link line. Defaults to msvc with --linker, otherwise it's detected from the linker's name"))
+            .arg(Arg::with_name("KEEP_LINKER_ARG").long("keep-linker-arg").takes_value(true).multiple(true).number_of_values(1).help("Name of a linker option of the link line to write to the linker file, e.g. \"WHOLEARCHIVE\", \"INCLUDE\" or \"ALTERNATENAME\". Other options are dropped. May be repeated, and is added to the crate's package.metadata.unreal_rust_compile.keep-linker-args"))
+            .arg(Arg::with_name("WARN_UNUSED_KEEP_ARGS").long("warn-unused-keep-args").takes_value(false).help("Warn about kept linker arg names that didn't match any option of the link line"))
+            .arg(Arg::with_name("NO_NATVIS").long("no-natvis").takes_value(false).help("Don't forward the /NATVIS debugger visualizers of Rust's standard library to the linker file"))
+            .arg(Arg::with_name("EXCLUDE_SYSTEM_LIB").long("exclude-system-lib").takes_value(true).multiple(true).number_of_values(1).help("Windows import library to leave out of the linker file, e.g. \"bcrypt\" or \"bcrypt.lib\". May be repeated, and is added to the ones UE links already (msvcrt, kernel32, ws2_32, ...)"))
+            .arg(Arg::with_name("KEEP_UE_SYSTEM_LIBS").long("keep-ue-system-libs").takes_value(false).help("Keep the Windows import libraries UE links already in the linker file instead of leaving them out"))
+            .arg(Arg::with_name("UE_CRT").long("ue-crt").takes_value(true).possible_values(&["md", "mdd", "mt", "mtd"]).help("The CRT UE links (md unless the target enables the static or debug CRT). With gen_response_file, fail with an explanation if an MSVC link line links another one, instead of the final link failing with LNK2038"))
+            .arg(Arg::with_name("DUMP_LINK_LINE").long("dump-link-line").takes_value(true).help("Write the raw link line(s) selected from rustc's output to this file, before they're parsed"))
+            .arg(Arg::with_name("DUMP_ALL_LINK_LINES").long("dump-all-link-lines").takes_value(false).requires("DUMP_LINK_LINE").help("Write every candidate link line to the --dump-link-line file, not only the selected ones"))
+            .arg(Arg::with_name("SUBSYSTEM").long("subsystem").takes_value(true).help("Write /SUBSYSTEM:<value> to the linker file, e.g. \"WINDOWS\" or \"WINDOWS,6.02\". rustc's is dropped otherwise"))
+            .arg(Arg::with_name("UE_CONFIG").long("ue-config").takes_value(true).help("UE build configuration (e.g. Development or Shipping) substituted for {config} in the output paths"))
+            .arg(Arg::with_name("MAP_FILE").long("map-file").takes_value(true).help("Have UE's link write a map file of the module to this path, with /MAP:<path> in the linker file (-Wl,-Map= for GNU linkers), and print its path"))
+            .arg(Arg::with_name("PDB").long("pdb").takes_value(true).help("Write /PDB:<path> to the linker file, so UE's link writes the module's PDB to this path rather than one derived from its output"))
+            .arg(Arg::with_name("PDB_ALT_PATH").long("pdb-alt-path").takes_value(true).min_values(0).max_values(1).help("Write /PDBALTPATH:<value> to the linker file, the PDB path embedded in the module for debuggers and symbol servers to look up. Defaults to %_PDB%, the PDB's file name without its directory"))
+            .arg(Arg::with_name("ENTRY").long("entry").takes_value(true).help("Write /ENTRY:<symbol> to the linker file. rustc's is dropped otherwise"))
+            .arg(Arg::with_name("PORTABLE_PATHS").long("portable-paths").takes_value(false).help("Replace the rustc sysroot in the linker and lib files with $SYSROOT, so that they're identical across machines"))
+            .arg(Arg::with_name("SYSROOT").long("sysroot").takes_value(true).requires("PORTABLE_PATHS").help("Sysroot to replace with --portable-paths. Defaults to the output of `rustc --print sysroot` in cargo's working directory"))
+            .arg(Arg::with_name("ARG_QUOTING").long("arg-quoting").takes_value(true).possible_values(&["msvc", "powershell", "posix", "rsp"]).default_value("msvc").help("How the args in the linker and lib files are quoted: double quoted values as rustc prints them, one single quoted string per line for PowerShell or POSIX shells, or as response files to pass with @file, with each arg quoted and escaped as a whole and CRLF line endings"))
+            .arg(Arg::with_name("OUTPUT_FORMAT").long("output-format").takes_value(true).possible_values(&["text", "json"]).default_value("text").help("With json, also write the parsed link line to <output_linker_file>.json: the linker flavor, library paths, objects, rlibs, def file, system libraries, frameworks, linker options and the args that were dropped"))
+            .arg(Arg::with_name("RSP_ENCODING").long("rsp-encoding").takes_value(true).possible_values(&["utf8", "utf8-bom", "utf16"]).help("Encoding of the response files with --arg-quoting rsp. LIB.EXE and link.exe read files without a byte order mark as ANSI, so use utf8-bom or utf16 for non-ASCII paths [default: utf8]"))
+            .arg(Arg::with_name("EXPAND_THIN_ARCHIVES").long("expand-thin-archives").takes_value(false).help("Replace thin archives in the lib file with the objects they reference, which LIB.EXE can't find itself. Without it, thin archives are only warned about"))
+            .arg(Arg::with_name("DSYM").long("dsym").takes_value(false).help("Run dsymutil on the binary of each processed link line, writing <binary>.dSYM next to the lib file, so that the code can be debugged on macOS"))
+            .arg(Arg::with_name("COPY_OBJECTS_TO").long("copy-objects-to").takes_value(true).help("Copy the object files of the lib file to this directory and refer to the copies instead, so that they survive cargo cleaning up its temporary files"))
+            .arg(Arg::with_name("CXX_BRIDGE").long("cxx-bridge").takes_value(true).multiple(true).number_of_values(1).requires("GEN_RESPONSE_FILE").help("Rust file of the crate with a cxx bridge (or autocxx include_cpp!) whose C++ side the tool generates and compiles, instead of a build script, adding the objects to the lib file. Relative to the crate's directory. May be repeated. Disables the link args cache"))
+            .arg(Arg::with_name("CXX_GENERATOR").long("cxx-generator").takes_value(true).possible_values(&["cxx", "autocxx"]).default_value("cxx").help("Generator of the C++ side of the --cxx-bridge files: cxxbridge or autocxx-gen"))
+            .arg(Arg::with_name("CXX_INCLUDE_DIR").long("cxx-include-dir").takes_value(true).multiple(true).number_of_values(1).requires("CXX_BRIDGE").help("Directory of the C++ headers the bridges include. May be repeated"))
+            .arg(Arg::with_name("CXX_COMPILER").long("cxx-compiler").takes_value(true).requires("CXX_BRIDGE").help("Command compiling the bridges' C++ sources, e.g. \"clang++ -c -std=c++20 -fPIC\", to which include directories, the object and the source are appended in its link line's syntax. Defaults to \"cl /nologo /c /EHsc /std:c++17 /MD\" (or --ue-crt's flag) for MSVC link lines and \"c++ -c -std=c++17 -fPIC\" otherwise"))
+            .arg(Arg::with_name("CXX_OUT_DIR").long("cxx-out-dir").takes_value(true).requires("CXX_BRIDGE").help("Directory to write the bridges' headers, sources and objects to, with the placeholders of --output_linker_file. Defaults to <output_lib_link_file>.cxx"))
+            .arg(Arg::with_name("SYMBOL_PREFIX").long("symbol-prefix").takes_value(true).help("Fail if a symbol the def file (or version script, or synthesized def file) exports doesn't start with this prefix, e.g. \"game_\", as it may collide with another plugin's in the same process. Checked after --export-filter and --export-exclude"))
+            .arg(Arg::with_name("EXPORT_FILTER").long("export-filter").takes_value(true).multiple(true).number_of_values(1).help("Only export symbols matching this glob pattern from the def file. May be repeated"))
+            .arg(Arg::with_name("EXPORT_EXCLUDE").long("export-exclude").takes_value(true).multiple(true).number_of_values(1).help("Don't export symbols matching this glob pattern from the def file. May be repeated"))
+            .arg(Arg::with_name("VERBOSE_CARGO").long("verbose-cargo").multiple(true).takes_value(false).help("Forward -v to cargo. Specify twice for -vv"))
+            .arg(cfg_arg())
+            .arg(Arg::with_name("ANDROID_NDK").long("android-ndk").takes_value(true).min_values(0).max_values(1).help("Cross compile for Android with the clang and llvm-ar of this NDK (r19 or newer). Defaults to $ANDROID_NDK_ROOT or $NDKROOT. Adds --target aarch64-linux-android to the cargo args unless they pick a target"))
+            .arg(Arg::with_name("ANDROID_API").long("android-api").takes_value(true).requires("ANDROID_NDK").help("Android API level to link against with --android-ndk [default: 26]"))
+            .arg(Arg::with_name("TARGETS").long("targets").takes_value(true).use_delimiter(true).conflicts_with_all(&["ANDROID_NDK", "LINUX_MULTIARCH_ROOT", "TARGET_SPEC"]).help("Build for each of these targets (comma separated) in turn. The output file paths (and --dump-link-line) are templates in which {target} is replaced by the target, and the def file is written to build_def.<target>.def"))
+            .arg(Arg::with_name("LINUX_MULTIARCH_ROOT").long("linux-multiarch-root").takes_value(true).min_values(0).max_values(1).conflicts_with_all(&["ANDROID_NDK", "PLATFORM_CONFIG", "TARGET_SPEC"]).help("Cross compile for Linux with UE's clang toolchain at this path. Defaults to $LINUX_MULTIARCH_ROOT. Adds --target x86_64-unknown-linux-gnu to the cargo args unless they pick a target"))
+            .arg(Arg::with_name("PLATFORM_CONFIG").long("platform-config").takes_value(true).conflicts_with("ANDROID_NDK").help("TOML file describing a platform the tool doesn't know: its target spec, linkers, link line format and rules rewriting the linker file args (see the README)"))
+            .arg(Arg::with_name("TARGET_SPEC").long("target-spec").takes_value(true).conflicts_with("ANDROID_NDK").help("Custom target spec JSON to build for, passed to cargo as --target. Its linker and linker flavor are used to find and parse the link line. Overrides the platform config's target_spec"))
+            .arg(Arg::with_name("AUTO_INSTALL").long("auto-install").takes_value(false).help("Install the toolchain the crate pins in rust-toolchain.toml, and its components and the targets built, with rustup if they're missing, instead of failing"))
+            .arg(Arg::with_name("CLEAN_ENV").long("clean-env").takes_value(false).help("Run cargo with a minimal environment (see the README) plus the --env-passthrough variables"))
+            .arg(Arg::with_name("ENV_PASSTHROUGH").long("env-passthrough").takes_value(true).multiple(true).number_of_values(1).requires("CLEAN_ENV").help("Name of an environment variable to pass to cargo with --clean-env. May be repeated"))
+            .args(&cargo_network_args())
             .arg(Arg::with_name("CARGO_ARGS").multiple(true).last(true).allow_hyphen_values(true).help("Arguments to cargo. Cargo will be run with \"crate_dir\" as the working directory."))
         )
+        .subcommand(SubCommand::with_name("verify-abi")
+            .about("Check that the functions and statics the headers declare are the symbols the crate's library exports")
+            .version("0.1")
+            .arg(Arg::with_name("HEADER").long("header").required(true).takes_value(true).multiple(true).number_of_values(1).help("Header gen-bindings generated, e.g. output_header_file and output_functions_header_file. May be repeated"))
+            .arg(Arg::with_name("LIB").long("lib").required_unless("EXPORT_LIST").conflicts_with("EXPORT_LIST").takes_value(true).help("Staticlib of the crate (.a or .lib), whose own objects' unmangled symbols are the exports"))
+            .arg(Arg::with_name("CRATE_NAME").long("crate_name").takes_value(true).requires("LIB").help("Name of the crate whose objects in the staticlib are checked. Defaults to the staticlib's name without its lib prefix"))
+            .arg(Arg::with_name("EXPORT_LIST").long("export_list").takes_value(true).help("Def file, version script or exported symbols list rustc generated for the crate, e.g. the one the rustc subcommand's lib file passes to the linker"))
+            .arg(Arg::with_name("NM").long("nm").takes_value(true).requires("LIB").help("nm to list the staticlib's symbols with, e.g. llvm-nm for MSVC .lib files. Defaults to nm"))
+        )
+        .subcommand(SubCommand::with_name("gen-buildcs")
+            .about("Generate a Build.cs fragment linking the crate's library, adding its headers' directories to the include paths and staging its shared library")
+            .version("0.1")
+            .arg(Arg::with_name("CRATE_DIR").long("crate_dir").required(true).takes_value(true).help("Input crate directory"))
+            .arg(Arg::with_name("HEADER").long("header").required(true).takes_value(true).multiple(true).number_of_values(1).help("Header gen-bindings generates for the crate, whose directory goes into PublicIncludePaths. May be repeated"))
+            .arg(Arg::with_name("OUTPUT_FILE").long("output_file").takes_value(true).help("Destination filename for the fragment. Printed to stdout without one"))
+            .arg(Arg::with_name("LINKAGE").long("linkage").takes_value(true).possible_values(&["static", "dynamic"]).help("Whether to link the crate's staticlib, or its cdylib and stage it next to the binaries. Defaults to the crate type the crate builds, or static if it builds both"))
+            .arg(Arg::with_name("MODULE_DIR").long("module_dir").takes_value(true).help("Directory of the module's Build.cs. Paths are written relative to ModuleDirectory if given, or else absolute"))
+            .args(&cargo_network_args())
+            .arg(Arg::with_name("CARGO_ARGS").multiple(true).last(true).allow_hyphen_values(true).help("Arguments the crate is built with, e.g. \"--release\" or \"--target x86_64-pc-windows-msvc\", which pick the artifacts' directory"))
+        )
+        .subcommand(SubCommand::with_name("ios")
+            .about("Compile the crate as a staticlib for iOS, writing the archive and the frameworks and libraries it needs")
+            .version("0.1")
+            .arg(Arg::with_name("OUTPUT_LINKER_FILE").long("output_linker_file").required(true).takes_value(true).help("Path to output the frameworks and libraries to link at"))
+            .arg(Arg::with_name("OUTPUT_LIB_LINK_FILE").long("output_lib_link_file").required(true).takes_value(true).help("Path to output the staticlib path(s) at"))
+            .arg(Arg::with_name("CRATE_DIR").long("crate_dir").visible_alias("cwd").takes_value(true).help("Working directory to run cargo in. Defaults to the current directory"))
+            .arg(Arg::with_name("IOS_TARGET").long("ios-target").takes_value(true).multiple(true).number_of_values(1).help("Target to build. May be repeated. Defaults to aarch64-apple-ios"))
+            .arg(Arg::with_name("SIMULATOR").long("simulator").takes_value(false).help("Also build for the simulator: aarch64-apple-ios-sim and x86_64-apple-ios"))
+            .arg(Arg::with_name("AUTO_INSTALL").long("auto-install").takes_value(false).help("Install the toolchain the crate pins in rust-toolchain.toml, and its components and the targets built, with rustup if they're missing, instead of failing"))
+            .arg(Arg::with_name("LIPO").long("lipo").takes_value(true).help("Merge the staticlibs into this universal archive with lipo, and write it to the lib file instead. The targets need to have different architectures"))
+            .arg(Arg::with_name("WRITE_UNCHANGED").long("write-unchanged").takes_value(false).help("Rewrite the linker and lib files even when their contents didn't change"))
+            .arg(cfg_arg())
+            .args(&cargo_network_args())
+            .arg(Arg::with_name("CARGO_ARGS").multiple(true).last(true).allow_hyphen_values(true).help("Arguments to cargo rustc, e.g. --release"))
+        )
+        .subcommand(SubCommand::with_name("doctor")
+            .about("Check the toolchain and environment, printing how to fix any problems")
+            .version("0.1")
+            .arg(Arg::with_name("TARGET").long("target").takes_value(true).multiple(true).number_of_values(1).help("Target that needs to be installed and linkable. May be repeated. Defaults to checking the host's linker"))
+            .arg(Arg::with_name("LINKER").long("linker").takes_value(true).help("Linker to look for instead of the targets' default ones (link.exe for MSVC targets, cc otherwise)"))
+            .arg(Arg::with_name("OUTPUT_PATH").long("output-path").takes_value(true).multiple(true).number_of_values(1).help("Output file that needs to be writable, e.g. the linker or lib file. May be repeated"))
+        )
+        .subcommand(SubCommand::with_name("bootstrap")
+            .about("Provision a build machine: install the toolchain with the targets and components, and check it with a test build and header")
+            .version("0.1")
+            .arg(Arg::with_name("CRATE_DIR").long("crate_dir").takes_value(true).help("Crate whose rust-toolchain.toml (or rust-toolchain) pins the toolchain to install"))
+            .arg(Arg::with_name("TOOLCHAIN").long("toolchain").takes_value(true).help("Toolchain to install when the crate doesn't pin one [default: stable]"))
+            .arg(Arg::with_name("TARGET").long("target").takes_value(true).multiple(true).number_of_values(1).help("Target to install and test build for, besides the host. May be repeated"))
+            .arg(Arg::with_name("COMPONENT").long("component").takes_value(true).multiple(true).number_of_values(1).help("Component to install besides the pinned ones, e.g. rust-src. May be repeated"))
+            .arg(Arg::with_name("EXPAND").long("expand").takes_value(false).help("Check that headers can be generated with --expand, which needs a nightly toolchain"))
+        )
         .subcommand(SubCommand::with_name("source-files")
             .about("Get a list of all source files required to compile the crate")
             .version("0.1")
             .arg(Arg::with_name("CRATE_DIR").long("crate_dir").required(true).takes_value(true).help("Input crate directory"))
+            .arg(Arg::with_name("EXCLUDE_DIR").long("exclude-dir").takes_value(true).multiple(true).number_of_values(1).help("Directory not to descend into. Matched against directory names, or against the path relative to the target's source directory if it contains a path separator. Glob patterns are supported"))
+            .arg(Arg::with_name("SINCE").long("since").takes_value(true).help("Snapshot file of a previous run. Only the files added, changed or removed since then are printed, as \"added\", \"changed\" or \"removed\", a tab and the path, and the snapshot is updated. The snapshot's format is internal"))
+            .arg(Arg::with_name("MAX_DEPTH").long("max-depth").takes_value(true).validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string())).help("How many levels of subdirectories of each target's source directory to list. 0 only lists the source directory itself"))
+            .arg(Arg::with_name("FORMAT").long("format").takes_value(true).possible_values(&["text", "ninja", "json"]).default_value("text").help("Output format. \"ninja\" writes a build edge for ninja_target that depends on every source file, \"json\" an array of paths"))
+            .arg(Arg::with_name("WITH_METADATA").long("with-metadata").takes_value(false).help("With --format json, print { path, size, sha256 } objects instead of paths. The files are hashed in parallel"))
+            .arg(Arg::with_name("NINJA_TARGET").long("ninja-target").takes_value(true).required_if("FORMAT", "ninja").help("Output of the ninja build edge, e.g. the generated header"))
+            .arg(Arg::with_name("NINJA_RULE").long("ninja-rule").takes_value(true).default_value("gen-bindings").help("Rule of the ninja build edge"))
+            .arg(Arg::with_name("INCLUDE_HEADER").long("include-header").takes_value(true).multiple(true).number_of_values(1).help("Additional (e.g. generated) header to append to the list of files"))
+            .args(&cargo_network_args())
         )
-	.get_matches();
+        .subcommand(SubCommand::with_name("list-targets")
+            .about("List the targets of the crate along with their crate types")
+            .version("0.1")
+            .arg(Arg::with_name("CRATE_DIR").long("crate_dir").required(true).takes_value(true).help("Input crate directory"))
+            .arg(Arg::with_name("FORMAT").long("format").takes_value(true).possible_values(&["text", "json"]).default_value("text").help("Output format"))
+            .args(&cargo_network_args())
+        )
+        .subcommand(SubCommand::with_name("target-dir")
+            .about("Print cargo's target directory for the crate")
+            .version("0.1")
+            .arg(Arg::with_name("CRATE_DIR").long("crate_dir").required(true).takes_value(true).help("Input crate directory"))
+            .args(&cargo_network_args())
+        )
+        .arg(Arg::with_name("BASE_DIR").long("base-dir").global(true).takes_value(true).help("Directory relative crate directories, --manifest-path and output paths are resolved against. Defaults to the current directory"))
+        .arg(Arg::with_name("DUMP_CONFIG").long("dump-config").global(true).takes_value(false).help("Print the options in effect for the subcommand as JSON, along with whether each was given on the command line or is a default, and exit without running it"))
+        .after_help("EXIT CODES:\n    0  success\n    1  internal error\n    2  compile or link failure\n    3  usage error\n    4  header changed (with --exit-code-on-change)\n    5  environment problem (doctor finding, missing toolchain)")
+}
+
+fn main() {
+    let exit_code = match std::panic::catch_unwind(run) {
+        Ok(Ok(exit_code)) => exit_code,
+        Ok(Err(err)) => {
+            eprintln!("Error: {:?}", err);
+            ExitCode::InternalError
+        }
+        // the panic hook already printed the message
+        Err(_) => ExitCode::InternalError,
+    };
+    exit_code.exit();
+}
+
+fn run() -> Result<ExitCode> {
+    // rustc runs the tool as its linker with --capture-link-args fake-linker
+    if let Some(record) = std::env::var_os(fake_linker::RECORD_ENV_VAR) {
+        fake_linker::run(Path::new(&record))?;
+        return Ok(ExitCode::Success);
+    }
+    let matches = match app().get_matches_safe() {
+        Ok(matches) => matches,
+        Err(err) if err.use_stderr() => {
+            eprintln!("{}", err.message);
+            return Ok(ExitCode::UsageError);
+        }
+        Err(err) => {
+            // --help and --version
+            println!("{}", err.message);
+            return Ok(ExitCode::Success);
+        }
+    };
+
+    if let (name, Some(matches)) = matches.subcommand() {
+        if matches.is_present("DUMP_CONFIG") {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&effective_config(name, matches))?
+            );
+            return Ok(ExitCode::Success);
+        }
+    }
 
     // pull arguments from the argument parser
-    if let Some(matches) = matches.subcommand_matches("gen-bindings") {
-        let crate_dir: PathBuf = matches
-            .value_of("CRATE_DIR")
+    match matches.subcommand() {
+        ("gen-bindings", Some(matches)) => gen_bindings(matches),
+        ("bindings-sources", Some(matches)) => bindings_sources(matches),
+        ("gen-ue-wrappers", Some(matches)) => gen_ue_wrappers(matches),
+        ("gen-rust-bindings", Some(matches)) => gen_rust_bindings(matches),
+        ("batch", Some(matches)) => batch(matches),
+        ("source-files", Some(matches)) => source_files(matches),
+        ("list-targets", Some(matches)) => list_targets(matches),
+        ("target-dir", Some(matches)) => target_dir(matches),
+        ("rustc", Some(matches)) => rustc(matches),
+        ("verify-abi", Some(matches)) => verify_abi(matches),
+        ("gen-buildcs", Some(matches)) => gen_buildcs(matches),
+        ("doctor", Some(matches)) => doctor(matches),
+        ("bootstrap", Some(matches)) => bootstrap(matches),
+        ("ios", Some(matches)) => ios(matches),
+        _ => Ok(ExitCode::Success),
+    }
+}
+
+/// The options of a subcommand invocation, keyed by the value names shown in `--help`. Each has
+/// its values (the number of occurrences for flags) and where they came from.
+fn effective_config(subcommand: &str, matches: &ArgMatches) -> serde_json::Value {
+    let options: BTreeMap<&str, serde_json::Value> = matches
+        .args
+        .iter()
+        .filter(|(name, _)| **name != "DUMP_CONFIG")
+        .map(|(name, arg)| {
+            let values: Vec<_> = arg.vals.iter().map(|v| v.to_string_lossy()).collect();
+            let value = if values.is_empty() {
+                serde_json::json!(arg.occurs)
+            } else {
+                serde_json::json!(values)
+            };
+            let source = if arg.occurs > 0 { "cli" } else { "default" };
+            (
+                *name,
+                serde_json::json!({ "value": value, "source": source }),
+            )
+        })
+        .collect();
+    serde_json::json!({ "subcommand": subcommand, "options": options })
+}
+
+/// Keep the hash file of a header up to date. `written` is the data if the header was just
+/// written; otherwise the hash is checked against the header on disk, which an earlier run or a
+/// hand edit may have left it stale for.
+fn update_header_hash(hash_file: &Path, header_path: &str, written: Option<&[u8]>) -> Result<()> {
+    let header_name = Path::new(header_path)
+        .file_name()
+        .map_or_else(|| header_path.into(), |name| name.to_string_lossy());
+    match written {
+        Some(data) => header::write_hash(hash_file, &header_name, data),
+        None if Path::new(header_path).exists() => {
+            header::write_hash(hash_file, &header_name, &std::fs::read(header_path)?)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Write a header generated along with the output header if it changed, and return whether it
+/// did. `stamp_line` is the line of its stamp, if it's stamped. The diff of the change is added to
+/// `diffs` if they're collected.
+fn write_companion_header(
+    path: &Path,
+    data: &[u8],
+    stamp_line: Option<usize>,
+    diffs: Option<&mut String>,
+) -> Result<bool> {
+    let existing_data = std::fs::read(path).unwrap_or_default();
+    let changed = header::changed(data, Some(&existing_data), stamp_line);
+    if changed {
+        if let Some(diffs) = diffs {
+            diffs.push_str(&header::unified_diff(
+                &path.to_string_lossy(),
+                &existing_data,
+                data,
+                stamp_line,
+            ));
+        }
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, data)?;
+        println!("Header changed: {}", path.display());
+    }
+    Ok(changed)
+}
+
+fn gen_bindings(matches: &ArgMatches) -> Result<ExitCode> {
+    let crate_dirs: Vec<PathBuf> = match matches.value_of("WORKSPACE") {
+        Some(root) => bindings::workspace_lib_crates(&resolve_crate_dir(matches, root)?)?,
+        None => matches
+            .values_of("CRATE_DIR")
             .expect("crate_dir not provided")
-            .into();
-        let header_path: &str = matches
-            .value_of("OUTPUT_HEADER_FILE")
-            .expect("output_header_file not provided");
-        let generated = cbindgen::generate(&crate_dir).expect("Couldn't generate headers.");
-        let mut new_data: Vec<u8> = Vec::new();
-        let mut existing_data: Vec<u8> = Vec::new();
-
-        generated.write(&mut new_data);
-        if let Ok(mut current_header) = std::fs::File::open(&header_path) {
-            use std::io::Read;
-            current_header.read_to_end(&mut existing_data)?;
-        }
-        std::fs::create_dir_all(PathBuf::from(header_path).parent().unwrap())?;
-        if new_data != existing_data {
+            .map(|crate_dir| resolve_crate_dir(matches, crate_dir))
+            .collect::<Result<_>>()?,
+    };
+    let header_paths: Vec<String> = matches
+        .values_of("OUTPUT_HEADER_FILE")
+        .expect("output_header_file not provided")
+        .map(|path| match path {
+            "-" => path.to_owned(),
+            _ => resolve_path(matches, path).to_string_lossy().into_owned(),
+        })
+        .collect();
+    let header_paths: Vec<&str> = header_paths.iter().map(String::as_str).collect();
+    let merge = matches.is_present("MERGE") || matches.is_present("WORKSPACE");
+    if merge && header_paths.len() != 1 {
+        eprintln!("Merged headers need a single --output_header_file");
+        return Ok(ExitCode::UsageError);
+    } else if !merge && crate_dirs.len() != header_paths.len() {
+        eprintln!("Each --crate_dir needs a matching --output_header_file");
+        return Ok(ExitCode::UsageError);
+    }
+    let options = BindingsOptions::from_matches(matches);
+    let stamp_line = options.stamp_line()?;
+    let exit_code_on_change = matches.is_present("EXIT_CODE_ON_CHANGE");
+    let write_hash = matches.is_present("WRITE_HASH");
+    let mut diffs = matches.is_present("HEADER_DIFF").then(String::new);
+    let changed = if let [header_path] = &header_paths[..] {
+        let write_to_stdout = *header_path == "-";
+        // stdin is only read when explicitly asked for, not because the output is stdout
+        let existing_header_path = match matches.value_of("EXISTING_HEADER") {
+            Some("-") => Some(PathBuf::from("-")),
+            Some(path) => Some(resolve_path(matches, path)),
+            None if write_to_stdout => None,
+            None => Some(PathBuf::from(header_path)),
+        };
+        if exit_code_on_change && existing_header_path.is_none() {
+            eprintln!("--exit-code-on-change needs --existing-header when writing to stdout");
+            return Ok(ExitCode::UsageError);
+        }
+        let functions_header = matches
+            .value_of("OUTPUT_FUNCTIONS_HEADER_FILE")
+            .map(|path| resolve_path(matches, path));
+        if functions_header.is_some() && write_to_stdout {
+            eprintln!("--output_functions_header_file needs an output header file to include");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("LAYOUT_ASSERTS") && write_to_stdout {
+            eprintln!("--layout-asserts needs an output header file to include");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("UE_ENUMS") && write_to_stdout {
+            eprintln!("--ue-enums needs an output header file to include");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("UE_STRUCTS") && write_to_stdout {
+            eprintln!("--ue-structs needs an output header file to include");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("CPP_WRAPPERS") && write_to_stdout {
+            eprintln!("--cpp-wrappers needs an output header file to include");
+            return Ok(ExitCode::UsageError);
+        }
+        let depfile = matches
+            .value_of("DEPFILE")
+            .map(|path| resolve_path(matches, path));
+        if depfile.is_some() && write_to_stdout {
+            eprintln!("--depfile needs an output header file to list as the target");
+            return Ok(ExitCode::UsageError);
+        }
+        let mut sources = depfile.as_ref().map(|_| Vec::new());
+        let (new_data, functions_data) = if merge {
+            (
+                generate_merged_header(&crate_dirs, &options, sources.as_mut())?,
+                None,
+            )
+        } else if functions_header.is_some() {
+            let types_include = Path::new(header_path)
+                .file_name()
+                .expect("output_header_file has a file name")
+                .to_string_lossy();
+            let (types_data, functions_data) =
+                generate_split_headers(&crate_dirs[0], &options, &types_include, sources.as_mut())?;
+            (types_data, Some(functions_data))
+        } else {
+            let new_data = generate_header(&crate_dirs[0], &options, sources.as_mut())
+                .context("Couldn't generate headers.")?;
+            (new_data, None)
+        };
+        let existing_data = existing_header_path
+            .map(|path| header::read_existing(&path, matches.is_present("EXISTING_HEADER")))
+            .transpose()?;
+        let hash_file = match matches.value_of("HASH_FILE") {
+            Some(path) => Some(resolve_path(matches, path)),
+            None if write_hash && write_to_stdout => {
+                eprintln!("--write-hash needs an output header file, pass --hash-file instead");
+                return Ok(ExitCode::UsageError);
+            }
+            None if write_hash => Some(header::hash_path(Path::new(header_path))),
+            None => None,
+        };
+        let changed = header::changed(&new_data, existing_data.as_deref(), stamp_line);
+        if let (Some(diffs), Some(existing_data)) = (&mut diffs, &existing_data) {
+            diffs.push_str(&header::unified_diff(
+                header_path,
+                existing_data,
+                &new_data,
+                stamp_line,
+            ));
+        }
+        if write_to_stdout {
+            // the header is the only thing that may go to stdout in this mode
+            io::stdout().write_all(&new_data)?;
+            if changed {
+                eprintln!("Header changed");
+            }
+        } else if changed {
             std::fs::create_dir_all(PathBuf::from(header_path).parent().unwrap())?;
-            std::fs::write(&header_path, new_data)?;
+            std::fs::write(header_path, &new_data)?;
             println!("Header changed");
         }
+        if let Some(hash_file) = &hash_file {
+            let written = (write_to_stdout || changed).then_some(&new_data[..]);
+            update_header_hash(hash_file, header_path, written)?;
+        }
+        if let (Some(depfile), Some(mut sources)) = (&depfile, sources) {
+            // files read by this tool rather than cbindgen
+            sources.extend(options.cbindgen_config.iter().cloned());
+            sources.extend(options.license_header.iter().cloned());
+            let functions_header = functions_header.as_ref().map(|path| path.to_string_lossy());
+            let targets: Vec<&str> = std::iter::once(*header_path)
+                .chain(functions_header.as_deref())
+                .collect();
+            header::write_depfile(depfile, &targets, &sources)?;
+        }
+        let mut wrappers_changed = false;
+        if matches.is_present("CPP_WRAPPERS") {
+            let wrappers_path = match matches.value_of("CPP_WRAPPERS") {
+                Some(path) => resolve_path(matches, path),
+                None => wrappers::default_wrappers_path(Path::new(header_path)),
+            };
+            // the functions are in the functions header of split headers
+            let (include, functions) = match (&functions_header, &functions_data) {
+                (Some(path), Some(data)) => (path.as_path(), data),
+                _ => (Path::new(header_path), &new_data),
+            };
+            let include = include
+                .file_name()
+                .expect("output header files have a file name")
+                .to_string_lossy();
+            let config = bindings::load_config(&crate_dirs[0], &options)?;
+            let wrappers =
+                wrappers::wrappers_header(&include, &String::from_utf8_lossy(functions), &config);
+            wrappers_changed =
+                write_companion_header(&wrappers_path, wrappers.as_bytes(), None, diffs.as_mut())?;
+        }
+        let mut structs_changed = false;
+        if matches.is_present("UE_STRUCTS") {
+            let structs_path = match matches.value_of("UE_STRUCTS") {
+                Some(path) => resolve_path(matches, path),
+                None => blueprint::default_structs_path(Path::new(header_path)),
+            };
+            let header_name = Path::new(header_path)
+                .file_name()
+                .expect("output_header_file has a file name")
+                .to_string_lossy();
+            let structs_name = structs_path
+                .file_name()
+                .context("--ue-structs has no file name")?
+                .to_string_lossy();
+            let config = bindings::load_config(&crate_dirs[0], &options)?;
+            let (mirrors, skipped) = blueprint::mirrors_header(
+                &header_name,
+                &String::from_utf8_lossy(&new_data),
+                &structs_name,
+                &config,
+                matches
+                    .value_of("UE_CATEGORY")
+                    .expect("ue-category has a default"),
+            );
+            for (name, reason) in &skipped {
+                let message = format!("no USTRUCT mirror for `{}`: {}", name, reason);
+                eprintln!("{}", color::diagnostic("warning", message, options.color));
+            }
+            structs_changed =
+                write_companion_header(&structs_path, mirrors.as_bytes(), None, diffs.as_mut())?;
+        }
+        let mut enums_changed = false;
+        if matches.is_present("UE_ENUMS") {
+            let enums_path = match matches.value_of("UE_ENUMS") {
+                Some(path) => resolve_path(matches, path),
+                None => blueprint::default_enums_path(Path::new(header_path)),
+            };
+            let header_name = Path::new(header_path)
+                .file_name()
+                .expect("output_header_file has a file name")
+                .to_string_lossy();
+            let enums_name = enums_path
+                .file_name()
+                .context("--ue-enums has no file name")?
+                .to_string_lossy();
+            let config = bindings::load_config(&crate_dirs[0], &options)?;
+            let (mirrors, skipped) = blueprint::enums_header(
+                &header_name,
+                &String::from_utf8_lossy(&new_data),
+                &enums_name,
+                &config,
+            );
+            for (name, reason) in &skipped {
+                let message = format!("no UENUM mirror for `{}`: {}", name, reason);
+                eprintln!("{}", color::diagnostic("warning", message, options.color));
+            }
+            enums_changed =
+                write_companion_header(&enums_path, mirrors.as_bytes(), None, diffs.as_mut())?;
+        }
+        let mut functions_changed = false;
+        if let (Some(functions_header), Some(functions_data)) = (&functions_header, functions_data)
+        {
+            functions_changed = write_companion_header(
+                functions_header,
+                &functions_data,
+                stamp_line,
+                diffs.as_mut(),
+            )?;
+            if write_hash {
+                let functions_header = functions_header.to_string_lossy();
+                let hash_file = header::hash_path(Path::new(&*functions_header));
+                let written = functions_changed.then_some(&functions_data[..]);
+                update_header_hash(&hash_file, &functions_header, written)?;
+            }
+        }
+        let mut asserts_changed = false;
+        if matches.is_present("LAYOUT_ASSERTS") {
+            let asserts_path = match matches.value_of("LAYOUT_ASSERTS") {
+                Some(path) => resolve_path(matches, path),
+                None => layout::default_asserts_path(Path::new(header_path)),
+            };
+            let layouts = layout::type_layouts(
+                &crate_dirs[0],
+                matches.value_of("LAYOUT_TOOLCHAIN"),
+                matches.value_of("LAYOUT_TARGET"),
+                &options.cfgs,
+            )?;
+            let config = bindings::load_config(&crate_dirs[0], &options)?;
+            let header_name = Path::new(header_path)
+                .file_name()
+                .expect("output_header_file has a file name")
+                .to_string_lossy();
+            let asserts = layout::asserts_header(
+                &header_name,
+                &String::from_utf8_lossy(&new_data),
+                &layouts,
+                &config,
+            );
+            asserts_changed =
+                write_companion_header(&asserts_path, asserts.as_bytes(), None, diffs.as_mut())?;
+        }
+        changed
+            || functions_changed
+            || asserts_changed
+            || wrappers_changed
+            || structs_changed
+            || enums_changed
+    } else {
+        if header_paths.contains(&"-") || matches.is_present("EXISTING_HEADER") {
+            eprintln!("Reading or writing headers through stdin/stdout requires a single crate");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("DEPFILE") {
+            eprintln!("--depfile requires a single output_header_file");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("HASH_FILE") {
+            eprintln!("--hash-file requires a single crate, use --write-hash instead");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("LAYOUT_ASSERTS") {
+            eprintln!("--layout-asserts requires a single crate");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("UE_ENUMS") {
+            eprintln!("--ue-enums requires a single crate");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("UE_STRUCTS") {
+            eprintln!("--ue-structs requires a single crate");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("CPP_WRAPPERS") {
+            eprintln!("--cpp-wrappers requires a single crate");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("OUTPUT_FUNCTIONS_HEADER_FILE") {
+            eprintln!("--output_functions_header_file requires a single crate");
+            return Ok(ExitCode::UsageError);
+        }
+        if matches.is_present("INCLUDE_GUARD") {
+            eprintln!(
+                "--include-guard requires a single crate, headers can't share an include guard"
+            );
+            return Ok(ExitCode::UsageError);
+        }
+        let transactional = matches.is_present("TRANSACTIONAL");
+        let mut staged = Vec::new();
+        let mut changed = false;
+        for (crate_dir, header_path) in crate_dirs.iter().zip(&header_paths) {
+            let new_data = generate_header(crate_dir, &options, None)?;
+            let existing_data = std::fs::read(header_path).unwrap_or_default();
+            if header::changed(&new_data, Some(&existing_data), stamp_line) {
+                changed = true;
+                if let Some(diffs) = &mut diffs {
+                    diffs.push_str(&header::unified_diff(
+                        header_path,
+                        &existing_data,
+                        &new_data,
+                        stamp_line,
+                    ));
+                }
+                if transactional {
+                    staged.push((PathBuf::from(header_path), new_data));
+                } else {
+                    std::fs::create_dir_all(PathBuf::from(header_path).parent().unwrap())?;
+                    std::fs::write(header_path, &new_data)?;
+                    println!("Header changed: {}", header_path);
+                    if write_hash {
+                        let hash_file = header::hash_path(Path::new(header_path));
+                        update_header_hash(&hash_file, header_path, Some(&new_data))?;
+                    }
+                }
+            } else if write_hash {
+                let hash_file = header::hash_path(Path::new(header_path));
+                update_header_hash(&hash_file, header_path, None)?;
+            }
+        }
+        header::write_all_or_nothing(&staged)?;
+        for (header_path, data) in &staged {
+            println!("Header changed: {}", header_path.display());
+            if write_hash {
+                let header_path = header_path.to_string_lossy();
+                let hash_file = header::hash_path(Path::new(&*header_path));
+                update_header_hash(&hash_file, &header_path, Some(data))?;
+            }
+        }
+        changed
+    };
+    if let Some(diffs) = &diffs {
+        match matches.value_of("HEADER_DIFF") {
+            Some(path) => std::fs::write(resolve_path(matches, path), diffs)?,
+            None => eprint!("{}", diffs),
+        }
+    }
+    if let (true, Some(stamp_file)) = (changed, matches.value_of("STAMP_FILE")) {
+        header::touch(&resolve_path(matches, stamp_file))?;
+    }
+    if changed && exit_code_on_change {
+        return Ok(ExitCode::HeaderChanged);
+    }
+    Ok(ExitCode::Success)
+}
+
+fn bindings_sources(matches: &ArgMatches) -> Result<ExitCode> {
+    let crate_dir = matches
+        .value_of("CRATE_DIR")
+        .expect("crate_dir not provided");
+    let crate_dir = resolve_crate_dir(matches, crate_dir)?;
+    let options = BindingsOptions::from_matches(matches);
+    let sources = bindings::bindings_sources(&crate_dir, &options)?;
+    println!("{}", serde_json::to_string_pretty(&sources)?);
+    Ok(ExitCode::Success)
+}
+
+fn gen_ue_wrappers(matches: &ArgMatches) -> Result<ExitCode> {
+    let crate_dir = matches
+        .value_of("CRATE_DIR")
+        .expect("crate_dir not provided");
+    let crate_dir = resolve_crate_dir(matches, crate_dir)?;
+    let header_path = resolve_path(
+        matches,
+        matches
+            .value_of("OUTPUT_HEADER_FILE")
+            .expect("output_header_file not provided"),
+    );
+    let source_path = resolve_path(
+        matches,
+        matches
+            .value_of("OUTPUT_SOURCE_FILE")
+            .expect("output_source_file not provided"),
+    );
+    let options = BindingsOptions::from_matches(matches);
+    let header = generate_header(&crate_dir, &options, None)?;
+    let config = bindings::load_config(&crate_dir, &options)?;
+    let header_name = header_path
+        .file_name()
+        .context("output_header_file has no file name")?
+        .to_string_lossy();
+    let library = blueprint::library(
+        &String::from_utf8_lossy(&header),
+        &config,
+        &blueprint::LibraryOptions {
+            header_name: &header_name,
+            bindings_header_name: matches
+                .value_of("BINDINGS_HEADER")
+                .expect("bindings_header not provided"),
+            category: matches
+                .value_of("CATEGORY")
+                .expect("category has a default"),
+            string_free_fn: matches.value_of("STRING_FREE_FN"),
+            structs_header_name: matches.value_of("STRUCTS_HEADER"),
+        },
+    );
+    let color = ColorChoice::from_arg(matches.value_of("COLOR"));
+    for (function, reason) in &library.skipped {
+        let message = format!("skipped `{}`: {}", function, reason);
+        eprintln!("{}", color::diagnostic("warning", message, color));
+    }
+    write_companion_header(&header_path, library.header.as_bytes(), None, None)?;
+    write_companion_header(&source_path, library.source.as_bytes(), None, None)?;
+    Ok(ExitCode::Success)
+}
+
+fn gen_rust_bindings(matches: &ArgMatches) -> Result<ExitCode> {
+    let current_dir = std::env::current_dir()?;
+    let headers = matches
+        .values_of("HEADER")
+        .expect("header not provided")
+        .map(|header| {
+            let header = current_dir.join(resolve_path(matches, header));
+            if !header.is_file() {
+                anyhow::bail!("Header {} doesn't exist", header.display());
+            }
+            Ok(header)
+        })
+        .collect::<Result<_>>()?;
+    let mut include_dirs: Vec<PathBuf> = matches
+        .values_of("INCLUDE_DIR")
+        .into_iter()
+        .flatten()
+        .map(|dir| current_dir.join(resolve_path(matches, dir)))
+        .collect();
+    if let Some(path) = matches.value_of("INCLUDE_DIRS_FILE") {
+        let path = current_dir.join(resolve_path(matches, path));
+        include_dirs.extend(rust_bindings::read_include_dirs(&path)?);
+    }
+    let values = |name| -> Vec<String> {
+        matches
+            .values_of(name)
+            .into_iter()
+            .flatten()
+            .map(str::to_owned)
+            .collect()
+    };
+    let options = rust_bindings::RustBindingsOptions {
+        headers,
+        include_dirs,
+        defines: values("DEFINE"),
+        allowlist: values("ALLOWLIST"),
+        clang_args: values("CLANG_ARG"),
+    };
+    let output_path = resolve_path(
+        matches,
+        matches
+            .value_of("OUTPUT_FILE")
+            .expect("output_file not provided"),
+    );
+    let module = rust_bindings::generate(&options)?;
+    if std::fs::read(&output_path).ok().as_deref() != Some(&module[..]) {
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&output_path, &module)?;
+        println!("Bindings changed: {}", output_path.display());
+    }
+    Ok(ExitCode::Success)
+}
+
+fn verify_abi(matches: &ArgMatches) -> Result<ExitCode> {
+    let color = ColorChoice::from_arg(matches.value_of("COLOR"));
+    let mut declared = Vec::new();
+    for header in matches.values_of("HEADER").expect("header not provided") {
+        let path = resolve_path(matches, header);
+        let header = std::fs::read_to_string(&path)
+            .with_context(|| format!("Couldn't read header {}", path.display()))?;
+        declared.extend(verify_abi::declared_symbols(&header));
+    }
+    let exported: Vec<String> = if let Some(lib) = matches.value_of("LIB") {
+        let lib = resolve_path(matches, lib);
+        let crate_name = match matches.value_of("CRATE_NAME") {
+            Some(crate_name) => crate_name.replace('-', "_"),
+            None => {
+                let stem = lib.file_stem().unwrap_or_default().to_string_lossy();
+                stem.strip_prefix("lib").unwrap_or(&stem).to_owned()
+            }
+        };
+        let nm = matches.value_of("NM").unwrap_or(exports::DEFAULT_NM);
+        exports::staticlib_exports(nm, &lib, &crate_name)?
+            .into_iter()
+            .map(|export| export.name)
+            .collect()
+    } else {
+        let path = resolve_path(matches, matches.value_of("EXPORT_LIST").unwrap());
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Couldn't read export list {}", path.display()))?;
+        def_file::exported_symbols(&contents)
+    };
+    let (missing, undeclared) = verify_abi::compare(&declared, &exported);
+    for symbol in &missing {
+        let message = format!("{} is declared by the header, but not exported", symbol);
+        eprintln!("{}", color::diagnostic("error", message, color));
+    }
+    for symbol in &undeclared {
+        let message = format!("{} is exported, but not declared by the header", symbol);
+        eprintln!("{}", color::diagnostic("error", message, color));
     }
+    if !missing.is_empty() || !undeclared.is_empty() {
+        return Ok(ExitCode::CompileFailure);
+    }
+    println!("{} symbols declared and exported", exported.len());
+    Ok(ExitCode::Success)
+}
 
-    if let Some(matches) = matches.subcommand_matches("source-files") {
-        let crate_dir: PathBuf = matches
+fn gen_buildcs(matches: &ArgMatches) -> Result<ExitCode> {
+    let crate_dir = resolve_crate_dir(
+        matches,
+        matches
             .value_of("CRATE_DIR")
-            .expect("crate_dir not provided")
-            .into();
-        let cargo_toml_path = crate_dir.join("Cargo.toml");
-        let ctx = GlobalContext::default().unwrap();
-        let ws = cargo::core::Workspace::new(&cargo_toml_path, &ctx).unwrap();
-        let (packages, _) = cargo::ops::resolve_ws(&ws, false).unwrap();
-        for package in packages.package_ids() {
-            if let Some(path) = package.source_id().local_path() {
-                let package_toml_path = path.join("Cargo.toml");
-                let package = ws.load(&package_toml_path).unwrap();
-                for target in package.targets() {
-                    if let TargetKind::Lib(_) = target.kind() {
-                        if let TargetSourcePath::Path(path) = target.src_path() {
-                            let dir = path.parent().unwrap();
-                            visit_dirs(dir, &|entry| {
-                                println!("{}", entry.path().to_string_lossy());
-                            })
-                            .expect("error walking directory");
-                        }
-                    }
+            .expect("crate_dir not provided"),
+    )?;
+    let cargo_toml_path = crate_dir.join("Cargo.toml").canonicalize()?;
+    let ctx = cargo_context(matches)?;
+    let ws = Workspace::new(&cargo_toml_path, &ctx)?;
+    let package = ws.current()?;
+    let lib = package
+        .targets()
+        .iter()
+        .find(|target| target.is_lib())
+        .with_context(|| format!("{} has no lib target", package.name()))?;
+    let crate_types: Vec<String> = lib
+        .rustc_crate_types()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    let has = |crate_type: &str| crate_types.iter().any(|t| t == crate_type);
+    let linkage = match matches
+        .value_of("LINKAGE")
+        .and_then(build_cs::Linkage::from_arg)
+    {
+        Some(build_cs::Linkage::Static) if !has("staticlib") => {
+            eprintln!("--linkage static requires the crate to build a staticlib");
+            return Ok(ExitCode::UsageError);
+        }
+        Some(build_cs::Linkage::Dynamic) if !has("cdylib") => {
+            eprintln!("--linkage dynamic requires the crate to build a cdylib");
+            return Ok(ExitCode::UsageError);
+        }
+        Some(linkage) => linkage,
+        None if has("staticlib") => build_cs::Linkage::Static,
+        None if has("cdylib") => build_cs::Linkage::Dynamic,
+        None => {
+            eprintln!(
+                "{} builds neither a staticlib nor a cdylib, so there's nothing for UE to link",
+                lib.name()
+            );
+            return Ok(ExitCode::UsageError);
+        }
+    };
+
+    let cargo_args: Vec<&str> = matches
+        .values_of("CARGO_ARGS")
+        .into_iter()
+        .flatten()
+        .collect();
+    let mut artifact_dir = ws.target_dir().into_path_unlocked();
+    let target = match cargo_target(&cargo_args) {
+        Some(target) => {
+            artifact_dir.push(&target);
+            target
+        }
+        None => rustc_host()?,
+    };
+    artifact_dir.push(cargo_profile_dir(&cargo_args));
+    let artifacts = build_cs::artifacts(lib.name(), linkage, &target, &artifact_dir);
+
+    let current_dir = std::env::current_dir()?;
+    let mut include_dirs: Vec<PathBuf> = Vec::new();
+    for header in matches.values_of("HEADER").expect("header not provided") {
+        let header = resolved_output_path(&current_dir.join(resolve_path(matches, header)));
+        let dir = header.parent().unwrap_or(&header).to_owned();
+        if !include_dirs.contains(&dir) {
+            include_dirs.push(dir);
+        }
+    }
+    let module_dir = match matches.value_of("MODULE_DIR") {
+        Some(dir) => {
+            let resolved = resolve_path(matches, dir);
+            Some(resolved.canonicalize().with_context(|| {
+                format!("Module directory {} doesn't exist", resolved.display())
+            })?)
+        }
+        None => None,
+    };
+    let snippet = build_cs::snippet(lib.name(), &artifacts, &include_dirs, module_dir.as_deref());
+    match matches.value_of("OUTPUT_FILE") {
+        Some(path) => {
+            let path = resolve_path(matches, path);
+            if std::fs::read(&path).ok().as_deref() != Some(snippet.as_bytes()) {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, &snippet)?;
+                println!("Build.cs fragment changed: {}", path.display());
+            }
+        }
+        None => print!("{}", snippet),
+    }
+    Ok(ExitCode::Success)
+}
+
+fn batch(matches: &ArgMatches) -> Result<ExitCode> {
+    let modules_path = matches.value_of("MODULES").expect("modules not provided");
+    let modules = batch::read_modules(&resolve_path(matches, modules_path))?;
+    let options = BindingsOptions::from_matches(matches);
+    let transactional = matches.is_present("TRANSACTIONAL");
+    let ctx = cargo_context(matches)?;
+    let mut staged = Vec::new();
+    let mut source_lists = Vec::new();
+    let mut changed = Vec::new();
+    let mut failures = 0;
+    for module in &modules {
+        let crate_dir = module.crate_dir.display();
+        let output = match batch::process_module(module, &options, &ctx) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("{}: error: {:#}", crate_dir, err);
+                failures += 1;
+                continue;
+            }
+        };
+        if let (Some(path), Some(contents)) = (&module.source_files_file, output.source_files) {
+            if transactional {
+                source_lists.push((path, contents));
+            } else {
+                batch::write_source_files(path, &contents)?;
+            }
+        }
+        match output.header {
+            Some(new_data) => {
+                if transactional {
+                    staged.push((module.output_header_file.clone(), new_data));
+                } else {
+                    let header_path = &module.output_header_file;
+                    std::fs::create_dir_all(header_path.parent().unwrap())?;
+                    std::fs::write(header_path, &new_data)?;
+                    println!("{}: header changed: {}", crate_dir, header_path.display());
                 }
+                changed.push(module);
             }
+            None => println!("{}: header unchanged", crate_dir),
         }
     }
+    if transactional {
+        if failures == 0 {
+            staged.extend(
+                source_lists
+                    .into_iter()
+                    .map(|(path, contents)| (path.clone(), contents.into_bytes())),
+            );
+            header::write_all_or_nothing(&staged)?;
+            for module in &changed {
+                println!(
+                    "{}: header changed: {}",
+                    module.crate_dir.display(),
+                    module.output_header_file.display()
+                );
+            }
+        } else {
+            eprintln!(
+                "{} of {} modules failed, not writing any headers or source file lists",
+                failures,
+                modules.len()
+            );
+            changed.clear();
+        }
+    }
+    println!(
+        "{} modules, {} changed, {} failed",
+        modules.len(),
+        changed.len(),
+        failures
+    );
+    if let (false, Some(stamp_file)) = (changed.is_empty(), matches.value_of("STAMP_FILE")) {
+        header::touch(&resolve_path(matches, stamp_file))?;
+    }
+    if failures > 0 {
+        return Ok(ExitCode::CompileFailure);
+    }
+    if !changed.is_empty() && matches.is_present("EXIT_CODE_ON_CHANGE") {
+        return Ok(ExitCode::HeaderChanged);
+    }
+    Ok(ExitCode::Success)
+}
 
-    if let Some(matches) = matches.subcommand_matches("rustc") {
-        let output_linker_file: &str = matches
+fn source_files(matches: &ArgMatches) -> Result<ExitCode> {
+    let crate_dir = resolve_crate_dir(
+        matches,
+        matches
+            .value_of("CRATE_DIR")
+            .expect("crate_dir not provided"),
+    )?;
+    let cargo_toml_path = crate_dir.join("Cargo.toml");
+    let ctx = cargo_context(matches)?;
+    let ws = Workspace::new(&cargo_toml_path, &ctx)
+        .with_context(|| format!("Couldn't load the workspace of {}", crate_dir.display()))?;
+    let mut walk_options = WalkOptions {
+        max_depth: matches
+            .value_of("MAX_DEPTH")
+            .map(|depth| depth.parse().expect("max-depth was validated")),
+        ..Default::default()
+    };
+    if let Some(exclude_dirs) = matches.values_of("EXCLUDE_DIR") {
+        for exclude_dir in exclude_dirs {
+            walk_options
+                .exclude_dirs
+                .push(glob::Pattern::new(exclude_dir)?);
+        }
+    }
+    let mut files = Vec::new();
+    for package in local_packages(&ws).context("Couldn't list the workspace's packages")? {
+        let walk_options = walk_options.with_ignore_file(package.root())?;
+        let package_files = lib_source_files(&package, &walk_options)
+            .with_context(|| format!("Couldn't list the source files of {}", package.name()))?;
+        files.extend(package_files);
+    }
+    for header in matches.values_of("INCLUDE_HEADER").into_iter().flatten() {
+        let header = resolve_path(matches, header);
+        // the crate's own files are listed canonicalized, a generated header may not exist yet
+        let canonical = header.canonicalize().unwrap_or_else(|_| header.clone());
+        if !files.contains(&header) && !files.contains(&canonical) {
+            files.push(header);
+        }
+    }
+    if matches.is_present("WITH_METADATA") && matches.value_of("FORMAT") != Some("json") {
+        eprintln!("--with-metadata needs --format json");
+        return Ok(ExitCode::UsageError);
+    }
+    if let Some(snapshot_path) = matches.value_of("SINCE") {
+        if let Some(format @ ("ninja" | "json")) = matches.value_of("FORMAT") {
+            eprintln!("--since can't be combined with --format {}", format);
+            return Ok(ExitCode::UsageError);
+        }
+        let snapshot_path = resolve_path(matches, snapshot_path);
+        let previous = source_files::Snapshot::load(&snapshot_path);
+        let current = source_files::Snapshot::take(&files, &previous)?;
+        for change in current.changes_since(&previous) {
+            println!("{}", change);
+        }
+        current.save(&snapshot_path)?;
+    } else if matches.value_of("FORMAT") == Some("ninja") {
+        let ninja_target = matches
+            .value_of("NINJA_TARGET")
+            .expect("ninja-target not provided");
+        let ninja_rule = matches
+            .value_of("NINJA_RULE")
+            .expect("ninja-rule not provided");
+        println!(
+            "{}",
+            source_files::ninja_build_edge(Path::new(ninja_target), ninja_rule, &files)?
+        );
+    } else if matches.value_of("FORMAT") == Some("json") {
+        let json = if matches.is_present("WITH_METADATA") {
+            serde_json::to_string_pretty(&source_files::files_metadata(&files)?)?
+        } else {
+            serde_json::to_string_pretty(&files)?
+        };
+        println!("{}", json);
+    } else {
+        for file in files {
+            println!("{}", file.to_string_lossy());
+        }
+    }
+    Ok(ExitCode::Success)
+}
+
+fn list_targets(matches: &ArgMatches) -> Result<ExitCode> {
+    let crate_dir = resolve_crate_dir(
+        matches,
+        matches
+            .value_of("CRATE_DIR")
+            .expect("crate_dir not provided"),
+    )?;
+    let cargo_toml_path = crate_dir.join("Cargo.toml").canonicalize()?;
+    let ctx = cargo_context(matches)?;
+    let ws = Workspace::new(&cargo_toml_path, &ctx)?;
+    let targets = source_files::member_targets(&ws);
+    if matches.value_of("FORMAT") == Some("json") {
+        println!("{}", serde_json::to_string_pretty(&targets)?);
+    } else {
+        for target in targets {
+            println!(
+                "{}\t{}\t{}\t{}",
+                target.name,
+                target.kind,
+                target.crate_types.join(","),
+                target
+                    .src_path
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            );
+        }
+    }
+    Ok(ExitCode::Success)
+}
+
+fn target_dir(matches: &ArgMatches) -> Result<ExitCode> {
+    let crate_dir = resolve_crate_dir(
+        matches,
+        matches
+            .value_of("CRATE_DIR")
+            .expect("crate_dir not provided"),
+    )?;
+    let cargo_toml_path = crate_dir.join("Cargo.toml").canonicalize()?;
+    let ctx = cargo_context(matches)?;
+    let ws = Workspace::new(&cargo_toml_path, &ctx)?;
+    println!("{}", ws.target_dir().into_path_unlocked().to_string_lossy());
+    Ok(ExitCode::Success)
+}
+
+fn doctor(matches: &ArgMatches) -> Result<ExitCode> {
+    let color = ColorChoice::from_arg(matches.value_of("COLOR"));
+    let options = doctor::DoctorOptions {
+        targets: matches
+            .values_of("TARGET")
+            .into_iter()
+            .flatten()
+            .map(str::to_owned)
+            .collect(),
+        linker: matches.value_of("LINKER").map(str::to_owned),
+        output_paths: matches
+            .values_of("OUTPUT_PATH")
+            .into_iter()
+            .flatten()
+            .map(|path| resolve_path(matches, path))
+            .collect(),
+    };
+    let checks = doctor::run_checks(&options);
+    for check in &checks {
+        let label = match check.status {
+            doctor::Status::Ok => "ok",
+            doctor::Status::Warning => "warning",
+            doctor::Status::Error => "error",
+        };
+        eprintln!("{}", color::diagnostic(label, &check.message, color));
+        if let Some(fix) = &check.fix {
+            eprintln!("  fix: {}", fix);
+        }
+    }
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    let (errors, warnings) = (count(doctor::Status::Error), count(doctor::Status::Warning));
+    eprintln!("{} errors, {} warnings", errors, warnings);
+    if errors > 0 {
+        return Ok(ExitCode::EnvironmentError);
+    }
+    Ok(ExitCode::Success)
+}
+
+fn bootstrap(matches: &ArgMatches) -> Result<ExitCode> {
+    let color = ColorChoice::from_arg(matches.value_of("COLOR"));
+    let pinned = match matches.value_of("CRATE_DIR") {
+        Some(crate_dir) => toolchain::pinned_toolchain(&resolve_path(matches, crate_dir))?,
+        None => None,
+    };
+    let mut toolchain = match pinned {
+        Some(pinned) => {
+            eprintln!(
+                "info: {} pins toolchain {}",
+                pinned.file.display(),
+                pinned.channel
+            );
+            pinned
+        }
+        None => toolchain::PinnedToolchain {
+            channel: matches
+                .value_of("TOOLCHAIN")
+                .unwrap_or(bootstrap::DEFAULT_CHANNEL)
+                .to_owned(),
+            ..toolchain::PinnedToolchain::default()
+        },
+    };
+    for component in matches.values_of("COMPONENT").into_iter().flatten() {
+        if !toolchain.components.iter().any(|c| c == component) {
+            toolchain.components.push(component.to_owned());
+        }
+    }
+    let targets: Vec<String> = matches
+        .values_of("TARGET")
+        .into_iter()
+        .flatten()
+        .map(str::to_owned)
+        .collect();
+    let expand = matches.is_present("EXPAND");
+    if expand && !toolchain.channel.starts_with("nightly") {
+        let message = format!(
+            "--expand needs a nightly toolchain, but {} would be installed",
+            toolchain.channel
+        );
+        eprintln!("{}", color::diagnostic("error", message, color));
+        return Ok(ExitCode::UsageError);
+    }
+
+    eprintln!("info: installing toolchain {}", toolchain.channel);
+    if let Err(err) = toolchain.install(&targets) {
+        eprintln!(
+            "{}",
+            color::diagnostic("error", format!("{:#}", err), color)
+        );
+        return Ok(ExitCode::EnvironmentError);
+    }
+
+    let probe_dir = std::env::temp_dir().join(format!(
+        "unreal_rust_compile_bootstrap_{}",
+        std::process::id()
+    ));
+    let result = bootstrap_test_build(&probe_dir, &toolchain.channel, &targets, expand);
+    let _ = std::fs::remove_dir_all(&probe_dir);
+    if let Err(err) = result {
+        eprintln!(
+            "{}",
+            color::diagnostic("error", format!("{:#}", err), color)
+        );
+        return Ok(ExitCode::CompileFailure);
+    }
+    eprintln!("info: toolchain {} is ready", toolchain.channel);
+    Ok(ExitCode::Success)
+}
+
+/// Build the probe crate for the host and each of `targets`, and generate its header
+fn bootstrap_test_build(
+    probe_dir: &Path,
+    channel: &str,
+    targets: &[String],
+    expand: bool,
+) -> Result<()> {
+    bootstrap::write_probe_crate(probe_dir, channel)?;
+    for target in std::iter::once(None).chain(targets.iter().map(|t| Some(t.as_str()))) {
+        eprintln!("info: test build for {}", target.unwrap_or("the host"));
+        bootstrap::test_build(probe_dir, target)
+            .with_context(|| format!("Test build for {} failed", target.unwrap_or("the host")))?;
+    }
+    eprintln!("info: generating the test header");
+    let options = BindingsOptions {
+        expand: expand.then(Vec::new),
+        ..BindingsOptions::default()
+    };
+    let header =
+        generate_header(probe_dir, &options, None).context("Generating the test header failed")?;
+    bootstrap::check_probe_header(&header)
+}
+
+/// Check that the toolchain the crate pins is installed along with `targets`, installing what's
+/// missing with `--auto-install`. Returns the exit code to fail with if it isn't.
+fn check_pinned_toolchain(
+    matches: &ArgMatches,
+    cargo_dir: &Path,
+    targets: &[String],
+) -> Result<Option<ExitCode>> {
+    let color = ColorChoice::from_arg(matches.value_of("COLOR"));
+    let Some(pinned) = toolchain::pinned_toolchain(cargo_dir)? else {
+        return Ok(None);
+    };
+    eprintln!(
+        "info: {} pins toolchain {}",
+        pinned.file.display(),
+        pinned.channel
+    );
+    // custom target specs aren't installed with rustup
+    let targets: Vec<String> = targets
+        .iter()
+        .filter(|target| !target.ends_with(".json"))
+        .cloned()
+        .collect();
+    let missing = match pinned.missing(&targets) {
+        Ok(missing) => missing,
+        Err(err) => {
+            let message = format!("Couldn't check the pinned toolchain: {}", err);
+            eprintln!("{}", color::diagnostic("warning", message, color));
+            return Ok(None);
+        }
+    };
+    if missing.is_empty() {
+        return Ok(None);
+    }
+    if matches.is_present("AUTO_INSTALL") {
+        eprintln!("info: installing {}", missing.join(", "));
+        if let Err(err) = pinned.install(&targets) {
+            let message = format!("Couldn't install {}: {:#}", missing.join(", "), err);
+            eprintln!("{}", color::diagnostic("error", message, color));
+            return Ok(Some(ExitCode::EnvironmentError));
+        }
+        return Ok(None);
+    }
+    let message = format!(
+        "{} isn't installed. Pass --auto-install to install it, or run: rustup {}",
+        missing.join(", "),
+        pinned.install_args(&targets).join(" ")
+    );
+    eprintln!("{}", color::diagnostic("error", message, color));
+    Ok(Some(ExitCode::EnvironmentError))
+}
+
+/// The args of `rustc` naming files it writes, which may contain the placeholders
+const OUTPUT_PATH_ARGS: &[&str] = &[
+    "OUTPUT_LINKER_FILE",
+    "OUTPUT_LIB_LINK_FILE",
+    "DUMP_LINK_LINE",
+    "MAP_FILE",
+    "PDB",
+];
+
+/// Whether one of the output paths of `rustc` contains `placeholder`
+fn output_paths_contain(matches: &ArgMatches, placeholder: &str) -> bool {
+    OUTPUT_PATH_ARGS.iter().any(|name| {
+        matches
+            .value_of(name)
+            .is_some_and(|path| path.contains(placeholder))
+    })
+}
+
+/// The `/PDBALTPATH` embedding only the PDB's file name, so that debuggers look it up next to the
+/// module or on the symbol path rather than in the build machine's directories
+const DEFAULT_PDB_ALT_PATH: &str = "%_PDB%";
+
+fn rustc(matches: &ArgMatches) -> Result<ExitCode> {
+    if matches.is_present("RSP_ENCODING") && matches.value_of("ARG_QUOTING") != Some("rsp") {
+        eprintln!("--rsp-encoding needs --arg-quoting rsp");
+        return Ok(ExitCode::UsageError);
+    }
+    if matches.value_of("UE_CONFIG").is_none() && output_paths_contain(matches, CONFIG_PLACEHOLDER)
+    {
+        eprintln!(
+            "The output paths contain {}, which needs --ue-config",
+            CONFIG_PLACEHOLDER
+        );
+        return Ok(ExitCode::UsageError);
+    }
+    let cargo_args: Vec<&str> = matches
+        .values_of("CARGO_ARGS")
+        .into_iter()
+        .flatten()
+        .collect();
+    if matches.is_present("MANIFEST_PATH") && cargo_flag_given(&cargo_args, "--manifest-path") {
+        eprintln!("--manifest-path can't be given both to rustc and in the cargo args");
+        return Ok(ExitCode::UsageError);
+    }
+    let Some(targets) = matches.values_of("TARGETS") else {
+        return rustc_target(matches, None);
+    };
+    let outputs = ["OUTPUT_LINKER_FILE", "OUTPUT_LIB_LINK_FILE"];
+    if outputs.iter().any(|name| {
+        !matches
+            .value_of(name)
+            .unwrap_or_default()
+            .contains(TARGET_PLACEHOLDER)
+    }) {
+        eprintln!(
+            "With --targets, --output_linker_file and --output_lib_link_file need to contain {} to write separate files for each target",
+            TARGET_PLACEHOLDER
+        );
+        return Ok(ExitCode::UsageError);
+    }
+    // CARGO_BUILD_TARGET is overridden by the --target each of them is built with
+    if cargo_args_target(&cargo_args).is_some() {
+        eprintln!("--targets can't be combined with a --target in the cargo args");
+        return Ok(ExitCode::UsageError);
+    }
+    // build every target even if one fails, so that a single run reports all failures
+    let mut exit_code = ExitCode::Success;
+    let color = ColorChoice::from_arg(matches.value_of("COLOR"));
+    for target in targets {
+        eprintln!("info: building for {}", target);
+        let target_exit_code = match rustc_target(matches, Some(target)) {
+            Ok(target_exit_code) => target_exit_code,
+            Err(err) => {
+                let message = format!("{}: {:#}", target, err);
+                eprintln!("{}", color::diagnostic("error", message, color));
+                ExitCode::InternalError
+            }
+        };
+        if exit_code == ExitCode::Success {
+            exit_code = target_exit_code;
+        }
+    }
+    Ok(exit_code)
+}
+
+/// Compile the crate, for `target` if it's one of `--targets`
+fn rustc_target(matches: &ArgMatches, target: Option<&str>) -> Result<ExitCode> {
+    let mut cargo_args: Vec<&str> = matches
+        .values_of("CARGO_ARGS")
+        .expect("No cargo args provided")
+        .collect();
+    if let Some(manifest_path) = matches.value_of("MANIFEST_PATH") {
+        cargo_args.splice(0..0, ["--manifest-path", manifest_path]);
+    }
+    let cargo_args = resolve_manifest_path_arg(matches, &cargo_args);
+    let mut cargo_args: Vec<&str> = cargo_args.iter().map(String::as_str).collect();
+    if let Some(target) = target {
+        cargo_args.splice(0..0, ["--target", target]);
+    }
+    // the output paths are templates, e.g. for UBT to build every configuration of a target into
+    // the same intermediate directory
+    let template_target = match cargo_target(&cargo_args) {
+        Some(target) => target,
+        None if output_paths_contain(matches, TARGET_PLACEHOLDER) => rustc_host()?,
+        None => String::new(),
+    };
+    let profile = cargo_profile_dir(&cargo_args);
+    let output_path = |path: &str| {
+        let path = output_paths::fill(
+            path,
+            &template_target,
+            &profile,
+            matches.value_of("UE_CONFIG").unwrap_or_default(),
+        );
+        resolve_path(matches, &path)
+    };
+    let output_linker_file = output_path(
+        matches
             .value_of("OUTPUT_LINKER_FILE")
-            .expect("output_linker_file not provided");
-        let output_linker_file: PathBuf = output_linker_file.into();
-        let output_lib_link_file: PathBuf = matches
+            .expect("output_linker_file not provided"),
+    );
+    let output_lib_link_file = output_path(
+        matches
             .value_of("OUTPUT_LIB_LINK_FILE")
-            .expect("output_lib_link_file not provided")
-            .into();
-        let cargo_args = matches
-            .values_of("CARGO_ARGS")
-            .expect("No cargo args provided");
-        let gen_response_file = matches.is_present("GEN_RESPONSE_FILE");
-        use itertools::join;
-
-        eprintln!("Cargo args {}", join(cargo_args.clone(), ", "));
-        eprintln!("env args {}", join(std::env::args(), ", "));
-
-        let mut extra_cargo_args = Vec::new();
-        let rand_arg = format!("-Clink-arg=/VERSION:{}", rand::random::<u16>());
-        if gen_response_file {
-            extra_cargo_args.extend(&["--print", "link-args", "-C", "save-temps", &rand_arg]);
-        }
-        // generate the header file data and write it into a vec of bytes
-        // Build the cargo command from the args
-        let rustc_arg = Vec::from(["rustc"]);
-        let compile_result = Command::new("cargo")
-            .env("CARGO_INCREMENTAL", "1")
-            .args(
-                rustc_arg
-                    .into_iter()
-                    .chain(cargo_args.chain(extra_cargo_args.into_iter())),
+            .expect("output_lib_link_file not provided"),
+    );
+    if resolved_output_path(&output_linker_file) == resolved_output_path(&output_lib_link_file) {
+        eprintln!(
+            "--output_linker_file and --output_lib_link_file both refer to {}, they need to be different files",
+            resolved_output_path(&output_linker_file).display()
+        );
+        return Ok(ExitCode::UsageError);
+    }
+    let android = if matches.is_present("ANDROID_NDK") {
+        let root = match matches.value_of("ANDROID_NDK") {
+            Some(root) => resolve_path(matches, root),
+            None => {
+                match std::env::var_os("ANDROID_NDK_ROOT").or_else(|| std::env::var_os("NDKROOT")) {
+                    Some(root) => root.into(),
+                    None => {
+                        eprintln!("--android-ndk needs the NDK's path, or ANDROID_NDK_ROOT or NDKROOT to be set");
+                        return Ok(ExitCode::UsageError);
+                    }
+                }
+            }
+        };
+        let api_level = matches
+            .value_of("ANDROID_API")
+            .unwrap_or(android::DEFAULT_API_LEVEL);
+        let ndk = android::AndroidNdk::new(&root, api_level)?;
+        let target = cargo_target(&cargo_args).unwrap_or_else(|| {
+            cargo_args.splice(0..0, ["--target", android::DEFAULT_TARGET]);
+            android::DEFAULT_TARGET.to_owned()
+        });
+        Some((ndk, target))
+    } else {
+        None
+    };
+    let linux_cross = if matches.is_present("LINUX_MULTIARCH_ROOT") {
+        let root = match matches.value_of("LINUX_MULTIARCH_ROOT") {
+            Some(root) => resolve_path(matches, root),
+            None => match std::env::var_os(linux_cross::ROOT_ENV_VAR) {
+                Some(root) => root.into(),
+                None => {
+                    eprintln!(
+                        "--linux-multiarch-root needs the toolchain's path, or {} to be set",
+                        linux_cross::ROOT_ENV_VAR
+                    );
+                    return Ok(ExitCode::UsageError);
+                }
+            },
+        };
+        let target = cargo_target(&cargo_args).unwrap_or_else(|| {
+            cargo_args.splice(0..0, ["--target", linux_cross::DEFAULT_TARGET]);
+            linux_cross::DEFAULT_TARGET.to_owned()
+        });
+        let toolchain = linux_cross::LinuxCrossToolchain::new(&root, &target)?;
+        Some((toolchain, target))
+    } else {
+        None
+    };
+    let platform = match matches.value_of("PLATFORM_CONFIG") {
+        Some(path) => platform::read_platform_config(&resolve_path(matches, path))?,
+        None => platform::PlatformConfig::default(),
+    };
+    let target_spec_path = match matches.value_of("TARGET_SPEC") {
+        Some(path) => Some(resolve_path(matches, path)),
+        None => platform.target_spec.clone(),
+    };
+    let target_spec_arg;
+    let target_spec = match &target_spec_path {
+        Some(path) => {
+            if cargo_target(&cargo_args).is_some() {
+                eprintln!("A target spec can't be combined with a --target in the cargo args");
+                return Ok(ExitCode::UsageError);
+            }
+            let spec = platform::read_target_spec(path)?;
+            // cargo runs in the crate directory, so the spec has to be found from there
+            target_spec_arg = std::path::absolute(path)?.to_string_lossy().into_owned();
+            cargo_args.splice(0..0, ["--target", target_spec_arg.as_str()]);
+            Some(spec)
+        }
+        None => None,
+    };
+    let cargo_dir = resolve_crate_dir(matches, matches.value_of("CRATE_DIR").unwrap_or("."))?;
+    let toolchain_targets: Vec<String> = cargo_target(&cargo_args).into_iter().collect();
+    if let Some(exit_code) = check_pinned_toolchain(matches, &cargo_dir, &toolchain_targets)? {
+        return Ok(exit_code);
+    }
+    let gen_response_file = matches.is_present("GEN_RESPONSE_FILE");
+    let strict_routing = matches.is_present("STRICT_ROUTING");
+    let fail_if_no_link_args = matches.value_of("FAIL_IF_NO_LINK_ARGS") == Some("true");
+    let synthesize_def_file = gen_response_file && matches.is_present("SYNTHESIZE_DEF_FILE");
+    let write_unchanged = matches.is_present("WRITE_UNCHANGED");
+    let arg_quoting = ArgQuoting::from_arg(matches.value_of("ARG_QUOTING"));
+    let rsp_encoding = (arg_quoting == ArgQuoting::Rsp)
+        .then(|| ResponseFileEncoding::from_arg(matches.value_of("RSP_ENCODING")));
+    let json_file = (matches.value_of("OUTPUT_FORMAT") == Some("json"))
+        .then(|| link_info::json_path(&output_linker_file));
+    // the cache doesn't track the C++ headers the bridges include
+    let use_cache =
+        gen_response_file && !matches.is_present("NO_CACHE") && !matches.is_present("CXX_BRIDGE");
+    let color = ColorChoice::from_arg(matches.value_of("COLOR"));
+    let spec_format = target_spec.as_ref().and_then(|spec| spec.linker_format());
+    let linker_flavor = match matches.value_of("LINKER_FORMAT") {
+        Some("gnu") => Some(LinkerFlavor::Gnu),
+        Some(_) => Some(LinkerFlavor::Msvc),
+        None if platform.linker_format.is_some() => platform.linker_format,
+        None if spec_format.is_some() => spec_format,
+        None if matches.is_present("LINKER") => Some(LinkerFlavor::Msvc),
+        None if android.is_some() || linux_cross.is_some() => Some(LinkerFlavor::Gnu),
+        None => None,
+    };
+    let linkers: Vec<&str> = matches
+        .value_of("LINKER")
+        .into_iter()
+        .chain(platform.linkers.iter().map(String::as_str))
+        .chain(target_spec.as_ref().and_then(|spec| spec.linker.as_deref()))
+        .collect();
+    let cargo_verbosity = match matches.occurrences_of("VERBOSE_CARGO") {
+        0 => None,
+        1 => Some("-v"),
+        _ => Some("-vv"),
+    };
+    use itertools::join;
+
+    eprintln!("Cargo args {}", join(&cargo_args, ", "));
+    eprintln!("env args {}", join(std::env::args(), ", "));
+
+    // the targets' lib files may share a directory
+    // UE's link doesn't run in our working directory
+    let map_file = matches
+        .value_of("MAP_FILE")
+        .map(|path| resolved_output_path(&output_path(path)));
+    let pdb = matches
+        .value_of("PDB")
+        .map(|path| resolved_output_path(&output_path(path)));
+    let def_file_path = output_paths::def_file_path(&output_lib_link_file, target);
+    let mut export_filter = ExportFilter::default();
+    for pattern in matches.values_of("EXPORT_FILTER").into_iter().flatten() {
+        export_filter.include.push(glob::Pattern::new(pattern)?);
+    }
+    for pattern in matches.values_of("EXPORT_EXCLUDE").into_iter().flatten() {
+        export_filter.exclude.push(glob::Pattern::new(pattern)?);
+    }
+    let crate_dir = match cargo_dir
+        .join(manifest_path_from_args(&cargo_args))
+        .parent()
+    {
+        Some(dir) => dir.to_owned(),
+        None => cargo_dir.clone(),
+    };
+    let cxx_options = matches.values_of("CXX_BRIDGE").map(|bridges| {
+        let out_dir = match matches.value_of("CXX_OUT_DIR") {
+            Some(dir) => output_path(dir),
+            None => {
+                let mut dir = output_lib_link_file.clone().into_os_string();
+                dir.push(".cxx");
+                dir.into()
+            }
+        };
+        cxx_bridge::CxxOptions {
+            generator: matches
+                .value_of("CXX_GENERATOR")
+                .and_then(cxx_bridge::CxxGenerator::from_arg)
+                .expect("cxx-generator has a default"),
+            bridges: bridges.map(|bridge| crate_dir.join(bridge)).collect(),
+            out_dir: resolved_output_path(&out_dir),
+            include_dirs: matches
+                .values_of("CXX_INCLUDE_DIR")
+                .into_iter()
+                .flatten()
+                .map(|dir| resolved_output_path(&resolve_path(matches, dir)))
+                .collect(),
+            compiler: matches
+                .value_of("CXX_COMPILER")
+                .map(link_args::parse_posix_quotes),
+        }
+    });
+    let cache_key = if use_cache {
+        let tool_args: Vec<String> = std::env::args()
+            .skip(1)
+            .chain(target.map(str::to_owned))
+            .collect();
+        let manifest_path = cargo_dir.join(manifest_path_from_args(&cargo_args));
+        let key = cargo_context(matches).and_then(|ctx| {
+            cache::cache_key(
+                &manifest_path,
+                &tool_args,
+                |name| std::env::var_os(name),
+                &ctx,
             )
-            .output();
-
-        // If the cargo command completed with errors, return a nonzero status code
-        let command_success = match compile_result {
-            Ok(output) => {
-                let text = std::str::from_utf8(&output.stderr).expect("Cargo did not output utf8");
-                println!("{}", text); // output the compiler output
-                let stdout =
-                    std::str::from_utf8(&output.stdout).expect("Cargo did not output utf8");
-                let mut success = false;
-                if gen_response_file {
-                    // println!("stdout {}", stdout);
-                    if let Some(last_line) = stdout.lines().last() {
-                        if last_line.contains(".def") {
-                            success = true;
-                            let mut output_linker_file =
-                                std::fs::File::create(&output_linker_file)?;
-                            let mut output_lib_file = std::fs::File::create(&output_lib_link_file)?;
-                            let args = parse_quotes(&last_line);
-                            if let Some(linker_flavor) = args.first() {
-                                let linker_flavor_path = PathBuf::from(linker_flavor);
-                                let linker_flavor_filename = linker_flavor_path
-                                    .file_name()
-                                    .unwrap_or(std::ffi::OsStr::new(""));
-                                match linker_flavor_filename.to_str().unwrap_or("") {
-                                    "link.exe" | "lld-link.exe" | "rust-lld.exe" => {}
-                                    _ => panic!("Unrecognized linker flavor {}", linker_flavor),
+        });
+        match key {
+            Ok(key) => Some(key),
+            Err(err) => {
+                eprintln!("Not using link args cache: {}", err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if let Some(key) = &cache_key {
+        if cache::lookup(
+            key,
+            &output_linker_file,
+            &output_lib_link_file,
+            &def_file_path,
+            rsp_encoding,
+            json_file.as_deref(),
+        )
+        .is_some()
+        {
+            println!("link args unchanged (cached)");
+            if let Some(map_file) = &map_file {
+                println!("map file: {}", map_file.display());
+            }
+            return Ok(ExitCode::Success);
+        }
+    }
+
+    let mut extra_cargo_args = Vec::new();
+    let rand_arg = relink_arg(linker_flavor, &cargo_args);
+    let fake_linker =
+        gen_response_file && matches.value_of("CAPTURE_LINK_ARGS") == Some("fake-linker");
+    let fake_linker_flavor = link_flavor(linker_flavor, &cargo_args);
+    let fake_linker_record = std::env::temp_dir().join(format!(
+        "unreal_rust_compile_link_args_{}_{}.jsonl",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    let fake_linker_arg = format!("linker={}", std::env::current_exe()?.to_string_lossy());
+    // the fake linker preserves the inputs rustc would delete, so it doesn't need save-temps
+    let mut link_inputs_dir = output_lib_link_file.clone().into_os_string();
+    link_inputs_dir.push(".inputs");
+    let link_inputs_dir = PathBuf::from(link_inputs_dir);
+    if fake_linker {
+        extra_cargo_args.extend([rand_arg.as_str(), "-C", &fake_linker_arg]);
+    } else if gen_response_file {
+        extra_cargo_args.extend(&["--print", "link-args", "-C", "save-temps", &rand_arg]);
+    }
+    let cfg_args: Vec<String> = bindings::cfgs_from_matches(matches)
+        .iter()
+        .map(|cfg| cfg.rustc_arg())
+        .collect();
+    for cfg_arg in &cfg_args {
+        extra_cargo_args.extend(["--cfg", cfg_arg.as_str()]);
+    }
+    let cross_link_args = match &linux_cross {
+        Some((toolchain, _)) => toolchain.rustc_link_args(),
+        None => Vec::new(),
+    };
+    for link_arg in &cross_link_args {
+        extra_cargo_args.extend(["-C", link_arg.as_str()]);
+    }
+    // the extra args are rustc's, so they have to follow a `--`
+    if !extra_cargo_args.is_empty() && !cargo_args.contains(&"--") {
+        extra_cargo_args.insert(0, "--");
+    }
+    // generate the header file data and write it into a vec of bytes
+    // Build the cargo command from the args
+    // cargo's own flags have to come before CARGO_ARGS, which may end in `--`
+    let rustc_arg = Vec::from(["rustc"]);
+    // an explicit --color in the cargo args wins
+    let color_args = if cargo_flag_given(&cargo_args, "--color") {
+        Vec::new()
+    } else {
+        vec!["--color", color.cargo_value()]
+    };
+    // the staticlib a def file is synthesized from is found in cargo's JSON messages
+    let message_format_args =
+        if synthesize_def_file && !cargo_flag_given(&cargo_args, "--message-format") {
+            vec!["--message-format", "json-render-diagnostics"]
+        } else {
+            Vec::new()
+        };
+    let network_args: Vec<&str> = [
+        ("OFFLINE", "--offline"),
+        ("LOCKED", "--locked"),
+        ("FROZEN", "--frozen"),
+    ]
+    .iter()
+    .filter(|(name, flag)| matches.is_present(name) && !cargo_flag_given(&cargo_args, flag))
+    .map(|(_, flag)| *flag)
+    .collect();
+    let mut cargo = Command::new("cargo");
+    if matches.is_present("CLEAN_ENV") {
+        cargo.env_clear();
+        let passthrough = matches.values_of("ENV_PASSTHROUGH").into_iter().flatten();
+        cargo.envs(cargo_env::clean_env(passthrough, |name| {
+            std::env::var_os(name)
+        }));
+    }
+    if let Some((ndk, target)) = &android {
+        cargo.envs(ndk.cargo_env(target));
+    }
+    if let Some((toolchain, target)) = &linux_cross {
+        cargo.envs(toolchain.cargo_env(target));
+    }
+    if fake_linker {
+        cargo.env(fake_linker::RECORD_ENV_VAR, &fake_linker_record);
+        let flavor = match fake_linker_flavor {
+            LinkerFlavor::Msvc => "msvc",
+            LinkerFlavor::Gnu => "gnu",
+        };
+        cargo.env(fake_linker::FLAVOR_ENV_VAR, flavor);
+        // the previous build's inputs aren't needed anymore
+        if link_inputs_dir.is_dir() {
+            std::fs::remove_dir_all(&link_inputs_dir)?;
+        }
+        cargo.env(
+            fake_linker::INPUTS_DIR_ENV_VAR,
+            std::path::absolute(&link_inputs_dir)?,
+        );
+    }
+    let compile_result = cargo
+        .current_dir(&cargo_dir)
+        .env("CARGO_INCREMENTAL", "1")
+        .args(
+            rustc_arg
+                .into_iter()
+                .chain(cargo_verbosity)
+                .chain(color_args)
+                .chain(message_format_args)
+                .chain(network_args)
+                .chain(cargo_args.iter().copied().chain(extra_cargo_args)),
+        )
+        .output();
+
+    let manifest_path = cargo_dir.join(manifest_path_from_args(&cargo_args));
+    let mut keep_linker_args = manifest_list(&manifest_path, "keep-linker-args")?;
+    keep_linker_args.extend(
+        matches
+            .values_of("KEEP_LINKER_ARG")
+            .into_iter()
+            .flatten()
+            .map(str::to_owned),
+    );
+    let mut unused_keep_args = Vec::new();
+    let mut allowed_linkers = Vec::new();
+    for arg in manifest_list(&manifest_path, "allowed-linkers")?
+        .iter()
+        .map(String::as_str)
+        .chain(matches.values_of("ALLOWED_LINKERS").into_iter().flatten())
+    {
+        match link_args::AllowedLinker::from_arg(arg) {
+            Ok(linker) => allowed_linkers.push(linker),
+            Err(message) => {
+                eprintln!("{}", message);
+                return Ok(ExitCode::UsageError);
+            }
+        }
+    }
+    let permissive = matches.is_present("PERMISSIVE");
+    // If the cargo command completed with errors, return a nonzero status code
+    let command_success = match compile_result {
+        Ok(output) => {
+            let text = std::str::from_utf8(&output.stderr).expect("Cargo did not output utf8");
+            println!("{}", text); // output the compiler output
+            let mut stdout = std::str::from_utf8(&output.stdout)
+                .expect("Cargo did not output utf8")
+                .to_owned();
+            if fake_linker {
+                let record = std::fs::read_to_string(&fake_linker_record).unwrap_or_default();
+                let _ = std::fs::remove_file(&fake_linker_record);
+                let linker = matches
+                    .value_of("LINKER")
+                    .unwrap_or(match fake_linker_flavor {
+                        LinkerFlavor::Msvc => "link.exe",
+                        LinkerFlavor::Gnu => "cc",
+                    });
+                for link_line in
+                    fake_linker::recorded_link_lines(&record, linker, fake_linker_flavor)?
+                {
+                    stdout.push_str(&link_line);
+                    stdout.push('\n');
+                }
+            }
+            let stdout = stdout.as_str();
+            let mut success = false;
+            if !output.status.success() {
+                eprintln!("cargo failed with {}", output.status);
+                if NETWORK_DENIED_MESSAGES.iter().any(|m| text.contains(m)) {
+                    eprintln!("{}", color::diagnostic("error", "cargo needs network access or a Cargo.lock update, which --offline, --locked or --frozen forbid. Vendor the dependencies or update Cargo.lock on a machine with network access", color));
+                }
+            } else if gen_response_file {
+                // println!("stdout {}", stdout);
+                let linkers: Vec<&str> = linkers
+                    .iter()
+                    .copied()
+                    .chain(allowed_linkers.iter().map(|linker| linker.name.as_str()))
+                    .collect();
+                let all_link_lines = find_link_lines(stdout, &linkers);
+                let link_lines = if matches.is_present("MERGE_LINK_LINES") {
+                    // in the same order however cargo scheduled the builds
+                    let mut link_lines = all_link_lines.clone();
+                    link_lines.sort_by_key(|link_line| link_args::link_line_artifact(link_line));
+                    link_lines
+                } else {
+                    select_link_line(&all_link_lines, matches.value_of("ARTIFACT"))
+                        .into_iter()
+                        .collect()
+                };
+                if let Some(dump_path) = matches.value_of("DUMP_LINK_LINE") {
+                    let dumped = if matches.is_present("DUMP_ALL_LINK_LINES") {
+                        &all_link_lines
+                    } else {
+                        &link_lines
+                    };
+                    let contents: String =
+                        dumped.iter().map(|line| format!("{}\n", line)).collect();
+                    std::fs::write(output_path(dump_path), contents)?;
+                }
+                // one flavor per link line, which it's parsed, checked and routed as: the given
+                // one, its linker's, or for linkers that aren't known the one its options suggest
+                let mut unknown_linker = None;
+                let flavors: Vec<LinkerFlavor> = link_lines
+                    .iter()
+                    .map(|link_line| {
+                        if let Some(flavor) = linker_flavor {
+                            return flavor;
+                        }
+                        link_args::known_linker_flavor(link_line, &allowed_linkers).unwrap_or_else(
+                            || {
+                                let guess = LinkerFlavor::guess(link_line);
+                                let linker = link_args::linker_token(link_line).unwrap_or_default();
+                                unknown_linker.get_or_insert((linker, guess));
+                                guess
+                            },
+                        )
+                    })
+                    .collect();
+                let ue_crt = matches.value_of("UE_CRT").and_then(crt::Crt::from_arg);
+                let crt_mismatch = ue_crt.and_then(|ue_crt| {
+                    link_lines
+                        .iter()
+                        .zip(&flavors)
+                        .filter(|(_, flavor)| **flavor == LinkerFlavor::Msvc)
+                        .find_map(|(link_line, _)| crt::check_crt(link_line, ue_crt).err())
+                });
+                let unknown_linker = unknown_linker.map(|(linker, guess)| {
+                    format!("Unrecognized linker {}. Accept it with --allowed-linkers {}={} (or the crate's package.metadata.unreal_rust_compile.allowed-linkers), or pass --permissive to parse its link line as {} anyway", linker, link_args::file_name(&linker), guess.name(), guess.name())
+                });
+                if let (Some(message), true) = (&unknown_linker, permissive) {
+                    eprintln!("{}", color::diagnostic("warning", message, color));
+                }
+                if let Some(message) = unknown_linker.filter(|_| !permissive) {
+                    eprintln!("{}", color::diagnostic("error", message, color));
+                } else if let Some(message) = crt_mismatch {
+                    eprintln!("{}", color::diagnostic("error", message, color));
+                } else if !link_lines.is_empty() {
+                    success = true;
+                    let mut linker_output = LinkerOutput::default();
+                    for (link_line, flavor) in link_lines.iter().zip(&flavors) {
+                        linker_output.merge(parse_link_line_as(link_line, &def_file_path, *flavor));
+                    }
+                    linker_output.deduplicate(flavors[0]);
+                    for name in &keep_linker_args {
+                        let mut used = false;
+                        for (link_line, flavor) in link_lines.iter().zip(&flavors) {
+                            let flavor = *flavor;
+                            for option in link_args::find_options(link_line, flavor, name) {
+                                used = true;
+                                if !linker_output.linker_lines.contains(&option) {
+                                    linker_output.linker_lines.push(option);
                                 }
-                            } else {
-                                panic!("No linker args found!");
                             }
-                            let mut idx = 0;
-                            while idx < args.len() {
-                                let arg = &args[idx];
-                                idx += 1;
-                                if arg.starts_with("/") || arg.starts_with("-") {
-                                    let arg_end_idx = arg.find(":");
-                                    let option_name = &arg[1..arg_end_idx.unwrap_or(arg.len())];
-                                    let option_arg = if let Some(arg_end_idx) = arg_end_idx {
-                                        &arg[(1 + arg_end_idx)..]
-                                    } else {
-                                        ""
-                                    };
-                                    match option_name {
-                                        "LIBPATH" | "IMPLIB" => {
-                                            writeln!(
-                                                &mut output_linker_file,
-                                                "/{}:\"{}\"",
-                                                option_name, option_arg
-                                            )?;
-                                        }
-                                        "flavor" => {
-                                            // consume argument
-                                            idx += 1;
-                                        }
-                                        "DEF" => {
-                                            let def_file_path = output_lib_link_file
-                                                .with_file_name("build_def.def");
-                                            if let Ok(_metadata) = std::fs::metadata(option_arg) {
-                                                std::fs::copy(option_arg, &def_file_path)
-                                                    .expect("Failed to copy def file");
-                                            }
-                                            // include DEF file for both linker and lib
-                                            writeln!(
-                                                &mut output_linker_file,
-                                                "/DEF:\"{}\"",
-                                                def_file_path.to_string_lossy()
-                                            )?;
-                                            writeln!(
-                                                &mut output_lib_file,
-                                                "/DEF:\"{}\"",
-                                                def_file_path.to_string_lossy()
-                                            )?;
-                                        }
-                                        _ => {}
-                                    }
-                                } else if !arg.ends_with(".exe") {
-                                    if arg.ends_with(".o") || arg.ends_with(".rlib") {
-                                        // include only object/rlib files in lib file
-                                        writeln!(&mut output_lib_file, "\"{}\"", arg)?;
-                                    } else {
-                                        writeln!(&mut output_linker_file, "\"{}\"", arg)?;
-                                    }
+                        }
+                        if !used {
+                            unused_keep_args.push(name);
+                        }
+                    }
+                    if matches.is_present("NO_NATVIS") {
+                        linker_output.remove_linker_option("NATVIS");
+                    }
+                    if let Some(subsystem) = matches.value_of("SUBSYSTEM") {
+                        linker_output.set_linker_option("SUBSYSTEM", subsystem);
+                    }
+                    if let Some(entry) = matches.value_of("ENTRY") {
+                        linker_output.set_linker_option("ENTRY", entry);
+                    }
+                    if let Some(map_file) = &map_file {
+                        linker_output.set_map_file(flavors[0], map_file);
+                    }
+                    if flavors[0] == LinkerFlavor::Msvc {
+                        if let Some(pdb) = &pdb {
+                            let pdb = format!("\"{}\"", pdb.display());
+                            linker_output.set_linker_option("PDB", &pdb);
+                        }
+                        if matches.is_present("PDB_ALT_PATH") {
+                            let alt_path = matches
+                                .value_of("PDB_ALT_PATH")
+                                .unwrap_or(DEFAULT_PDB_ALT_PATH);
+                            linker_output.set_linker_option("PDBALTPATH", alt_path);
+                        }
+                    } else if pdb.is_some() || matches.is_present("PDB_ALT_PATH") {
+                        let message = "--pdb and --pdb-alt-path only apply to MSVC link lines";
+                        eprintln!("{}", color::diagnostic("warning", message, color));
+                    }
+                    let anomalies: Vec<_> = link_lines
+                        .iter()
+                        .zip(&flavors)
+                        .flat_map(|(link_line, flavor)| {
+                            link_args::check_routing_as(link_line, &linker_output, *flavor)
+                        })
+                        .collect();
+                    for anomaly in &anomalies {
+                        let label = if strict_routing { "error" } else { "warning" };
+                        eprintln!("{}", color::diagnostic(label, anomaly, color));
+                    }
+                    if strict_routing && !anomalies.is_empty() {
+                        return Ok(ExitCode::CompileFailure);
+                    }
+                    // rustc may print inputs relative to cargo's working directory
+                    linker_output.resolve_relative_inputs(&cargo_dir);
+                    platform::apply_arg_rules(&platform.arg_rules, &mut linker_output.linker_lines);
+                    let mut system_libs: Vec<String> = matches
+                        .values_of("EXCLUDE_SYSTEM_LIB")
+                        .into_iter()
+                        .flatten()
+                        .map(str::to_owned)
+                        .collect();
+                    if !matches.is_present("KEEP_UE_SYSTEM_LIBS") {
+                        system_libs
+                            .extend(link_args::UE_SYSTEM_LIBS.iter().map(|lib| lib.to_string()));
+                    }
+                    linker_output.remove_system_libs(&system_libs);
+                    if let Some(cxx_options) = &cxx_options {
+                        match cxx_objects(cxx_options, &crate_dir, flavors[0], ue_crt) {
+                            Ok(objects) => linker_output.lib_lines.extend(objects),
+                            Err(err) => {
+                                eprintln!(
+                                    "{}",
+                                    color::diagnostic("error", format!("{:#}", err), color)
+                                );
+                                return Ok(ExitCode::CompileFailure);
+                            }
+                        }
+                    }
+                    if matches.is_present("EXPAND_THIN_ARCHIVES") {
+                        linker_output.expand_thin_archives()?;
+                    } else {
+                        for archive in linker_output.thin_archives() {
+                            let message = format!("{} is a thin archive, whose members LIB.EXE won't find. Pass --expand-thin-archives to list them in the lib file instead", archive);
+                            eprintln!("{}", color::diagnostic("warning", message, color));
+                        }
+                    }
+                    if let Some(dir) = matches.value_of("COPY_OBJECTS_TO") {
+                        // the lib step doesn't run in our working directory
+                        let dir = std::path::absolute(resolve_path(matches, dir))?;
+                        linker_output.copy_objects_to(&dir)?;
+                    }
+                    if matches.is_present("PORTABLE_PATHS") {
+                        let sysroot = match matches.value_of("SYSROOT") {
+                            Some(sysroot) => sysroot.to_owned(),
+                            None => rustc_sysroot(&cargo_dir)?,
+                        };
+                        linker_output.replace_prefix(&sysroot, link_args::SYSROOT_PLACEHOLDER);
+                    }
+                    let link_info = json_file.as_ref().map(|_| {
+                        link_info::LinkInfo::new(&linker_output, flavors[0], &def_file_path)
+                    });
+                    linker_output.requote(arg_quoting);
+                    if let Some(def_file) = &linker_output.def_file {
+                        if std::fs::metadata(def_file).is_ok() {
+                            let mut contents = std::fs::read_to_string(def_file)?;
+                            if !export_filter.is_empty() {
+                                contents = export_filter.apply(&contents);
+                            }
+                            if let Some(prefix) = matches.value_of("SYMBOL_PREFIX") {
+                                let symbols = def_file::exported_symbols(&contents);
+                                if let Some(message) = unprefixed_symbols(&symbols, prefix) {
+                                    eprintln!("{}", color::diagnostic("error", message, color));
+                                    return Ok(ExitCode::CompileFailure);
                                 }
                             }
-                        } else {
-                            println!("NO LINKER ARGS");
+                            // the def file is an input of UE's link too
+                            write_output_file(&def_file_path, &contents, write_unchanged)?;
+                        }
+                    }
+                    let outputs = [
+                        (&output_linker_file, linker_output.linker_file_contents()),
+                        (&output_lib_link_file, linker_output.lib_file_contents()),
+                    ];
+                    for (path, contents) in outputs {
+                        let contents = link_args::output_file_bytes(&contents, rsp_encoding);
+                        write_output_file(path, contents, write_unchanged)?;
+                    }
+                    if let (Some(path), Some(link_info)) = (&json_file, &link_info) {
+                        let contents = serde_json::to_string_pretty(link_info)? + "\n";
+                        write_output_file(path, contents, write_unchanged)?;
+                    }
+                    if let Some(map_file) = &map_file {
+                        println!("map file: {}", map_file.display());
+                    }
+                    if matches.is_present("DSYM") {
+                        let dsym_dir = resolved_output_path(&output_lib_link_file)
+                            .parent()
+                            .map(Path::to_owned)
+                            .unwrap_or_default();
+                        for link_line in &link_lines {
+                            let Some(binary) = link_args::link_line_output(link_line) else {
+                                continue;
+                            };
+                            let binary = Path::new(&binary);
+                            let mut dsym_name = binary.file_name().unwrap_or_default().to_owned();
+                            dsym_name.push(".dSYM");
+                            if let Err(err) = run_dsymutil(binary, &dsym_dir.join(dsym_name)) {
+                                eprintln!("{}", color::diagnostic("error", err, color));
+                                success = false;
+                            }
                         }
                     }
+                    if let Some(key) = &cache_key {
+                        if let Err(err) = cache::store(key, &output_linker_file, &linker_output) {
+                            eprintln!("Failed to write link args cache: {}", err);
+                        }
+                    }
+                } else if let Some((staticlib, crate_name)) = synthesize_def_file
+                    .then(|| exports::staticlib_artifact(stdout))
+                    .flatten()
+                {
+                    let nm = matches.value_of("NM").unwrap_or(exports::DEFAULT_NM);
+                    let exports = exports::staticlib_exports(nm, &staticlib, &crate_name)?;
+                    if let Some(prefix) = matches.value_of("SYMBOL_PREFIX") {
+                        let symbols: Vec<String> = exports
+                            .iter()
+                            .filter(|export| export_filter.keeps(&export.name))
+                            .map(|export| export.name.clone())
+                            .collect();
+                        if let Some(message) = unprefixed_symbols(&symbols, prefix) {
+                            eprintln!("{}", color::diagnostic("error", message, color));
+                            return Ok(ExitCode::CompileFailure);
+                        }
+                    }
+                    let def_file = def_file_path.to_string_lossy();
+                    let mut linker_output = LinkerOutput::default();
+                    let contents = match fake_linker_flavor {
+                        LinkerFlavor::Msvc => {
+                            let def_line = format!("/DEF:\"{}\"", def_file);
+                            linker_output.linker_lines.push(def_line.clone());
+                            linker_output.lib_lines.push(def_line);
+                            exports::def_file(&exports, &export_filter)
+                        }
+                        LinkerFlavor::Gnu => {
+                            let def_line = format!("-Wl,--version-script=\"{}\"", def_file);
+                            linker_output.linker_lines.push(def_line);
+                            exports::version_script(&exports, &export_filter)
+                        }
+                    };
+                    // LIB.EXE and ar take the staticlib's members
+                    linker_output
+                        .lib_lines
+                        .push(format!("\"{}\"", staticlib.display()));
+                    if let Some(cxx_options) = &cxx_options {
+                        let ue_crt = matches.value_of("UE_CRT").and_then(crt::Crt::from_arg);
+                        match cxx_objects(cxx_options, &crate_dir, fake_linker_flavor, ue_crt) {
+                            Ok(objects) => linker_output.lib_lines.extend(objects),
+                            Err(err) => {
+                                eprintln!(
+                                    "{}",
+                                    color::diagnostic("error", format!("{:#}", err), color)
+                                );
+                                return Ok(ExitCode::CompileFailure);
+                            }
+                        }
+                    }
+                    linker_output.requote(arg_quoting);
+                    write_output_file(&def_file_path, &contents, write_unchanged)?;
+                    let outputs = [
+                        (&output_linker_file, linker_output.linker_file_contents()),
+                        (&output_lib_link_file, linker_output.lib_file_contents()),
+                    ];
+                    for (path, contents) in outputs {
+                        let contents = link_args::output_file_bytes(&contents, rsp_encoding);
+                        write_output_file(path, contents, write_unchanged)?;
+                    }
+                    eprintln!(
+                        "info: rustc printed no link line, synthesized {} from the {} exports of {}",
+                        def_file_path.display(),
+                        exports.len(),
+                        staticlib.display()
+                    );
+                    success = true;
+                } else if let Some(artifact) = matches.value_of("ARTIFACT") {
+                    println!(
+                        "NO LINKER ARGS: rustc printed no link line producing {}",
+                        artifact
+                    );
+                } else if fail_if_no_link_args {
+                    println!("NO LINKER ARGS: cargo succeeded, but rustc printed no link line referencing a .def file. Check that the crate builds a cdylib, or pass --fail-if-no-link-args false if no link step is expected");
                 } else {
+                    println!("NO LINKER ARGS: cargo succeeded without a link step");
                     success = true;
                 }
-                success
+            } else {
+                success = true;
             }
-            Err(err) => {
-                eprintln!("Compile error: {}", err);
-                false
+            success
+        }
+        Err(err) => {
+            eprintln!("Compile error: {}", err);
+            return Ok(ExitCode::InternalError);
+        }
+    };
+    if matches.is_present("WARN_UNUSED_KEEP_ARGS") {
+        for name in unused_keep_args {
+            let message = format!("Kept linker arg {} didn't match any linker option", name);
+            eprintln!("{}", color::diagnostic("warning", message, color));
+        }
+    }
+    if !command_success {
+        return Ok(ExitCode::CompileFailure);
+    }
+    Ok(ExitCode::Success)
+}
+
+/// The error about the exported symbols that don't start with `prefix`, if any
+fn unprefixed_symbols(symbols: &[String], prefix: &str) -> Option<String> {
+    let unprefixed: Vec<&str> = symbols
+        .iter()
+        .map(String::as_str)
+        .filter(|symbol| !symbol.starts_with(prefix))
+        .collect();
+    if unprefixed.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{} exported symbols don't start with {} and may collide with other plugins': {}",
+        unprefixed.len(),
+        prefix,
+        unprefixed.join(", ")
+    ))
+}
+
+/// Generate and compile the C++ side of the crate's cxx bridges, and return the lib file lines of
+/// the objects
+fn cxx_objects(
+    options: &cxx_bridge::CxxOptions,
+    crate_dir: &Path,
+    flavor: LinkerFlavor,
+    crt: Option<crt::Crt>,
+) -> Result<Vec<String>> {
+    let sources = cxx_bridge::generate(options, crate_dir)?;
+    let objects = cxx_bridge::compile(options, flavor, crt, &sources)?;
+    Ok(objects
+        .iter()
+        .map(|object| format!("\"{}\"", object.display()))
+        .collect())
+}
+
+fn ios(matches: &ArgMatches) -> Result<ExitCode> {
+    let output_linker_file = resolve_path(
+        matches,
+        matches
+            .value_of("OUTPUT_LINKER_FILE")
+            .expect("output_linker_file not provided"),
+    );
+    let output_lib_link_file = resolve_path(
+        matches,
+        matches
+            .value_of("OUTPUT_LIB_LINK_FILE")
+            .expect("output_lib_link_file not provided"),
+    );
+    let cargo_args: Vec<&str> = matches
+        .values_of("CARGO_ARGS")
+        .into_iter()
+        .flatten()
+        .collect();
+    let cargo_args = resolve_manifest_path_arg(matches, &cargo_args);
+    let cargo_args: Vec<&str> = cargo_args.iter().map(String::as_str).collect();
+    let cargo_dir = resolve_crate_dir(matches, matches.value_of("CRATE_DIR").unwrap_or("."))?;
+    let write_unchanged = matches.is_present("WRITE_UNCHANGED");
+
+    let mut targets: Vec<&str> = match matches.values_of("IOS_TARGET") {
+        Some(targets) => targets.collect(),
+        None => vec![ios::DEVICE_TARGET],
+    };
+    if matches.is_present("SIMULATOR") {
+        targets.extend(ios::SIMULATOR_TARGETS);
+    }
+    targets.dedup();
+    let toolchain_targets: Vec<String> = targets.iter().map(|target| target.to_string()).collect();
+    if let Some(exit_code) = check_pinned_toolchain(matches, &cargo_dir, &toolchain_targets)? {
+        return Ok(exit_code);
+    }
+    let lipo_output = matches
+        .value_of("LIPO")
+        .map(|path| resolve_path(matches, path));
+    if lipo_output.is_some() {
+        for (i, target) in targets.iter().enumerate() {
+            let arch = ios::architecture(target);
+            if let Some(other) = targets[..i].iter().find(|t| ios::architecture(t) == arch) {
+                eprintln!("--lipo can't merge {} and {}, which are both {}. Build the device and simulator libs separately", other, target, arch);
+                return Ok(ExitCode::UsageError);
             }
-        };
-        if !command_success {
-            std::process::exit(1);
         }
     }
-    Ok(())
+
+    let cfg_args: Vec<String> = bindings::cfgs_from_matches(matches)
+        .iter()
+        .map(|cfg| cfg.rustc_arg())
+        .collect();
+    let network_args: Vec<&str> = [
+        ("OFFLINE", "--offline"),
+        ("LOCKED", "--locked"),
+        ("FROZEN", "--frozen"),
+    ]
+    .iter()
+    .filter(|(name, flag)| matches.is_present(name) && !cargo_flag_given(&cargo_args, flag))
+    .map(|(_, flag)| *flag)
+    .collect();
+
+    let mut archives = Vec::new();
+    let mut native_static_libs = Vec::new();
+    for target in &targets {
+        let mut rustc_args = vec!["--print", "native-static-libs"];
+        for cfg_arg in &cfg_args {
+            rustc_args.extend(["--cfg", cfg_arg.as_str()]);
+        }
+        // the rustc args have to follow a `--`
+        if !cargo_args.contains(&"--") {
+            rustc_args.insert(0, "--");
+        }
+        let output = Command::new("cargo")
+            .current_dir(&cargo_dir)
+            .args(["rustc", "--message-format", "json-render-diagnostics"])
+            .args(["--target", target, "--crate-type", "staticlib"])
+            .args(&network_args)
+            .args(&cargo_args)
+            .args(rustc_args)
+            .output()
+            .context("Couldn't run cargo")?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        println!("{}", stderr);
+        if !output.status.success() {
+            eprintln!("cargo failed for {} with {}", target, output.status);
+            return Ok(ExitCode::CompileFailure);
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match ios::staticlib_artifacts(&stdout).pop() {
+            Some(archive) => archives.push(archive),
+            None => {
+                eprintln!("cargo built no staticlib for {}", target);
+                return Ok(ExitCode::CompileFailure);
+            }
+        }
+        native_static_libs.extend(ios::native_static_libs(&stderr));
+    }
+
+    if let Some(lipo_output) = &lipo_output {
+        if let Err(err) = ios::run_lipo(&archives, lipo_output) {
+            eprintln!("{}", err);
+            return Ok(ExitCode::CompileFailure);
+        }
+        archives = vec![lipo_output.clone()];
+    }
+    let linker_output = LinkerOutput {
+        linker_lines: ios::linker_lines(&native_static_libs),
+        lib_lines: archives
+            .iter()
+            .map(|archive| format!("\"{}\"", archive.to_string_lossy()))
+            .collect(),
+        ..LinkerOutput::default()
+    };
+    write_output_file(
+        &output_linker_file,
+        linker_output.linker_file_contents(),
+        write_unchanged,
+    )?;
+    write_output_file(
+        &output_lib_link_file,
+        linker_output.lib_file_contents(),
+        write_unchanged,
+    )?;
+    Ok(ExitCode::Success)
 }
@@ -0,0 +1,119 @@
+//! The output files of the rustc subcommand: their paths, which are templates of the target, the
+//! cargo profile and the UE config, the def file written next to them, and writing them
+
+use std::path::{Path, PathBuf};
+
+/// The placeholder for the target in the output paths of `rustc`: the one `--targets` builds
+/// for, the `--target` of the cargo args or the host
+pub const TARGET_PLACEHOLDER: &str = "{target}";
+
+/// The placeholder for the directory of the cargo profile in the output paths of `rustc`
+pub const PROFILE_PLACEHOLDER: &str = "{profile}";
+
+/// The placeholder for `--ue-config` in the output paths of `rustc`
+pub const CONFIG_PLACEHOLDER: &str = "{config}";
+
+/// `path` with the placeholders replaced
+pub fn fill(path: &str, target: &str, profile: &str, config: &str) -> String {
+    path.replace(TARGET_PLACEHOLDER, target)
+        .replace(PROFILE_PLACEHOLDER, profile)
+        .replace(CONFIG_PLACEHOLDER, config)
+}
+
+/// The def file written next to the lib file. Each of `--targets` gets its own, as they share
+/// the lib file's directory unless it's templated on the target too
+pub fn def_file_path(output_lib_link_file: &Path, target: Option<&str>) -> PathBuf {
+    match target {
+        Some(target) => output_lib_link_file.with_file_name(format!("build_def.{}.def", target)),
+        None => output_lib_link_file.with_file_name("build_def.def"),
+    }
+}
+
+/// Write one of the linker, lib and def files. Unless `write_unchanged`, a file that already has
+/// the contents is left alone, because rewriting it would still make the build system relink.
+pub fn write_output_file(
+    path: &Path,
+    contents: impl AsRef<[u8]>,
+    write_unchanged: bool,
+) -> std::io::Result<()> {
+    let contents = contents.as_ref();
+    if !write_unchanged && std::fs::read(path).ok().as_deref() == Some(contents) {
+        println!("linker args unchanged: {}", path.display());
+        Ok(())
+    } else {
+        // templated output paths may point to directories that don't exist yet
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templated_paths() {
+        let template = "Intermediate/{target}/{config}/link_{profile}.rsp";
+        assert_eq!(
+            fill(template, "x86_64-pc-windows-msvc", "release", "Shipping"),
+            "Intermediate/x86_64-pc-windows-msvc/Shipping/link_release.rsp"
+        );
+        assert_eq!(
+            fill(
+                "{target}/{target}.rsp",
+                "aarch64-linux-android",
+                "debug",
+                ""
+            ),
+            "aarch64-linux-android/aarch64-linux-android.rsp"
+        );
+        assert_eq!(
+            fill("link.rsp", "aarch64-linux-android", "debug", ""),
+            "link.rsp"
+        );
+
+        let lib_file = Path::new("Intermediate/x86_64-pc-windows-msvc/lib.rsp");
+        assert_eq!(
+            def_file_path(lib_file, None),
+            Path::new("Intermediate/x86_64-pc-windows-msvc/build_def.def")
+        );
+        assert_eq!(
+            def_file_path(lib_file, Some("x86_64-pc-windows-msvc")),
+            Path::new("Intermediate/x86_64-pc-windows-msvc/build_def.x86_64-pc-windows-msvc.def")
+        );
+    }
+
+    #[test]
+    fn unchanged_files_untouched() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("x86_64-pc-windows-msvc").join("link.rsp");
+        write_output_file(&path, "/DEBUG\n", false).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"/DEBUG\n");
+
+        let modified = |path: &Path| std::fs::metadata(path).unwrap().modified().unwrap();
+        let backdate = |path: &Path| {
+            let earlier = modified(path) - std::time::Duration::from_secs(60);
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(earlier)
+                .unwrap();
+            earlier
+        };
+        let earlier = backdate(&path);
+        write_output_file(&path, "/DEBUG\n", false).unwrap();
+        assert_eq!(modified(&path), earlier);
+
+        write_output_file(&path, "/DEBUG\n", true).unwrap();
+        assert!(modified(&path) > earlier);
+
+        let earlier = backdate(&path);
+        write_output_file(&path, "/DEBUG:FULL\n", false).unwrap();
+        assert!(modified(&path) > earlier);
+        assert_eq!(std::fs::read(&path).unwrap(), b"/DEBUG:FULL\n");
+    }
+}
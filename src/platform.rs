@@ -0,0 +1,174 @@
+//! Platform config files, which describe targets the tool doesn't know about (e.g. consoles
+//! under NDA) without changing it: a custom target spec, the platform's linkers and rules
+//! translating the args of its link lines for the linker file
+
+use crate::link_args::LinkerFlavor;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// A rewrite of the linker file lines matching a glob pattern
+#[derive(Debug, Clone)]
+pub struct ArgRule {
+    pub pattern: glob::Pattern,
+    /// The lines replacing a matching one, where `{}` stands for the matched line. No lines
+    /// drop it.
+    pub replace: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct PlatformConfig {
+    /// Target spec JSON passed to cargo as `--target`
+    pub target_spec: Option<PathBuf>,
+    /// Linkers whose link lines are recognized besides the known ones
+    pub linkers: Vec<String>,
+    pub linker_format: Option<LinkerFlavor>,
+    pub arg_rules: Vec<ArgRule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArgRuleFile {
+    pattern: String,
+    replace: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlatformConfigFile {
+    target_spec: Option<PathBuf>,
+    #[serde(default)]
+    linkers: Vec<String>,
+    linker_format: Option<String>,
+    #[serde(default)]
+    arg_rules: Vec<ArgRuleFile>,
+}
+
+fn parse_linker_format(format: &str) -> Result<LinkerFlavor> {
+    match format {
+        "msvc" => Ok(LinkerFlavor::Msvc),
+        "gnu" => Ok(LinkerFlavor::Gnu),
+        _ => bail!("Unknown linker_format \"{}\", expected msvc or gnu", format),
+    }
+}
+
+/// Read a `.toml` platform config. Relative paths are resolved against the directory of the
+/// file.
+pub fn read_platform_config(path: &Path) -> Result<PlatformConfig> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read platform config {}", path.display()))?;
+    let file: PlatformConfigFile = toml::from_str(&contents)
+        .with_context(|| format!("Invalid platform config {}", path.display()))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut arg_rules = Vec::with_capacity(file.arg_rules.len());
+    for rule in file.arg_rules {
+        arg_rules.push(ArgRule {
+            pattern: glob::Pattern::new(&rule.pattern)?,
+            replace: rule.replace,
+        });
+    }
+    Ok(PlatformConfig {
+        target_spec: file.target_spec.map(|spec| base_dir.join(spec)),
+        linkers: file.linkers,
+        linker_format: file
+            .linker_format
+            .as_deref()
+            .map(parse_linker_format)
+            .transpose()?,
+        arg_rules,
+    })
+}
+
+/// The fields of a custom target spec that affect how its link lines are parsed
+#[derive(Debug, Default, Deserialize)]
+pub struct TargetSpec {
+    pub linker: Option<String>,
+    #[serde(rename = "linker-flavor")]
+    pub linker_flavor: Option<String>,
+}
+
+impl TargetSpec {
+    /// The format of the link lines of the spec's linker flavor, if it has one
+    pub fn linker_format(&self) -> Option<LinkerFlavor> {
+        let flavor = self.linker_flavor.as_deref()?;
+        Some(match flavor {
+            "msvc" | "msvc-lld" | "lld-link" => LinkerFlavor::Msvc,
+            _ => LinkerFlavor::Gnu,
+        })
+    }
+}
+
+pub fn read_target_spec(path: &Path) -> Result<TargetSpec> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read target spec {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid target spec {}", path.display()))
+}
+
+/// Rewrite the lines matching a rule with its replacement. Only the first matching rule applies.
+pub fn apply_arg_rules(rules: &[ArgRule], lines: &mut Vec<String>) {
+    if rules.is_empty() {
+        return;
+    }
+    let mut translated = Vec::with_capacity(lines.len());
+    for line in lines.drain(..) {
+        match rules.iter().find(|rule| rule.pattern.matches(&line)) {
+            Some(rule) => {
+                translated.extend(rule.replace.iter().map(|r| r.replace("{}", &line)));
+            }
+            None => translated.push(line),
+        }
+    }
+    *lines = translated;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn platform_config_arg_rules() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let config_path = dir.join("console.toml");
+        std::fs::write(
+            &config_path,
+            r#"
+target_spec = "console.json"
+linkers = ["console-ld.exe"]
+linker_format = "gnu"
+
+[[arg_rules]]
+pattern = "-Wl,--console-private*"
+replace = []
+
+[[arg_rules]]
+pattern = "-lc"
+replace = ["-Wl,--start-group", "{}", "-Wl,--end-group"]
+"#,
+        )
+        .unwrap();
+        let config = read_platform_config(&config_path).unwrap();
+        assert_eq!(config.target_spec, Some(dir.join("console.json")));
+        assert_eq!(config.linker_format, Some(LinkerFlavor::Gnu));
+
+        let mut lines = vec![
+            "-L\"/sdk/lib\"".to_owned(),
+            "-Wl,--console-private-opt".to_owned(),
+            "-lc".to_owned(),
+        ];
+        apply_arg_rules(&config.arg_rules, &mut lines);
+        assert_eq!(
+            lines,
+            vec![
+                "-L\"/sdk/lib\"",
+                "-Wl,--start-group",
+                "-lc",
+                "-Wl,--end-group"
+            ]
+        );
+
+        std::fs::write(&config_path, "linker_format = \"ld\"").unwrap();
+        assert!(read_platform_config(&config_path).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Rust FFI declarations of the C functions UE headers declare, generated by running bindgen
+
+use anyhow::{bail, Context, Result};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What to generate declarations for
+pub struct RustBindingsOptions {
+    /// The headers, absolute
+    pub headers: Vec<PathBuf>,
+    pub include_dirs: Vec<PathBuf>,
+    /// `NAME` or `NAME=VALUE` preprocessor definitions
+    pub defines: Vec<String>,
+    /// Regexes of the functions, variables and types to declare. Everything the headers
+    /// themselves declare if empty
+    pub allowlist: Vec<String>,
+    /// Further args for clang, after the defaults of `-x c++ -std=c++17`
+    pub clang_args: Vec<String>,
+}
+
+/// Read a file of include directories, one per line, as a Build.cs can write its include paths.
+/// Relative directories are relative to the file.
+pub fn read_include_dirs(path: &Path) -> Result<Vec<PathBuf>> {
+    let contents = std::fs::read_to_string(path).with_context(|| {
+        format!(
+            "Couldn't read the include directories of {}",
+            path.display()
+        )
+    })?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| base_dir.join(line))
+        .collect())
+}
+
+/// The header bindgen is run on, including each of `headers`
+pub fn wrapper_header(headers: &[PathBuf]) -> String {
+    headers
+        .iter()
+        .map(|header| format!("#include \"{}\"\n", header.display()))
+        .collect()
+}
+
+/// `text` with regex metacharacters escaped
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The bindgen args to generate the declarations of `wrapper`, a [`wrapper_header`]
+pub fn bindgen_args(wrapper: &Path, options: &RustBindingsOptions) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![wrapper.into()];
+    if options.allowlist.is_empty() {
+        for header in &options.headers {
+            args.push("--allowlist-file".into());
+            args.push(regex_escape(&header.to_string_lossy()).into());
+        }
+    }
+    for pattern in &options.allowlist {
+        for kind in &[
+            "--allowlist-function",
+            "--allowlist-var",
+            "--allowlist-type",
+        ] {
+            args.push(kind.into());
+            args.push(pattern.into());
+        }
+    }
+    args.extend(["--", "-x", "c++", "-std=c++17"].iter().map(OsString::from));
+    for dir in &options.include_dirs {
+        let mut arg = OsString::from("-I");
+        arg.push(dir);
+        args.push(arg);
+    }
+    args.extend(
+        options
+            .defines
+            .iter()
+            .map(|define| format!("-D{}", define).into()),
+    );
+    args.extend(options.clang_args.iter().map(OsString::from));
+    args
+}
+
+/// Run bindgen on the headers and return the Rust module it generated
+pub fn generate(options: &RustBindingsOptions) -> Result<Vec<u8>> {
+    let wrapper_path = std::env::temp_dir().join(format!(
+        "unreal_rust_compile_{}_{}.h",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    std::fs::write(&wrapper_path, wrapper_header(&options.headers))?;
+    let output = Command::new("bindgen")
+        .args(bindgen_args(&wrapper_path, options))
+        .output();
+    let _ = std::fs::remove_file(&wrapper_path);
+    let output = output.context("Couldn't run bindgen. `cargo install bindgen-cli` installs it")?;
+    if !output.status.success() {
+        bail!(
+            "bindgen failed with {}:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindgen_invocation() {
+        let options = RustBindingsOptions {
+            headers: vec![PathBuf::from("/ue/Public/Game+Api.h")],
+            include_dirs: vec![PathBuf::from("/ue/Public")],
+            defines: vec!["GAME_API=".to_owned()],
+            allowlist: Vec::new(),
+            clang_args: vec!["-DWITH_EDITOR=0".to_owned()],
+        };
+        assert_eq!(
+            wrapper_header(&options.headers),
+            "#include \"/ue/Public/Game+Api.h\"\n"
+        );
+        let args = bindgen_args(Path::new("/tmp/wrapper.h"), &options);
+        let args: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+        assert_eq!(
+            args,
+            [
+                "/tmp/wrapper.h",
+                "--allowlist-file",
+                "/ue/Public/Game\\+Api\\.h",
+                "--",
+                "-x",
+                "c++",
+                "-std=c++17",
+                "-I/ue/Public",
+                "-DGAME_API=",
+                "-DWITH_EDITOR=0",
+            ]
+        );
+
+        let options = RustBindingsOptions {
+            allowlist: vec!["game_.*".to_owned()],
+            ..options
+        };
+        let args = bindgen_args(Path::new("/tmp/wrapper.h"), &options);
+        assert_eq!(args[1], "--allowlist-function");
+        assert_eq!(args[2], "game_.*");
+        assert_eq!(args[7], "--");
+    }
+
+    #[test]
+    fn include_dirs_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("includes.txt");
+        std::fs::write(&path, "Public\n\n/ue/Engine/Source/Runtime/Core/Public\n").unwrap();
+        let dirs = read_include_dirs(&path).unwrap();
+        assert_eq!(
+            dirs,
+            [
+                dir.join("Public"),
+                PathBuf::from("/ue/Engine/Source/Runtime/Core/Public")
+            ]
+        );
+    }
+}
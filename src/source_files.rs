@@ -0,0 +1,559 @@
+use anyhow::{bail, Context, Result};
+use cargo::core::manifest::TargetSourcePath;
+use cargo::core::{Package, TargetKind, Workspace};
+use cargo::GlobalContext;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Name of the gitignore style file in a crate that excludes files from source-files
+pub const IGNORE_FILE_NAME: &str = ".urc-ignore";
+
+/// Controls which files `visit_dirs` returns
+#[derive(Debug, Default, Clone)]
+pub struct WalkOptions {
+    /// Directories not to descend into. Patterns without a path separator are matched against
+    /// directory names, the others against the path relative to the walked directory.
+    pub exclude_dirs: Vec<glob::Pattern>,
+    /// How many levels of subdirectories to descend into. 0 only lists the walked directory.
+    pub max_depth: Option<usize>,
+    /// Files and directories excluded by a `.urc-ignore` file
+    pub ignore: Option<ignore::gitignore::Gitignore>,
+}
+
+impl WalkOptions {
+    /// These options plus the `.urc-ignore` file in `dir`, if there is one
+    pub fn with_ignore_file(&self, dir: &Path) -> Result<WalkOptions> {
+        let ignore_file = dir.join(IGNORE_FILE_NAME);
+        let mut options = self.clone();
+        if ignore_file.is_file() {
+            let mut builder = ignore::gitignore::GitignoreBuilder::new(dir);
+            if let Some(err) = builder.add(&ignore_file) {
+                return Err(err.into());
+            }
+            options.ignore = Some(builder.build()?);
+        }
+        Ok(options)
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.ignore
+            .as_ref()
+            .is_some_and(|ignore| ignore.matched(path, is_dir).is_ignore())
+    }
+
+    fn is_excluded(&self, root: &Path, dir: &Path) -> bool {
+        let match_options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        self.exclude_dirs.iter().any(|pattern| {
+            let pattern_str = pattern.as_str();
+            if pattern_str.contains('/') || pattern_str.contains('\\') {
+                dir.strip_prefix(root)
+                    .map(|relative| pattern.matches_path_with(relative, match_options))
+                    .unwrap_or(false)
+            } else {
+                dir.file_name()
+                    .map(|name| pattern.matches_with(&name.to_string_lossy(), match_options))
+                    .unwrap_or(false)
+            }
+        })
+    }
+}
+
+/// Recursively list the files in `dir`
+pub fn visit_dirs(dir: &Path, options: &WalkOptions) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    visit_dirs_inner(dir, dir, 0, options, &mut files)?;
+    Ok(files)
+}
+
+fn visit_dirs_inner(
+    root: &Path,
+    dir: &Path,
+    depth: usize,
+    options: &WalkOptions,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    if dir.is_dir() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if options.is_ignored(&path, path.is_dir()) {
+                continue;
+            }
+            if path.is_dir() {
+                let too_deep = options.max_depth.is_some_and(|max| depth >= max);
+                if !too_deep && !options.is_excluded(root, &path) {
+                    visit_dirs_inner(root, &path, depth + 1, options, files)?;
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Load every package of the workspace that lives on the local filesystem
+pub fn local_packages(ws: &Workspace) -> Result<Vec<Package>> {
+    let (packages, _) = cargo::ops::resolve_ws(ws, false)?;
+    let mut local_packages = Vec::new();
+    for package in packages.package_ids() {
+        if let Some(path) = package.source_id().local_path() {
+            let package_toml_path = path.join("Cargo.toml");
+            local_packages.push(ws.load(&package_toml_path)?);
+        }
+    }
+    Ok(local_packages)
+}
+
+/// All files in the source directories of the package's lib targets
+pub fn lib_source_files(package: &Package, options: &WalkOptions) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for target in package.targets() {
+        if let TargetKind::Lib(_) = target.kind() {
+            if let TargetSourcePath::Path(path) = target.src_path() {
+                let dir = path.parent().unwrap();
+                files.extend(visit_dirs(dir, options)?);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Files whose modification can change the compiled output of the crate at `cargo_toml_path`
+pub fn tracked_files(cargo_toml_path: &Path, ctx: &GlobalContext) -> Result<Vec<PathBuf>> {
+    let ws = Workspace::new(&cargo_toml_path.canonicalize()?, ctx)?;
+    let mut files = Vec::new();
+    let lock_file = ws.root().join("Cargo.lock");
+    if lock_file.exists() {
+        files.push(lock_file);
+    }
+    for package in local_packages(&ws)? {
+        files.push(package.manifest_path().to_owned());
+        files.extend(lib_source_files(&package, &WalkOptions::default())?);
+    }
+    Ok(files)
+}
+
+/// Escape a path for use in a ninja build statement. Ninja can't escape a newline, `$` before
+/// one continues the line, so paths with one are an error.
+pub fn ninja_escape(path: &Path) -> Result<String> {
+    let path = path.to_string_lossy();
+    if path.contains('\n') {
+        bail!("ninja can't refer to a path with a newline: {:?}", path);
+    }
+    let mut escaped = String::new();
+    for c in path.chars() {
+        if let '$' | ' ' | ':' = c {
+            escaped.push('$');
+        }
+        escaped.push(c);
+    }
+    Ok(escaped)
+}
+
+/// A ninja build edge producing `target` with `rule`, with `inputs` as implicit dependencies
+pub fn ninja_build_edge(target: &Path, rule: &str, inputs: &[PathBuf]) -> Result<String> {
+    let mut edge = format!("build {}: {}", ninja_escape(target)?, rule);
+    if !inputs.is_empty() {
+        edge.push_str(" |");
+        for input in inputs {
+            edge.push_str(" $\n    ");
+            edge.push_str(&ninja_escape(input)?);
+        }
+    }
+    Ok(edge)
+}
+
+/// A target of a workspace member, as printed by list-targets
+#[derive(Debug, Serialize)]
+pub struct TargetInfo {
+    pub package: String,
+    pub name: String,
+    pub kind: String,
+    pub crate_types: Vec<String>,
+    pub src_path: Option<PathBuf>,
+}
+
+/// The targets of every member of the workspace
+pub fn member_targets(ws: &Workspace) -> Vec<TargetInfo> {
+    let mut targets = Vec::new();
+    for package in ws.members() {
+        for target in package.targets() {
+            let src_path = match target.src_path() {
+                TargetSourcePath::Path(path) => Some(path.clone()),
+                TargetSourcePath::Metabuild => None,
+            };
+            targets.push(TargetInfo {
+                package: package.name().to_string(),
+                name: target.name().to_owned(),
+                kind: target.kind().description().to_owned(),
+                crate_types: target
+                    .rustc_crate_types()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                src_path,
+            });
+        }
+    }
+    targets
+}
+
+/// A source file with its size and contents hash, as printed by `--with-metadata`
+#[derive(Debug, Serialize)]
+pub struct FileMetadata {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Read the size and hash of every file, spread over the available cores. The result is in the
+/// order of `files`.
+pub fn files_metadata(files: &[PathBuf]) -> Result<Vec<FileMetadata>> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = files.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = files
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|path| {
+                            let contents = fs::read(path)
+                                .with_context(|| format!("Couldn't read {}", path.display()))?;
+                            Ok(FileMetadata {
+                                path: path.clone(),
+                                size: contents.len() as u64,
+                                sha256: hex::encode(Sha256::digest(&contents)),
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect();
+        let mut metadata = Vec::with_capacity(files.len());
+        for worker in workers {
+            metadata.extend(worker.join().expect("hashing thread panicked")?);
+        }
+        Ok(metadata)
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FileState {
+    len: u64,
+    modified_nanos: u128,
+    sha256: String,
+}
+
+/// The state of a set of files, to report which of them changed between source-files runs. The
+/// file format is internal and may change between versions; an unreadable snapshot is treated
+/// as empty.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    files: BTreeMap<PathBuf, FileState>,
+}
+
+/// How a file differs from the previous snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileChange {
+    Added(PathBuf),
+    Changed(PathBuf),
+    Removed(PathBuf),
+}
+
+impl std::fmt::Display for FileChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileChange::Added(path) => write!(f, "added\t{}", path.display()),
+            FileChange::Changed(path) => write!(f, "changed\t{}", path.display()),
+            FileChange::Removed(path) => write!(f, "removed\t{}", path.display()),
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn load(path: &Path) -> Snapshot {
+        std::fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Snapshot `files`. Files whose size and modification time match `previous` aren't read
+    /// again.
+    pub fn take(files: &[PathBuf], previous: &Snapshot) -> Result<Snapshot> {
+        let mut snapshot = Snapshot::default();
+        for path in files {
+            let metadata = fs::metadata(path)?;
+            let len = metadata.len();
+            let modified_nanos = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos();
+            let sha256 = match previous.files.get(path) {
+                Some(state) if state.len == len && state.modified_nanos == modified_nanos => {
+                    state.sha256.clone()
+                }
+                _ => hex::encode(Sha256::digest(fs::read(path)?)),
+            };
+            let state = FileState {
+                len,
+                modified_nanos,
+                sha256,
+            };
+            snapshot.files.insert(path.clone(), state);
+        }
+        Ok(snapshot)
+    }
+
+    /// The files that were added, removed, or whose contents changed since `previous`. Files
+    /// that were only touched don't count as changed.
+    pub fn changes_since(&self, previous: &Snapshot) -> Vec<FileChange> {
+        let mut changes = Vec::new();
+        for (path, state) in &self.files {
+            match previous.files.get(path) {
+                None => changes.push(FileChange::Added(path.clone())),
+                Some(old) if old.sha256 != state.sha256 => {
+                    changes.push(FileChange::Changed(path.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        for path in previous.files.keys() {
+            if !self.files.contains_key(path) {
+                changes.push(FileChange::Removed(path.clone()));
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A directory with `files` (relative paths) in it
+    fn source_tree(files: &[&str]) -> TempDir {
+        let tree = tempfile::tempdir().unwrap();
+        let root = tree.path();
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        tree
+    }
+
+    /// The files `visit_dirs` lists under `root`, relative to it and sorted
+    fn visited(root: &Path, options: &WalkOptions) -> Vec<String> {
+        let mut files: Vec<String> = visit_dirs(root, options)
+            .unwrap()
+            .iter()
+            .map(|path| {
+                let relative = path.strip_prefix(root).unwrap();
+                relative.to_string_lossy().replace('\\', "/")
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn walk() {
+        let tree = source_tree(&["lib.rs", "ffi/mod.rs", "ffi/types/vec.rs"]);
+        let root = tree.path();
+        assert_eq!(
+            visited(root, &WalkOptions::default()),
+            ["ffi/mod.rs", "ffi/types/vec.rs", "lib.rs"]
+        );
+        assert!(visit_dirs(&root.join("missing"), &WalkOptions::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn excluded_dirs() {
+        let tree = source_tree(&[
+            "lib.rs",
+            "generated/bindings.rs",
+            "ffi/generated/types.rs",
+            "ffi/mod.rs",
+            "tests/data/case.rs",
+        ]);
+        let root = tree.path();
+        let options = WalkOptions {
+            exclude_dirs: vec![glob::Pattern::new("gen*").unwrap()],
+            ..WalkOptions::default()
+        };
+        // names match at any depth
+        assert_eq!(
+            visited(root, &options),
+            ["ffi/mod.rs", "lib.rs", "tests/data/case.rs"]
+        );
+        let options = WalkOptions {
+            exclude_dirs: vec![
+                glob::Pattern::new("ffi/generated").unwrap(),
+                glob::Pattern::new("*/data").unwrap(),
+            ],
+            ..WalkOptions::default()
+        };
+        // paths match relative to the walked directory
+        assert_eq!(
+            visited(root, &options),
+            ["ffi/mod.rs", "generated/bindings.rs", "lib.rs"]
+        );
+    }
+
+    #[test]
+    fn max_depth() {
+        let tree = source_tree(&["lib.rs", "ffi/mod.rs", "ffi/types/vec.rs"]);
+        let root = tree.path();
+        let depth = |max_depth| WalkOptions {
+            max_depth: Some(max_depth),
+            ..WalkOptions::default()
+        };
+        assert_eq!(visited(root, &depth(0)), ["lib.rs"]);
+        assert_eq!(visited(root, &depth(1)), ["ffi/mod.rs", "lib.rs"]);
+        assert_eq!(
+            visited(root, &depth(2)),
+            ["ffi/mod.rs", "ffi/types/vec.rs", "lib.rs"]
+        );
+    }
+
+    #[test]
+    fn ninja_escaping() {
+        assert_eq!(
+            ninja_escape(Path::new("C:/Game Rust/$lib.rs")).unwrap(),
+            "C$:/Game$ Rust/$$lib.rs"
+        );
+        assert_eq!(ninja_escape(Path::new("src/lib.rs")).unwrap(), "src/lib.rs");
+        assert!(ninja_escape(Path::new("src/new\nline.rs")).is_err());
+        assert_eq!(
+            ninja_build_edge(
+                Path::new("Game Rust.stamp"),
+                "phony",
+                &[PathBuf::from("C:/src/lib.rs")]
+            )
+            .unwrap(),
+            "build Game$ Rust.stamp: phony | $\n    C$:/src/lib.rs"
+        );
+        assert_eq!(
+            ninja_build_edge(Path::new("out.stamp"), "phony", &[]).unwrap(),
+            "build out.stamp: phony"
+        );
+        assert!(ninja_build_edge(
+            Path::new("out.stamp"),
+            "phony",
+            &[
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src/new\nline.rs")
+            ]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn changes_since_snapshot() {
+        let tree = source_tree(&["lib.rs", "ffi.rs", "old.rs"]);
+        let root = tree.path();
+        let snapshot_path = root.join("snapshot.json");
+        let files =
+            |names: &[&str]| -> Vec<PathBuf> { names.iter().map(|name| root.join(name)).collect() };
+
+        // a missing snapshot is empty, so every file is new
+        let previous = Snapshot::load(&snapshot_path);
+        let snapshot = Snapshot::take(&files(&["lib.rs", "ffi.rs", "old.rs"]), &previous).unwrap();
+        assert_eq!(snapshot.changes_since(&previous).len(), 3);
+        snapshot.save(&snapshot_path).unwrap();
+
+        fs::write(root.join("lib.rs"), "pub mod ffi;\n").unwrap();
+        // touched without changing its contents
+        fs::write(root.join("ffi.rs"), "").unwrap();
+        fs::write(root.join("new.rs"), "").unwrap();
+        let previous = Snapshot::load(&snapshot_path);
+        let snapshot = Snapshot::take(&files(&["lib.rs", "ffi.rs", "new.rs"]), &previous).unwrap();
+        let mut changes = snapshot.changes_since(&previous);
+        changes.sort_by_key(ToString::to_string);
+        assert_eq!(
+            changes,
+            [
+                FileChange::Added(root.join("new.rs")),
+                FileChange::Changed(root.join("lib.rs")),
+                FileChange::Removed(root.join("old.rs")),
+            ]
+        );
+        assert!(snapshot.changes_since(&snapshot).is_empty());
+
+        fs::write(&snapshot_path, "not a snapshot").unwrap();
+        assert!(Snapshot::load(&snapshot_path).files.is_empty());
+    }
+
+    #[test]
+    fn ignore_file() {
+        let tree = source_tree(&[
+            "src/lib.rs",
+            "src/fixtures/world.rs",
+            "src/ffi/fixtures/types.rs",
+            "src/ffi/mod.rs",
+            "src/ffi/mod.rs.snap",
+            "src/keep.snap",
+        ]);
+        let root = tree.path();
+        let src = root.join("src");
+        // no ignore file
+        let options = WalkOptions::default().with_ignore_file(root).unwrap();
+        assert_eq!(visited(&src, &options).len(), 6);
+
+        fs::write(
+            root.join(IGNORE_FILE_NAME),
+            "# comment\n/src/fixtures/\n*.snap\n!keep.snap\n",
+        )
+        .unwrap();
+        let options = WalkOptions::default().with_ignore_file(root).unwrap();
+        // anchored patterns are relative to the package root, the others match at any depth
+        assert_eq!(
+            visited(&src, &options),
+            ["ffi/fixtures/types.rs", "ffi/mod.rs", "keep.snap", "lib.rs"]
+        );
+    }
+
+    #[test]
+    fn json_shape() {
+        let tree = source_tree(&["lib.rs", "ffi.rs"]);
+        let root = tree.path();
+        fs::write(root.join("lib.rs"), "abc").unwrap();
+        let files = [root.join("lib.rs"), root.join("ffi.rs")];
+        let path = |name: &str| root.join(name).to_string_lossy().into_owned();
+        assert_eq!(
+            serde_json::to_value(&files).unwrap(),
+            serde_json::json!([path("lib.rs"), path("ffi.rs")])
+        );
+        assert_eq!(
+            serde_json::to_value(files_metadata(&files).unwrap()).unwrap(),
+            serde_json::json!([
+                {
+                    "path": path("lib.rs"),
+                    "size": 3,
+                    "sha256": "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+                },
+                {
+                    "path": path("ffi.rs"),
+                    "size": 0,
+                    "sha256": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! The toolchain a crate pins with `rust-toolchain.toml`, checked before cargo runs so that a
+//! build machine missing it fails (or installs it) up front instead of in the middle of the build
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The toolchain file names rustup looks for, in order of precedence
+const TOOLCHAIN_FILES: &[&str] = &["rust-toolchain", "rust-toolchain.toml"];
+
+/// A pinned toolchain with the components and targets it needs
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PinnedToolchain {
+    pub file: PathBuf,
+    pub channel: String,
+    pub components: Vec<String>,
+    pub targets: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolchainSection {
+    channel: Option<String>,
+    path: Option<PathBuf>,
+    #[serde(default)]
+    components: Vec<String>,
+    #[serde(default)]
+    targets: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolchainFile {
+    toolchain: ToolchainSection,
+}
+
+/// The toolchain pinned by the closest toolchain file in `dir` or its parents, as rustup finds
+/// it. Toolchains given by `path` rather than `channel` aren't managed by rustup, so they're
+/// `None` like no toolchain file at all.
+pub fn pinned_toolchain(dir: &Path) -> Result<Option<PinnedToolchain>> {
+    let Some(file) = dir.ancestors().find_map(|dir| {
+        TOOLCHAIN_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    }) else {
+        return Ok(None);
+    };
+    let contents = std::fs::read_to_string(&file)
+        .with_context(|| format!("Couldn't read {}", file.display()))?;
+    parse_toolchain_file(&contents, &file)
+}
+
+fn parse_toolchain_file(contents: &str, file: &Path) -> Result<Option<PinnedToolchain>> {
+    // the legacy format is just the channel name
+    let legacy = contents.trim();
+    if !legacy.is_empty() && !legacy.contains(['\n', '=', '[']) {
+        return Ok(Some(PinnedToolchain {
+            file: file.to_owned(),
+            channel: legacy.to_owned(),
+            ..PinnedToolchain::default()
+        }));
+    }
+    let parsed: ToolchainFile =
+        toml::from_str(contents).with_context(|| format!("Invalid {}", file.display()))?;
+    let section = parsed.toolchain;
+    match (section.channel, section.path) {
+        (Some(channel), _) => Ok(Some(PinnedToolchain {
+            file: file.to_owned(),
+            channel,
+            components: section.components,
+            targets: section.targets,
+        })),
+        (None, Some(_)) => Ok(None),
+        (None, None) => bail!("{} has neither a channel nor a path", file.display()),
+    }
+}
+
+fn rustup_lines(args: &[&str]) -> Result<Vec<String>> {
+    let output = Command::new("rustup")
+        .args(args)
+        .output()
+        .context("Couldn't run rustup")?;
+    if !output.status.success() {
+        bail!("rustup {} failed with {}", args.join(" "), output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        // e.g. "stable-x86_64-pc-windows-msvc (default)"
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Whether an installed toolchain name (`<channel>-<host>`) is the channel's. A channel only
+/// matches its own dated or numbered versions, e.g. `nightly` doesn't match
+/// `nightly-2024-05-01-x86_64-pc-windows-msvc`.
+fn is_channel_toolchain(installed: &str, channel: &str) -> bool {
+    installed == channel
+        || installed
+            .strip_prefix(channel)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .is_some_and(|host| !host.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// Whether an installed component or target (components are suffixed with the host) is `name`
+fn is_installed(installed: &[String], name: &str) -> bool {
+    installed.iter().any(|line| {
+        line == name
+            || line
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('-'))
+                .is_some_and(|host| host.matches('-').count() >= 2)
+    })
+}
+
+impl PinnedToolchain {
+    /// What of the toolchain, its components and `targets` (besides its own) isn't installed,
+    /// formatted for a message. Empty if everything is.
+    pub fn missing(&self, targets: &[String]) -> Result<Vec<String>> {
+        let toolchains = rustup_lines(&["toolchain", "list"])?;
+        if !toolchains
+            .iter()
+            .any(|toolchain| is_channel_toolchain(toolchain, &self.channel))
+        {
+            return Ok(vec![format!("toolchain {}", self.channel)]);
+        }
+        let mut missing = Vec::new();
+        let components = rustup_lines(&[
+            "component",
+            "list",
+            "--installed",
+            "--toolchain",
+            &self.channel,
+        ])?;
+        for component in &self.components {
+            if !is_installed(&components, component) {
+                missing.push(format!("component {}", component));
+            }
+        }
+        let installed_targets = rustup_lines(&[
+            "target",
+            "list",
+            "--installed",
+            "--toolchain",
+            &self.channel,
+        ])?;
+        for target in self.targets.iter().chain(targets) {
+            if !is_installed(&installed_targets, target) {
+                missing.push(format!("target {}", target));
+            }
+        }
+        Ok(missing)
+    }
+
+    /// The rustup command installing the toolchain with its components and `targets`. rustup
+    /// skips whatever is installed already.
+    pub fn install_args(&self, targets: &[String]) -> Vec<String> {
+        let mut args: Vec<String> = [
+            "toolchain",
+            "install",
+            &self.channel,
+            "--profile",
+            "minimal",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        for component in &self.components {
+            args.extend(["--component".to_owned(), component.clone()]);
+        }
+        for target in self.targets.iter().chain(targets) {
+            args.extend(["--target".to_owned(), target.clone()]);
+        }
+        args
+    }
+
+    pub fn install(&self, targets: &[String]) -> Result<()> {
+        let status = Command::new("rustup")
+            .args(self.install_args(targets))
+            .status()
+            .context("Couldn't run rustup")?;
+        if !status.success() {
+            bail!("rustup toolchain install failed with {}", status);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toolchain_files() {
+        let file = Path::new("rust-toolchain.toml");
+        let pinned = parse_toolchain_file(
+            "[toolchain]\nchannel = \"nightly-2024-05-01\"\ncomponents = [\"rust-src\"]\ntargets = [\"aarch64-linux-android\"]\n",
+            file,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(pinned.channel, "nightly-2024-05-01");
+        assert_eq!(
+            pinned.install_args(&["x86_64-pc-windows-msvc".to_owned()]),
+            vec![
+                "toolchain",
+                "install",
+                "nightly-2024-05-01",
+                "--profile",
+                "minimal",
+                "--component",
+                "rust-src",
+                "--target",
+                "aarch64-linux-android",
+                "--target",
+                "x86_64-pc-windows-msvc",
+            ]
+        );
+        let legacy = parse_toolchain_file("1.75.0\n", Path::new("rust-toolchain"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(legacy.channel, "1.75.0");
+        assert_eq!(
+            parse_toolchain_file("[toolchain]\npath = \"/opt/rust\"\n", file).unwrap(),
+            None
+        );
+
+        assert!(is_channel_toolchain(
+            "nightly-x86_64-pc-windows-msvc",
+            "nightly"
+        ));
+        assert!(!is_channel_toolchain(
+            "nightly-2024-05-01-x86_64-pc-windows-msvc",
+            "nightly"
+        ));
+        let components = vec![
+            "rust-src".to_owned(),
+            "rustfmt-x86_64-pc-windows-msvc".to_owned(),
+        ];
+        assert!(is_installed(&components, "rustfmt"));
+        assert!(is_installed(&components, "rust-src"));
+        assert!(!is_installed(&components, "clippy"));
+    }
+}
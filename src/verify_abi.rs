@@ -0,0 +1,100 @@
+//! verify-abi: a check that the functions and statics the headers declare are exactly the symbols
+//! the crate's library exports, so that a stale header fails the build instead of the link, or
+//! the game at runtime
+
+use crate::wrappers::function_declarations;
+use std::collections::HashSet;
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The statics the header declares, e.g. `extern const int32_t MAX_PLAYERS;` or
+/// `extern void (*on_event)(int32_t);`
+fn declared_statics(header: &str) -> Vec<String> {
+    let mut statics = Vec::new();
+    for line in header.lines().map(str::trim) {
+        let Some(declaration) = line
+            .strip_prefix("extern ")
+            .and_then(|declaration| declaration.strip_suffix(';'))
+        else {
+            continue;
+        };
+        // function pointers are named in the parentheses of `(*name)`, the others last, before
+        // any array length
+        let (head, pointer) = match declaration.split_once("(*") {
+            Some((_, pointer)) => (pointer.split(')').next().unwrap_or_default(), true),
+            None if declaration.contains('(') => continue,
+            None => (declaration.split('[').next().unwrap_or_default(), false),
+        };
+        let head = head.trim_end();
+        let name = &head[head.trim_end_matches(is_ident_char).len()..];
+        // a name without a type before it isn't a declaration
+        if !name.is_empty() && (pointer || name.len() != head.len()) {
+            statics.push(name.to_owned());
+        }
+    }
+    statics
+}
+
+/// The symbols the header declares: its functions and statics
+pub fn declared_symbols(header: &str) -> Vec<String> {
+    let mut symbols: Vec<String> = function_declarations(header, None)
+        .into_iter()
+        .map(|declaration| declaration.name)
+        .collect();
+    symbols.extend(declared_statics(header));
+    symbols
+}
+
+/// The declared symbols that aren't exported, and the exported ones that aren't declared, each
+/// sorted and without duplicates
+pub fn compare(declared: &[String], exported: &[String]) -> (Vec<String>, Vec<String>) {
+    let declared: HashSet<&String> = declared.iter().collect();
+    let exported: HashSet<&String> = exported.iter().collect();
+    let mut missing: Vec<String> = declared
+        .difference(&exported)
+        .map(|s| s.to_string())
+        .collect();
+    let mut undeclared: Vec<String> = exported
+        .difference(&declared)
+        .map(|s| s.to_string())
+        .collect();
+    missing.sort();
+    undeclared.sort();
+    (missing, undeclared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_against_exports() {
+        let header = "#define WORLD_MAX 4\n\ntypedef void (*Callback)(int32_t);\n\nextern \"C\" {\n\nextern const int32_t MAX_PLAYERS;\n\nextern void (*on_event)(int32_t);\n\nextern uint8_t SEEDS[4];\n\nGAME_API World *CDECL world_new(void);\n\nvoid world_each(World *world,\n                Callback callback);\n\n}  // extern \"C\"\n";
+        let declared = declared_symbols(header);
+        assert_eq!(
+            declared,
+            [
+                "world_new",
+                "world_each",
+                "MAX_PLAYERS",
+                "on_event",
+                "SEEDS"
+            ]
+        );
+        let exported: Vec<String> = [
+            "world_new",
+            "MAX_PLAYERS",
+            "on_event",
+            "SEEDS",
+            "world_free",
+        ]
+        .iter()
+        .map(|symbol| symbol.to_string())
+        .collect();
+        let (missing, undeclared) = compare(&declared, &exported);
+        assert_eq!(missing, ["world_each"]);
+        assert_eq!(undeclared, ["world_free"]);
+    }
+}
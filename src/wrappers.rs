@@ -0,0 +1,363 @@
+//! C++ RAII wrappers around the handles of a header: a move-only class per type the crate frees
+//! with a `<prefix>_free` function, whose constructor, factories and methods forward to the
+//! `<prefix>_*` functions taking or returning the handle
+
+use std::path::{Path, PathBuf};
+
+/// Suffixes of the functions freeing a handle
+const DESTRUCTOR_SUFFIXES: &[&str] = &["_free", "_destroy", "_delete"];
+
+/// Members every wrapper has, which methods can't be named as
+const RESERVED_METHODS: &[&str] = &["Get", "Release", "Reset"];
+
+/// A parameter of a function declaration, e.g. `const Foo *foo`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    /// The parameter as written in the header
+    pub text: String,
+    /// Its type without the name, e.g. `const Foo *`
+    pub ty: String,
+    pub name: String,
+}
+
+/// A function declaration of the header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declaration {
+    pub ret: String,
+    pub name: String,
+    pub params: Vec<Param>,
+}
+
+/// The wrappers header of a header without an explicit path: `<header>_wrappers.h` next to it
+pub fn default_wrappers_path(header_path: &Path) -> PathBuf {
+    let stem = header_path
+        .file_stem()
+        .map_or_else(|| "header".into(), |stem| stem.to_string_lossy());
+    header_path.with_file_name(format!("{}_wrappers.h", stem))
+}
+
+/// Split a parameter list at the commas that aren't nested in parentheses, e.g. those of a
+/// function pointer parameter
+fn split_params(list: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                params.push(list[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(list[start..].trim());
+    params
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+pub fn parse_param(text: &str) -> Option<Param> {
+    // function pointers are named inside the parentheses: `void (*callback)(int32_t)`
+    let (ty, name) = if let Some(start) = text.find("(*") {
+        let end = start + 2 + text[start + 2..].find(')')?;
+        let name = text[start + 2..end].trim();
+        let ty = format!("{}(*){}", &text[..start], &text[end + 1..]);
+        (ty, name.to_owned())
+    } else {
+        let split = text.trim_end_matches(is_ident_char).len();
+        (
+            text[..split].trim_end().to_owned(),
+            text[split..].to_owned(),
+        )
+    };
+    if name.is_empty() || ty.is_empty() {
+        return None;
+    }
+    Some(Param {
+        text: text.to_owned(),
+        ty,
+        name,
+    })
+}
+
+/// The function declarations of the header, e.g. `Foo *foo_new(int32_t width);` with the
+/// functions' `prefix` (e.g. an API macro) stripped. Declarations cbindgen wrapped over several
+/// lines are joined.
+pub fn function_declarations(header: &str, prefix: Option<&str>) -> Vec<Declaration> {
+    let mut declarations = Vec::new();
+    for chunk in header.split("\n\n") {
+        let declaration = chunk
+            .lines()
+            .map(str::trim)
+            .filter(|line| {
+                !["//", "/*", "*", "#"]
+                    .iter()
+                    .any(|start| line.starts_with(start))
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let Some(declaration) = declaration.strip_suffix(");") else {
+            continue;
+        };
+        if declaration.starts_with("typedef ") || declaration.contains('{') {
+            continue;
+        }
+        let mut declaration = declaration.trim_start_matches("extern ");
+        if let Some(prefix) = prefix {
+            declaration = declaration
+                .strip_prefix(prefix)
+                .map_or(declaration, str::trim_start);
+        }
+        let Some((head, params)) = declaration.split_once('(') else {
+            continue;
+        };
+        let name_start = head.trim_end_matches(is_ident_char).len();
+        let (ret, name) = (head[..name_start].trim_end(), &head[name_start..]);
+        if ret.is_empty() || name.is_empty() {
+            continue;
+        }
+        let params: Option<Vec<Param>> = match params.trim() {
+            "" | "void" => Some(Vec::new()),
+            params => split_params(params).into_iter().map(parse_param).collect(),
+        };
+        if let Some(params) = params {
+            declarations.push(Declaration {
+                ret: ret.to_owned(),
+                name: name.to_owned(),
+                params,
+            });
+        }
+    }
+    declarations
+}
+
+/// A pointer type without spaces and its constness, e.g. `("Foo*", true)` for `const Foo *`
+fn pointer_type(ty: &str) -> (String, bool) {
+    let (ty, is_const) = match ty.strip_prefix("const ") {
+        Some(ty) => (ty, true),
+        None => (ty, false),
+    };
+    (ty.replace(' ', ""), is_const)
+}
+
+/// `foo_bar` as `FooBar`
+pub fn camel_case(snake: &str) -> String {
+    snake
+        .split('_')
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect()
+}
+
+/// A declaration of `name` with type `ty`, spaced as cbindgen does: `Foo *name`
+fn declare(ty: &str, name: &str) -> String {
+    if ty.ends_with('*') {
+        format!("{}{}", ty, name)
+    } else {
+        format!("{} {}", ty, name)
+    }
+}
+
+/// The arguments forwarding `params`
+fn forward(params: &[Param]) -> String {
+    params
+        .iter()
+        .map(|param| param.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn param_list(params: &[Param]) -> String {
+    params
+        .iter()
+        .map(|param| param.text.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The wrapper class of the handle freed by `destructor`, if it frees one
+fn wrapper_class(destructor: &Declaration, declarations: &[Declaration]) -> Option<String> {
+    let prefix = DESTRUCTOR_SUFFIXES
+        .iter()
+        .find_map(|suffix| destructor.name.strip_suffix(suffix))?;
+    let [handle] = &destructor.params[..] else {
+        return None;
+    };
+    let (handle_type, is_const) = pointer_type(&handle.ty);
+    let pointee = handle.ty.strip_suffix('*')?.trim_end();
+    if destructor.ret != "void" || is_const || pointee.contains('*') || pointee == "void" {
+        return None;
+    }
+    let class = format!("{}Handle", pointee.trim_start_matches("struct "));
+    let ptr = &handle.ty;
+    let mut constructors = String::new();
+    let mut factories = String::new();
+    let mut methods = String::new();
+    let mut names: Vec<String> = RESERVED_METHODS.iter().map(|&name| name.into()).collect();
+    for declaration in declarations {
+        let Some(rest) = declaration
+            .name
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_prefix('_'))
+        else {
+            continue;
+        };
+        if declaration.name == destructor.name {
+            continue;
+        }
+        let takes_handle = declaration
+            .params
+            .first()
+            .is_some_and(|param| pointer_type(&param.ty).0 == handle_type);
+        let params = param_list(&declaration.params);
+        let args = forward(&declaration.params);
+        if !takes_handle && pointer_type(&declaration.ret) == (handle_type.clone(), false) {
+            if rest == "new" {
+                let explicit = if declaration.params.len() == 1 {
+                    "explicit "
+                } else {
+                    ""
+                };
+                constructors.push_str(&format!(
+                    "  {}{}({}) : Ptr({}({})) {{}}\n",
+                    explicit, class, params, declaration.name, args
+                ));
+            } else {
+                let name = camel_case(rest);
+                if names.contains(&name) {
+                    continue;
+                }
+                factories.push_str(&format!(
+                    "  static {} {}({}) {{ return {}({}({})); }}\n",
+                    class, name, params, class, declaration.name, args
+                ));
+                names.push(name);
+            }
+        } else if takes_handle {
+            let name = camel_case(rest);
+            if names.contains(&name) {
+                continue;
+            }
+            let is_const = pointer_type(&declaration.params[0].ty).1;
+            let args = std::iter::once("Ptr")
+                .chain(
+                    declaration.params[1..]
+                        .iter()
+                        .map(|param| param.name.as_str()),
+                )
+                .collect::<Vec<_>>()
+                .join(", ");
+            methods.push_str(&format!(
+                "  {}({}){} {{ return {}({}); }}\n",
+                declare(&declaration.ret, &name),
+                param_list(&declaration.params[1..]),
+                if is_const { " const" } else { "" },
+                declaration.name,
+                args
+            ));
+            names.push(name);
+        }
+    }
+    if constructors.is_empty() {
+        constructors = format!("  {}() : Ptr(nullptr) {{}}\n", class);
+    }
+    let mut wrapper = format!("class {} {{\n public:\n{}", class, constructors);
+    wrapper.push_str(&format!(
+        "  explicit {0}({1}) : Ptr(ptr) {{}}\n  ~{0}() {{ Reset(); }}\n  {0}(const {0} &) = delete;\n  {0} &operator=(const {0} &) = delete;\n  {0}({0} &&other) noexcept : Ptr(other.Release()) {{}}\n  {0} &operator=({0} &&other) noexcept {{\n    Reset(other.Release());\n    return *this;\n  }}\n",
+        class,
+        declare(ptr, "ptr")
+    ));
+    for members in [&factories, &methods] {
+        if !members.is_empty() {
+            wrapper.push('\n');
+            wrapper.push_str(members);
+        }
+    }
+    wrapper.push_str(&format!(
+        "\n  {} const {{ return Ptr; }}\n  {} {{ return std::exchange(Ptr, nullptr); }}\n  void Reset({} = nullptr) {{\n    if (Ptr) {{\n      {}(Ptr);\n    }}\n    Ptr = ptr;\n  }}\n  explicit operator bool() const {{ return Ptr != nullptr; }}\n\n private:\n  {};\n}};\n",
+        declare(ptr, "Get()"),
+        declare(ptr, "Release()"),
+        declare(ptr, "ptr"),
+        destructor.name,
+        declare(ptr, "Ptr")
+    ));
+    Some(wrapper)
+}
+
+/// The companion header of `header_name` (with contents `header`) with a wrapper class per
+/// handle the header frees
+pub fn wrappers_header(header_name: &str, header: &str, config: &cbindgen::Config) -> String {
+    let declarations = function_declarations(header, config.function.prefix.as_deref());
+    let classes: Vec<String> = declarations
+        .iter()
+        .filter_map(|declaration| wrapper_class(declaration, &declarations))
+        .collect();
+    let namespaces = crate::bindings::cpp_namespaces(config);
+    let mut wrappers = format!(
+        "// C++ wrappers owning the handles of {}, generated from its functions\n\n#pragma once\n\n#include <utility>\n\n#include \"{}\"\n\n",
+        header_name, header_name
+    );
+    for namespace in &namespaces {
+        wrappers.push_str(&format!("namespace {} {{\n", namespace));
+    }
+    if !namespaces.is_empty() {
+        wrappers.push('\n');
+    }
+    wrappers.push_str(&classes.join("\n"));
+    if !namespaces.is_empty() {
+        wrappers.push('\n');
+    }
+    for namespace in namespaces.iter().rev() {
+        wrappers.push_str(&format!("}}  // namespace {}\n", namespace));
+    }
+    wrappers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_wrappers() {
+        let header = "namespace Game {\n\nstruct World;\n\nextern \"C\" {\n\n/**\n * Create a world\n */\nGAME_API World *world_new(int32_t width, int32_t height);\n\nGAME_API World *world_load(const char *path);\n\nGAME_API\nvoid world_free(World *world);\n\nGAME_API int32_t world_width(const World *world);\n\nGAME_API void world_each(World *world,\n                         void (*callback)(int32_t x, int32_t y),\n                         void *data);\n\n}  // extern \"C\"\n\n}  // namespace Game\n";
+        let mut config = cbindgen::Config {
+            language: cbindgen::Language::Cxx,
+            namespace: Some("Game".to_owned()),
+            ..cbindgen::Config::default()
+        };
+        config.function.prefix = Some("GAME_API".to_owned());
+        let declarations = function_declarations(header, config.function.prefix.as_deref());
+        assert_eq!(declarations.len(), 5);
+        assert_eq!(declarations[2].ret, "void");
+        assert_eq!(declarations[4].params[1].name, "callback");
+        assert_eq!(
+            declarations[4].params[1].ty,
+            "void (*)(int32_t x, int32_t y)"
+        );
+
+        let wrappers = wrappers_header("Game.h", header, &config);
+        assert!(
+            wrappers.contains("#include \"Game.h\"\n\nnamespace Game {\n\nclass WorldHandle {\n")
+        );
+        assert!(wrappers.contains(
+            "  WorldHandle(int32_t width, int32_t height) : Ptr(world_new(width, height)) {}\n"
+        ));
+        assert!(wrappers.contains("  explicit WorldHandle(World *ptr) : Ptr(ptr) {}\n"));
+        assert!(wrappers.contains("  static WorldHandle Load(const char *path) { return WorldHandle(world_load(path)); }\n"));
+        assert!(wrappers.contains("  int32_t Width() const { return world_width(Ptr); }\n"));
+        assert!(wrappers.contains("  void Each(void (*callback)(int32_t x, int32_t y), void *data) { return world_each(Ptr, callback, data); }\n"));
+        assert!(wrappers.contains("      world_free(Ptr);\n"));
+        assert!(wrappers.ends_with("};\n\n}  // namespace Game\n"));
+    }
+}